@@ -264,6 +264,11 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                                 resources: self.resources.clone(),
                                 depends_on: Some(self.depends_on.clone()),
                                 retries: Some(self.retries.as_ref().cloned().unwrap_or(5)),
+                                retry_count: None,
+                                config_schema: None,
+                                skip_if: None,
+                                priority: 0,
+                                retry_policy: None,
                                 agent: testsys_model::Agent {
                                     name: "agent".to_string(),
                                     image: self.image.as_ref().cloned().ok_or_else(|| "Image is required to build a test".to_string())?,
@@ -273,7 +278,15 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                                     secrets: Some(self.secrets.clone()),
                                     capabilities: Some(self.capabilities.clone()),
                                     privileged: self.privileged,
-                                    timeout: None
+                                    keep_running_timeout_seconds: None,
+                                    cancel_requested: false,
+                                    timeout: None,
+                                    env: Default::default(),
+                                    node_selector: Default::default(),
+                                    tolerations: Default::default(),
+                                    init_containers: Default::default(),
+                                    sidecars: Default::default(),
+                                    rerun_cases: Default::default(),
                                 },
                             },
                         ))
@@ -453,10 +466,19 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                                 configuration: Some(configuration),
                                 secrets: Some(self.secrets.clone()),
                                 capabilities: Some(self.capabilities.clone()),
+                                keep_running_timeout_seconds: None,
+                                cancel_requested: false,
                                 timeout: None,
                                 privileged: self.privileged,
+                                env: Default::default(),
+                                node_selector: Default::default(),
+                                tolerations: Default::default(),
+                                init_containers: Default::default(),
+                                sidecars: Default::default(),
+                                rerun_cases: Default::default(),
                             },
-                            destruction_policy: self.destruction_policy.as_ref().cloned().unwrap_or_default()
+                            destruction_policy: self.destruction_policy.as_ref().cloned().unwrap_or_default(),
+                            used_by: Vec::new(),
                         },
                         ))
                     }
@@ -25,6 +25,8 @@ pub(crate) struct SendResults {
     skipped: u64,
     #[argh(option, description = "additional result information")]
     other_info: Option<String>,
+    #[argh(option, description = "location of uploaded result artifacts")]
+    artifact_uri: Option<String>,
 }
 
 impl SendResults {
@@ -36,6 +38,10 @@ impl SendResults {
             num_failed: self.failed,
             num_skipped: self.skipped,
             other_info: self.other_info.clone(),
+            artifact_uri: self.artifact_uri.clone(),
+            start_time: None,
+            end_time: None,
+            cases: None,
         };
         k8s_client
             .send_test_results(test_results)
@@ -79,6 +79,10 @@ where
             num_failed: 0,
             num_skipped: 0,
             other_info: Some("Running Test".to_string()),
+            artifact_uri: None,
+            start_time: None,
+            end_time: None,
+            cases: None,
         };
 
         info_client
@@ -97,6 +101,10 @@ where
                 .send_test_update(results.clone())
                 .await
                 .map_err(|e| format!("{:?}", e))?;
+            info_client
+                .send_progress((u64::from(i) * 100 / u64::from(self.config.hello_count)) as u8)
+                .await
+                .map_err(|e| format!("{:?}", e))?;
         }
         if let Some(nested) = &self.config.nested {
             println!("Nested Data:\n {:?}", nested.data);
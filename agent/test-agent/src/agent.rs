@@ -1,14 +1,19 @@
 use crate::error::{self, AgentError, Error, Result};
 use crate::{BootstrapData, Client, InfoClient, Runner};
+use chrono::{SecondsFormat, Utc};
 use log::{debug, error, info, trace};
 use snafu::ResultExt;
 use std::fs::File;
+use std::future::Future;
 use std::path::PathBuf;
 use std::time::Duration;
 use tar::Builder;
 use testsys_model::{Outcome, TestResults};
 use tokio::time::sleep;
 
+/// How often the test agent sends a heartbeat while the `Runner` is running.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
 /// The `TestAgent` is the main entrypoint for the program running in a TestPod. It starts a test
 /// run, regularly checks the health of the test run, observes cancellation of a test run, and sends
 /// the results of a test run.
@@ -102,19 +107,18 @@ where
             .await
             .map_err(error::Error::Client)?;
 
-        let mut test_results = match self
-            .runner
-            .run(&self.info_client)
-            .await
-            .map_err(error::Error::Runner)
-        {
-            Ok(ok) => ok,
-            Err(e) => {
+        let run_start = now();
+        let run = with_heartbeat(&self.client, self.runner.run(&self.info_client)).await;
+        let mut test_results = match run.map(|run| run.map_err(error::Error::Runner)) {
+            Some(Ok(ok)) => ok,
+            Some(Err(e)) => {
                 self.send_error_best_effort(&e).await;
                 self.terminate_best_effort().await;
                 return Err(e);
             }
+            None => return self.cancel().await,
         };
+        set_run_time(&mut test_results, run_start);
 
         // If we are unable to get the number of retries it is safer to assume it is zero
         // then to error.
@@ -145,19 +149,22 @@ where
                 .await
                 .map_err(error::Error::Client)?;
 
-            test_results = match self
-                .runner
-                .rerun_failed(&test_results, &self.info_client)
-                .await
-                .map_err(error::Error::Runner)
-            {
-                Ok(ok) => ok,
-                Err(e) => {
+            let run_start = now();
+            let rerun = with_heartbeat(
+                &self.client,
+                self.runner.rerun_failed(&test_results, &self.info_client),
+            )
+            .await;
+            test_results = match rerun.map(|rerun| rerun.map_err(error::Error::Runner)) {
+                Some(Ok(ok)) => ok,
+                Some(Err(e)) => {
                     self.send_error_best_effort(&e).await;
                     self.terminate_best_effort().await;
                     return Err(e);
                 }
+                None => return self.cancel().await,
             };
+            set_run_time(&mut test_results, run_start);
             retry_count += 1;
         }
 
@@ -172,6 +179,11 @@ where
             return Err(e);
         }
 
+        // The progress is only informational, so failing to send it does not fail the test.
+        if let Err(e) = self.info_client.send_progress(100).await {
+            error!("Unable to send progress: {}", e);
+        }
+
         // Test finished successfully. Try to terminate. If termination fails, we try to send the
         // error to k8s, and return the error so that the process will exit with error.
         if let Err(e) = self.runner.terminate().await.map_err(error::Error::Runner) {
@@ -193,6 +205,18 @@ where
         Ok(())
     }
 
+    /// Stops the `Runner` after cancellation of the test was requested. The controller has
+    /// already marked the test as `Cancelled`, so neither results nor completion are sent.
+    async fn cancel(&mut self) -> Result<(), C::E, R::E> {
+        info!("Cancellation was requested, terminating the test runner.");
+        if let Err(e) = self.runner.terminate().await.map_err(error::Error::Runner) {
+            error!("unable to terminate test runner: {}", e);
+            self.send_error_best_effort(&e).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
     /// Returns `true` if the error was successfully sent, `false` if the error could not be sent.
     async fn send_error_best_effort(&mut self, e: &Error<C::E, R::E>) {
         if let Err(send_error) = self.client.send_error(e).await {
@@ -262,3 +286,42 @@ where
         }
     }
 }
+
+/// Drive `future` to completion while sending a heartbeat every `HEARTBEAT_INTERVAL`. Failing to
+/// send a heartbeat is logged but does not interrupt `future`. Returns `None` without finishing
+/// `future` if cancellation of the test has been requested.
+async fn with_heartbeat<C, F>(client: &C, future: F) -> Option<F::Output>
+where
+    C: Client,
+    F: Future,
+{
+    let heartbeat = async {
+        loop {
+            if let Err(e) = client.send_heartbeat().await {
+                error!("Unable to send heartbeat: {}", e);
+            }
+            match client.cancel_requested().await {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => error!("Unable to check whether the test was cancelled: {}", e),
+            }
+            sleep(HEARTBEAT_INTERVAL).await;
+        }
+    };
+    tokio::select! {
+        output = future => Some(output),
+        _ = heartbeat => None,
+    }
+}
+
+/// The current time as an RFC 3339 timestamp.
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Record when the run that produced `test_results` started and finished, unless the `Runner`
+/// already did.
+fn set_run_time(test_results: &mut TestResults, run_start: String) {
+    test_results.start_time.get_or_insert(run_start);
+    test_results.end_time.get_or_insert_with(now);
+}
@@ -61,6 +61,11 @@ impl Client for DefaultClient {
         Ok(test_data.spec.agent.keep_running)
     }
 
+    async fn cancel_requested(&self) -> Result<bool, Self::E> {
+        let test_data = self.client.get(&self.name).await.context(K8sSnafu)?;
+        Ok(test_data.spec.agent.cancel_requested)
+    }
+
     async fn retries(&self) -> Result<u32, Self::E> {
         let test_data = self.client.get(&self.name).await.context(K8sSnafu)?;
         Ok(test_data.spec.retries.unwrap_or_default())
@@ -112,6 +117,14 @@ impl Client for DefaultClient {
         Ok(())
     }
 
+    async fn send_heartbeat(&self) -> Result<(), Self::E> {
+        self.client
+            .send_heartbeat(&self.name)
+            .await
+            .context(K8sSnafu)?;
+        Ok(())
+    }
+
     async fn send_test_update(&self, results: TestResults) -> Result<(), Self::E> {
         self.client
             .send_test_update(&self.name, results)
@@ -166,4 +179,23 @@ impl InfoClient for DefaultInfoClient {
             .map_err(|e| InfoClientError::RequestFailed(Some(e.into())))?;
         Ok(())
     }
+
+    async fn send_progress(&self, percent: u8) -> InfoClientResult<()> {
+        self.client
+            .send_progress(&self.data.test_name, percent)
+            .await
+            .map_err(|e| InfoClientError::RequestFailed(Some(e.into())))?;
+        Ok(())
+    }
+
+    async fn rerun_cases(&self) -> InfoClientResult<Vec<String>> {
+        Ok(self
+            .client
+            .get(&self.data.test_name)
+            .await
+            .map_err(|e| InfoClientError::RequestFailed(Some(e.into())))?
+            .spec
+            .agent
+            .rerun_cases)
+    }
 }
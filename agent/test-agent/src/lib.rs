@@ -76,6 +76,10 @@ pub trait Runner<I: InfoClient>: Sized + Send {
             num_passed: 0,
             num_skipped: 0,
             other_info: Some("rerun_failed not defined".to_string()),
+            artifact_uri: None,
+            start_time: None,
+            end_time: None,
+            cases: None,
         })
     }
 
@@ -96,7 +100,7 @@ pub trait Runner<I: InfoClient>: Sized + Send {
 /// k8s cluster. In practice you will use the provided implementation by calling
 /// `DefaultClient::new()`.
 #[async_trait]
-pub trait Client: Sized {
+pub trait Client: Sized + Send + Sync {
     /// The error type returned by this trait's functions.
     type E: Debug + Display + Send + Sync + 'static;
 
@@ -137,6 +141,20 @@ pub trait Client: Sized {
     where
         E: Debug + Display + Send + Sync;
 
+    /// Record that the test agent is still alive. This is called periodically while the [`Runner`]
+    /// is running so that the controller can detect test agents that have died. The default
+    /// implementation does not record anything.
+    async fn send_heartbeat(&self) -> Result<(), Self::E> {
+        Ok(())
+    }
+
+    /// Determine if the user has requested that the test be cancelled. This is checked
+    /// periodically while the [`Runner`] is running, and the [`Runner`] is terminated once it
+    /// returns `true`. The default implementation never cancels the test.
+    async fn cancel_requested(&self) -> Result<bool, Self::E> {
+        Ok(false)
+    }
+
     /// Set the task state as `Completed` indicating that no more retries or testing will occur.
     async fn send_test_completed(&self) -> Result<(), Self::E>;
 }
@@ -152,6 +170,19 @@ pub struct DefaultClient {
 pub trait InfoClient: Sized + Send + Sync {
     async fn new(d: BootstrapData) -> InfoClientResult<Self>;
     async fn send_test_update(&self, results: TestResults) -> InfoClientResult<()>;
+    /// Report how far the [`Runner`] is through its tests, in percent. Values above 100 are
+    /// recorded as 100. Runners can call this periodically while they run, and the [`TestAgent`]
+    /// reports 100 once the results have been sent. The default implementation does not record
+    /// anything.
+    async fn send_progress(&self, _percent: u8) -> InfoClientResult<()> {
+        Ok(())
+    }
+    /// The names of the test cases to run instead of all of them, e.g. after
+    /// `TestClient::rerun_failed_cases_only`. Every case should be run if this is empty. The
+    /// default implementation returns no cases.
+    async fn rerun_cases(&self) -> InfoClientResult<Vec<String>> {
+        Ok(Vec::new())
+    }
 }
 
 pub struct DefaultInfoClient {
@@ -136,6 +136,11 @@ impl Client for MockClient {
         Ok(0)
     }
 
+    async fn send_heartbeat(&self) -> Result<(), Self::E> {
+        println!("MockClient::send_heartbeat");
+        Ok(())
+    }
+
     async fn send_test_completed(&self) -> Result<(), Self::E> {
         println!("MockClient::send_test_completed");
         Ok(())
@@ -155,6 +160,11 @@ impl InfoClient for MyInfoClient {
         println!("MyInfoClient::send_test_update");
         Ok(())
     }
+
+    async fn send_progress(&self, _percent: u8) -> InfoClientResult<()> {
+        println!("MyInfoClient::send_progress");
+        Ok(())
+    }
 }
 
 /// This test runs [`MyRunner`] inside a [`TestAgent`] with k8s and the container environment mocked
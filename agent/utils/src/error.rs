@@ -1,4 +1,5 @@
 use aws_sdk_iam::error::{AttachRolePolicyError, CreateRoleError, GetRoleError};
+use aws_sdk_s3::error::PutObjectError;
 use aws_sdk_ssm::error::{CreateActivationError, DescribeInstanceInformationError};
 use aws_sdk_sts::error::AssumeRoleError;
 use aws_sdk_sts::types::SdkError;
@@ -69,6 +70,19 @@ pub enum Error {
     #[snafu(display("{} was missing from {}", what, from))]
     Missing { what: String, from: String },
 
+    #[snafu(display("Unable to upload 's3://{}/{}': {}", bucket, key, source))]
+    PutObject {
+        bucket: String,
+        key: String,
+        source: SdkError<PutObjectError>,
+    },
+
+    #[snafu(display("Failed to read file at '{}': {}", path, source))]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Secret was missing: {}", source))]
     SecretMissing {
         source: agent_common::secrets::Error,
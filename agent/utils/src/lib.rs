@@ -2,6 +2,7 @@
 
 `agent-utils` is a collection of functions that may be used by agent implementations.
 `aws` contains several functions that can be used to set up an aws environment.
+`s3` contains functions for uploading test artifacts to S3.
 
 !*/
 
@@ -19,6 +20,7 @@ use std::{env, fs};
 pub mod aws;
 pub mod constants;
 mod error;
+pub mod s3;
 pub mod ssm;
 
 /// Decode base64 blob and write to a file at the specified path
@@ -0,0 +1,116 @@
+use crate::constants::DEFAULT_REGION;
+use crate::error::{self, Result};
+use async_trait::async_trait;
+use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_s3::types::ByteStream;
+use aws_sdk_s3::Region;
+use log::info;
+use snafu::ResultExt;
+use std::path::Path;
+
+/// Something that can store an object in an S3 bucket. This is implemented for the S3 client and
+/// allows the upload logic to be tested without AWS.
+#[async_trait]
+pub trait S3Uploader {
+    async fn put_object(&self, bucket: &str, key: &str, body: Vec<u8>) -> Result<()>;
+}
+
+#[async_trait]
+impl S3Uploader for aws_sdk_s3::Client {
+    async fn put_object(&self, bucket: &str, key: &str, body: Vec<u8>) -> Result<()> {
+        self.put_object()
+            .bucket(bucket)
+            .key(key)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .context(error::PutObjectSnafu { bucket, key })?;
+        Ok(())
+    }
+}
+
+/// The `s3://` URI of the object `key` in `bucket`.
+pub fn s3_uri(bucket: &str, key: &str) -> String {
+    format!("s3://{}/{}", bucket, key.trim_start_matches('/'))
+}
+
+/// Upload the file at `path` to `bucket` under `key` and return its `s3://` URI, see
+/// [`upload_file`]. The region is read from `AWS_REGION` (or the default AWS config) and falls
+/// back to `DEFAULT_REGION`. The returned URI is meant to be recorded as the `artifact_uri` of the
+/// `TestResults` sent by the test agent, where `testsys results --output-dir` can download it.
+pub async fn upload_results_to_s3<P>(bucket: &str, key: &str, path: P) -> Result<String>
+where
+    P: AsRef<Path> + Send,
+{
+    let region_provider =
+        RegionProviderChain::default_provider().or_else(Region::new(DEFAULT_REGION));
+    let config = aws_config::from_env().region(region_provider).load().await;
+    let client = aws_sdk_s3::Client::new(&config);
+    upload_file(&client, bucket, key, path).await
+}
+
+/// Upload the file at `path` using `uploader` and return the `s3://` URI of the uploaded object.
+pub async fn upload_file<U, P>(uploader: &U, bucket: &str, key: &str, path: P) -> Result<String>
+where
+    U: S3Uploader + Sync,
+    P: AsRef<Path> + Send,
+{
+    let path = path.as_ref();
+    let body = tokio::fs::read(path).await.context(error::ReadFileSnafu {
+        path: path.display().to_string(),
+    })?;
+    let uri = s3_uri(bucket, key);
+    info!("Uploading '{}' to '{}'", path.display(), uri);
+    uploader.put_object(bucket, key, body).await?;
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockUploader {
+        objects: Mutex<Vec<(String, String, Vec<u8>)>>,
+    }
+
+    #[async_trait]
+    impl S3Uploader for MockUploader {
+        async fn put_object(&self, bucket: &str, key: &str, body: Vec<u8>) -> Result<()> {
+            self.objects
+                .lock()
+                .unwrap()
+                .push((bucket.to_string(), key.to_string(), body));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_file_uri() {
+        let path = std::env::temp_dir().join("agent-utils-upload-file-uri.txt");
+        std::fs::write(&path, b"results").unwrap();
+        let uploader = MockUploader::default();
+        let uri = upload_file(&uploader, "bucket", "runs/1/results.tar.gz", &path)
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(uri, "s3://bucket/runs/1/results.tar.gz");
+        let objects = uploader.objects.lock().unwrap();
+        assert_eq!(
+            *objects,
+            vec![(
+                "bucket".to_string(),
+                "runs/1/results.tar.gz".to_string(),
+                b"results".to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn s3_uri_format() {
+        assert_eq!(s3_uri("bucket", "key"), "s3://bucket/key");
+        assert_eq!(s3_uri("bucket", "/dir/key"), "s3://bucket/dir/key");
+    }
+}
@@ -176,6 +176,10 @@ async fn test_results(
         num_failed: (task_count - running_count) as u64,
         num_skipped: 0,
         other_info: None,
+        artifact_uri: None,
+        start_time: None,
+        end_time: None,
+        cases: None,
     })
 }
 
@@ -188,6 +188,10 @@ where
                         "Instances '{:?}' successfully migrated to {}",
                         &self.config.instance_ids, &self.config.migrate_to_version
                     )),
+                    artifact_uri: None,
+                    start_time: None,
+                    end_time: None,
+                    cases: None,
                 })
             }
             Err(e) => match e {
@@ -208,6 +212,10 @@ where
                             "Instance(s) '{:?}' successfully migrated to {}; Instance(s) '{:?}' failed to migrate",
                             &self.config.instance_ids, target_version, instance_ids
                         )),
+                        artifact_uri: None,
+                        start_time: None,
+                        end_time: None,
+                        cases: None,
                     })
                 }
                 _ => Err(e),
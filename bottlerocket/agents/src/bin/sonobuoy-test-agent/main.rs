@@ -33,13 +33,16 @@ spec:
 !*/
 
 use agent_utils::aws::aws_config;
+use agent_utils::s3::upload_results_to_s3;
 use agent_utils::{base64_decode_write_file, init_agent_logger};
 use async_trait::async_trait;
 use bottlerocket_agents::constants::{E2E_REPO_CONFIG_PATH, TEST_CLUSTER_KUBECONFIG_PATH};
 use bottlerocket_agents::error::Error;
 use bottlerocket_agents::sonobuoy::{delete_sonobuoy, rerun_failed_sonobuoy, run_sonobuoy};
-use bottlerocket_types::agent_config::{SonobuoyConfig, AWS_CREDENTIALS_SECRET_NAME};
-use log::{debug, info};
+use bottlerocket_types::agent_config::{
+    SonobuoyConfig, AWS_CREDENTIALS_SECRET_NAME, SONOBUOY_RESULTS_FILENAME,
+};
+use log::{debug, error, info};
 use std::path::PathBuf;
 use test_agent::{
     BootstrapData, ClientError, DefaultClient, DefaultInfoClient, InfoClient, Spec, TestAgent,
@@ -47,9 +50,13 @@ use test_agent::{
 use testsys_model::{SecretName, TestResults};
 
 struct SonobuoyTestRunner {
+    test_name: String,
     config: SonobuoyConfig,
     aws_secret_name: Option<SecretName>,
     results_dir: PathBuf,
+    /// The number of sonobuoy runs that have finished, used to upload each run's results to a
+    /// different key.
+    runs: u32,
 }
 
 #[async_trait]
@@ -63,9 +70,11 @@ where
     async fn new(spec: Spec<Self::C>, _info_client: &I) -> Result<Self, Self::E> {
         info!("Initializing Sonobuoy test agent...");
         Ok(Self {
+            test_name: spec.name,
             config: spec.configuration,
             aws_secret_name: spec.secrets.get(AWS_CREDENTIALS_SECRET_NAME).cloned(),
             results_dir: spec.results_dir,
+            runs: 0,
         })
     }
 
@@ -94,14 +103,15 @@ where
             None => None,
         };
 
-        run_sonobuoy(
+        let results = run_sonobuoy(
             TEST_CLUSTER_KUBECONFIG_PATH,
             e2e_repo_config,
             &self.config,
             &self.results_dir,
             info_client,
         )
-        .await
+        .await?;
+        Ok(self.upload_results(results).await)
     }
 
     async fn rerun_failed(
@@ -136,14 +146,15 @@ where
             None => None,
         };
 
-        rerun_failed_sonobuoy(
+        let results = rerun_failed_sonobuoy(
             TEST_CLUSTER_KUBECONFIG_PATH,
             e2e_repo_config,
             &self.config,
             &self.results_dir,
             info_client,
         )
-        .await
+        .await?;
+        Ok(self.upload_results(results).await)
     }
 
     async fn terminate(&mut self) -> Result<(), Self::E> {
@@ -151,6 +162,34 @@ where
     }
 }
 
+impl SonobuoyTestRunner {
+    /// Upload the sonobuoy results tarball of the run that produced `results` to the
+    /// `results_bucket`, if there is one, and record its location in `results`. The results are
+    /// still available from the test pod, so failing to upload them does not fail the test.
+    async fn upload_results(&mut self, mut results: TestResults) -> TestResults {
+        self.runs += 1;
+        let bucket = match &self.config.results_bucket {
+            Some(bucket) => bucket,
+            None => return results,
+        };
+        let key = format!(
+            "{}/{}/{}",
+            self.test_name, self.runs, SONOBUOY_RESULTS_FILENAME
+        );
+        match upload_results_to_s3(
+            bucket,
+            &key,
+            self.results_dir.join(SONOBUOY_RESULTS_FILENAME),
+        )
+        .await
+        {
+            Ok(uri) => results.artifact_uri = Some(uri),
+            Err(e) => error!("Unable to upload the sonobuoy results: {}", e),
+        }
+        results
+    }
+}
+
 #[tokio::main]
 async fn main() {
     init_agent_logger(env!("CARGO_CRATE_NAME"), None);
@@ -397,6 +397,10 @@ pub(crate) fn process_incomplete_sonobuoy_test_results(
         num_failed,
         num_skipped: 0,
         other_info: Some("Running".to_string()),
+        artifact_uri: None,
+        start_time: None,
+        end_time: None,
+        cases: None,
     })
 }
 
@@ -495,6 +499,10 @@ pub(crate) fn process_sonobuoy_test_results(
         num_failed,
         num_skipped,
         other_info: Some(progress.join(", ")),
+        artifact_uri: None,
+        start_time: None,
+        end_time: None,
+        cases: None,
     })
 }
 
@@ -74,6 +74,9 @@ pub struct SonobuoyConfig {
     pub kube_conformance_image: Option<String>,
     pub sonobuoy_image: Option<String>,
     pub assume_role: Option<String>,
+    /// If set, the sonobuoy results tarball of each run is uploaded to this S3 bucket and its
+    /// `s3://` URI is recorded as the `artifact_uri` of the run's results.
+    pub results_bucket: Option<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
@@ -0,0 +1,20 @@
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
+
+/// Print a shell completion script for the CLI to stdout.
+#[derive(Debug, Parser)]
+pub(crate) struct Completion {
+    /// The shell to generate the completion script for.
+    #[clap(value_enum)]
+    shell: Shell,
+}
+
+impl Completion {
+    pub(crate) fn run(&self) -> Result<()> {
+        let mut command = crate::Args::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(self.shell, &mut command, name, &mut std::io::stdout());
+        Ok(())
+    }
+}
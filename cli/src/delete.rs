@@ -1,27 +1,117 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use futures::TryStreamExt;
-use testsys_model::test_manager::{DeleteEvent, TestManager};
+use futures::{Stream, TryStreamExt};
+use testsys_model::clients::CrdClient;
+use testsys_model::test_manager::{self, DeleteEvent, TestManager};
 
-/// Delete all objects from a testsys cluster.
+/// Delete objects from a testsys cluster. If no tests are named and no selector is given, all
+/// objects are deleted.
 #[derive(Debug, Parser)]
-pub(crate) struct Delete {}
+pub(crate) struct Delete {
+    /// The names of the tests to delete.
+    #[clap(conflicts_with = "selector")]
+    tests: Vec<String>,
+
+    /// Delete every test and resource matching this label selector, e.g. `testsys.system/run=1`.
+    /// Tests with `keep_running` set are also deleted.
+    #[clap(long)]
+    selector: Option<String>,
+
+    /// Also delete the resources used by the named tests.
+    #[clap(long, requires = "tests")]
+    cascade: bool,
+
+    /// Delete the named tests even if they have `keep_running` set.
+    #[clap(long, requires = "tests")]
+    force: bool,
+}
 
 impl Delete {
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
-        let mut stream = client.delete_all().await.context("Unable to delete all")?;
-
-        while let Some(delete) = stream
-            .try_next()
-            .await
-            .context("A deletion error occurred")?
-        {
-            match delete {
-                DeleteEvent::Starting(crd) => println!("Starting delete for {}", crd.name()),
-                DeleteEvent::Deleted(crd) => println!("Delete finished for {}", crd.name()),
-                DeleteEvent::Failed(crd) => println!("Delete failed for {}", crd.name()),
+        if let Some(selector) = &self.selector {
+            return delete_selected(&client, selector).await;
+        }
+        if self.tests.is_empty() {
+            let stream = client.delete_all().await.context("Unable to delete all")?;
+            return print_delete_events(stream).await;
+        }
+
+        let test_client = client.test_client();
+        let mut tests = Vec::new();
+        let mut skipped = Vec::new();
+        for name in &self.tests {
+            let test = test_client
+                .get(name)
+                .await
+                .context(format!("Unable to get test '{}'", name))?;
+            if test.spec.agent.keep_running && !self.force {
+                println!(
+                    "Skipping '{}' because `keep_running` is set, use `--force` to delete it",
+                    name
+                );
+                skipped.push(name.as_str());
+            } else {
+                tests.push(test);
             }
         }
+
+        if !tests.is_empty() {
+            let stream = client
+                .delete_tests(tests, self.cascade)
+                .await
+                .context("Unable to delete tests")?;
+            print_delete_events(stream).await?;
+        }
+        if !skipped.is_empty() {
+            println!("Skipped: {}", skipped.join(", "));
+        }
         Ok(())
     }
 }
+
+/// Delete the tests and resources matching `selector`.
+async fn delete_selected(client: &TestManager, selector: &str) -> Result<()> {
+    let tests = client
+        .test_client()
+        .delete_collection(selector)
+        .await
+        .context(format!("Unable to delete tests matching '{}'", selector))?;
+    for name in &tests {
+        println!("Deleting test '{}'", name);
+    }
+    let resources = client
+        .resource_client()
+        .delete_collection(selector)
+        .await
+        .context(format!(
+            "Unable to delete resources matching '{}'",
+            selector
+        ))?;
+    for name in &resources {
+        println!("Deleting resource '{}'", name);
+    }
+    println!(
+        "Deleting {} tests and {} resources.",
+        tests.len(),
+        resources.len()
+    );
+    Ok(())
+}
+
+async fn print_delete_events<S>(mut stream: S) -> Result<()>
+where
+    S: Stream<Item = test_manager::Result<DeleteEvent>> + Unpin,
+{
+    while let Some(delete) = stream
+        .try_next()
+        .await
+        .context("A deletion error occurred")?
+    {
+        match delete {
+            DeleteEvent::Starting(crd) => println!("Starting delete for {}", crd.name()),
+            DeleteEvent::Deleted(crd) => println!("Delete finished for {}", crd.name()),
+            DeleteEvent::Failed(crd) => println!("Delete failed for {}", crd.name()),
+        }
+    }
+    Ok(())
+}
@@ -1,12 +1,21 @@
-use anyhow::{Error, Result};
-use clap::Parser;
-use testsys_model::clients::CrdClient;
-use testsys_model::test_manager::TestManager;
-use testsys_model::CrdExt;
+use anyhow::{Context, Error, Result};
+use clap::{Parser, ValueEnum};
+use testsys_model::clients::{AllowNotFound, CrdClient};
+use testsys_model::test_manager::{event_time, TestManager};
+use testsys_model::{CrdExt, ResourceAction, Test};
 
-/// Retrieve the YAML description of a test or resource.
+/// Describe a test in detail, including its resources and related Kubernetes events. To retrieve
+/// the YAML description of a test or resource use `--test` or `--resource`.
 #[derive(Debug, Parser)]
 pub(crate) struct Describe {
+    /// The name of the test to describe.
+    #[clap(conflicts_with_all = &["test", "resource"])]
+    name: Option<String>,
+
+    /// The output format used when describing a test by name.
+    #[clap(long, short = 'o', value_enum, default_value = "text")]
+    output: DescribeOutput,
+
     /// The name of the test to return the description from.
     #[clap(long, conflicts_with = "resource")]
     test: Option<String>,
@@ -16,19 +25,170 @@ pub(crate) struct Describe {
     resource: Option<String>,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DescribeOutput {
+    /// A human readable description including resources and events.
+    Text,
+    /// The raw YAML of the `Test` object.
+    Yaml,
+}
+
 impl Describe {
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
-        match (self.test, self.resource) {
-            (Some(test), None) => {
+        match (self.name, self.test, self.resource) {
+            (Some(name), None, None) => match self.output {
+                DescribeOutput::Text => describe_test(&client, &name).await?,
+                DescribeOutput::Yaml => {
+                    println!("{}", client.test_client().get(name).await?.to_yaml()?)
+                }
+            },
+            (None, Some(test), None) => {
                 let test_yaml = client.test_client().get(test).await?.to_yaml()?;
                 println!("{}", test_yaml);
             }
-            (None, Some(resource)) => {
+            (None, None, Some(resource)) => {
                 let resource_yaml = client.resource_client().get(resource).await?.to_yaml()?;
                 println!("{}", resource_yaml);
             }
-            _ => return Err(Error::msg("Invalid arguments were provided. Exactly one of `--test` and `--resource` must be used.")),
+            _ => return Err(Error::msg("Invalid arguments were provided. Exactly one of a test name, `--test` and `--resource` must be used.")),
         };
         Ok(())
     }
 }
+
+/// Print the spec, status, resources and events of the test `name`.
+async fn describe_test(client: &TestManager, name: &str) -> Result<()> {
+    let test = match client.test_client().get(name).await {
+        Ok(test) => test,
+        Err(e) if e.is_not_found() => {
+            return Err(Error::msg(format!("The test '{}' does not exist", name)))
+        }
+        Err(e) => return Err(e).context(format!("Unable to get test '{}'", name)),
+    };
+    print_test(&test);
+
+    println!("Resources:");
+    if test.spec.resources.is_empty() {
+        println!("  <none>");
+    }
+    let resource_client = client.resource_client();
+    for resource_name in &test.spec.resources {
+        let resource = resource_client
+            .get(resource_name)
+            .await
+            .allow_not_found(|_| ())
+            .context(format!("Unable to get resource '{}'", resource_name))?;
+        match resource {
+            None => println!("  {}: <not found>", resource_name),
+            Some(resource) => {
+                println!(
+                    "  {}: creation {}, destruction {}",
+                    resource_name,
+                    resource.task_state(ResourceAction::Create),
+                    resource.task_state(ResourceAction::Destroy)
+                );
+                if let Some(error) = resource.creation_error() {
+                    println!("    Creation Error: {}", error.error);
+                }
+                if let Some(error) = resource.destruction_error() {
+                    println!("    Destruction Error: {}", error.error);
+                }
+                for (action, label) in [
+                    (ResourceAction::Create, "Creation"),
+                    (ResourceAction::Destroy, "Destruction"),
+                ] {
+                    let steps = resource.steps(action);
+                    if !steps.is_empty() {
+                        println!("    {} Steps:", label);
+                    }
+                    for step in steps {
+                        println!("      {}: {}", step.name, step.state);
+                    }
+                }
+            }
+        }
+    }
+
+    println!("Events:");
+    let events = client
+        .test_events(name)
+        .await
+        .context(format!("Unable to get events for '{}'", name))?;
+    if events.is_empty() {
+        println!("  <none>");
+    }
+    for event in events {
+        let time = event_time(&event)
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_else(|| "<unknown>".to_string());
+        println!(
+            "  {}  {}  {}  {}/{}  {}",
+            time,
+            event.type_.unwrap_or_default(),
+            event.reason.unwrap_or_default(),
+            event.involved_object.kind.unwrap_or_default(),
+            event.involved_object.name.unwrap_or_default(),
+            event.message.unwrap_or_default().trim()
+        );
+    }
+    Ok(())
+}
+
+fn print_test(test: &Test) {
+    let agent_status = test.agent_status();
+    println!(
+        "Name: {}",
+        test.metadata.name.as_deref().unwrap_or_default()
+    );
+    println!("Agent Image: {}", test.spec.agent.image);
+    println!("Keep Running: {}", test.spec.agent.keep_running);
+    if let Some(depends_on) = &test.spec.depends_on {
+        println!("Depends On: {}", depends_on.join(", "));
+    }
+    if let Some(retries) = test.spec.retries {
+        println!("Retries: {}", retries);
+    }
+    if test.spec.priority != 0 {
+        println!("Priority: {}", test.spec.priority);
+    }
+    if let Some(skip_if) = &test.spec.skip_if {
+        println!("Skip If: {}", skip_if);
+    }
+    println!("Task State: {}", agent_status.task_state);
+    if let Some(skip_reason) = &agent_status.skip_reason {
+        println!("Skip Reason: {}", skip_reason);
+    }
+    if let Some(error) = &agent_status.error {
+        println!("Error: {}", error);
+    }
+    if let Some(error) = test.resource_error() {
+        println!("Resource Error: {}", error);
+    }
+    if let Some(last_update) = test
+        .status
+        .as_ref()
+        .and_then(|status| status.last_update.as_ref())
+    {
+        println!("Last Update: {}", last_update);
+    }
+    println!("Results:");
+    let runs = agent_status
+        .results_history
+        .iter()
+        .chain(agent_status.results.iter());
+    let mut has_results = false;
+    for (run, results) in runs.enumerate() {
+        has_results = true;
+        println!(
+            "  Run {}: {} (passed: {}, failed: {}, skipped: {})",
+            run + 1,
+            results.outcome,
+            results.num_passed,
+            results.num_failed,
+            results.num_skipped
+        );
+    }
+    if !has_results {
+        println!("  <none>");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::run::is_provenance_annotation;
+use anyhow::{Context, Error, Result};
+use clap::{value_parser, Parser};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use testsys_model::clients::{AllowNotFound, CrdClient};
+use testsys_model::test_manager::{read_manifest, TestManager};
+use testsys_model::Crd;
+
+/// The number of unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// Compare the `Test`s and `Resource`s in a YAML file with the objects in the cluster. A unified
+/// diff of the name, labels, annotations and spec of each object is printed. The status and the
+/// metadata set by the server are ignored. Exits with 1 if there are any differences.
+#[derive(Debug, Parser)]
+pub(crate) struct Diff {
+    /// Path to the test or resource YAML file.
+    #[clap(value_parser = value_parser!(PathBuf))]
+    path: PathBuf,
+}
+
+impl Diff {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let crds = read_manifest(&self.path).context("Unable to read manifest")?;
+        let mut changed = false;
+        for crd in crds {
+            let (kind, name, local, live) = match &crd {
+                Crd::Test(test) => {
+                    let name = test.metadata.name.clone().unwrap_or_default();
+                    let live = client
+                        .test_client()
+                        .get(&name)
+                        .await
+                        .allow_not_found(|_| ())
+                        .context(format!("Unable to get test '{}'", name))?;
+                    (
+                        "test",
+                        name,
+                        normalize(&test.metadata, &test.spec)?,
+                        live.map(|live| normalize(&live.metadata, &live.spec))
+                            .transpose()?,
+                    )
+                }
+                Crd::Resource(resource) => {
+                    let name = resource.metadata.name.clone().unwrap_or_default();
+                    let live = client
+                        .resource_client()
+                        .get(&name)
+                        .await
+                        .allow_not_found(|_| ())
+                        .context(format!("Unable to get resource '{}'", name))?;
+                    (
+                        "resource",
+                        name,
+                        normalize(&resource.metadata, &resource.spec)?,
+                        live.map(|live| normalize(&live.metadata, &live.spec))
+                            .transpose()?,
+                    )
+                }
+            };
+            let from = match &live {
+                Some(_) => format!("live/{}/{}", kind, name),
+                None => format!("live/{}/{} (not found)", kind, name),
+            };
+            let diff = unified_diff(
+                live.as_deref().unwrap_or_default(),
+                &local,
+                &from,
+                &format!("local/{}/{}", kind, name),
+            );
+            if !diff.is_empty() {
+                changed = true;
+                print!("{}", diff);
+            }
+        }
+        if changed {
+            // Like `diff` and `kubectl diff`, differences are reported with exit code 1, which is
+            // the exit code for any error.
+            return Err(Error::msg(
+                "The manifest differs from the objects in the cluster",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The YAML that is compared for an object: its name, labels, annotations and spec. Both the local
+/// and the live objects have been deserialized into the same type, so defaulted fields match. The
+/// provenance annotations added by `run` are left out because they are never in the manifest.
+fn normalize<S: Serialize>(metadata: &ObjectMeta, spec: &S) -> Result<String> {
+    let mut normalized_metadata = Map::new();
+    normalized_metadata.insert("name".to_string(), metadata.name.clone().into());
+    if let Some(labels) = &metadata.labels {
+        normalized_metadata.insert("labels".to_string(), serde_json::to_value(labels)?);
+    }
+    let annotations: BTreeMap<_, _> = metadata
+        .annotations
+        .iter()
+        .flatten()
+        .filter(|(key, _)| !is_provenance_annotation(key))
+        .collect();
+    if !annotations.is_empty() {
+        normalized_metadata.insert(
+            "annotations".to_string(),
+            serde_json::to_value(annotations)?,
+        );
+    }
+    let mut normalized = Map::new();
+    normalized.insert("metadata".to_string(), Value::Object(normalized_metadata));
+    normalized.insert("spec".to_string(), serde_json::to_value(spec)?);
+    serde_yaml::to_string(&Value::Object(normalized)).context("Unable to serialize object")
+}
+
+/// A unified diff from `old` to `new`, or an empty string if they are the same.
+fn unified_diff(old: &str, new: &str, from: &str, to: &str) -> String {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let lines = difflib::unified_diff(&old, &new, from, to, "", "", CONTEXT_LINES);
+    if lines.is_empty() {
+        return String::new();
+    }
+    // Replace the headers because `difflib` ends them with a tab for the (empty) file dates.
+    let mut diff = format!("--- {}\n+++ {}\n", from, to);
+    for line in lines.into_iter().skip(2) {
+        diff.push_str(&line);
+    }
+    diff
+}
+
+#[test]
+fn test_unified_diff() {
+    assert_eq!(unified_diff("a\nb\n", "a\nb\n", "live", "local"), "");
+    assert_eq!(
+        unified_diff("a\nb\nc\n", "a\nx\nc\n", "live", "local"),
+        "--- live\n+++ local\n@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"
+    );
+    assert_eq!(
+        unified_diff("", "a\n", "live", "local"),
+        "--- live\n+++ local\n@@ -0,0 +1 @@\n+a\n"
+    );
+}
+
+#[test]
+fn test_normalize_ignores_server_metadata() {
+    let spec = serde_json::json!({ "resources": ["cluster"] });
+    let local = ObjectMeta {
+        name: Some("my-test".to_string()),
+        ..ObjectMeta::default()
+    };
+    let live = ObjectMeta {
+        namespace: Some("testsys".to_string()),
+        resource_version: Some("42".to_string()),
+        uid: Some("1234".to_string()),
+        annotations: Some(BTreeMap::from([(
+            testsys_model::constants::ANNOTATION_GIT_SHA.to_string(),
+            "0123abc".to_string(),
+        )])),
+        ..local.clone()
+    };
+    assert_eq!(
+        normalize(&local, &spec).unwrap(),
+        normalize(&live, &spec).unwrap()
+    );
+    assert!(normalize(&local, &spec).unwrap().contains("cluster"));
+}
@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Event;
+use testsys_model::test_manager::{event_time, TestManager};
+
+/// Print the Kubernetes events for testsys `Test`s, `Resource`s and pods, following new events as
+/// they arrive.
+#[derive(Debug, Parser)]
+pub(crate) struct Events {
+    /// Only include events matching this field selector, e.g. `involvedObject.name=my-test`.
+    #[clap(long, short = 's')]
+    selector: Option<String>,
+
+    /// Print the existing events and exit instead of following new events.
+    #[clap(long)]
+    no_follow: bool,
+}
+
+impl Events {
+    /// Returns `true` unless `--no-follow` was given.
+    pub(crate) fn follows(&self) -> bool {
+        !self.no_follow
+    }
+
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        if self.no_follow {
+            let events = client
+                .events(self.selector.as_deref())
+                .await
+                .context("Unable to get events")?;
+            for event in &events {
+                print_event(event);
+            }
+            return Ok(());
+        }
+        let stream = client.watch_events(self.selector.as_deref());
+        futures::pin_mut!(stream);
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(event) => print_event(&event),
+                // The watch is retried, so keep waiting for events.
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_event(event: &Event) {
+    let time = event_time(event)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_else(|| "<unknown>".to_string());
+    println!(
+        "{}  {}  {}/{}  {}",
+        time,
+        event.reason.as_deref().unwrap_or_default(),
+        event.involved_object.kind.as_deref().unwrap_or_default(),
+        event.involved_object.name.as_deref().unwrap_or_default(),
+        event.message.as_deref().unwrap_or_default().trim()
+    );
+}
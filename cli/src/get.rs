@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use terminal_size::{Height, Width};
+use testsys_model::clients::CrdClient;
+use testsys_model::test_manager::{CrdType, SelectionParams, StatusColumn, TestManager};
+
+/// Get a TestSys object by kind and name. Without a name, every object of that kind is listed.
+#[derive(Debug, Parser)]
+pub(crate) struct Get {
+    /// The kind of object to get.
+    #[clap(value_enum)]
+    kind: Kind,
+
+    /// The name of the object to get.
+    #[clap(conflicts_with = "field_selector")]
+    name: Option<String>,
+
+    /// Only list the objects matching this field selector, e.g. `metadata.name=my-test`. Only
+    /// `metadata.name` and `metadata.namespace` are supported by the server for TestSys objects.
+    #[clap(long)]
+    field_selector: Option<String>,
+
+    /// The output format. [default: `yaml` when a name is given, otherwise `table`]
+    #[clap(long, short = 'o', value_enum)]
+    output: Option<GetOutput>,
+}
+
+/// The kinds of objects that can be fetched with `get`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Kind {
+    /// A TestSys `Test`.
+    #[clap(alias = "tests")]
+    Test,
+    /// A TestSys `Resource`.
+    #[clap(alias = "resources")]
+    Resource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GetOutput {
+    /// A human readable table.
+    Table,
+    /// The YAML of the objects.
+    Yaml,
+    /// The JSON of the objects.
+    Json,
+    /// The compact JSON of each object on its own line, e.g. for `jq`.
+    Jsonl,
+}
+
+impl Get {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let output = self.output.unwrap_or(match self.name {
+            Some(_) => GetOutput::Yaml,
+            None => GetOutput::Table,
+        });
+        if output == GetOutput::Table {
+            return print_table(&client, self.kind, self.name, self.field_selector).await;
+        }
+        match (self.kind, &self.name) {
+            (Kind::Test, Some(name)) => print_object(
+                &client
+                    .test_client()
+                    .get(name)
+                    .await
+                    .context(format!("Unable to get test '{}'", name))?,
+                output,
+            ),
+            (Kind::Test, None) => {
+                let test_client = client.test_client();
+                let tests = match &self.field_selector {
+                    Some(selector) => test_client.list_with_field_selector(selector).await,
+                    None => test_client.get_all().await,
+                };
+                print_object(&tests.context("Unable to get tests")?, output)
+            }
+            (Kind::Resource, Some(name)) => print_object(
+                &client
+                    .resource_client()
+                    .get(name)
+                    .await
+                    .context(format!("Unable to get resource '{}'", name))?,
+                output,
+            ),
+            (Kind::Resource, None) => {
+                let resource_client = client.resource_client();
+                let resources = match &self.field_selector {
+                    Some(selector) => resource_client.list_with_field_selector(selector).await,
+                    None => resource_client.get_all().await,
+                };
+                print_object(&resources.context("Unable to get resources")?, output)
+            }
+        }
+    }
+}
+
+/// Print `object` as YAML or JSON. With JSON Lines, each item of a list is printed on its own line.
+fn print_object<T: Serialize>(object: &T, output: GetOutput) -> Result<()> {
+    match output {
+        GetOutput::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(object).context("Unable to serialize JSON")?
+        ),
+        GetOutput::Jsonl => {
+            match serde_json::to_value(object).context("Unable to serialize JSON")? {
+                Value::Array(items) => write_json_lines(std::io::stdout().lock(), items)?,
+                item => write_json_lines(std::io::stdout().lock(), [item])?,
+            }
+        }
+        GetOutput::Yaml | GetOutput::Table => print!(
+            "{}",
+            serde_yaml::to_string(object).context("Unable to serialize YAML")?
+        ),
+    }
+    Ok(())
+}
+
+/// Write each of `items` to `writer` as compact JSON on its own line, flushing after every line so
+/// that a consumer reading a pipe sees each object as soon as it is written.
+pub(crate) fn write_json_lines<W, I>(mut writer: W, items: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    for item in items {
+        serde_json::to_writer(&mut writer, &item).context("Unable to serialize JSON")?;
+        writeln!(writer).context("Unable to write output")?;
+        writer.flush().context("Unable to write output")?;
+    }
+    Ok(())
+}
+
+/// Print the objects of `kind` as a table, only including `name` or the objects matching
+/// `field_selector` if they are given.
+async fn print_table(
+    client: &TestManager,
+    kind: Kind,
+    name: Option<String>,
+    field_selector: Option<String>,
+) -> Result<()> {
+    let selection_params = SelectionParams {
+        crd_type: Some(match kind {
+            Kind::Test => CrdType::Test,
+            Kind::Resource => CrdType::Resource,
+        }),
+        name,
+        field_selector,
+        ..Default::default()
+    };
+    let mut status = client
+        .status(&selection_params)
+        .await
+        .context("Unable to get objects")?;
+    status.add_column(StatusColumn::name());
+    status.add_column(StatusColumn::state());
+    if kind == Kind::Test {
+        status.add_column(StatusColumn::passed());
+        status.add_column(StatusColumn::failed());
+        status.add_column(StatusColumn::skipped());
+    }
+    status.add_column(StatusColumn::last_update());
+    let (terminal_size::Width(width), _) =
+        terminal_size::terminal_size().unwrap_or((Width(120), Height(0)));
+    println!("{:width$}", status, width = width as usize);
+    Ok(())
+}
+
+#[test]
+fn test_write_json_lines() {
+    let items = vec![
+        serde_json::json!({ "name": "test-1", "info": "multi\nline" }),
+        serde_json::json!({ "name": "test-2", "nested": { "values": [1, 2] } }),
+    ];
+    let mut output = Vec::new();
+    write_json_lines(&mut output, &items).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.ends_with("}\n"));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), items.len());
+    for (line, item) in lines.iter().zip(&items) {
+        assert_eq!(&serde_json::from_str::<Value>(line).unwrap(), item);
+    }
+
+    let mut output = Vec::new();
+    write_json_lines(&mut output, Vec::<Value>::new()).unwrap();
+    assert!(output.is_empty());
+}
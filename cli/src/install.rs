@@ -1,28 +1,174 @@
 use anyhow::{Context, Result};
+use clap::builder::NonEmptyStringValueParser;
 use clap::Parser;
-use testsys_model::test_manager::{ImageConfig, TestManager};
+use std::path::PathBuf;
+use std::str::FromStr;
+use testsys_model::system::{ControllerLogFormat, ControllerLogging};
+use testsys_model::test_manager::{
+    install_manifest, read_install_manifest, validate_quantity, ControllerResources, ImageConfig,
+    InstallOptions, TestManager,
+};
 
 /// The install subcommand is responsible for putting all of the necessary components for testsys in
 /// a k8s cluster.
 #[derive(Debug, Parser)]
 pub(crate) struct Install {
-    /// Controller image pull secret
-    #[clap(long = "controller-image-secret", short = 's')]
+    /// Controller image pull secret. The secret is added to the `imagePullSecrets` of the
+    /// controller's pod spec.
+    #[clap(
+        long = "controller-image-secret",
+        short = 's',
+        visible_alias = "controller-image-pull-secret",
+        value_parser = NonEmptyStringValueParser::new()
+    )]
     secret: Option<String>,
 
-    /// Controller image uri
+    /// Controller image uri, e.g. an image mirrored to a private registry.
     // TODO - add default controller_uri after images are published.
-    #[clap(long = "controller-uri")]
-    controller_uri: String,
+    #[clap(
+        long = "controller-uri",
+        visible_alias = "controller-image",
+        required_unless_present = "from_file",
+        value_parser = NonEmptyStringValueParser::new()
+    )]
+    controller_uri: Option<String>,
+
+    /// The CPU request of the controller container, e.g. `100m`. [default: 100m]
+    #[clap(long, value_parser = parse_quantity)]
+    controller_cpu_request: Option<String>,
+
+    /// The memory request of the controller container, e.g. `128Mi`. [default: 128Mi]
+    #[clap(long, value_parser = parse_quantity)]
+    controller_memory_request: Option<String>,
+
+    /// The CPU limit of the controller container, e.g. `500m`. [default: 500m]
+    #[clap(long, value_parser = parse_quantity)]
+    controller_cpu_limit: Option<String>,
+
+    /// The memory limit of the controller container, e.g. `512Mi`. [default: 512Mi]
+    #[clap(long, value_parser = parse_quantity)]
+    controller_memory_limit: Option<String>,
+
+    /// The name of the controller's service account. [default: testsys-controller-service-account]
+    #[clap(long, value_parser = NonEmptyStringValueParser::new())]
+    service_account: Option<String>,
+
+    /// Create roles and role bindings that only grant access to the testsys namespace instead of
+    /// cluster roles and cluster role bindings.
+    #[clap(long)]
+    namespaced_rbac: bool,
+
+    /// Install into the existing testsys namespace instead of creating it, e.g. when the
+    /// namespace is created by a cluster admin. Fails if the namespace does not exist.
+    #[clap(long)]
+    skip_namespace_creation: bool,
+
+    /// The maximum number of test agents the controller runs at once. By default there is no
+    /// limit.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_concurrent_agents: Option<u32>,
+
+    /// The log level of the controller, set as its `RUST_LOG`, e.g. `info` or `controller=debug`.
+    /// By default the controller logs everything from itself.
+    #[clap(long, value_parser = NonEmptyStringValueParser::new())]
+    controller_log_level: Option<String>,
+
+    /// The format of the controller's logs, `text` for human-readable lines or `json` for one
+    /// JSON object per line. [default: text]
+    #[clap(long, value_parser = ControllerLogFormat::from_str)]
+    controller_log_format: Option<ControllerLogFormat>,
+
+    /// The number of controller replicas. With more than one, the replicas elect a leader with a
+    /// `Lease`. [default: 1]
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    controller_replicas: Option<u32>,
+
+    /// Print the manifests that would be applied to the cluster instead of installing them.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Install with server-side apply using the `testsys` field manager, so that re-running
+    /// `install` to upgrade does not conflict with existing objects or with `kubectl`.
+    #[clap(long, conflicts_with = "dry_run")]
+    apply: bool,
+
+    /// Install the manifests in this file, or in the `.yaml` files in this directory, e.g. the
+    /// output of `--dry-run`, instead of generating them. The objects are applied verbatim.
+    #[clap(
+        long,
+        conflicts_with_all = &[
+            "secret",
+            "controller_uri",
+            "controller_cpu_request",
+            "controller_memory_request",
+            "controller_cpu_limit",
+            "controller_memory_limit",
+            "service_account",
+            "namespaced_rbac",
+            "skip_namespace_creation",
+            "max_concurrent_agents",
+            "controller_log_level",
+            "controller_log_format",
+            "controller_replicas",
+            "dry_run",
+        ]
+    )]
+    from_file: Option<PathBuf>,
 }
 
 impl Install {
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
-        let controller_image = match (self.secret, self.controller_uri) {
-            (Some(secret), image) => ImageConfig::WithCreds { secret, image },
-            (None, image) => ImageConfig::Image(image),
+        if let Some(path) = &self.from_file {
+            let objects = read_install_manifest(path).context(format!(
+                "Unable to read the install manifests from '{}'",
+                path.display()
+            ))?;
+            client
+                .install_from_objects(&objects, self.apply)
+                .await
+                .context(
+                    "Unable to install testsys to the cluster. (Some artifacts may be left behind)",
+                )?;
+            println!("testsys components were successfully installed.");
+            return Ok(());
+        }
+        // `clap` requires the controller image unless `--from-file` is used.
+        let image = self.controller_uri.unwrap_or_default();
+        let controller_image = match self.secret {
+            Some(secret) => ImageConfig::WithCreds { secret, image },
+            None => ImageConfig::Image(image),
         };
-        client.install(controller_image).await.context(
+        let defaults = ControllerResources::default();
+        let resources = ControllerResources {
+            cpu_request: self.controller_cpu_request.unwrap_or(defaults.cpu_request),
+            memory_request: self
+                .controller_memory_request
+                .unwrap_or(defaults.memory_request),
+            cpu_limit: self.controller_cpu_limit.unwrap_or(defaults.cpu_limit),
+            memory_limit: self
+                .controller_memory_limit
+                .unwrap_or(defaults.memory_limit),
+        };
+        let options = InstallOptions {
+            resources,
+            service_account: self.service_account,
+            namespaced_rbac: self.namespaced_rbac,
+            skip_namespace_creation: self.skip_namespace_creation,
+            max_concurrent_agents: self.max_concurrent_agents.map(|max| max as usize),
+            logging: ControllerLogging {
+                level: self.controller_log_level,
+                format: self.controller_log_format.unwrap_or_default(),
+            },
+            apply: self.apply,
+            controller_replicas: self.controller_replicas,
+        };
+        if self.dry_run {
+            let manifest = install_manifest(controller_image, &options, client.namespace())
+                .context("Unable to create the testsys install manifests")?;
+            print!("{}", manifest);
+            return Ok(());
+        }
+        client.install(controller_image, &options).await.context(
             "Unable to install testsys to the cluster. (Some artifacts may be left behind)",
         )?;
 
@@ -31,3 +177,8 @@ impl Install {
         Ok(())
     }
 }
+
+fn parse_quantity(quantity: &str) -> std::result::Result<String, String> {
+    validate_quantity(quantity).map_err(|e| e.to_string())?;
+    Ok(quantity.to_string())
+}
@@ -0,0 +1,69 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+
+/// The exit code for a command that was interrupted with Ctrl-C, matching the code a shell reports
+/// for a process stopped by `SIGINT`.
+pub(crate) const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// The error returned by a command that was interrupted with Ctrl-C. The objects the command was
+/// following are left in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Interrupted;
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Wait for Ctrl-C. If the signal handler cannot be installed this never finishes, so the command
+/// runs as if Ctrl-C was never pressed.
+pub(crate) async fn ctrl_c() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        futures::future::pending::<()>().await
+    }
+}
+
+/// Run `future` until it finishes or until `interrupt` does. Returns `None` if it was interrupted,
+/// in which case `future` is dropped without being polled again.
+pub(crate) async fn until_interrupted<F, I>(future: F, interrupt: I) -> Option<F::Output>
+where
+    F: Future,
+    I: Future<Output = ()>,
+{
+    tokio::select! {
+        output = future => Some(output),
+        _ = interrupt => None,
+    }
+}
+
+#[tokio::test]
+async fn test_until_interrupted() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// Records when the future holding it is dropped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    assert_eq!(
+        until_interrupted(async { 1 }, futures::future::pending()).await,
+        Some(1)
+    );
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flag = DropFlag(dropped.clone());
+    let watch = async move {
+        let _flag = flag;
+        futures::future::pending::<()>().await
+    };
+    assert_eq!(until_interrupted(watch, async {}).await, None);
+    assert!(dropped.load(Ordering::SeqCst));
+}
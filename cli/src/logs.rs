@@ -1,7 +1,13 @@
+use crate::run::parse_duration;
 use anyhow::{Context, Error, Result};
 use clap::Parser;
 use futures::StreamExt;
-use testsys_model::test_manager::{ResourceState, TestManager};
+use std::time::{Duration, Instant};
+use testsys_model::clients::StatusCode;
+use testsys_model::test_manager::{self, LogOptions, ResourceState, TestManager};
+
+/// How long to wait between checks for a test agent pod that has not started yet.
+const POD_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 /// Restart an object from a testsys cluster.
 #[derive(Debug, Parser)]
@@ -11,7 +17,7 @@ pub(crate) struct Logs {
     test: Option<String>,
 
     /// The name of the resource we want logs from.
-    #[clap(long, conflicts_with = "test", requires = "state")]
+    #[clap(long, conflicts_with = "test", requires = "resource_state")]
     resource: Option<String>,
 
     /// The resource state we want logs for (Creation, Destruction).
@@ -19,35 +25,91 @@ pub(crate) struct Logs {
     resource_state: Option<ResourceState>,
 
     /// Retrieve logs for the testsys controller
-    #[clap(long = "controller", conflicts_with_all = &["test", "resource", "state"])]
+    #[clap(long = "controller", conflicts_with_all = &["test", "resource", "resource_state"])]
     controller: bool,
 
     /// Include logs from dependencies.
     #[clap(long, short)]
     include_resources: bool,
 
+    /// Interleave the logs of the test agent with the logs of the resource agents of every
+    /// resource the test depends on. Each line is prefixed with its pod and the time it was logged.
+    #[clap(long, requires = "test")]
+    all_agents: bool,
+
     /// Follow logs
     #[clap(long, short)]
     follow: bool,
+
+    /// Only show logs newer than a duration, e.g. `10m` or `1h30m`.
+    #[clap(long, value_parser = parse_duration)]
+    since: Option<Duration>,
+
+    /// Only show this many of the most recent lines. With `--follow`, new lines are streamed after
+    /// these.
+    #[clap(long, value_parser = clap::value_parser!(i64).range(0..))]
+    tail: Option<i64>,
+
+    /// How long to wait for the test agent pod to start before giving up, e.g. `90s` or `5m`. A
+    /// number without a unit is a number of seconds.
+    #[clap(long, default_value = "60", value_parser = parse_duration, conflicts_with_all = &["resource", "controller"])]
+    pod_timeout: Duration,
 }
 
 impl Logs {
+    /// Returns `true` if `--follow` was given.
+    pub(crate) fn follows(&self) -> bool {
+        self.follow
+    }
+
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let options = LogOptions {
+            follow: self.follow,
+            since: self.since,
+            tail: self.tail,
+        };
         match (self.test, self.resource, self.resource_state, self.controller) {
+            (Some(test), None, None, false) if self.all_agents => {
+                let mut logs = client.all_agent_logs(&test, &options).await.context("Unable to get logs.")?;
+                while let Some(line) = logs.next().await {
+                    let line = line.context("Unable to read line")?;
+                    match line.timestamp {
+                        Some(timestamp) => println!("[{}] {} {}", line.pod, timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ"), line.line),
+                        None => println!("[{}] {}", line.pod, line.line),
+                    }
+                }
+            }
             (Some(test), None, None, false ) => {
-                let mut logs = client.test_logs(test, self.follow).await.context("Unable to get logs.")?;
+                let timeout = self.pod_timeout;
+                let start = Instant::now();
+                let mut waiting = false;
+                let mut logs = loop {
+                    match client.test_logs(&test, &options).await {
+                        Err(e) if is_pod_pending(&e) => {
+                            if start.elapsed() >= timeout {
+                                return Err(Error::msg(format!("The agent pod for test '{}' did not start within {:?}. Use `cli status` to check the state of the test.", test, timeout)));
+                            }
+                            if !waiting {
+                                eprintln!("Waiting for the agent pod for test '{}' to start...", test);
+                                waiting = true;
+                            }
+                            tokio::time::sleep(POD_POLL_INTERVAL).await;
+                        }
+                        result => break result.context("Unable to get logs.")?,
+                    }
+                };
                 while let Some(line) = logs.next().await {
                     print!("{}", String::from_utf8_lossy(&line.context("Unable to read line")?));
                 }
             }
             (None, Some(resource), Some(state), false) => {
-                let mut logs = client.resource_logs(resource, state, self.follow).await.context("Unable to get logs.")?;
+                let mut logs = client.resource_logs(resource, state, &options).await.context("Unable to get logs.")?;
                 while let Some(line) = logs.next().await {
                     print!("{}", String::from_utf8_lossy(&line.context("Unable to read line")?));
                 }
             }
             (None, None, None, true) => {
-                let mut logs = client.controller_logs(self.follow).await.context("Unable to get logs.")?;
+                let mut logs = client.controller_logs(&options).await.context("Unable to get logs.")?;
                 while let Some(line) = logs.next().await {
                     print!("{}", String::from_utf8_lossy(&line.context("Unable to read line")?));
                 }
@@ -57,3 +119,40 @@ impl Logs {
         Ok(())
     }
 }
+
+/// Returns `true` if `error` was caused by a test agent pod that has not been created or whose
+/// container is still starting. The API server reports a container that is still starting with
+/// `400 Bad Request` and a message like `container "x" in pod "y" is waiting to start:
+/// ContainerCreating`; any other `400` is a real error.
+fn is_pod_pending(error: &test_manager::Error) -> bool {
+    match error {
+        test_manager::Error::NotFound { .. } => true,
+        test_manager::Error::Kube {
+            source: kube::Error::Api(response),
+            ..
+        } => {
+            response.code == StatusCode::BAD_REQUEST.as_u16()
+                && response.message.contains("is waiting to start")
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn test_is_pod_pending() {
+    let bad_request = |message: &str| test_manager::Error::Kube {
+        action: "get logs".to_string(),
+        source: kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: message.to_string(),
+            reason: "BadRequest".to_string(),
+            code: 400,
+        }),
+    };
+    assert!(is_pod_pending(&bad_request(
+        "container \"my-test\" in pod \"my-test-abcde\" is waiting to start: ContainerCreating"
+    )));
+    assert!(!is_pod_pending(&bad_request(
+        "a container name must be specified for pod my-test-abcde"
+    )));
+}
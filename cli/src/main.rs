@@ -5,23 +5,35 @@ This is the command line interface for setting up a TestSys Cluster and running
 !*/
 
 mod add_secret;
+mod completion;
 mod delete;
 mod describe;
+mod diff;
+mod events;
+mod get;
 mod install;
+mod interrupt;
 mod logs;
 mod restart;
+mod restart_controller;
 mod restart_test;
 mod results;
+mod retry;
 mod run;
 mod run_file;
+mod run_template;
 mod status;
+mod top;
 mod uninstall;
+mod watch;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use env_logger::Builder;
+use interrupt::{Interrupted, INTERRUPTED_EXIT_CODE};
 use log::LevelFilter;
 use std::path::PathBuf;
+use std::time::Duration;
 use testsys_model::test_manager::TestManager;
 
 /// The command line interface for setting up a Bottlerocket TestSys cluster and running tests.
@@ -35,6 +47,18 @@ struct Args {
     /// Path to the kubeconfig file. Also can be passed with the KUBECONFIG environment variable.
     #[clap(long = "kubeconfig")]
     kubeconfig: Option<PathBuf>,
+    /// The kubeconfig context to use. Defaults to the kubeconfig's current context.
+    #[clap(long = "context", visible_alias = "kubeconfig-context")]
+    context: Option<String>,
+    /// The namespace that testsys is installed in. Defaults to the `TESTSYS_NAMESPACE` environment
+    /// variable if it is set, otherwise `testsys`.
+    #[clap(long = "namespace")]
+    namespace: Option<String>,
+    /// How long to wait for each Kubernetes API call before giving up, e.g. `30s` or `2m`.
+    /// Commands that follow changes as they happen, e.g. `watch`, `events`, `logs --follow` and
+    /// `run --wait`, are not limited.
+    #[clap(long = "request-timeout", default_value = "30s", value_parser = run::parse_duration)]
+    request_timeout: Duration,
     #[clap(subcommand)]
     command: Command,
 }
@@ -47,6 +71,10 @@ enum Command {
     Uninstall(uninstall::Uninstall),
     /// Restart a test.
     Restart(restart::Restart),
+    /// Restart the testsys controller.
+    RestartController(restart_controller::RestartController),
+    /// Retry failed tests in place.
+    Retry(retry::Retry),
     /// Run a testsys test.
     Run(run::Run),
     /// Get logs from testsys objects.
@@ -61,6 +89,33 @@ enum Command {
     Delete(delete::Delete),
     /// Get the YAML representation of testsys objects.
     Describe(describe::Describe),
+    /// Compare the objects in a YAML file with the objects in the cluster.
+    Diff(diff::Diff),
+    /// Print the Kubernetes events for testsys objects as they happen.
+    Events(events::Events),
+    /// Get a testsys object by kind and name, or list every object of a kind.
+    Get(get::Get),
+    /// Show the CPU and memory used by the agent pod of each running test.
+    Top(top::Top),
+    /// Follow the state of a test until it finishes.
+    Watch(watch::Watch),
+    /// Generate a shell completion script.
+    Completion(completion::Completion),
+}
+
+impl Command {
+    /// Returns `true` if the command keeps following changes until they stop, so its API calls
+    /// may go unanswered for a long time.
+    fn follows(&self) -> bool {
+        match self {
+            Command::Watch(_) => true,
+            Command::Events(events) => events.follows(),
+            Command::Logs(logs) => logs.follows(),
+            Command::Run(run) => run.follows(),
+            Command::Status(status) => status.follows(),
+            _ => false,
+        }
+    }
 }
 
 #[tokio::main]
@@ -68,27 +123,43 @@ async fn main() {
     let args = Args::parse();
     init_logger(args.log_level);
     if let Err(e) = run(args).await {
+        if e.downcast_ref::<Interrupted>().is_some() {
+            eprintln!("{}", e);
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
         eprintln!("{:?}", e);
         std::process::exit(1);
     }
 }
 
 async fn run(args: Args) -> Result<()> {
-    let client = match args.kubeconfig {
-        Some(path) => TestManager::new_from_kubeconfig_path(&path)
-            .await
-            .context(format!(
-                "Unable to create testsys client from path '{:?}'",
-                path
-            ))?,
-        None => TestManager::new()
-            .await
-            .context("Unable to create default testsys client")?,
+    // Completion scripts can be generated without a cluster.
+    if let Command::Completion(completion) = &args.command {
+        return completion.run();
+    }
+    // A watch that sees no changes for longer than the timeout would fail.
+    let request_timeout = (!args.command.follows()).then_some(args.request_timeout);
+    let client = TestManager::new_with_options(
+        args.kubeconfig.as_deref(),
+        args.context.as_deref(),
+        request_timeout,
+    )
+    .await
+    .context(match (&args.kubeconfig, &args.context) {
+        (_, Some(context)) => format!("Unable to create testsys client for context '{}'", context),
+        (Some(path), None) => format!("Unable to create testsys client from path '{:?}'", path),
+        (None, None) => "Unable to create default testsys client".to_string(),
+    })?;
+    let client = match args.namespace {
+        Some(namespace) => client.with_namespace(namespace),
+        None => client,
     };
     match args.command {
         Command::Install(install) => install.run(client).await,
         Command::Uninstall(uninstall) => uninstall.run(client).await,
         Command::Restart(restart) => restart.run(client).await,
+        Command::RestartController(restart_controller) => restart_controller.run(client).await,
+        Command::Retry(retry) => retry.run(client).await,
         Command::Run(run) => run.run(client).await,
         Command::Logs(logs) => logs.run(client).await,
         Command::AddSecret(add_secret) => add_secret.run(client).await,
@@ -96,6 +167,13 @@ async fn run(args: Args) -> Result<()> {
         Command::Results(results) => results.run(client).await,
         Command::Delete(delete) => delete.run(client).await,
         Command::Describe(describe) => describe.run(client).await,
+        Command::Diff(diff) => diff.run(client).await,
+        Command::Events(events) => events.run(client).await,
+        Command::Get(get) => get.run(client).await,
+        Command::Top(top) => top.run(client).await,
+        Command::Watch(watch) => watch.run(client).await,
+        // Handled before the client is created.
+        Command::Completion(_) => Ok(()),
     }
 }
 
@@ -116,3 +194,9 @@ fn init_logger(level: LevelFilter) {
         }
     }
 }
+
+#[test]
+fn verify_args() {
+    use clap::CommandFactory;
+    Args::command().debug_assert();
+}
@@ -0,0 +1,42 @@
+use crate::run::parse_duration;
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use std::time::Duration;
+use testsys_model::test_manager::TestManager;
+
+/// Restart the testsys controller, e.g. after changing its configuration, like
+/// `kubectl rollout restart` does for the controller deployment.
+#[derive(Debug, Parser)]
+pub(crate) struct RestartController {
+    /// Wait for the new controller pod to be ready.
+    #[clap(long)]
+    wait: bool,
+
+    /// How long to wait for the new controller pod to be ready, e.g. `30s` or `5m`.
+    #[clap(long, requires = "wait", default_value = "5m", value_parser = parse_duration)]
+    timeout: Duration,
+}
+
+impl RestartController {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        client
+            .restart_controller()
+            .await
+            .context("Unable to restart the controller")?;
+        println!("The controller is restarting.");
+        if !self.wait {
+            return Ok(());
+        }
+        tokio::time::timeout(self.timeout, client.wait_for_controller_rollout())
+            .await
+            .map_err(|_| {
+                Error::msg(format!(
+                    "The controller was not ready within {:?}",
+                    self.timeout
+                ))
+            })?
+            .context("Unable to wait for the controller to restart")?;
+        println!("The controller was restarted.");
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Error, Result};
 use clap::{value_parser, Parser};
-use std::path::PathBuf;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use testsys_model::test_manager::TestManager;
 
 /// Retrieve the results of a test.
@@ -12,13 +14,119 @@ pub(crate) struct Results {
     /// The place the test results should be written (results.tar.gz)
     #[clap(long, value_parser = value_parser!(PathBuf), default_value = "results.tar.gz")]
     destination: PathBuf,
+    /// Download the artifacts that the test agent recorded in the test's results to this
+    /// directory instead of copying the results from the test pod. `s3://` artifacts are
+    /// downloaded using the AWS credentials and region from the environment.
+    #[clap(long, value_parser = value_parser!(PathBuf), conflicts_with = "destination")]
+    output_dir: Option<PathBuf>,
 }
 
 impl Results {
     pub(crate) async fn run(&self, client: TestManager) -> Result<()> {
-        client
-            .write_test_results(&self.test_name, &self.destination)
+        match &self.output_dir {
+            Some(output_dir) => self.download_artifacts(client, output_dir).await,
+            None => client
+                .write_test_results(&self.test_name, &self.destination)
+                .await
+                .context("Unable to write results"),
+        }
+    }
+
+    async fn download_artifacts(&self, client: TestManager, output_dir: &Path) -> Result<()> {
+        let artifact_uris: Vec<String> = client
+            .test_client()
+            .get_results_history(&self.test_name)
             .await
-            .context("Unable to write results")
+            .context(format!("Unable to get results for '{}'", self.test_name))?
+            .into_iter()
+            .filter_map(|results| results.artifact_uri)
+            .collect();
+        if artifact_uris.is_empty() {
+            println!(
+                "Test '{}' does not have any artifact locations in its results.",
+                self.test_name
+            );
+            return Ok(());
+        }
+
+        tokio::fs::create_dir_all(output_dir)
+            .await
+            .context(format!(
+                "Unable to create directory '{}'",
+                output_dir.display()
+            ))?;
+        let mut file_names = HashSet::new();
+        for (index, uri) in artifact_uris.iter().enumerate() {
+            let url = Url::parse(uri).context(format!("Invalid artifact location '{}'", uri))?;
+            let mut file_name = url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .filter(|segment| !segment.is_empty())
+                .unwrap_or("artifact")
+                .to_string();
+            // Multiple runs of a test may have uploaded artifacts with the same name.
+            if !file_names.insert(file_name.clone()) {
+                file_name = format!("{}-{}", index, file_name);
+            }
+            let destination = output_dir.join(file_name);
+            download(&url, &destination).await?;
+            println!("Downloaded '{}' to '{}'", uri, destination.display());
+        }
+        Ok(())
     }
 }
+
+/// Write the artifact located at `url` to `destination`.
+async fn download(url: &Url, destination: &Path) -> Result<()> {
+    let data = match url.scheme() {
+        "http" | "https" => reqwest::get(url.clone())
+            .await
+            .and_then(|response| response.error_for_status())
+            .context(format!("Unable to download '{}'", url))?
+            .bytes()
+            .await
+            .context(format!("Unable to read '{}'", url))?
+            .to_vec(),
+        "s3" => download_s3(url).await?,
+        "file" => {
+            let path = url
+                .to_file_path()
+                .map_err(|_| Error::msg(format!("Invalid file location '{}'", url)))?;
+            tokio::fs::read(&path)
+                .await
+                .context(format!("Unable to read '{}'", path.display()))?
+        }
+        scheme => {
+            return Err(Error::msg(format!(
+                "Unable to download '{}', the '{}' scheme is not supported",
+                url, scheme
+            )))
+        }
+    };
+    tokio::fs::write(destination, data)
+        .await
+        .context(format!("Unable to write '{}'", destination.display()))
+}
+
+/// Read the object at the `s3://` `url` using the credentials and region from the environment.
+async fn download_s3(url: &Url) -> Result<Vec<u8>> {
+    let bucket = url
+        .host_str()
+        .ok_or_else(|| Error::msg(format!("Invalid S3 location '{}'", url)))?;
+    let key = url.path().trim_start_matches('/');
+    let config = aws_config::load_from_env().await;
+    let data = aws_sdk_s3::Client::new(&config)
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+        .context(format!("Unable to download '{}'", url))?
+        .body
+        .collect()
+        .await
+        .context(format!("Unable to read '{}'", url))?
+        .into_bytes()
+        .to_vec();
+    Ok(data)
+}
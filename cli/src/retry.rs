@@ -0,0 +1,85 @@
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use testsys_model::clients::CrdClient;
+use testsys_model::test_manager::TestManager;
+use testsys_model::{TaskState, Test};
+
+/// Retry tests in place. Either name the tests to retry or use `--all-failed` to retry every test
+/// whose agent is in the `error` state.
+#[derive(Debug, Parser)]
+pub(crate) struct Retry {
+    /// The names of the tests to retry.
+    #[clap(required_unless_present = "all_failed", conflicts_with = "all_failed")]
+    tests: Vec<String>,
+
+    /// Retry every test whose agent is in the `error` state.
+    #[clap(long)]
+    all_failed: bool,
+
+    /// Only retry failed tests matching this label selector, e.g. `suite=conformance`.
+    #[clap(long, requires = "all_failed")]
+    selector: Option<String>,
+
+    /// Print the tests that would be retried without retrying them.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl Retry {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let test_client = client.test_client();
+        let names = if self.all_failed {
+            let tests = match &self.selector {
+                Some(selector) => test_client.list_with_labels(selector).await,
+                None => test_client.get_all().await,
+            }
+            .context("Unable to list tests")?;
+            failed_test_names(&tests)
+        } else {
+            self.tests
+        };
+
+        if names.is_empty() {
+            println!("There are no tests to retry.");
+            return Ok(());
+        }
+        if self.dry_run {
+            for name in &names {
+                println!("Would retry '{}'", name);
+            }
+            return Ok(());
+        }
+
+        let mut failures = Vec::new();
+        for name in &names {
+            match test_client.retry(name).await {
+                Ok(_) => println!("Retrying '{}'", name),
+                Err(e) => {
+                    eprintln!("Unable to retry '{}': {}", name, e);
+                    failures.push(name.as_str());
+                }
+            }
+        }
+        println!(
+            "Retried {} of {} tests.",
+            names.len() - failures.len(),
+            names.len()
+        );
+        if !failures.is_empty() {
+            return Err(Error::msg(format!(
+                "Unable to retry the following tests: {}",
+                failures.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The names of the tests whose agent is in the `error` state.
+fn failed_test_names(tests: &[Test]) -> Vec<String> {
+    tests
+        .iter()
+        .filter(|test| test.agent_status().task_state == TaskState::Error)
+        .filter_map(|test| test.metadata.name.clone())
+        .collect()
+}
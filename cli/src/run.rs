@@ -1,25 +1,416 @@
-use crate::run_file;
-use anyhow::Result;
-use clap::Parser;
+use crate::interrupt::{ctrl_c, Interrupted};
+use crate::watch::{print_left_in_place, report_test, watch_test};
+use crate::{run_file, run_template};
+use anyhow::{Context, Error, Result};
+use clap::{value_parser, Parser, ValueEnum};
+use futures::stream::{self, FuturesUnordered};
+use futures::{Future, Stream, StreamExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use testsys_model::clients::CrdClient;
+use testsys_model::constants::{ANNOTATION_CI_JOB_URL, ANNOTATION_GIT_SHA};
 use testsys_model::test_manager::TestManager;
+use testsys_model::Test;
 
 /// Run testsys tests.
 #[derive(Debug, Parser)]
 pub(crate) struct Run {
     #[clap(subcommand)]
     command: Command,
+
+    /// Wait for the created tests to finish. Exits with an error if any test errored or had
+    /// failures.
+    #[clap(long, global = true)]
+    wait: bool,
+
+    /// Update objects that already exist in the cluster instead of failing to create them, so
+    /// that a manifest can be applied again after it has been changed.
+    #[clap(long, global = true)]
+    apply: bool,
+
+    /// Have the API server validate the objects, e.g. against the CRD schemas and with admission
+    /// webhooks, without creating them. Only `server` is supported.
+    #[clap(long, global = true, value_enum, conflicts_with_all = &["apply", "wait"])]
+    dry_run: Option<DryRun>,
+
+    /// How long to wait for the tests to finish, e.g. `30m` or `1h30m`. Requires `--wait`.
+    #[clap(long, global = true, requires = "wait", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// The most tests to run at once. Each test is only created when a slot is free, i.e. when
+    /// one of the tests that are running finishes. Requires `--wait`.
+    #[clap(long, global = true, requires = "wait", value_parser = value_parser!(u64).range(1..))]
+    parallelism: Option<u64>,
+}
+
+/// Where a dry run is evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DryRun {
+    /// Send the objects to the API server without persisting them.
+    Server,
 }
 
 #[derive(Debug, Parser)]
 enum Command {
     /// Run a test from a YAML file.
     File(run_file::RunFile),
+    /// Run a test from a YAML template, substituting `${param}` placeholders with `--set` values.
+    Template(run_template::RunTemplate),
 }
 
 impl Run {
+    /// Returns `true` if `--wait` was given.
+    pub(crate) fn follows(&self) -> bool {
+        self.wait
+    }
+
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
-        match self.command {
-            Command::File(run_file) => run_file.run(client).await,
+        let dry_run = self.dry_run == Some(DryRun::Server);
+        if let Some(parallelism) = self.parallelism {
+            let tests = match self.command {
+                Command::File(run_file) => run_file.create_resources(&client, self.apply).await?,
+                Command::Template(run_template) => run_template.create_resources(&client).await?,
+            };
+            let limit = usize::try_from(parallelism).unwrap_or(usize::MAX);
+            return run_with_parallelism(&client, tests, self.apply, limit, self.timeout).await;
+        }
+        let tests = match self.command {
+            Command::File(run_file) => run_file.run(&client, self.apply, dry_run).await?,
+            Command::Template(run_template) => {
+                run_template.run(&client, self.apply, dry_run).await?
+            }
+        };
+        if self.wait {
+            wait_for_tests(&client, &tests, self.timeout).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The provenance annotations stamped on the tests created by `run`, each with the environment
+/// variables its value is read from in order of preference. `TESTSYS_*` variables can be set
+/// explicitly, the others are set by common CI systems.
+const PROVENANCE: &[(&str, &[&str])] = &[
+    (
+        ANNOTATION_CI_JOB_URL,
+        &["TESTSYS_CI_JOB_URL", "CI_JOB_URL", "BUILD_URL"],
+    ),
+    (
+        ANNOTATION_GIT_SHA,
+        &[
+            "TESTSYS_GIT_SHA",
+            "GITHUB_SHA",
+            "CI_COMMIT_SHA",
+            "GIT_COMMIT",
+        ],
+    ),
+];
+
+/// The provenance annotations whose environment variables are set and not empty, reading each
+/// variable with `var`.
+fn provenance_annotations<F>(var: F) -> BTreeMap<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    PROVENANCE
+        .iter()
+        .filter_map(|(annotation, keys)| {
+            keys.iter()
+                .filter_map(|key| var(key))
+                .find(|value| !value.is_empty())
+                .map(|value| (annotation.to_string(), value))
+        })
+        .collect()
+}
+
+/// Returns `true` if `key` is one of the provenance annotations, see `PROVENANCE`.
+pub(crate) fn is_provenance_annotation(key: &str) -> bool {
+    PROVENANCE.iter().any(|(annotation, _)| *annotation == key)
+}
+
+/// Annotate `test` with where it was created from, see `PROVENANCE`, so that the annotations are
+/// part of the request that creates it. Nothing is added if none of the environment variables are
+/// set.
+pub(crate) fn add_provenance(test: &mut Test) {
+    let annotations = provenance_annotations(|key| std::env::var(key).ok());
+    if annotations.is_empty() {
+        return;
+    }
+    test.metadata
+        .annotations
+        .get_or_insert_with(Default::default)
+        .extend(annotations);
+}
+
+/// Watch `tests` until they have all finished, then report their results. Returns an error if any
+/// test did not pass, if `timeout` expires, or if the wait is interrupted with Ctrl-C, in which case
+/// the state of the unfinished tests is printed. The tests are never deleted.
+async fn wait_for_tests(
+    client: &TestManager,
+    tests: &[String],
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let mut unfinished: BTreeSet<&str> = tests.iter().map(String::as_str).collect();
+    let mut watches: FuturesUnordered<_> = tests
+        .iter()
+        .map(|name| async move { (name.as_str(), watch_test(client, name).await) })
+        .collect();
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => futures::future::pending().await,
+        }
+    };
+    futures::pin_mut!(deadline);
+    let ctrl_c = ctrl_c();
+    futures::pin_mut!(ctrl_c);
+
+    let mut failures = Vec::new();
+    while !watches.is_empty() {
+        tokio::select! {
+            Some((name, result)) = watches.next() => {
+                unfinished.remove(name);
+                if let Err(e) = result {
+                    eprintln!("{:?}", e);
+                    failures.push(name);
+                }
+            }
+            _ = &mut deadline => {
+                return Err(Error::msg(format!(
+                    "The following tests did not finish in time: {}",
+                    unfinished.into_iter().collect::<Vec<_>>().join(", ")
+                )));
+            }
+            _ = &mut ctrl_c => {
+                // Stop watching before printing the state of the tests that are still running.
+                drop(watches);
+                print_left_in_place(client, unfinished).await;
+                return Err(Interrupted.into());
+            }
+        }
+    }
+
+    for name in tests {
+        if failures.contains(&name.as_str()) {
+            continue;
+        }
+        if let Err(e) = report_test(client, name).await {
+            eprintln!("{}", e);
+            failures.push(name.as_str());
         }
     }
+    if !failures.is_empty() {
+        return Err(Error::msg(format!(
+            "The following tests did not pass: {}",
+            failures.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Call `start` for each of `items` and run the futures it returns, with no more than `limit` of
+/// them running at once. The next item is only started when a running future finishes. The outputs
+/// are yielded in the order the futures finish.
+fn bounded<I, F, Fut>(items: I, limit: usize, start: F) -> impl Stream<Item = Fut::Output>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future,
+{
+    stream::iter(items).map(start).buffer_unordered(limit)
+}
+
+/// Create and watch `tests`, with no more than `parallelism` of them created and unfinished at
+/// once, then report the results of all of them. Returns an error if any test could not be created
+/// or did not pass, if `timeout` expires, or if the run is interrupted with Ctrl-C, in which case
+/// the state of the running tests is printed. Tests that were not started are never created.
+async fn run_with_parallelism(
+    client: &TestManager,
+    tests: Vec<Test>,
+    apply: bool,
+    parallelism: usize,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let total = tests.len();
+    let mut unfinished: BTreeSet<String> = tests
+        .iter()
+        .map(|test| test.metadata.name.clone().unwrap_or_default())
+        .collect();
+    let running = Mutex::new(BTreeSet::new());
+    let running = &running;
+    let mut runs = Box::pin(bounded(tests, parallelism, |mut test| async move {
+        add_provenance(&mut test);
+        let name = test.metadata.name.clone().unwrap_or_default();
+        let result = async {
+            let test_client = client.test_client();
+            if apply {
+                test_client
+                    .create_or_update(test)
+                    .await
+                    .context(format!("Unable to apply test '{}'", name))?;
+            } else {
+                test_client
+                    .create(test)
+                    .await
+                    .context(format!("Unable to create test '{}'", name))?;
+            }
+            println!("Successfully added '{}'.", name);
+            running.lock().unwrap().insert(name.clone());
+            watch_test(client, &name).await?;
+            report_test(client, &name).await
+        }
+        .await;
+        running.lock().unwrap().remove(&name);
+        (name, result)
+    }));
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => futures::future::pending().await,
+        }
+    };
+    futures::pin_mut!(deadline);
+    let ctrl_c = ctrl_c();
+    futures::pin_mut!(ctrl_c);
+
+    let mut failures = Vec::new();
+    while !unfinished.is_empty() {
+        tokio::select! {
+            Some((name, result)) = runs.next() => {
+                if let Err(e) = result {
+                    eprintln!("{:?}", e);
+                    failures.push(name.clone());
+                }
+                unfinished.remove(&name);
+            }
+            _ = &mut deadline => {
+                return Err(Error::msg(format!(
+                    "The following tests did not finish in time: {}",
+                    unfinished.into_iter().collect::<Vec<_>>().join(", ")
+                )));
+            }
+            _ = &mut ctrl_c => {
+                let running = std::mem::take(&mut *running.lock().unwrap());
+                // Stop watching before printing the state of the tests that are still running.
+                drop(runs);
+                print_left_in_place(client, running.iter().map(String::as_str)).await;
+                return Err(Interrupted.into());
+            }
+        }
+    }
+
+    println!("{} of {} tests passed.", total - failures.len(), total);
+    if !failures.is_empty() {
+        failures.sort();
+        return Err(Error::msg(format!(
+            "The following tests did not pass: {}",
+            failures.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Parse a duration made of whole numbers followed by a unit of `d`, `h`, `m` or `s`, e.g. `1h30m`.
+/// A number without a unit is a number of seconds. Returns an error if the duration does not fit in
+/// a `u64` number of seconds.
+pub(crate) fn parse_duration(input: &str) -> std::result::Result<Duration, String> {
+    let invalid = || format!("'{}' is not a valid duration, e.g. '30m' or '1h30m'", input);
+    let too_long = || format!("'{}' is too long a duration", input);
+    if input.is_empty() {
+        return Err(invalid());
+    }
+    let mut secs = 0u64;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        secs = value
+            .checked_mul(unit)
+            .and_then(|value| secs.checked_add(value))
+            .ok_or_else(too_long)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        secs = secs.checked_add(value).ok_or_else(too_long)?;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+    assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+    assert_eq!(
+        parse_duration("1d2h3m4s").unwrap(),
+        Duration::from_secs(93784)
+    );
+    assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+    assert!(parse_duration("").is_err());
+    assert!(parse_duration("m").is_err());
+    assert!(parse_duration("30x").is_err());
+    assert!(parse_duration("18446744073709551615d").is_err());
+    assert!(parse_duration("18446744073709551615s1s").is_err());
+}
+
+#[test]
+fn test_provenance_annotations() {
+    let env = BTreeMap::from([
+        ("BUILD_URL", "https://ci.example.com/job/1"),
+        ("CI_JOB_URL", ""),
+        ("GITHUB_SHA", "0123abc"),
+        ("TESTSYS_GIT_SHA", "4567def"),
+    ]);
+    let annotations = provenance_annotations(|key| env.get(key).map(|value| value.to_string()));
+    assert_eq!(
+        annotations,
+        BTreeMap::from([
+            (
+                ANNOTATION_CI_JOB_URL.to_string(),
+                "https://ci.example.com/job/1".to_string()
+            ),
+            (ANNOTATION_GIT_SHA.to_string(), "4567def".to_string()),
+        ])
+    );
+    assert!(provenance_annotations(|_| None).is_empty());
+    assert!(is_provenance_annotation(ANNOTATION_GIT_SHA));
+    assert!(!is_provenance_annotation("example.com/git-sha"));
+}
+
+#[tokio::test]
+async fn test_bounded() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let in_flight = AtomicUsize::new(0);
+    let most_in_flight = AtomicUsize::new(0);
+    let mut finished: Vec<u64> = bounded(0..10u64, 3, |i| {
+        let in_flight = &in_flight;
+        let most_in_flight = &most_in_flight;
+        async move {
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            most_in_flight.fetch_max(now, Ordering::SeqCst);
+            // Finish out of order so that slots are freed by different items.
+            tokio::time::sleep(Duration::from_millis((10 - i) % 4 * 5)).await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            i
+        }
+    })
+    .collect()
+    .await;
+
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 3);
+    assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+    finished.sort();
+    assert_eq!(finished, (0..10).collect::<Vec<_>>());
 }
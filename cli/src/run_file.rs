@@ -1,30 +1,205 @@
-use anyhow::{Context, Result};
+use crate::run::add_provenance;
+use anyhow::{Context, Error, Result};
 use clap::{value_parser, Parser};
+use std::collections::HashSet;
+use std::io::Read;
 use std::path::PathBuf;
-use testsys_model::test_manager::{read_manifest, TestManager};
+use testsys_model::clients::{BulkResult, CrdClient};
+use testsys_model::test_manager::{convert_manifest, read_manifest, TestManager};
+use testsys_model::{Crd, Resource, Test};
 
 /// Run a test stored in a YAML file at `path`.
 #[derive(Debug, Parser)]
 pub(crate) struct RunFile {
-    /// Path to test crd YAML file.
+    /// Path to test crd YAML file. Use `-` to read the manifest from stdin.
     #[clap(value_parser = value_parser!(PathBuf))]
     path: PathBuf,
 }
 
 impl RunFile {
-    pub(crate) async fn run(&self, client: TestManager) -> Result<()> {
-        // Create the resource objects from its path.
-        let crds = read_manifest(&self.path).context("Unable to read manifest")?;
-        for crd in crds {
-            let name = crd.name();
-            client
-                .create_object(crd)
+    /// Create the objects in the manifest and return the names of the tests that were created. If
+    /// `apply` is set, objects that already exist are updated instead. If `dry_run` is set, the
+    /// objects are only validated by the API server and no tests are created.
+    pub(crate) async fn run(
+        &self,
+        client: &TestManager,
+        apply: bool,
+        dry_run: bool,
+    ) -> Result<Vec<String>> {
+        let crds = self.read()?;
+        check_resources_exist(client, &crds).await?;
+        if dry_run {
+            server_dry_run(client, crds).await?;
+            return Ok(Vec::new());
+        }
+        let (resources, mut tests) = split_crds(crds);
+        tests.iter_mut().for_each(add_provenance);
+
+        // Create the resources first so that they are available to the tests. None of the tests
+        // are created if any of the resources could not be.
+        add_resources(client, resources, apply).await?;
+        let total = tests.len();
+        let test_client = client.test_client();
+        let results = if apply {
+            test_client
+                .create_or_update_all(tests)
+                .await
+                .context("Unable to apply tests")?
+        } else {
+            test_client
+                .create_all(tests)
+                .await
+                .context("Unable to create tests")?
+        };
+        report_created(&results, total)?;
+        Ok(results.succeeded)
+    }
+
+    /// Create the resources in the manifest and return its tests without creating them, so that
+    /// the caller can decide when each one is started. If `apply` is set, resources that already
+    /// exist are updated instead.
+    pub(crate) async fn create_resources(
+        &self,
+        client: &TestManager,
+        apply: bool,
+    ) -> Result<Vec<Test>> {
+        let crds = self.read()?;
+        check_resources_exist(client, &crds).await?;
+        let (resources, tests) = split_crds(crds);
+        add_resources(client, resources, apply).await?;
+        Ok(tests)
+    }
+
+    /// Read the objects in the manifest at `path`, or from stdin if `path` is `-`.
+    fn read(&self) -> Result<Vec<Crd>> {
+        if self.path.as_os_str() == "-" {
+            let mut manifest = String::new();
+            std::io::stdin()
+                .read_to_string(&mut manifest)
+                .context("Unable to read manifest from stdin")?;
+            convert_manifest(manifest).context("Unable to read manifest")
+        } else {
+            read_manifest(&self.path).context("Unable to read manifest")
+        }
+    }
+}
+
+/// Separate the resources from the tests in `crds`.
+fn split_crds(crds: Vec<Crd>) -> (Vec<Resource>, Vec<Test>) {
+    let mut resources = Vec::new();
+    let mut tests = Vec::new();
+    for crd in crds {
+        match crd {
+            Crd::Resource(resource) => resources.push(resource),
+            Crd::Test(test) => tests.push(test),
+        }
+    }
+    (resources, tests)
+}
+
+/// Create `resources`, or update the ones that already exist if `apply` is set, and print which
+/// of them were created. Returns an error if any of them were not.
+async fn add_resources(client: &TestManager, resources: Vec<Resource>, apply: bool) -> Result<()> {
+    let total = resources.len();
+    let resource_client = client.resource_client();
+    let results = if apply {
+        resource_client
+            .create_or_update_all(resources)
+            .await
+            .context("Unable to apply resources")?
+    } else {
+        resource_client
+            .create_all(resources)
+            .await
+            .context("Unable to create resources")?
+    };
+    report_created(&results, total)
+}
+
+/// Print which of the `total` objects were created. Returns an error if any of them were not.
+fn report_created(results: &BulkResult, total: usize) -> Result<()> {
+    for name in &results.succeeded {
+        println!("Successfully added '{}'.", name);
+    }
+    for (name, e) in &results.failed {
+        eprintln!("Unable to create '{}': {}", name, e);
+    }
+    println!("Created {} of {} objects.", results.succeeded.len(), total);
+    if !results.is_success() {
+        let failures: Vec<&str> = results
+            .failed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        return Err(Error::msg(format!(
+            "Unable to create the following objects: {}",
+            failures.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Send each of the `crds` to the API server as a dry-run create, reporting whether each one is
+/// valid. Returns an error if any of them is not.
+pub(crate) async fn server_dry_run(client: &TestManager, crds: Vec<Crd>) -> Result<()> {
+    let resource_client = client.resource_client();
+    let test_client = client.test_client();
+    let mut failures = Vec::new();
+    for crd in crds {
+        let name = crd.name().unwrap_or_default();
+        let result = match &crd {
+            Crd::Resource(resource) => resource_client.create_dry_run(resource).await.map(|_| ()),
+            Crd::Test(test) => test_client.create_dry_run(test).await.map(|_| ()),
+        };
+        match result {
+            Ok(()) => println!("'{}' is valid (server dry run).", name),
+            Err(e) => {
+                eprintln!("'{}' is invalid: {}", name, e);
+                failures.push(name);
+            }
+        }
+    }
+    if !failures.is_empty() {
+        return Err(Error::msg(format!(
+            "The following objects are invalid: {}",
+            failures.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Make sure every resource needed by a `Test` in `crds` is either part of the manifest or already
+/// exists in the cluster.
+pub(crate) async fn check_resources_exist(client: &TestManager, crds: &[Crd]) -> Result<()> {
+    let manifest_resources: HashSet<String> = crds
+        .iter()
+        .filter_map(|crd| match crd {
+            Crd::Resource(resource) => resource.metadata.name.clone(),
+            Crd::Test(_) => None,
+        })
+        .collect();
+    let resource_client = client.resource_client();
+    for crd in crds {
+        let test = match crd {
+            Crd::Test(test) => test,
+            Crd::Resource(_) => continue,
+        };
+        for resource in &test.spec.resources {
+            if manifest_resources.contains(resource) {
+                continue;
+            }
+            let exists = resource_client
+                .exists(resource)
                 .await
-                .context("Unable to create object")?;
-            if let Some(name) = name {
-                println!("Successfully added '{}'.", name);
+                .context(format!("Unable to get resource '{}'", resource))?;
+            if !exists {
+                return Err(Error::msg(format!(
+                    "Test '{}' requires resource '{}' which does not exist",
+                    crd.name().unwrap_or_default(),
+                    resource
+                )));
             }
         }
-        Ok(())
     }
+    Ok(())
 }
@@ -0,0 +1,104 @@
+use crate::run::add_provenance;
+use crate::run_file::{check_resources_exist, server_dry_run};
+use anyhow::{Context, Result};
+use clap::{value_parser, Parser};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::PathBuf;
+use testsys_model::clients::CrdClient;
+use testsys_model::test_manager::TestManager;
+use testsys_model::{Crd, Test, TestTemplate};
+
+/// Run a test from a YAML file containing `${param}` placeholders.
+#[derive(Debug, Parser)]
+pub(crate) struct RunTemplate {
+    /// Path to the test template YAML file.
+    #[clap(value_parser = value_parser!(PathBuf))]
+    path: PathBuf,
+
+    /// The value of a template parameter, e.g. `--set variant=aws-k8s-1.24`. Can be repeated.
+    #[clap(long = "set", value_parser = parse_param)]
+    params: Vec<(String, String)>,
+}
+
+impl RunTemplate {
+    /// Render the template and create the test, returning its name. If `apply` is set, a test that
+    /// already exists is updated instead. If `dry_run` is set, the test is only validated by the
+    /// API server and is not created.
+    pub(crate) async fn run(
+        &self,
+        client: &TestManager,
+        apply: bool,
+        dry_run: bool,
+    ) -> Result<Vec<String>> {
+        let mut test = self.render()?;
+        check_resources_exist(client, &[Crd::Test(test.clone())]).await?;
+        if dry_run {
+            server_dry_run(client, vec![Crd::Test(test)]).await?;
+            return Ok(Vec::new());
+        }
+
+        add_provenance(&mut test);
+        let test_client = client.test_client();
+        let test = if apply {
+            test_client
+                .create_or_update(test)
+                .await
+                .context("Unable to apply test")?
+        } else {
+            test_client
+                .create(test)
+                .await
+                .context("Unable to create test")?
+        };
+        let name = test.metadata.name.unwrap_or_default();
+        println!("Successfully added '{}'.", name);
+        Ok(vec![name])
+    }
+
+    /// Render the template and return the test without creating it, so that the caller can
+    /// decide when it is started.
+    pub(crate) async fn create_resources(&self, client: &TestManager) -> Result<Vec<Test>> {
+        let test = self.render()?;
+        check_resources_exist(client, &[Crd::Test(test.clone())]).await?;
+        Ok(vec![test])
+    }
+
+    /// Read the template at `path` and substitute the `--set` parameters.
+    fn render(&self) -> Result<Test> {
+        let file = File::open(&self.path)
+            .context(format!("Unable to open template '{}'", self.path.display()))?;
+        let template: TestTemplate =
+            serde_yaml::from_reader(file).context("Unable to read template")?;
+        let params: BTreeMap<String, String> = self.params.iter().cloned().collect();
+        template
+            .render(&params)
+            .context("Unable to render template")
+    }
+}
+
+/// Parse a template parameter of the form `name=value`.
+fn parse_param(input: &str) -> std::result::Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("'{}' is not of the form 'name=value'", input)),
+    }
+}
+
+#[test]
+fn test_parse_param() {
+    assert_eq!(
+        parse_param("variant=aws-k8s-1.24").unwrap(),
+        ("variant".to_string(), "aws-k8s-1.24".to_string())
+    );
+    assert_eq!(
+        parse_param("query=a=b").unwrap(),
+        ("query".to_string(), "a=b".to_string())
+    );
+    assert_eq!(
+        parse_param("empty=").unwrap(),
+        ("empty".to_string(), String::new())
+    );
+    assert!(parse_param("variant").is_err());
+    assert!(parse_param("=x").is_err());
+}
@@ -1,13 +1,27 @@
+use crate::get::write_json_lines;
+use crate::interrupt::{ctrl_c, Interrupted};
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 use terminal_size::{Height, Width};
-use testsys_model::test_manager::{CrdState, CrdType, SelectionParams, StatusColumn, TestManager};
+use testsys_model::clients::WatchEvent;
+use testsys_model::constants::{LABEL_REGION, LABEL_VARIANT};
+use testsys_model::test_manager::{
+    CrdState, CrdType, GroupedStatus, SelectionParams, StatusColumn, StatusSnapshot, TestManager,
+};
+use testsys_model::Crd;
 
 /// Check the status of a TestSys object.
 #[derive(Debug, Parser)]
 pub(crate) struct Status {
-    /// Output the results in JSON format.
-    #[clap(long = "json")]
+    /// The format used to print the status.
+    #[clap(long, short = 'o', value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
+    /// Output the results in JSON format (same as `--output json`).
+    #[clap(long = "json", conflicts_with = "output")]
     json: bool,
 
     /// Include the status of resources when reporting status
@@ -38,9 +52,48 @@ pub(crate) struct Status {
     /// Only include objects with the specified name
     #[clap(long)]
     name: Option<String>,
+
+    /// Only include objects matching this field selector, e.g. `metadata.name=my-test`. Only
+    /// `metadata.name` and `metadata.namespace` are supported by the server for TestSys objects
+    #[clap(long)]
+    field_selector: Option<String>,
+
+    /// Print the number of tests that passed, failed and are running for each value of this
+    /// label, e.g. `testsys.system/suite`, instead of the status of each object. Tests without
+    /// the label are counted as `<ungrouped>`.
+    #[clap(long, conflicts_with = "resources")]
+    group_by: Option<String>,
+
+    /// Keep redrawing the status as the selected objects change until interrupted with Ctrl-C.
+    #[clap(long, short = 'w')]
+    watch: bool,
+}
+
+/// The shortest time between two redraws of `status --watch`. Changes that arrive sooner are
+/// drawn together.
+const WATCH_REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The formats `status` can be printed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// A human readable table.
+    Table,
+    /// A human readable table with the region and variant labels of each object and the node each
+    /// test agent ran on.
+    Wide,
+    /// A JSON representation of the status including the full objects.
+    Json,
+    /// The compact JSON of each object on its own line, e.g. for `jq`.
+    Jsonl,
+    /// A YAML representation of the status including the full objects.
+    Yaml,
 }
 
 impl Status {
+    pub(crate) fn follows(&self) -> bool {
+        self.watch
+    }
+
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
         let crd_type = match (self.tests, self.resources) {
             (true, false) => Some(CrdType::Test),
@@ -49,14 +102,111 @@ impl Status {
         };
         let selection_params = SelectionParams {
             crd_type,
-            labels: self.labels,
-            name: self.name,
-            state: self.state,
+            labels: self.labels.clone(),
+            name: self.name.clone(),
+            field_selector: self.field_selector.clone(),
+            state: self.state.clone(),
+        };
+        let output = if self.json {
+            OutputFormat::Json
+        } else {
+            self.output
+        };
+        if self.watch {
+            return self.watch(&client, &selection_params, output).await;
+        }
+        self.print(&client, &selection_params, output).await
+    }
+
+    /// Redraw the status each time a `Test` or `Resource` changes, at most once every
+    /// `WATCH_REDRAW_INTERVAL`, until Ctrl-C is pressed. The objects are listed once and then kept
+    /// up to date from the changes seen by the watch.
+    async fn watch(
+        &self,
+        client: &TestManager,
+        selection_params: &SelectionParams,
+        output: OutputFormat,
+    ) -> Result<()> {
+        let mut changes = client
+            .watch(selection_params)
+            .context("Unable to watch status")?;
+        // The state of an object changes as it runs, so the objects in every state are kept.
+        let all_states = SelectionParams {
+            state: None,
+            ..selection_params.clone()
         };
-        let mut status = client
-            .status(&selection_params)
+        let mut objects: BTreeMap<_, _> = client
+            .list(&all_states)
+            .await
+            .context("Unable to get status")?
+            .into_iter()
+            .map(|crd| (object_key(&crd), crd))
+            .collect();
+        let ctrl_c = ctrl_c();
+        futures::pin_mut!(ctrl_c);
+        let mut redraws = RedrawThrottle::new(WATCH_REDRAW_INTERVAL);
+        // Draw the current status straight away.
+        redraws.changed();
+        loop {
+            let redraw_at = redraws.redraw_at(Instant::now());
+            let redraw = async {
+                match redraw_at {
+                    Some(redraw_at) => {
+                        tokio::time::sleep_until(tokio::time::Instant::from_std(redraw_at)).await
+                    }
+                    None => futures::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = redraw => {
+                    let status = StatusSnapshot::new(
+                        objects
+                            .values()
+                            .filter(|crd| selection_params.matches_state(crd))
+                            .cloned()
+                            .collect(),
+                    );
+                    // Clear the screen and move the cursor to the top left corner.
+                    print!("\x1B[2J\x1B[H");
+                    self.print_status(client, status, output).await?;
+                    redraws.redrawn(Instant::now());
+                }
+                Some(event) = changes.next() => {
+                    // Watch errors are retried by the watch, which lists the objects again.
+                    if let Ok(event) = event {
+                        apply_change(&mut objects, event);
+                        redraws.changed();
+                    }
+                }
+                _ = &mut ctrl_c => return Err(Interrupted.into()),
+            }
+        }
+    }
+
+    /// Print the status of the objects selected by `selection_params`.
+    async fn print(
+        &self,
+        client: &TestManager,
+        selection_params: &SelectionParams,
+        output: OutputFormat,
+    ) -> Result<()> {
+        let status = client
+            .status(selection_params)
             .await
             .context("Unable to get status")?;
+        self.print_status(client, status, output).await
+    }
+
+    /// Print `status` with the columns and in the format that were asked for.
+    async fn print_status(
+        &self,
+        client: &TestManager,
+        mut status: StatusSnapshot,
+        output: OutputFormat,
+    ) -> Result<()> {
+        if let Some(label) = &self.group_by {
+            return print_groups(&status.group_by(label), output);
+        }
 
         status.add_column(StatusColumn::name());
         status.add_column(StatusColumn::crd_type());
@@ -64,6 +214,8 @@ impl Status {
         status.add_column(StatusColumn::passed());
         status.add_column(StatusColumn::failed());
         status.add_column(StatusColumn::skipped());
+        status.add_column(StatusColumn::flaky());
+        status.add_column(StatusColumn::resource_error());
 
         if self.progress {
             status.add_column(StatusColumn::progress());
@@ -73,17 +225,177 @@ impl Status {
             status.add_column(StatusColumn::last_update());
         }
 
-        if self.json {
-            println!(
+        if output == OutputFormat::Wide {
+            let node_names = client
+                .test_node_names()
+                .await
+                .context("Unable to get the nodes of the test agents")?;
+            status.add_column(StatusColumn::label("REGION", LABEL_REGION));
+            status.add_column(StatusColumn::label("VARIANT", LABEL_VARIANT));
+            status.add_column(StatusColumn::node(node_names));
+        }
+
+        match output {
+            OutputFormat::Table | OutputFormat::Wide => {
+                let (terminal_size::Width(width), _) =
+                    terminal_size::terminal_size().unwrap_or((Width(120), Height(0)));
+                println!("{:width$}", status, width = width as usize);
+            }
+            OutputFormat::Json => println!(
                 "{}",
                 serde_json::to_string_pretty(&status)
                     .context("Could not create string from status.")?
-            );
-        } else {
-            let (terminal_size::Width(width), _) =
-                terminal_size::terminal_size().unwrap_or((Width(120), Height(0)));
-            println!("{:width$}", status, width = width as usize);
+            ),
+            OutputFormat::Jsonl => write_json_lines(std::io::stdout().lock(), status.crds())?,
+            OutputFormat::Yaml => println!(
+                "{}",
+                serde_yaml::to_string(&status).context("Could not create string from status.")?
+            ),
         }
         Ok(())
     }
 }
+
+/// The key of `crd` in the objects shown by `status --watch`, which sorts the tests before the
+/// resources and each of them by name, like `TestManager::list`.
+fn object_key(crd: &Crd) -> (bool, String) {
+    (
+        matches!(crd, Crd::Resource(_)),
+        crd.name().unwrap_or_default(),
+    )
+}
+
+/// Update the `objects` shown by `status --watch` with a change seen by the watch.
+fn apply_change(objects: &mut BTreeMap<(bool, String), Crd>, event: WatchEvent<Crd>) {
+    match event {
+        WatchEvent::Added(crd) | WatchEvent::Modified(crd) => {
+            objects.insert(object_key(&crd), crd);
+        }
+        WatchEvent::Deleted(crd) => {
+            objects.remove(&object_key(&crd));
+        }
+    }
+}
+
+/// Decides when `status --watch` redraws. The first change after a quiet period is drawn
+/// immediately, later changes are held back until `interval` has passed since the last redraw and
+/// are then drawn together.
+#[derive(Debug)]
+struct RedrawThrottle {
+    interval: Duration,
+    last_redraw: Option<Instant>,
+    changed: bool,
+}
+
+impl RedrawThrottle {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_redraw: None,
+            changed: false,
+        }
+    }
+
+    /// Record that the status has changed since the last redraw.
+    fn changed(&mut self) {
+        self.changed = true;
+    }
+
+    /// When the next redraw should happen, given that it is now `now`. Returns `None` if nothing
+    /// has changed since the last redraw.
+    fn redraw_at(&self, now: Instant) -> Option<Instant> {
+        if !self.changed {
+            return None;
+        }
+        Some(match self.last_redraw {
+            Some(last_redraw) => (last_redraw + self.interval).max(now),
+            None => now,
+        })
+    }
+
+    /// Record that the status was redrawn at `now`.
+    fn redrawn(&mut self, now: Instant) {
+        self.last_redraw = Some(now);
+        self.changed = false;
+    }
+}
+
+/// Print the test totals of each group.
+fn print_groups(grouped: &GroupedStatus, output: OutputFormat) -> Result<()> {
+    match output {
+        OutputFormat::Table | OutputFormat::Wide => println!("{}", grouped),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(grouped)
+                .context("Could not create string from status.")?
+        ),
+        OutputFormat::Jsonl => {
+            let records = grouped
+                .groups
+                .iter()
+                .map(|(group, totals)| serde_json::json!({ "group": group, "totals": totals }))
+                .chain([serde_json::json!({ "total": grouped.total })]);
+            write_json_lines(std::io::stdout().lock(), records)?
+        }
+        OutputFormat::Yaml => println!(
+            "{}",
+            serde_yaml::to_string(grouped).context("Could not create string from status.")?
+        ),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_redraw_throttle() {
+    let start = Instant::now();
+    let interval = Duration::from_millis(250);
+    let mut throttle = RedrawThrottle::new(interval);
+    assert_eq!(throttle.redraw_at(start), None);
+
+    // The first change is drawn immediately.
+    throttle.changed();
+    assert_eq!(throttle.redraw_at(start), Some(start));
+    throttle.redrawn(start);
+    assert_eq!(throttle.redraw_at(start), None);
+
+    // Changes soon after a redraw wait for the interval and are drawn together.
+    let soon = start + Duration::from_millis(10);
+    throttle.changed();
+    throttle.changed();
+    assert_eq!(throttle.redraw_at(soon), Some(start + interval));
+    throttle.changed();
+    assert_eq!(
+        throttle.redraw_at(start + Duration::from_millis(100)),
+        Some(start + interval)
+    );
+    throttle.redrawn(start + interval);
+    assert_eq!(throttle.redraw_at(start + interval), None);
+
+    // A change after a quiet period is drawn immediately.
+    let later = start + Duration::from_secs(5);
+    throttle.changed();
+    assert_eq!(throttle.redraw_at(later), Some(later));
+}
+
+#[test]
+fn test_apply_change() {
+    use testsys_model::{create_resource_crd, create_test_crd, ResourceSpec, TestSpec};
+
+    let test = Crd::Test(create_test_crd("a-test", None, TestSpec::default()));
+    let resource = Crd::Resource(create_resource_crd(
+        "a-cluster",
+        None,
+        ResourceSpec::default(),
+    ));
+    let mut objects = BTreeMap::new();
+    apply_change(&mut objects, WatchEvent::Added(resource.clone()));
+    apply_change(&mut objects, WatchEvent::Added(test.clone()));
+    apply_change(&mut objects, WatchEvent::Modified(test.clone()));
+    // Tests are shown before resources.
+    let names: Vec<_> = objects.values().filter_map(Crd::name).collect();
+    assert_eq!(names, vec!["a-test", "a-cluster"]);
+
+    apply_change(&mut objects, WatchEvent::Deleted(test));
+    let names: Vec<_> = objects.values().filter_map(Crd::name).collect();
+    assert_eq!(names, vec!["a-cluster"]);
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Error, Result};
+use clap::{Parser, ValueEnum};
+use std::cmp::Ordering;
+use testsys_model::test_manager::{self, AgentUsage, TestManager};
+
+/// Show the CPU and memory used by the agent pod of each running test. Requires metrics-server.
+#[derive(Debug, Parser)]
+pub(crate) struct Top {
+    /// The column to sort by. CPU and memory are sorted from the most used.
+    #[clap(long, value_enum, default_value_t = SortBy::Memory)]
+    sort_by: SortBy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortBy {
+    /// The name of the test.
+    Name,
+    /// The CPU used by the agent pod.
+    Cpu,
+    /// The memory used by the agent pod.
+    Memory,
+}
+
+impl Top {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let mut usage = match client.agent_usage().await {
+            Ok(usage) => usage,
+            // The message says what to do, so it is not wrapped in more context.
+            Err(e @ test_manager::Error::MetricsUnavailable) => {
+                return Err(Error::msg(e.to_string()))
+            }
+            Err(e) => return Err(e).context("Unable to get the resource usage of agent pods"),
+        };
+        if usage.is_empty() {
+            println!("No test agent pods are running");
+            return Ok(());
+        }
+        sort_usage(&mut usage, self.sort_by);
+        print!("{}", usage_table(&usage));
+        Ok(())
+    }
+}
+
+/// Sort `usage` by the `sort_by` column, breaking ties by test name.
+fn sort_usage(usage: &mut [AgentUsage], sort_by: SortBy) {
+    usage.sort_by(|a, b| {
+        let descending = |a: f64, b: f64| b.partial_cmp(&a).unwrap_or(Ordering::Equal);
+        match sort_by {
+            SortBy::Name => Ordering::Equal,
+            SortBy::Cpu => descending(a.cpu_cores, b.cpu_cores),
+            SortBy::Memory => descending(a.memory_bytes, b.memory_bytes),
+        }
+        .then_with(|| a.test.cmp(&b.test))
+    });
+}
+
+/// Render `usage` as a table with the same units as `kubectl top`, millicores and mebibytes.
+fn usage_table(usage: &[AgentUsage]) -> String {
+    let rows: Vec<[String; 3]> = usage
+        .iter()
+        .map(|usage| {
+            [
+                usage.test.clone(),
+                format!("{:.0}m", usage.cpu_cores * 1000.0),
+                format!("{:.0}Mi", usage.memory_bytes / (1024.0 * 1024.0)),
+            ]
+        })
+        .collect();
+    let width = rows
+        .iter()
+        .map(|row| row[0].len())
+        .chain(Some("TEST".len()))
+        .max()
+        .unwrap_or_default();
+    let mut table = format!("{:<width$}  {:>8}  {:>8}\n", "TEST", "CPU", "MEMORY");
+    for [test, cpu, memory] in rows {
+        table.push_str(&format!("{:<width$}  {:>8}  {:>8}\n", test, cpu, memory));
+    }
+    table
+}
+
+#[test]
+fn test_usage_table() {
+    let usage = |test: &str, cpu_cores, memory_mebibytes: f64| AgentUsage {
+        test: test.to_string(),
+        pod: format!("{}-abcde", test),
+        cpu_cores,
+        memory_bytes: memory_mebibytes * 1024.0 * 1024.0,
+    };
+    let mut usage = vec![
+        usage("small", 0.5, 64.0),
+        usage("large-test", 0.25, 512.0),
+        usage("another", 0.5, 64.0),
+    ];
+    sort_usage(&mut usage, SortBy::Memory);
+    assert_eq!(
+        usage_table(&usage),
+        "TEST             CPU    MEMORY\n\
+         large-test      250m     512Mi\n\
+         another         500m      64Mi\n\
+         small           500m      64Mi\n"
+    );
+    sort_usage(&mut usage, SortBy::Cpu);
+    let tests: Vec<_> = usage.iter().map(|usage| usage.test.as_str()).collect();
+    assert_eq!(tests, vec!["another", "small", "large-test"]);
+    sort_usage(&mut usage, SortBy::Name);
+    let tests: Vec<_> = usage.iter().map(|usage| usage.test.as_str()).collect();
+    assert_eq!(tests, vec!["another", "large-test", "small"]);
+}
@@ -1,16 +1,42 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use testsys_model::test_manager::TestManager;
+use testsys_model::test_manager::{Error, TestManager};
 
 /// The uninstall subcommand is responsible for removing all testsys components from a k8s cluster.
 #[derive(Debug, Parser)]
-pub(crate) struct Uninstall {}
+pub(crate) struct Uninstall {
+    /// Also delete the testsys namespace.
+    #[clap(long)]
+    delete_namespace: bool,
+
+    /// Uninstall even if `Test` or `Resource` objects still exist. Deleting the CRDs will orphan
+    /// the finalizers of these objects.
+    #[clap(long)]
+    force: bool,
+}
 
 impl Uninstall {
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
-        client.uninstall().await.context(
-            "Unable to uninstall testsys from the cluster. (Some artifacts may be left behind)",
-        )?;
+        if self.force {
+            eprintln!(
+                "Warning: uninstalling with `--force`, any remaining tests and resources will be \
+                orphaned."
+            );
+        }
+        let deleted =
+            match client.uninstall(self.delete_namespace, self.force).await {
+                Ok(deleted) => deleted,
+                Err(e @ (Error::TestExisting | Error::ResourceExisting)) => return Err(e).context(
+                    "Refusing to uninstall testsys, delete the remaining objects or use `--force`",
+                ),
+                Err(e) => return Err(e).context(
+                    "Unable to uninstall testsys from the cluster. (Some artifacts may be left \
+                    behind)",
+                ),
+            };
+        for object in deleted {
+            println!("Deleted {}", object);
+        }
 
         println!("testsys components were successfully uninstalled.");
 
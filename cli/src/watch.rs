@@ -0,0 +1,135 @@
+use crate::interrupt::{ctrl_c, until_interrupted, Interrupted};
+use crate::run::parse_duration;
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use futures::StreamExt;
+use std::time::Duration;
+use testsys_model::clients::CrdClient;
+use testsys_model::test_manager::TestManager;
+use testsys_model::{TaskState, TestUserState};
+
+/// Follow the state of a test until it finishes. Exits with an error if the test errored or had
+/// failures.
+#[derive(Debug, Parser)]
+pub(crate) struct Watch {
+    /// The name of the test to watch.
+    test_name: String,
+
+    /// How long to wait for the test to finish before giving up, e.g. `30m` or `1h30m`. A number
+    /// without a unit is a number of seconds.
+    #[clap(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+}
+
+impl Watch {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let watch = async {
+            let watch = watch_test(&client, &self.test_name);
+            match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, watch).await.map_err(|_| {
+                    Error::msg(format!(
+                        "Test '{}' did not finish within {:?}",
+                        self.test_name, timeout
+                    ))
+                })?,
+                None => watch.await,
+            }
+        };
+        match until_interrupted(watch, ctrl_c()).await {
+            Some(result) => result?,
+            None => {
+                print_left_in_place(&client, [self.test_name.as_str()]).await;
+                return Err(Interrupted.into());
+            }
+        }
+        report_test(&client, &self.test_name).await
+    }
+}
+
+/// Print the current task state of each of the `tests` that a command stopped following when it
+/// was interrupted. The tests are not changed.
+pub(crate) async fn print_left_in_place<'a, I>(client: &TestManager, tests: I)
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    for test_name in tests {
+        match client.test_client().get(test_name).await {
+            Ok(test) => eprintln!(
+                "{}: {} (left in place)",
+                test_name,
+                test.agent_status().task_state
+            ),
+            Err(e) => eprintln!("{}: unable to get the current state: {}", test_name, e),
+        }
+    }
+}
+
+/// Print the latest results of the finished test `test_name`. Returns an error unless the test
+/// passed, had no tests or was skipped.
+pub(crate) async fn report_test(client: &TestManager, test_name: &str) -> Result<()> {
+    let test = client
+        .test_client()
+        .get(test_name)
+        .await
+        .context(format!("Unable to get test '{}'", test_name))?;
+    let agent_status = test.agent_status();
+    if let Some(results) = agent_status.results.last() {
+        println!(
+            "{}: {} (passed: {}, failed: {}, skipped: {})",
+            test_name, results.outcome, results.num_passed, results.num_failed, results.num_skipped
+        );
+        // Compare with the run before the latest retry, if there was one.
+        let previous = agent_status
+            .results
+            .iter()
+            .rev()
+            .nth(1)
+            .or_else(|| agent_status.results_history.last());
+        if let Some(previous) = previous {
+            let regression = results.regression_against(previous);
+            for case in &regression.new_failures {
+                println!("New failure since the previous run: {}", case);
+            }
+            for case in &regression.newly_skipped {
+                println!("Newly skipped since the previous run: {}", case);
+            }
+        }
+    }
+    match test.test_user_state() {
+        TestUserState::Passed | TestUserState::NoTests | TestUserState::Skipped => Ok(()),
+        state => Err(Error::msg(format!(
+            "Test '{}' finished in state '{}'",
+            test_name, state
+        ))),
+    }
+}
+
+/// Print each change to the test's task state until it finishes or a resource error is reported.
+pub(crate) async fn watch_test(client: &TestManager, test_name: &str) -> Result<()> {
+    let test_client = client.test_client();
+    let stream = test_client.stream_status(test_name);
+    futures::pin_mut!(stream);
+    let mut last_task_state = None;
+    while let Some(status) = stream.next().await {
+        let status = status.context(format!("Unable to watch test '{}'", test_name))?;
+        let task_state = status.agent.task_state;
+        if last_task_state != Some(task_state) {
+            println!("{}: {}", test_name, task_state);
+            last_task_state = Some(task_state);
+        }
+        if let Some(resource_error) = &status.controller.resource_error {
+            println!("Resource Error: {}", resource_error);
+            return Ok(());
+        }
+        if matches!(
+            task_state,
+            TaskState::Completed | TaskState::Error | TaskState::Cancelled
+        ) {
+            return Ok(());
+        }
+    }
+    Err(Error::msg(format!(
+        "Test '{}' was deleted before it finished",
+        test_name
+    )))
+}
@@ -1,6 +1,8 @@
 #![cfg(feature = "integ")]
 mod data;
 use assert_cmd::Command;
+use k8s_openapi::api::apps::v1::Deployment;
+use kube::api::{Patch, PatchParams};
 use selftest::Cluster;
 use testsys_model::{constants::NAMESPACE, Resource, Test};
 use tokio::time::Duration;
@@ -125,3 +127,46 @@ async fn test_system() {
         .await
         .unwrap();
 }
+
+/// We will test that `cli install --apply` can be re-run after an installed object was modified.
+#[tokio::test]
+async fn test_install_apply() {
+    let cluster = Cluster::new("integ-apply").unwrap();
+    cluster.load_image_to_cluster("controller:integ").unwrap();
+    let install = || {
+        let mut cmd = Command::cargo_bin("cli").unwrap();
+        cmd.args(&[
+            "--kubeconfig",
+            cluster.kubeconfig().to_str().unwrap(),
+            "install",
+            "--controller-uri",
+            "controller:integ",
+            "--apply",
+        ]);
+        cmd
+    };
+    install().assert().success();
+    cluster.wait_for_controller(POD_TIMEOUT).await.unwrap();
+
+    // Change a field that `install` owns, as `kubectl` would.
+    let deployments = cluster
+        .namespaced_api::<Deployment>(NAMESPACE)
+        .await
+        .unwrap();
+    deployments
+        .patch(
+            "testsys-controller",
+            &PatchParams::apply("kubectl").force(),
+            &Patch::Apply(serde_json::json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "spec": { "replicas": 2 }
+            })),
+        )
+        .await
+        .unwrap();
+
+    install().assert().success();
+    let deployment = deployments.get("testsys-controller").await.unwrap();
+    assert_eq!(deployment.spec.unwrap().replicas, Some(1));
+}
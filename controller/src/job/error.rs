@@ -19,6 +19,9 @@ pub(crate) enum JobError {
     #[snafu(display("Unable to get job: {}", source))]
     Get { source: kube::Error },
 
+    #[snafu(display("Unable to list jobs: {}", source))]
+    List { source: kube::Error },
+
     #[snafu(display("Job does not exist: {}", source))]
     NotFound { source: kube::Error },
 
@@ -10,9 +10,10 @@ use kube::Api;
 use std::collections::BTreeMap;
 use testsys_model::constants::{
     APP_COMPONENT, APP_CREATED_BY, APP_INSTANCE, APP_MANAGED_BY, APP_NAME, APP_PART_OF, CONTROLLER,
-    NAMESPACE, RESOURCE_AGENT, RESOURCE_AGENT_SERVICE_ACCOUNT, SECRETS_PATH, TESTSYS, TEST_AGENT,
-    TEST_AGENT_SERVICE_ACCOUNT,
+    ENV_NAMESPACE, RESOURCE_AGENT, RESOURCE_AGENT_SERVICE_ACCOUNT, SECRETS_PATH, TESTSYS,
+    TEST_AGENT, TEST_AGENT_SERVICE_ACCOUNT,
 };
+use testsys_model::system::default_namespace;
 use testsys_model::Agent;
 
 #[derive(Debug, Clone, Copy)]
@@ -32,14 +33,26 @@ pub(crate) struct JobBuilder<'a> {
 impl JobBuilder<'_> {
     pub(crate) async fn deploy(self, client: kube::Client) -> JobResult<Job> {
         let job = self.build();
-        let api: Api<Job> = Api::namespaced(client, NAMESPACE);
+        let api: Api<Job> = Api::namespaced(client, &default_namespace());
         api.create(&PostParams::default(), &job)
             .await
             .map_err(JobError::create)
     }
 
     fn build(self) -> Job {
-        let vars = env_vars(self.environment_variables);
+        // Agents are run in the controller's namespace and use it to find their `Test` or
+        // `Resource` object.
+        let namespace = default_namespace();
+        let mut environment_variables = self.environment_variables;
+        environment_variables.push((ENV_NAMESPACE, namespace.clone()));
+        // The agent's own variables are added last and cannot override the ones set by the
+        // controller.
+        for (name, value) in &self.agent.env {
+            if !environment_variables.iter().any(|(n, _)| n == name) {
+                environment_variables.push((name, value.clone()));
+            }
+        }
+        let vars = env_vars(environment_variables);
         let labels = create_labels(self.job_type, &self.agent.name, self.job_name);
         // Set up the container's security context
         let security_context = Some(SecurityContext {
@@ -54,7 +67,7 @@ impl JobBuilder<'_> {
         Job {
             metadata: ObjectMeta {
                 name: Some(self.job_name.into()),
-                namespace: Some(NAMESPACE.to_owned()),
+                namespace: Some(namespace),
                 labels: Some(labels.clone()),
                 ..ObjectMeta::default()
             },
@@ -62,14 +75,22 @@ impl JobBuilder<'_> {
                 backoff_limit: Some(0),
                 template: PodTemplateSpec {
                     spec: Some(PodSpec {
-                        containers: vec![Container {
+                        // Sidecars run alongside the agent container, e.g. to ship its logs.
+                        containers: std::iter::once(Container {
                             name: self.job_name.into(),
                             image: Some(self.agent.image.to_owned()),
                             env: if vars.is_empty() { None } else { Some(vars) },
                             volume_mounts: mounts(self.agent),
                             security_context,
                             ..Container::default()
-                        }],
+                        })
+                        .chain(self.agent.sidecars.iter().cloned())
+                        .collect(),
+                        init_containers: if self.agent.init_containers.is_empty() {
+                            None
+                        } else {
+                            Some(self.agent.init_containers.clone())
+                        },
                         restart_policy: Some(String::from("Never")),
                         image_pull_secrets: self.agent.pull_secret.as_ref().map(|secret| {
                             vec![LocalObjectReference {
@@ -81,6 +102,16 @@ impl JobBuilder<'_> {
                             JobType::ResourceAgent => RESOURCE_AGENT_SERVICE_ACCOUNT.to_owned(),
                         }),
                         volumes: volumes(self.agent),
+                        node_selector: if self.agent.node_selector.is_empty() {
+                            None
+                        } else {
+                            Some(self.agent.node_selector.clone())
+                        },
+                        tolerations: if self.agent.tolerations.is_empty() {
+                            None
+                        } else {
+                            Some(self.agent.tolerations.clone())
+                        },
                         ..PodSpec::default()
                     }),
                     metadata: Some(ObjectMeta {
@@ -5,11 +5,13 @@ pub(crate) use crate::job::error::{JobError, JobResult};
 pub(crate) use job_builder::{JobBuilder, JobType};
 use k8s_openapi::api::batch::v1::Job;
 use k8s_openapi::chrono::{Duration, Utc};
-use kube::api::{DeleteParams, PropagationPolicy};
-use kube::Api;
+use kube::api::{DeleteParams, ListParams, PropagationPolicy};
+use kube::{Api, ResourceExt};
 use log::debug;
-use snafu::ensure;
-use testsys_model::constants::NAMESPACE;
+use snafu::{ensure, ResultExt};
+use std::collections::BTreeSet;
+use testsys_model::constants::{APP_COMPONENT, TEST_AGENT};
+use testsys_model::system::default_namespace;
 
 lazy_static::lazy_static! {
     /// The maximum amount of time for a test to begin running (in seconds).
@@ -42,7 +44,7 @@ pub(crate) async fn get_job_state<S>(k8s_client: kube::Client, name: S) -> JobRe
 where
     S: AsRef<str>,
 {
-    let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
+    let api: Api<Job> = Api::namespaced(k8s_client, &default_namespace());
     let result = api.get(name.as_ref()).await.map_err(JobError::get);
     if let Err(JobError::NotFound { .. }) = &result {
         Ok(JobState::None)
@@ -52,6 +54,27 @@ where
     }
 }
 
+/// The names of the test agent jobs that have not finished yet, including jobs whose container has
+/// not started. A test agent job has the same name as its test.
+pub(crate) async fn active_test_agents(k8s_client: kube::Client) -> JobResult<BTreeSet<String>> {
+    let api: Api<Job> = Api::namespaced(k8s_client, &default_namespace());
+    let jobs = api
+        .list(&ListParams::default().labels(&format!("{}={}", APP_COMPONENT, TEST_AGENT)))
+        .await
+        .context(error::ListSnafu)?;
+    Ok(jobs
+        .items
+        .iter()
+        .filter(|job| {
+            matches!(
+                parse_job_state(job),
+                Ok(JobState::Unknown) | Ok(JobState::Running(_))
+            )
+        })
+        .map(|job| job.name_any())
+        .collect())
+}
+
 /// Transform the container counts in `job.status` to a `JobState`
 fn parse_job_state(job: &Job) -> JobResult<JobState> {
     // Return early if `job.status` is somehow `None`.
@@ -102,7 +125,7 @@ fn parse_job_state(job: &Job) -> JobResult<JobState> {
 }
 
 pub(crate) async fn delete_job(k8s_client: kube::Client, name: &str) -> JobResult<()> {
-    let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
+    let api: Api<Job> = Api::namespaced(k8s_client, &default_namespace());
     let result = api
         .delete(
             name,
@@ -0,0 +1,203 @@
+use crate::error::Result;
+use anyhow::Context;
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::{DateTime, Duration as ChronoDuration, Utc};
+use kube::api::{ObjectMeta, PostParams};
+use kube::{Api, Client};
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+use testsys_model::constants::{ENV_LEADER_ELECTION_LEASE, ENV_POD_NAME};
+use testsys_model::system::default_namespace;
+
+/// How long a replica holds the lease for after acquiring or renewing it. Another replica takes
+/// the lease if it is not renewed in time.
+const LEASE_DURATION_SECONDS: i32 = 15;
+
+/// How often the leader renews the lease, and how often the other replicas try to acquire it.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The `Lease` that a controller replica must hold to run the controllers when there is more than
+/// one replica, see `testsys_model::system::controller_lease`.
+pub(crate) struct LeaderElection {
+    api: Api<Lease>,
+    lease: String,
+    identity: String,
+}
+
+impl LeaderElection {
+    /// The leader election that this replica takes part in, read from
+    /// `TESTSYS_LEADER_ELECTION_LEASE` and `TESTSYS_POD_NAME`. Returns `None` if the lease is not
+    /// set, i.e. if this is the only replica.
+    pub(crate) fn from_env(client: Client) -> Result<Option<Self>> {
+        let lease = match std::env::var(ENV_LEADER_ELECTION_LEASE) {
+            Ok(lease) => lease,
+            Err(_) => return Ok(None),
+        };
+        let identity = std::env::var(ENV_POD_NAME).context(format!(
+            "{} is required when {} is set",
+            ENV_POD_NAME, ENV_LEADER_ELECTION_LEASE
+        ))?;
+        Ok(Some(Self {
+            api: Api::namespaced(client, &default_namespace()),
+            lease,
+            identity,
+        }))
+    }
+
+    /// The name of the lease.
+    pub(crate) fn lease(&self) -> &str {
+        &self.lease
+    }
+
+    /// Wait until this replica holds the lease.
+    pub(crate) async fn acquire(&self) {
+        loop {
+            match self.try_acquire_or_renew().await {
+                Ok(true) => {
+                    info!("'{}' acquired lease '{}'", self.identity, self.lease);
+                    return;
+                }
+                Ok(false) => debug!("Lease '{}' is held by another replica", self.lease),
+                Err(e) => warn!("Unable to acquire lease '{}': {:?}", self.lease, e),
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Keep renewing the lease. Returns when the lease has been taken by another replica or could
+    /// not be renewed before it expired, after which this replica must stop acting as the leader.
+    pub(crate) async fn hold(&self) {
+        let lease_duration = Duration::from_secs(LEASE_DURATION_SECONDS as u64);
+        let mut renewed = Instant::now();
+        loop {
+            tokio::time::sleep(RETRY_INTERVAL).await;
+            match self.try_acquire_or_renew().await {
+                Ok(true) => renewed = Instant::now(),
+                Ok(false) => {
+                    warn!("Lease '{}' was taken by another replica", self.lease);
+                    return;
+                }
+                Err(e) => {
+                    warn!("Unable to renew lease '{}': {:?}", self.lease, e);
+                    if renewed.elapsed() >= lease_duration {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Take or renew the lease if it is free, already held by this replica or expired. Returns
+    /// `false` if another replica holds it, or took it first.
+    async fn try_acquire_or_renew(&self) -> Result<bool> {
+        let now = Utc::now();
+        let lease = self
+            .api
+            .get_opt(&self.lease)
+            .await
+            .context(format!("Unable to get lease '{}'", self.lease))?;
+        let result = match lease {
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.lease.clone()),
+                        ..Default::default()
+                    },
+                    spec: Some(held_spec(&LeaseSpec::default(), &self.identity, now)),
+                };
+                self.api.create(&PostParams::default(), &lease).await
+            }
+            Some(lease) => {
+                let spec = lease.spec.clone().unwrap_or_default();
+                if !can_acquire(&spec, &self.identity, now) {
+                    return Ok(false);
+                }
+                // The resource version makes this fail if another replica updated the lease first.
+                let lease = Lease {
+                    spec: Some(held_spec(&spec, &self.identity, now)),
+                    ..lease
+                };
+                self.api
+                    .replace(&self.lease, &PostParams::default(), &lease)
+                    .await
+            }
+        };
+        match result {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => Err(e).context(format!("Unable to update lease '{}'", self.lease)),
+        }
+    }
+}
+
+/// Returns `true` if `identity` may take the lease with `spec` at `now`, i.e. if nobody holds it,
+/// `identity` already holds it, or its holder did not renew it in time.
+fn can_acquire(spec: &LeaseSpec, identity: &str, now: DateTime<Utc>) -> bool {
+    match (&spec.holder_identity, &spec.renew_time) {
+        (None, _) => true,
+        (Some(holder), _) if holder == identity => true,
+        (Some(_), None) => true,
+        (Some(_), Some(MicroTime(renewed))) => {
+            let duration = spec
+                .lease_duration_seconds
+                .unwrap_or(LEASE_DURATION_SECONDS);
+            *renewed + ChronoDuration::seconds(duration.into()) < now
+        }
+    }
+}
+
+/// The spec of the lease with `spec` after `identity` acquired or renewed it at `now`.
+fn held_spec(spec: &LeaseSpec, identity: &str, now: DateTime<Utc>) -> LeaseSpec {
+    let renewing = spec.holder_identity.as_deref() == Some(identity);
+    let transitions = spec.lease_transitions.unwrap_or_default();
+    LeaseSpec {
+        holder_identity: Some(identity.to_string()),
+        lease_duration_seconds: Some(LEASE_DURATION_SECONDS),
+        acquire_time: if renewing {
+            spec.acquire_time.clone()
+        } else {
+            Some(MicroTime(now))
+        },
+        renew_time: Some(MicroTime(now)),
+        lease_transitions: match &spec.holder_identity {
+            Some(holder) if holder != identity => Some(transitions + 1),
+            _ => Some(transitions),
+        },
+    }
+}
+
+#[test]
+fn test_can_acquire() {
+    let now = Utc::now();
+    let held = |holder: &str, seconds_ago: i64| LeaseSpec {
+        holder_identity: Some(holder.to_string()),
+        lease_duration_seconds: Some(LEASE_DURATION_SECONDS),
+        renew_time: Some(MicroTime(now - ChronoDuration::seconds(seconds_ago))),
+        ..Default::default()
+    };
+    assert!(can_acquire(&LeaseSpec::default(), "pod-a", now));
+    assert!(can_acquire(&held("pod-a", 1), "pod-a", now));
+    assert!(!can_acquire(&held("pod-b", 1), "pod-a", now));
+    assert!(can_acquire(&held("pod-b", 60), "pod-a", now));
+}
+
+#[test]
+fn test_held_spec() {
+    let now = Utc::now();
+    let acquired = held_spec(&LeaseSpec::default(), "pod-a", now);
+    assert_eq!(acquired.holder_identity.as_deref(), Some("pod-a"));
+    assert_eq!(acquired.acquire_time, Some(MicroTime(now)));
+    assert_eq!(acquired.lease_transitions, Some(0));
+
+    let later = now + ChronoDuration::seconds(5);
+    let renewed = held_spec(&acquired, "pod-a", later);
+    assert_eq!(renewed.acquire_time, Some(MicroTime(now)));
+    assert_eq!(renewed.renew_time, Some(MicroTime(later)));
+    assert_eq!(renewed.lease_transitions, Some(0));
+
+    let taken = held_spec(&renewed, "pod-b", later);
+    assert_eq!(taken.holder_identity.as_deref(), Some("pod-b"));
+    assert_eq!(taken.acquire_time, Some(MicroTime(later)));
+    assert_eq!(taken.lease_transitions, Some(1));
+}
@@ -17,22 +17,39 @@ and test agent pods when TestSys [`Test`] and [`Resource`] CRD instance is added
     clippy::unwrap_used
 )]
 
+use crate::leader_election::LeaderElection;
+use crate::metrics::{run_metrics_server, DEFAULT_METRICS_PORT};
 use crate::resource_controller::run_resource_controller;
 use crate::test_controller::run_test_controller;
+use clap::Parser;
 use env_logger::Builder;
 use futures::join;
 use kube::Client;
-use log::{error, info, LevelFilter};
+use log::{error, info, warn, Level, LevelFilter};
+use std::io::Write;
+use testsys_model::constants::ENV_LOG_FORMAT;
+use testsys_model::system::ControllerLogFormat;
 
 mod constants;
 mod error;
 mod job;
+mod leader_election;
+mod metrics;
 mod resource_controller;
 mod test_controller;
 mod utils;
 
+/// The TestSys controller.
+#[derive(Debug, Parser)]
+struct Args {
+    /// The port to serve Prometheus metrics on at `/metrics`.
+    #[clap(long, default_value_t = DEFAULT_METRICS_PORT)]
+    metrics_port: u16,
+}
+
 #[tokio::main]
 async fn main() {
+    let args = Args::parse();
     init_logger();
     info!("Starting");
 
@@ -45,30 +62,112 @@ async fn main() {
         }
     };
 
+    let leader_election = match LeaderElection::from_env(client.clone()) {
+        Ok(leader_election) => leader_election,
+        Err(e) => {
+            error!("Unable to set up leader election: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Run the controllers.
-    let future_1 = run_test_controller(client.clone());
-    let future_2 = run_resource_controller(client);
+    let future_1 = run_controllers(client.clone(), leader_election);
+    let future_2 = run_metrics_server(client, args.metrics_port);
 
     let _ = join!(future_1, future_2);
 }
 
+/// Run the test and resource controllers. If there is a `leader_election`, the controllers only
+/// run once this replica holds the lease, and the process exits if the lease is lost so that the
+/// controllers stop and the replica is restarted.
+async fn run_controllers(client: Client, leader_election: Option<LeaderElection>) {
+    let controllers = async {
+        join!(
+            run_test_controller(client.clone()),
+            run_resource_controller(client.clone())
+        )
+    };
+    let leader_election = match leader_election {
+        Some(leader_election) => leader_election,
+        None => {
+            let _ = controllers.await;
+            return;
+        }
+    };
+    info!("Waiting to acquire lease '{}'", leader_election.lease());
+    leader_election.acquire().await;
+    tokio::select! {
+        _ = controllers => {}
+        _ = leader_election.hold() => {
+            error!("Lost lease '{}', exiting", leader_election.lease());
+            std::process::exit(1);
+        }
+    }
+}
+
 /// The log level used when the `RUST_LOG` environment variable does not exist.
 const DEFAULT_LEVEL_FILTER: LevelFilter = LevelFilter::Trace;
 
 /// Extract the value of `RUST_LOG` if it exists, otherwise log this crate at
-/// `DEFAULT_LEVEL_FILTER`.
+/// `DEFAULT_LEVEL_FILTER`. Logs are written as JSON if `TESTSYS_LOG_FORMAT` is `json`.
 fn init_logger() {
-    match std::env::var(env_logger::DEFAULT_FILTER_ENV).ok() {
+    let mut builder = match std::env::var(env_logger::DEFAULT_FILTER_ENV).ok() {
         Some(_) => {
             // RUST_LOG exists; env_logger will use it.
-            Builder::from_default_env().init();
+            Builder::from_default_env()
         }
         None => {
             // RUST_LOG does not exist; use default log level for this crate only.
-            Builder::new()
+            let mut builder = Builder::new();
+            builder
                 .filter(Some(env!("CARGO_CRATE_NAME")), DEFAULT_LEVEL_FILTER)
-                .filter(Some("testsys_model"), DEFAULT_LEVEL_FILTER)
-                .init();
+                .filter(Some("testsys_model"), DEFAULT_LEVEL_FILTER);
+            builder
         }
+    };
+    let format = std::env::var(ENV_LOG_FORMAT)
+        .ok()
+        .map(|format| format.parse::<ControllerLogFormat>());
+    if let Some(Ok(ControllerLogFormat::Json)) = format {
+        builder.format(|buf, record| {
+            let line = json_log_line(
+                &buf.timestamp().to_string(),
+                record.level(),
+                record.target(),
+                &record.args().to_string(),
+            );
+            writeln!(buf, "{}", line)
+        });
     }
+    builder.init();
+    if let Some(Err(e)) = format {
+        warn!("Ignoring invalid {}: {}", ENV_LOG_FORMAT, e);
+    }
+}
+
+/// A log line as a compact JSON object.
+fn json_log_line(timestamp: &str, level: Level, target: &str, message: &str) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": level.as_str(),
+        "target": target,
+        "message": message,
+    })
+    .to_string()
+}
+
+#[test]
+fn test_json_log_line() {
+    let line = json_log_line(
+        "2022-06-01T00:00:00Z",
+        Level::Info,
+        "controller::test_controller",
+        "Test 'my-test' \"quoted\"\nsecond line",
+    );
+    assert!(!line.contains('\n'));
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value["level"], "INFO");
+    assert_eq!(value["target"], "controller::test_controller");
+    assert_eq!(value["message"], "Test 'my-test' \"quoted\"\nsecond line");
+    assert_eq!(value["timestamp"], "2022-06-01T00:00:00Z");
 }
@@ -0,0 +1,213 @@
+use crate::error::Result;
+use anyhow::Context;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use kube::Client;
+use log::{error, info};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use testsys_model::clients::{CrdClient, ResourceClient, TestClient};
+use testsys_model::{Resource, TaskState, Test};
+
+/// The port the metrics server listens on if `--metrics-port` is not given.
+pub(crate) const DEFAULT_METRICS_PORT: u16 = 8080;
+
+/// The number of times `reconcile` has returned an error for a `Test`.
+static TEST_RECONCILE_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of times `reconcile` has returned an error for a `Resource`.
+static RESOURCE_RECONCILE_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Count a reconciliation error from the test controller.
+pub(crate) fn record_test_reconcile_error() {
+    TEST_RECONCILE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a reconciliation error from the resource controller.
+pub(crate) fn record_resource_reconcile_error() {
+    RESOURCE_RECONCILE_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Serve `/metrics` in the Prometheus text format on `port` until the server fails.
+pub(crate) async fn run_metrics_server(client: Client, port: u16) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let make_service = make_service_fn(move |_| {
+        let client = client.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(client.clone(), request)
+            }))
+        }
+    });
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            error!("Unable to start the metrics server on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving metrics on {}", addr);
+    if let Err(e) = server.await {
+        error!("Metrics server error: {}", e);
+    }
+}
+
+async fn handle_request(
+    client: Client,
+    request: Request<Body>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Ok(response(StatusCode::NOT_FOUND, "Not found\n".to_string()));
+    }
+    Ok(match metrics(client).await {
+        Ok(text) => response(StatusCode::OK, text),
+        Err(e) => {
+            error!("Unable to collect metrics: {:?}", e);
+            response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Unable to collect metrics: {}\n", e),
+            )
+        }
+    })
+}
+
+fn response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// List the `Test`s and `Resource`s in the cluster and render the metrics for them.
+async fn metrics(client: Client) -> Result<String> {
+    let tests = TestClient::new_from_k8s_client(client.clone())
+        .get_all()
+        .await
+        .context("Unable to list tests")?;
+    let resources = ResourceClient::new_from_k8s_client(client)
+        .get_all()
+        .await
+        .context("Unable to list resources")?;
+    Ok(render_metrics(
+        &tests,
+        &resources,
+        TEST_RECONCILE_ERRORS.load(Ordering::Relaxed),
+        RESOURCE_RECONCILE_ERRORS.load(Ordering::Relaxed),
+    ))
+}
+
+/// Render the Prometheus text format metrics for `tests`, `resources` and the number of
+/// reconciliation errors from each controller.
+fn render_metrics(
+    tests: &[Test],
+    resources: &[Resource],
+    test_errors: u64,
+    resource_errors: u64,
+) -> String {
+    let mut text = String::new();
+    // Writing to a `String` cannot fail.
+    let _ = writeln!(
+        text,
+        "# HELP testsys_tests The number of tests by task state."
+    );
+    let _ = writeln!(text, "# TYPE testsys_tests gauge");
+    for state in TaskState::ALL {
+        let count = tests
+            .iter()
+            .filter(|test| test.agent_status().task_state == state)
+            .count();
+        let _ = writeln!(text, "testsys_tests{{task_state=\"{}\"}} {}", state, count);
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP testsys_resources The number of resources by action and task state."
+    );
+    let _ = writeln!(text, "# TYPE testsys_resources gauge");
+    for (action, task_state) in [
+        (
+            "creation",
+            Resource::creation_task_state as fn(&Resource) -> TaskState,
+        ),
+        ("destruction", Resource::destruction_task_state),
+    ] {
+        for state in TaskState::ALL {
+            let count = resources
+                .iter()
+                .filter(|resource| task_state(resource) == state)
+                .count();
+            let _ = writeln!(
+                text,
+                "testsys_resources{{action=\"{}\",task_state=\"{}\"}} {}",
+                action, state, count
+            );
+        }
+    }
+
+    let _ = writeln!(
+        text,
+        "# HELP testsys_reconcile_errors_total The number of reconciliation errors by controller."
+    );
+    let _ = writeln!(text, "# TYPE testsys_reconcile_errors_total counter");
+    let _ = writeln!(
+        text,
+        "testsys_reconcile_errors_total{{controller=\"test\"}} {}",
+        test_errors
+    );
+    let _ = writeln!(
+        text,
+        "testsys_reconcile_errors_total{{controller=\"resource\"}} {}",
+        resource_errors
+    );
+    text
+}
+
+#[test]
+fn test_render_metrics() {
+    use testsys_model::{AgentStatus, ResourceStatus, TestStatus};
+
+    let test = |task_state| Test {
+        status: Some(TestStatus {
+            agent: AgentStatus {
+                task_state,
+                ..AgentStatus::default()
+            },
+            ..TestStatus::default()
+        }),
+        ..Test::default()
+    };
+    let tests = [
+        test(TaskState::Running),
+        test(TaskState::Running),
+        test(TaskState::Completed),
+        Test::default(),
+    ];
+    let mut resource = Resource::default();
+    let mut status = ResourceStatus::default();
+    status.creation.task_state = TaskState::Completed;
+    status.destruction.task_state = TaskState::Running;
+    resource.status = Some(status);
+
+    let text = render_metrics(&tests, &[resource], 3, 0);
+    for line in [
+        "# TYPE testsys_tests gauge",
+        "testsys_tests{task_state=\"unknown\"} 1",
+        "testsys_tests{task_state=\"running\"} 2",
+        "testsys_tests{task_state=\"completed\"} 1",
+        "testsys_tests{task_state=\"error\"} 0",
+        "testsys_resources{action=\"creation\",task_state=\"completed\"} 1",
+        "testsys_resources{action=\"creation\",task_state=\"running\"} 0",
+        "testsys_resources{action=\"destruction\",task_state=\"running\"} 1",
+        "# TYPE testsys_reconcile_errors_total counter",
+        "testsys_reconcile_errors_total{controller=\"test\"} 3",
+        "testsys_reconcile_errors_total{controller=\"resource\"} 0",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "missing '{}' in:\n{}",
+            line,
+            text
+        );
+    }
+}
@@ -89,7 +89,9 @@ async fn creation_action(r: &ResourceInterface) -> Result<CreationAction> {
         TaskState::Unknown => creation_not_done_action(r, false).await,
         TaskState::Running => creation_not_done_action(r, true).await,
         TaskState::Completed => creation_completed_action(r).await,
-        TaskState::Error => Ok(CreationAction::Error(ErrorState::TaskFailed)),
+        TaskState::Error | TaskState::Cancelled => {
+            Ok(CreationAction::Error(ErrorState::TaskFailed))
+        }
     }
 }
 
@@ -228,25 +230,24 @@ async fn is_deletion_required(r: &ResourceInterface) -> Result<bool> {
         }
     }
     let test_client = TestClient::new_from_k8s_client(r.k8s_client());
-    let tests = test_client.get_all().await?;
-    for test in tests {
-        if test.spec().resources.contains(&r.name().to_string()) {
-            match destruction_policy {
-                DestructionPolicy::OnTestCompletion => {
-                    if test.agent_status().task_state != TaskState::Completed {
-                        return Ok(false);
-                    }
-                }
-                DestructionPolicy::OnTestSuccess => {
-                    if test.test_user_state() != TestUserState::Passed {
-                        return Ok(false);
-                    }
-                }
-                _ => (),
-            }
+    let tests: Vec<_> = test_client
+        .get_all()
+        .await?
+        .into_iter()
+        .filter(|test| test.spec().resources.contains(&r.name().to_string()))
+        .collect();
+    match destruction_policy {
+        DestructionPolicy::OnTestSuccess => Ok(tests
+            .iter()
+            .all(|test| test.test_user_state() == TestUserState::Passed)),
+        _ => {
+            let test_states: Vec<_> = tests
+                .iter()
+                .map(|test| test.agent_status().task_state)
+                .collect();
+            Ok(r.resource().should_destroy_now(&test_states))
         }
     }
-    Ok(true)
 }
 
 async fn destruction_action(r: &ResourceInterface) -> Result<DestructionAction> {
@@ -304,7 +305,9 @@ async fn destruction_action_with_resources(r: &ResourceInterface) -> Result<Dest
                 Ok(DestructionAction::RemoveResourceFinalizer)
             }
         }
-        TaskState::Error => Ok(DestructionAction::Error(ErrorState::TaskFailed)),
+        TaskState::Error | TaskState::Cancelled => {
+            Ok(DestructionAction::Error(ErrorState::TaskFailed))
+        }
     }
 }
 
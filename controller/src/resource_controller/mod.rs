@@ -1,8 +1,9 @@
 mod action;
 mod context;
 
-use crate::constants::requeue;
+use crate::constants::{no_requeue, requeue};
 use crate::error::{ReconciliationError, ReconciliationResult, Result};
+use crate::metrics::record_resource_reconcile_error;
 use crate::resource_controller::action::{
     action, Action, CreationAction, DestructionAction, ErrorState,
 };
@@ -16,15 +17,14 @@ use log::{debug, error, trace, warn};
 use std::ops::Deref;
 use std::sync::Arc;
 use testsys_model::clients::CrdClient;
-use testsys_model::constants::{
-    FINALIZER_CREATION_JOB, FINALIZER_MAIN, FINALIZER_RESOURCE, NAMESPACE,
-};
+use testsys_model::constants::{FINALIZER_CREATION_JOB, FINALIZER_MAIN, FINALIZER_RESOURCE};
+use testsys_model::system::default_namespace;
 use testsys_model::{CrdExt, ErrorResources, Resource, ResourceAction, ResourceError};
 
 pub(crate) async fn run_resource_controller(client: Client) {
     let context = new_context(client.clone());
     Controller::new(
-        Api::<Resource>::namespaced(client, NAMESPACE),
+        Api::<Resource>::namespaced(client, &default_namespace()),
         watcher::Config::default(),
     )
     .run(reconcile, handle_reconciliation_error, context)
@@ -46,6 +46,10 @@ pub(super) async fn reconcile(
     r: Arc<Resource>,
     ctx: Context,
 ) -> ReconciliationResult<RequeueAction> {
+    if r.is_imported() {
+        trace!("Ignoring imported resource: {}", r.object_name());
+        return Ok(no_requeue());
+    }
     let interface = ResourceInterface::new(r.deref().clone(), ctx)?;
     trace!(
         "Reconciling resource: {}",
@@ -68,7 +72,7 @@ async fn do_creation_action(r: ResourceInterface, action: CreationAction) -> Res
         CreationAction::Initialize => {
             let _ = r
                 .resource_client()
-                .initialize_status(r.resource().object_name())
+                .initialize_status_if_needed(r.resource().object_name())
                 .await
                 .with_context(|| format!("Unable to initialize '{}'", r.name()))?;
         }
@@ -217,5 +221,34 @@ pub(crate) fn handle_reconciliation_error(
     _: Context,
 ) -> RequeueAction {
     error!("Resource reconciliation error: {}", e);
+    record_resource_reconcile_error();
     requeue()
 }
+
+#[tokio::test]
+async fn test_imported_resource_is_ignored() {
+    use testsys_model::constants::ANNOTATION_IMPORTED;
+    use testsys_model::ResourceSpec;
+
+    // Every request to the k8s API fails, so reconciling a resource that the controller acts on
+    // returns an error.
+    let service = tower::service_fn(|_: http::Request<hyper::Body>| async {
+        Err::<http::Response<hyper::Body>, _>(std::io::Error::other("unexpected request"))
+    });
+    let context = new_context(Client::new(service, default_namespace()));
+    let resource = testsys_model::create_resource_crd("my-cluster", None, ResourceSpec::default());
+    assert!(reconcile(Arc::new(resource.clone()), context.clone())
+        .await
+        .is_err());
+
+    let mut imported = resource;
+    imported.metadata.annotations = Some(
+        [(ANNOTATION_IMPORTED.to_string(), "true".to_string())]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(
+        reconcile(Arc::new(imported), context).await.ok(),
+        Some(no_requeue())
+    );
+}
@@ -5,27 +5,42 @@ use crate::utils::parse_duration;
 use anyhow::Context;
 use kube::{Api, ResourceExt};
 use log::trace;
+use std::collections::BTreeSet;
 use std::fmt::{Display, Formatter};
 use testsys_model::clients::{CrdClient, HttpStatusCode, StatusCode};
-use testsys_model::constants::{FINALIZER_MAIN, FINALIZER_TEST_JOB, NAMESPACE};
-use testsys_model::{CrdExt, Outcome, Resource, ResourceAction, TaskState};
+use testsys_model::constants::{FINALIZER_MAIN, FINALIZER_TEST_JOB};
+use testsys_model::system::default_namespace;
+use testsys_model::{
+    next_tests_to_start, Clock, CrdExt, Outcome, Resource, ResourceAction, SystemClock, TaskState,
+    Test,
+};
 
 // These values configure how long to delay between tries.
 const MAX_RETRIES: u32 = 3;
 const BACKOFF_MS: u64 = 1000;
 
+/// A running test agent that has not sent a heartbeat for this long is considered dead.
+const AGENT_HEARTBEAT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
 /// The action that the controller needs to take in order to reconcile the `Test`.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(super) enum Action {
     Initialize,
     AddMainFinalizer,
+    Skip(String),
     WaitForResources,
     RegisterResourceCreationError(String),
     WaitForDependency(String),
+    WaitForAgentSlot,
     AddJobFinalizer,
     StartTest,
     WaitForTest,
     DeleteJob,
+    DeleteJobForRetry,
+    AcknowledgeRetry(u32),
+    Cancel,
+    AutomaticRetry,
+    WaitForAutomaticRetry,
     RemoveJobFinalizer,
     RemoveMainFinalizer,
     TestDone,
@@ -41,6 +56,7 @@ pub(super) enum ErrorState {
     JobStart,
     JobExitBeforeDone,
     JobTimeout,
+    AgentStale,
     HandleJobRemovedBeforeDone,
 }
 
@@ -61,6 +77,10 @@ impl Display for ErrorState {
             ErrorState::JobTimeout => {
                 Display::fmt("The test agent did not finish within the specified time", f)
             }
+            ErrorState::AgentStale => Display::fmt(
+                "The test agent stopped sending heartbeats and is presumed dead",
+                f,
+            ),
             ErrorState::HandleJobRemovedBeforeDone => {
                 Display::fmt("The job was removed before the test completed", f)
             }
@@ -82,11 +102,38 @@ pub(super) async fn determine_action(t: &TestInterface) -> Result<Action> {
         return Ok(Action::AddMainFinalizer);
     }
 
+    if let Some(action) = retry_action(t).await? {
+        return Ok(action);
+    }
+
+    if t.test().spec.agent.cancel_requested
+        && matches!(
+            t.test().agent_status().task_state,
+            TaskState::Unknown | TaskState::Running
+        )
+    {
+        return Ok(Action::Cancel);
+    }
+
+    if let Some(retry_at) = t.test().next_automatic_retry() {
+        return Ok(if retry_at <= SystemClock.now() {
+            Action::AutomaticRetry
+        } else {
+            Action::WaitForAutomaticRetry
+        });
+    }
+
     let agent_status = t.test().agent_status();
     match agent_status.task_state {
+        TaskState::Unknown if t.test().spec.should_skip(t.test().labels()) => {
+            Ok(Action::Skip(format!(
+                "The skip_if expression '{}' matched",
+                t.test().spec.skip_if.as_deref().unwrap_or_default()
+            )))
+        }
         TaskState::Unknown => task_not_done_action(t, false).await,
         TaskState::Running => task_not_done_action(t, true).await,
-        TaskState::Completed => Ok(Action::TestDone),
+        TaskState::Completed | TaskState::Cancelled => Ok(Action::TestDone),
         TaskState::Error => Ok(Action::Error(ErrorState::TestError(
             t.test().agent_error().unwrap_or("Unknown error").to_owned(),
         ))),
@@ -114,6 +161,25 @@ pub(super) async fn determine_delete_action(t: &TestInterface) -> Result<Action>
     }
 }
 
+/// Determines whether the user has requested that the test be run again in place, in which case
+/// the previous test agent job must be removed before the retry can be acknowledged.
+async fn retry_action(t: &TestInterface) -> Result<Option<Action>> {
+    let retry_count = t.test().spec.retry_count.unwrap_or_default();
+    let observed_retry_count = t
+        .test()
+        .status
+        .as_ref()
+        .and_then(|status| status.controller.observed_retry_count)
+        .unwrap_or_default();
+    if retry_count == observed_retry_count {
+        return Ok(None);
+    }
+    match t.get_job_state().await? {
+        JobState::None => Ok(Some(Action::AcknowledgeRetry(retry_count))),
+        _ => Ok(Some(Action::DeleteJobForRetry)),
+    }
+}
+
 enum Resources {
     NotReady,
     Ready,
@@ -124,7 +190,7 @@ async fn resource_readiness(t: &TestInterface) -> Result<Resources> {
     if t.test().spec.resources.is_empty() {
         return Ok(Resources::Ready);
     }
-    let resource_client: Api<Resource> = Api::namespaced(t.k8s_client(), NAMESPACE);
+    let resource_client: Api<Resource> = Api::namespaced(t.k8s_client(), &default_namespace());
     let resources_names = &t.test().spec.resources;
     for resource_name in resources_names {
         let result = resource_client.get(resource_name).await;
@@ -159,7 +225,7 @@ async fn resource_readiness(t: &TestInterface) -> Result<Resources> {
         match resource.task_state(ResourceAction::Create) {
             TaskState::Unknown | TaskState::Running => return Ok(Resources::NotReady),
             TaskState::Completed => continue,
-            TaskState::Error => {
+            TaskState::Error | TaskState::Cancelled => {
                 return Ok(Resources::Error(format!(
                     "Creation of resource '{}' failed",
                     resource_name
@@ -201,6 +267,48 @@ async fn dependency_wait_action(t: &TestInterface) -> Result<Option<Action>> {
     Ok(None)
 }
 
+/// Returns `StartTest` if `t` is among the waiting tests that fill the free test agent slots, see
+/// [`next_tests_to_start`]. Every reconcile chooses from the same waiting tests, so tests start in
+/// priority order and concurrent reconciles do not start more test agents than the slots allow.
+async fn agent_slot_action(t: &TestInterface) -> Result<Action> {
+    let cap = match t.max_concurrent_agents() {
+        None => return Ok(Action::StartTest),
+        Some(cap) => cap,
+    };
+    let active = t.active_test_agents().await?;
+    let waiting: Vec<Test> = t
+        .test_client()
+        .get_all()
+        .await
+        .context("Unable to list the tests that are waiting for a test agent")?
+        .into_iter()
+        .filter(|test| is_waiting_for_agent(test, &active))
+        .collect();
+    let selected = next_tests_to_start(active.len(), cap, &waiting);
+    if !selected.iter().any(|test| test.name_any() == t.name()) {
+        trace!(
+            "Test '{}' is waiting because {} of {} test agents are running and {} tests are waiting",
+            t.name(),
+            active.len(),
+            cap,
+            waiting.len()
+        );
+        return Ok(Action::WaitForAgentSlot);
+    }
+    Ok(Action::StartTest)
+}
+
+/// Returns `true` if `test` has not started and its test agent has not been created, so it needs a
+/// test agent slot before it can start.
+fn is_waiting_for_agent(test: &Test, active: &BTreeSet<String>) -> bool {
+    test.status.is_some()
+        && !test.is_delete_requested()
+        && !test.spec.agent.cancel_requested
+        && test.agent_status().task_state == TaskState::Unknown
+        && !test.spec.should_skip(test.labels())
+        && !active.contains(&test.name_any())
+}
+
 async fn task_not_done_action(t: &TestInterface, is_task_state_running: bool) -> Result<Action> {
     if !is_task_state_running && !t.test().has_finalizer(FINALIZER_TEST_JOB) {
         return Ok(Action::AddJobFinalizer);
@@ -216,15 +324,19 @@ async fn task_not_done_action(t: &TestInterface, is_task_state_running: bool) ->
                     Ok(Action::Error(ErrorState::ResourceErrorExists(s)))
                 }
             }
-            Resources::Ready => Ok(dependency_wait_action(t)
-                .await?
-                .unwrap_or(Action::StartTest)),
+            Resources::Ready => match dependency_wait_action(t).await? {
+                Some(action) => Ok(action),
+                None => agent_slot_action(t).await,
+            },
         },
         JobState::None => Ok(Action::Error(ErrorState::HandleJobRemovedBeforeDone)),
         JobState::Unknown => {
             trace!("Waiting for test agent '{}' container to start", t.name());
             Ok(Action::WaitForTest)
         }
+        JobState::Running(_) if is_task_state_running && is_heartbeat_stale(t) => {
+            Ok(Action::Error(ErrorState::AgentStale))
+        }
         JobState::Running(None) => {
             trace!("Test '{}' is running", t.name());
             Ok(Action::WaitForTest)
@@ -259,3 +371,11 @@ async fn task_not_done_action(t: &TestInterface, is_task_state_running: bool) ->
         JobState::Exited => Ok(Action::Error(ErrorState::JobExitBeforeDone)),
     }
 }
+
+/// Returns `true` if the test agent has reported a heartbeat but has not sent one recently. Test
+/// agents that have never sent a heartbeat are not considered stale.
+fn is_heartbeat_stale(t: &TestInterface) -> bool {
+    t.test()
+        .agent_status()
+        .is_heartbeat_stale(&SystemClock, AGENT_HEARTBEAT_TIMEOUT)
+}
@@ -1,9 +1,12 @@
 use crate::error::Result;
-use crate::job::{delete_job, get_job_state, JobState};
+use crate::job::{active_test_agents, delete_job, get_job_state, JobState};
 use anyhow::Context as AnyhowContext;
 use kube::{Api, Client};
+use log::warn;
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use testsys_model::clients::{CrdClient, TestClient};
+use testsys_model::constants::ENV_MAX_CONCURRENT_AGENTS;
 use testsys_model::Test;
 
 /// This is used by `kube-runtime` to pass any custom information we need when [`reconcile`] is
@@ -13,13 +16,32 @@ pub(crate) type Context = Arc<ContextData>;
 pub(crate) fn new_context(client: Client) -> Context {
     Arc::new(ContextData {
         test_client: TestClient::new_from_k8s_client(client),
+        max_concurrent_agents: max_concurrent_agents(),
     })
 }
 
+/// Read the maximum number of test agents that may run at once from the environment. An invalid
+/// value is ignored so that the controller can still run tests.
+fn max_concurrent_agents() -> Option<usize> {
+    let value = std::env::var(ENV_MAX_CONCURRENT_AGENTS).ok()?;
+    match value.parse() {
+        Ok(max_concurrent_agents) => Some(max_concurrent_agents),
+        Err(e) => {
+            warn!(
+                "Ignoring invalid {} '{}': {}",
+                ENV_MAX_CONCURRENT_AGENTS, value, e
+            );
+            None
+        }
+    }
+}
+
 /// This type is wrapped by [`kube::Context`] and contains information we need during [`reconcile`].
 #[derive(Clone)]
 pub(crate) struct ContextData {
     test_client: TestClient,
+    /// The maximum number of test agents that may run at once, if any.
+    max_concurrent_agents: Option<usize>,
 }
 
 impl ContextData {
@@ -65,6 +87,10 @@ impl TestInterface {
         self.context.api()
     }
 
+    pub(super) fn max_concurrent_agents(&self) -> Option<usize> {
+        self.context.max_concurrent_agents
+    }
+
     /// Access the inner `TestClient` object with fewer keystrokes.
     pub(super) fn test_client(&self) -> &TestClient {
         &self.context.test_client
@@ -76,6 +102,12 @@ impl TestInterface {
             .with_context(|| format!("Unable to get job state for test '{}'", self.name()))
     }
 
+    pub(super) async fn active_test_agents(&self) -> Result<BTreeSet<String>> {
+        active_test_agents(self.k8s_client())
+            .await
+            .context("Unable to list the running test agents")
+    }
+
     pub(super) async fn delete_job(&self) -> Result<()> {
         delete_job(self.k8s_client(), self.name())
             .await
@@ -1,5 +1,6 @@
 use crate::constants::requeue;
 use crate::error::ReconciliationError;
+use crate::metrics::record_test_reconcile_error;
 use crate::test_controller::context::{new_context, Context};
 use crate::test_controller::reconcile::reconcile;
 use futures::StreamExt;
@@ -33,5 +34,6 @@ pub(super) async fn run_test_controller(client: kube::Client) {
 /// `handle_reconciliation_error` is called when `reconcile` returns an error.
 fn handle_reconciliation_error(_: Arc<Test>, e: &ReconciliationError, _: Context) -> RequeueAction {
     error!("Reconciliation error: {}", e);
+    record_test_reconcile_error();
     requeue()
 }
@@ -1,7 +1,7 @@
 use crate::constants::{no_requeue, requeue, requeue_slow};
 use crate::error::{ReconciliationResult, Result};
 use crate::job::{JobBuilder, JobType};
-use crate::test_controller::action::{determine_action, Action};
+use crate::test_controller::action::{determine_action, Action, ErrorState};
 use crate::test_controller::context::{Context, TestInterface};
 use anyhow::Context as AnyhowContext;
 use kube_runtime::controller::Action as RequeueAction;
@@ -10,7 +10,7 @@ use std::ops::Deref;
 use std::sync::Arc;
 use testsys_model::clients::CrdClient;
 use testsys_model::constants::{ENV_TEST_NAME, FINALIZER_MAIN, FINALIZER_TEST_JOB};
-use testsys_model::{TaskState, Test};
+use testsys_model::{CrdExt, TaskState, Test};
 
 /// `reconcile` is called when a new `Test` object arrives, or when a `Test` object has been
 /// re-queued. This is the entrypoint to the controller logic.
@@ -18,13 +18,17 @@ pub(crate) async fn reconcile(
     t: Arc<Test>,
     context: Context,
 ) -> ReconciliationResult<RequeueAction> {
+    if t.is_imported() {
+        trace!("Ignoring imported test: {}", t.object_name());
+        return Ok(no_requeue());
+    }
     let mut t = TestInterface::new(t.deref().clone(), context)?;
     let action = determine_action(&t).await?;
     trace!("action {:?}", action);
     match action {
         Action::Initialize => {
             t.test_client()
-                .initialize_status(t.name())
+                .initialize_status_if_needed(t.name())
                 .await
                 .context(format!("Unable to initialize status for '{}'", t.name()))?;
             Ok(requeue())
@@ -37,6 +41,14 @@ pub(crate) async fn reconcile(
                 .context(format!("Unable to add main finalizer for '{}'", t.name()))?;
             Ok(requeue())
         }
+        Action::Skip(reason) => {
+            debug!("Skipping test '{}': {}", t.name(), reason);
+            t.test_client()
+                .mark_skipped(t.name(), &reason)
+                .await
+                .context(format!("Unable to mark '{}' as skipped", t.name()))?;
+            Ok(requeue())
+        }
         Action::WaitForResources => Ok(requeue()),
         Action::RegisterResourceCreationError(msg) => {
             t.test_client()
@@ -50,6 +62,7 @@ pub(crate) async fn reconcile(
             Ok(requeue())
         }
         Action::WaitForDependency(_) => Ok(requeue()),
+        Action::WaitForAgentSlot => Ok(requeue()),
         Action::AddJobFinalizer => {
             t.test_client()
                 .add_finalizer(FINALIZER_TEST_JOB, t.test())
@@ -66,6 +79,43 @@ pub(crate) async fn reconcile(
             t.delete_job().await?;
             Ok(requeue())
         }
+        Action::DeleteJobForRetry => {
+            debug!(
+                "Deleting the previous job for test '{}' before retrying",
+                t.name()
+            );
+            t.delete_job().await?;
+            Ok(requeue())
+        }
+        Action::AcknowledgeRetry(retry_count) => {
+            t.test_client()
+                .send_retry_observed(t.name(), retry_count)
+                .await
+                .context(format!(
+                    "Unable to acknowledge retry {} for '{}'",
+                    retry_count,
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::Cancel => {
+            debug!("Cancelling test '{}'", t.name());
+            t.delete_job().await?;
+            t.test_client()
+                .send_cancelled(t.name())
+                .await
+                .context(format!("Unable to cancel '{}'", t.name()))?;
+            Ok(requeue())
+        }
+        Action::AutomaticRetry => {
+            debug!("Retrying test '{}' because of its retry policy", t.name());
+            t.test_client()
+                .retry(t.name())
+                .await
+                .context(format!("Unable to retry '{}'", t.name()))?;
+            Ok(requeue())
+        }
+        Action::WaitForAutomaticRetry => Ok(requeue()),
         Action::RemoveJobFinalizer => {
             t.test_client()
                 .remove_finalizer(FINALIZER_TEST_JOB, t.test())
@@ -90,7 +140,7 @@ pub(crate) async fn reconcile(
         Action::Error(state) => {
             error!("Error state for test '{}': {}", t.name(), state);
             t.test_client()
-                .send_agent_task_state(t.name(), TaskState::Error)
+                .send_agent_task_state_forced(t.name(), TaskState::Error)
                 .await
                 .context(format!(
                     "Unable to send error task state for '{}'",
@@ -100,6 +150,12 @@ pub(crate) async fn reconcile(
                 .send_agent_error(t.name(), &state.to_string())
                 .await
                 .context(format!("Unable to send error message for '{}'", t.name()))?;
+            if state == ErrorState::JobTimeout {
+                t.test_client()
+                    .send_timed_out(t.name())
+                    .await
+                    .context(format!("Unable to mark '{}' as timed out", t.name()))?;
+            }
             Ok(requeue_slow())
         }
     }
@@ -1,8 +1,11 @@
 use crate::error::{self, Error, Result};
+use k8s_openapi::api::core::v1::{Container, Toleration};
 use k8s_openapi::serde::Deserializer;
 use regex::Regex;
 use schemars::gen::SchemaGenerator;
-use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::schema::{
+    ArrayValidation, InstanceType, ObjectValidation, Schema, SchemaObject, StringValidation,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
@@ -16,13 +19,17 @@ use std::str::FromStr;
 
 /// The states that an agent declares about its task (e.g. running tests or creating/destroying
 /// resources).
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[derive(
+    Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, JsonSchema,
+)]
 #[serde(rename_all = "camelCase")]
 pub enum TaskState {
     Unknown,
     Running,
     Completed,
     Error,
+    /// The task was stopped before it finished because cancellation was requested.
+    Cancelled,
 }
 
 impl Default for TaskState {
@@ -33,7 +40,37 @@ impl Default for TaskState {
 
 serde_plain::derive_display_from_serialize!(TaskState);
 
-#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+impl TaskState {
+    /// Every task state, e.g. for reporting a count for each state even when it is zero.
+    pub const ALL: [TaskState; 5] = [
+        TaskState::Unknown,
+        TaskState::Running,
+        TaskState::Completed,
+        TaskState::Error,
+        TaskState::Cancelled,
+    ];
+
+    /// Returns `true` if an agent's task is allowed to move from this state to `next`. A task
+    /// starts as `Unknown`, moves to `Running`, and finishes as `Completed` or `Error`. It can be
+    /// `Cancelled` or fail with an `Error` before it finishes running. Finished tasks never change
+    /// state, re-running a finished task is done by retrying it. Setting the current state again
+    /// is always allowed.
+    pub fn can_transition_to(&self, next: TaskState) -> bool {
+        match (self, next) {
+            (current, next) if *current == next => true,
+            (TaskState::Unknown, TaskState::Running | TaskState::Error | TaskState::Cancelled) => {
+                true
+            }
+            (
+                TaskState::Running,
+                TaskState::Completed | TaskState::Error | TaskState::Cancelled,
+            ) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Agent {
     /// The name of the agent.
@@ -44,7 +81,16 @@ pub struct Agent {
     pub pull_secret: Option<String>,
     /// Determine if the pod should keep running after it has finished or encountered and error.
     pub keep_running: bool,
-    /// The maximum amount of time an agent should be left to run.
+    /// The number of seconds after which `keep_running` should no longer be honored. This is set
+    /// by `TestClient::send_keep_running_for`.
+    pub keep_running_timeout_seconds: Option<u64>,
+    /// Set when the user has asked for the agent to stop its task. Agents should poll this, clean
+    /// up and exit.
+    #[serde(default)]
+    pub cancel_requested: bool,
+    /// The maximum amount of time an agent should be left to run, e.g. `30m` or `1h30m`. The
+    /// controller marks a test that runs for longer as timed out. This can be set with
+    /// `TestClient::set_timeout`.
     #[schemars(schema_with = "timeout_schema")]
     pub timeout: Option<String>,
     /// The configuration to pass to the agent. This is 'open' to allow agents to define their own
@@ -59,6 +105,36 @@ pub struct Agent {
     pub capabilities: Option<Vec<String>>,
     /// Whether the agent container needs to be privileged or not
     pub privileged: Option<bool>,
+    /// Extra environment variables for the agent container, e.g. `HTTP_PROXY`. Variables that the
+    /// controller sets for the agent, e.g. `TESTSYS_TEST_NAME`, cannot be overridden. This is set by
+    /// `TestClient::set_env`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Labels that a node must have for the agent pod to be scheduled on it, e.g.
+    /// `nvidia.com/gpu.present: "true"`. This is set by `TestClient::set_node_selector`.
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+    /// Tolerations for the agent pod, e.g. to allow it to be scheduled on tainted GPU nodes. This
+    /// is set by `TestClient::set_tolerations`.
+    #[serde(default)]
+    #[schemars(schema_with = "tolerations_schema")]
+    pub tolerations: Vec<Toleration>,
+    /// Containers that the controller runs before the agent container, e.g. to pull test assets.
+    /// Each must have a name and an image. This is set by `TestClient::set_init_containers`.
+    #[serde(default)]
+    #[schemars(schema_with = "init_containers_schema")]
+    pub init_containers: Vec<Container>,
+    /// Containers that the controller runs alongside the agent container, e.g. to ship its logs.
+    /// Each must have a name and an image, and the names must not collide with the agent
+    /// container or the init containers. This is set by `TestClient::set_sidecars`.
+    #[serde(default)]
+    #[schemars(schema_with = "init_containers_schema")]
+    pub sidecars: Vec<Container>,
+    /// The names of the test cases that the agent should run instead of all of them, e.g. the
+    /// cases that failed in the previous run. Every case is run if this is empty. This is set by
+    /// `TestClient::rerun_failed_cases_only`.
+    #[serde(default)]
+    pub rerun_cases: Vec<String>,
 }
 
 impl Agent {
@@ -85,6 +161,85 @@ pub fn config_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema
     schema.into()
 }
 
+/// The schema of the agent's init containers and sidecars. `Container` does not implement
+/// `JsonSchema`, so only the fields that every container needs are described and the others are
+/// preserved.
+pub fn init_containers_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    let mut extensions = BTreeMap::<String, Value>::new();
+    extensions.insert(
+        "x-kubernetes-preserve-unknown-fields".to_string(),
+        Value::Bool(true),
+    );
+    let container = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            required: ["name", "image"].iter().map(|s| s.to_string()).collect(),
+            properties: ["name", "image"]
+                .iter()
+                .map(|field| {
+                    (
+                        field.to_string(),
+                        SchemaObject {
+                            instance_type: Some(InstanceType::String.into()),
+                            ..SchemaObject::default()
+                        }
+                        .into(),
+                    )
+                })
+                .collect(),
+            ..ObjectValidation::default()
+        })),
+        extensions,
+        ..SchemaObject::default()
+    };
+    SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(Schema::from(container).into()),
+            ..ArrayValidation::default()
+        })),
+        ..SchemaObject::default()
+    }
+    .into()
+}
+
+/// The schema of the agent's tolerations. `Toleration` does not implement `JsonSchema`, so its
+/// fields are described here.
+pub fn tolerations_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    let property = |instance_type: InstanceType| {
+        Schema::from(SchemaObject {
+            instance_type: Some(instance_type.into()),
+            ..SchemaObject::default()
+        })
+    };
+    let toleration = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            properties: [
+                ("effect", property(InstanceType::String)),
+                ("key", property(InstanceType::String)),
+                ("operator", property(InstanceType::String)),
+                ("tolerationSeconds", property(InstanceType::Integer)),
+                ("value", property(InstanceType::String)),
+            ]
+            .into_iter()
+            .map(|(field, schema)| (field.to_string(), schema))
+            .collect(),
+            ..ObjectValidation::default()
+        })),
+        ..SchemaObject::default()
+    };
+    SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(Schema::from(toleration).into()),
+            ..ArrayValidation::default()
+        })),
+        ..SchemaObject::default()
+    }
+    .into()
+}
+
 pub fn timeout_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
     let mut extensions = BTreeMap::<String, Value>::new();
     extensions.insert("nullable".to_string(), Value::Bool(true));
@@ -295,3 +450,137 @@ fn secret_name_deserialize() {
     let deserialized = serde_json::from_value::<Something>(good_json).unwrap();
     assert_eq!(deserialized.foo.as_str(), "bar-baz");
 }
+
+#[test]
+fn task_state_serde() {
+    for task_state in [
+        TaskState::Unknown,
+        TaskState::Running,
+        TaskState::Completed,
+        TaskState::Error,
+        TaskState::Cancelled,
+    ] {
+        let serialized = serde_json::to_value(task_state).unwrap();
+        assert_eq!(serialized, serde_json::json!(task_state.to_string()));
+        assert_eq!(
+            serde_json::from_value::<TaskState>(serialized).unwrap(),
+            task_state
+        );
+    }
+    assert_eq!(TaskState::Cancelled.to_string(), "cancelled");
+}
+
+/// The JSON of an agent with only the required fields, merged with `fields`.
+#[cfg(test)]
+fn minimal_agent(fields: Value) -> Value {
+    let mut agent = serde_json::json!({
+        "name": "my-agent",
+        "image": "foo:v0.1.0",
+        "keepRunning": false
+    });
+    if let (Some(agent), Value::Object(fields)) = (agent.as_object_mut(), fields) {
+        agent.extend(fields);
+    }
+    agent
+}
+
+#[test]
+fn agent_optional_fields_serde() {
+    // Agents written before the optional fields existed have none of them.
+    let agent: Agent = serde_json::from_value(minimal_agent(serde_json::json!({}))).unwrap();
+    assert!(agent.env.is_empty());
+    assert!(agent.node_selector.is_empty());
+    assert!(agent.tolerations.is_empty());
+    assert!(agent.init_containers.is_empty());
+    assert!(agent.sidecars.is_empty());
+    assert!(agent.secrets.is_none());
+
+    for (field, value) in [
+        (
+            "env",
+            serde_json::json!({ "HTTP_PROXY": "http://proxy:3128", "NO_PROXY": "localhost" }),
+        ),
+        (
+            "nodeSelector",
+            serde_json::json!({ "nvidia.com/gpu.present": "true" }),
+        ),
+        (
+            "tolerations",
+            serde_json::json!([
+                { "key": "nvidia.com/gpu", "operator": "Exists", "effect": "NoSchedule" }
+            ]),
+        ),
+        (
+            "initContainers",
+            serde_json::json!([{
+                "name": "fetch-assets",
+                "image": "fetcher:v1",
+                "args": ["s3://bucket/assets", "/assets"]
+            }]),
+        ),
+        (
+            "sidecars",
+            serde_json::json!([{
+                "name": "fluent-bit",
+                "image": "fluent/fluent-bit:2.1",
+                "volumeMounts": [{ "name": "logs", "mountPath": "/var/log/agent" }]
+            }]),
+        ),
+        (
+            "secrets",
+            serde_json::json!({ "aws-credentials": "my-aws-secret" }),
+        ),
+    ] {
+        let agent: Agent =
+            serde_json::from_value(minimal_agent(serde_json::json!({ field: value.clone() })))
+                .unwrap();
+        let serialized = serde_json::to_value(&agent).unwrap();
+        assert_eq!(serialized[field], value, "{}", field);
+        assert_eq!(serde_json::from_value::<Agent>(serialized).unwrap(), agent);
+    }
+
+    assert!(
+        serde_json::from_value::<Agent>(minimal_agent(serde_json::json!({
+            "secrets": { "aws-credentials": "bad/name" }
+        })))
+        .is_err()
+    );
+}
+
+#[test]
+fn task_state_transitions() {
+    use TaskState::*;
+    let states = [Unknown, Running, Completed, Error, Cancelled];
+    let allowed = [
+        (Unknown, Unknown),
+        (Unknown, Running),
+        (Unknown, Error),
+        (Unknown, Cancelled),
+        (Running, Running),
+        (Running, Completed),
+        (Running, Error),
+        (Running, Cancelled),
+        (Completed, Completed),
+        (Error, Error),
+        (Cancelled, Cancelled),
+    ];
+    for current in states {
+        for next in states {
+            assert_eq!(
+                current.can_transition_to(next),
+                allowed.contains(&(current, next)),
+                "{} -> {}",
+                current,
+                next
+            );
+        }
+    }
+}
+
+#[test]
+fn tolerations_schema_describes_toleration() {
+    let schema = serde_json::to_value(schemars::schema_for!(Agent)).unwrap();
+    let toleration = &schema["properties"]["tolerations"]["items"]["properties"];
+    assert_eq!(toleration["tolerationSeconds"]["type"], "integer");
+    assert_eq!(toleration["key"]["type"], "string");
+}
@@ -0,0 +1,216 @@
+use super::error::{self, Result};
+use crate::clients::crd_client::JsonPatch;
+use crate::clients::{CrdClient, ResourceClient, TestClient};
+use crate::constants::ANNOTATION_IMPORTED;
+use crate::{Resource, Test};
+use kube::core::ObjectMeta;
+use kube::ResourceExt;
+use serde::{Deserialize, Serialize};
+use snafu::ensure;
+use std::collections::BTreeSet;
+
+/// The version of the [`TestBundle`] format written by `export_bundle`. Bundles with a newer
+/// version are rejected by `import_bundle`.
+pub const TEST_BUNDLE_VERSION: u32 = 1;
+
+/// A self-contained, serializable copy of a TestSys [`Test`] and the [`Resource`]s it depends on,
+/// including their statuses, e.g. for archiving the results of a CI run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TestBundle {
+    /// The version of the bundle format, see [`TEST_BUNDLE_VERSION`].
+    pub version: u32,
+    /// The test, including its status.
+    pub test: Test,
+    /// The resources used by the test and the resources they depend on, including their statuses.
+    pub resources: Vec<Resource>,
+}
+
+impl TestClient {
+    /// Export the TestSys [`Test`] `name`, the [`Resource`]s it uses and the resources they depend
+    /// on into a [`TestBundle`].
+    pub async fn export_bundle(&self, name: &str) -> Result<TestBundle> {
+        let test = self.get(name).await?;
+        let resource_client = self.resource_client();
+        let mut to_visit = test.spec.resources.clone();
+        let mut visited = BTreeSet::new();
+        let mut resources = Vec::new();
+        while let Some(resource_name) = to_visit.pop() {
+            if !visited.insert(resource_name.clone()) {
+                continue;
+            }
+            let resource = resource_client.get(&resource_name).await?;
+            to_visit.extend(resource.spec.depends_on.clone().unwrap_or_default());
+            resources.push(resource);
+        }
+        Ok(TestBundle {
+            version: TEST_BUNDLE_VERSION,
+            test,
+            resources,
+        })
+    }
+
+    /// Create the [`Test`] and [`Resource`]s in `bundle` in this client's namespace and restore
+    /// their statuses. The objects are created with the names, labels and annotations they were
+    /// exported with, and are annotated with `ANNOTATION_IMPORTED` so that the controllers leave
+    /// them alone, i.e. no agents are run for them and deleting them does not destroy the
+    /// resources that were created in another cluster. Returns an error if the bundle was written
+    /// by a newer version of TestSys or if any of the objects already exist, in which case the
+    /// objects that had already been imported are deleted.
+    pub async fn import_bundle(&self, bundle: TestBundle) -> Result<Test> {
+        ensure!(
+            bundle.version <= TEST_BUNDLE_VERSION,
+            error::UnsupportedBundleVersionSnafu {
+                version: bundle.version
+            }
+        );
+        let resource_client = self.resource_client();
+        let mut resources = Vec::new();
+        for resource in bundle.resources {
+            let created = resource_client
+                .create(Resource {
+                    metadata: imported_metadata(&resource.metadata),
+                    spec: resource.spec,
+                    status: None,
+                })
+                .await;
+            let name = match created {
+                Ok(created) => created.name_any(),
+                Err(e) => return Err(self.roll_back_import(e, None, &resources).await),
+            };
+            resources.push(name.clone());
+            if let Some(status) = resource.status {
+                let restored = resource_client
+                    .patch_status(
+                        name,
+                        vec![JsonPatch::new_add_operation("/status", status)],
+                        "restore status",
+                    )
+                    .await;
+                if let Err(e) = restored {
+                    return Err(self.roll_back_import(e, None, &resources).await);
+                }
+            }
+        }
+        let status = bundle.test.status.clone();
+        let test = match self
+            .create(Test {
+                metadata: imported_metadata(&bundle.test.metadata),
+                spec: bundle.test.spec,
+                status: None,
+            })
+            .await
+        {
+            Ok(test) => test,
+            Err(e) => return Err(self.roll_back_import(e, None, &resources).await),
+        };
+        let status = match status {
+            Some(status) => status,
+            None => return Ok(test),
+        };
+        let restored = self
+            .patch_status(
+                test.name_any(),
+                vec![JsonPatch::new_add_operation("/status", status)],
+                "restore status",
+            )
+            .await;
+        match restored {
+            Ok(test) => Ok(test),
+            Err(e) => Err(self
+                .roll_back_import(e, Some(&test.name_any()), &resources)
+                .await),
+        }
+    }
+
+    /// Delete the `test` and `resources` that were created by a failed `import_bundle` and return
+    /// an error for the `source` of the failure that lists any objects that could not be deleted.
+    async fn roll_back_import(
+        &self,
+        source: error::Error,
+        test: Option<&str>,
+        resources: &[String],
+    ) -> error::Error {
+        let mut left_behind = Vec::new();
+        if let Some(test) = test {
+            if self.delete(test).await.is_err() {
+                left_behind.push(format!("test '{}'", test));
+            }
+        }
+        let resource_client = self.resource_client();
+        for resource in resources.iter().rev() {
+            if resource_client.delete(resource).await.is_err() {
+                left_behind.push(format!("resource '{}'", resource));
+            }
+        }
+        error::InnerError::BundleImport {
+            source,
+            left_behind,
+        }
+        .into()
+    }
+
+    /// A [`ResourceClient`] for the namespace that this client uses.
+    fn resource_client(&self) -> ResourceClient {
+        ResourceClient::new_from_k8s_client_with_namespace(
+            self.api().clone().into_client(),
+            self.namespace(),
+        )
+    }
+}
+
+/// The metadata for an imported object. Only the name, labels and annotations are kept, the other
+/// fields are specific to the cluster and namespace that the object was exported from. The
+/// `ANNOTATION_IMPORTED` annotation is added so that the controllers ignore the object.
+fn imported_metadata(metadata: &ObjectMeta) -> ObjectMeta {
+    let mut annotations = metadata.annotations.clone().unwrap_or_default();
+    annotations.insert(ANNOTATION_IMPORTED.to_string(), "true".to_string());
+    ObjectMeta {
+        name: metadata.name.clone(),
+        labels: metadata.labels.clone(),
+        annotations: Some(annotations),
+        ..ObjectMeta::default()
+    }
+}
+
+#[test]
+fn test_bundle_serde() {
+    use crate::CrdExt;
+
+    let mut test = crate::create_test_crd("my-test", None, crate::TestSpec::default());
+    test.metadata.uid = Some("1234".to_string());
+    test.status = Some(crate::TestStatus::default());
+    let bundle = TestBundle {
+        version: TEST_BUNDLE_VERSION,
+        test,
+        resources: vec![crate::create_resource_crd(
+            "my-cluster",
+            None,
+            crate::ResourceSpec::default(),
+        )],
+    };
+    let serialized = serde_json::to_value(&bundle).unwrap();
+    assert_eq!(
+        serialized["version"],
+        serde_json::json!(TEST_BUNDLE_VERSION)
+    );
+    assert_eq!(
+        serialized["resources"][0]["metadata"]["name"],
+        serde_json::json!("my-cluster")
+    );
+    assert_eq!(
+        serde_json::from_value::<TestBundle>(serialized).unwrap(),
+        bundle
+    );
+
+    let metadata = imported_metadata(&bundle.test.metadata);
+    assert_eq!(metadata.name.as_deref(), Some("my-test"));
+    assert_eq!(metadata.uid, None);
+    assert_eq!(metadata.namespace, None);
+    assert!(Test {
+        metadata,
+        spec: Default::default(),
+        status: None,
+    }
+    .is_imported());
+}
@@ -1,20 +1,47 @@
-use super::HttpStatusCode;
-use crate::clients::error::{self, Result};
-use crate::constants::NAMESPACE;
-use crate::CrdExt;
+use super::{AllowNotFound, HttpStatusCode};
+use crate::clients::error::{self, Error, Result};
+use crate::system::default_namespace;
+use crate::{Clock, CrdExt, SystemClock};
 use chrono::{DateTime, SecondsFormat, Utc};
 use core::fmt::Debug;
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt, TryStreamExt};
 use http::StatusCode;
 use json_patch::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation, TestOperation};
 use k8s_openapi::NamespaceResourceScope;
-use kube::api::{ListParams, Patch, PatchParams, PostParams};
-use kube::{Api, Resource};
+use kube::api::{
+    ListParams, Patch, PatchParams, PostParams, WatchEvent as KubeWatchEvent, WatchParams,
+};
+use kube::runtime::{watcher, WatchStreamExt};
+use kube::{Api, Resource, ResourceExt};
 use log::trace;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
 use snafu::{ensure, OptionExt, ResultExt};
-use std::time::{Duration, SystemTime};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// The number of attempts `patch` and `patch_status` make by default when the object has been
+/// modified concurrently.
+const DEFAULT_MAX_CONFLICT_RETRIES: u32 = 5;
+
+/// The delay before the first retry of a conflicting patch. The delay doubles with each attempt.
+const CONFLICT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The delay before `get_eventually` first retries an object that was not found. The delay doubles
+/// with each attempt up to `MAX_NOT_FOUND_BACKOFF`.
+const NOT_FOUND_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The longest delay between the attempts made by `get_eventually`.
+const MAX_NOT_FOUND_BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long `watch_until` waits before checking the object again when the watch is unavailable.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The fields that the Kubernetes API server can select custom resources by.
+const CRD_FIELD_SELECTORS: &[&str] = &["metadata.name", "metadata.namespace"];
 
 /// A trait with implementations of code that is shared between more than one CRD object.
 #[async_trait::async_trait]
@@ -26,15 +53,41 @@ pub trait CrdClient: Sized {
         + Clone
         + Send
         + Sync
-        + CrdExt;
+        + CrdExt
+        + 'static;
     type CrdStatus: Serialize + Default + Send;
 
     // The following need to be implemented which allows the rest of the functions to have
     // default implementations.
 
-    fn new_from_api(api: Api<Self::Crd>) -> Self;
+    /// Create a client from `api`, which must be for the objects in `namespace`.
+    fn new_from_api(api: Api<Self::Crd>, namespace: &str) -> Self;
     fn kind(&self) -> &'static str;
     fn api(&self) -> &Api<Self::Crd>;
+    /// The namespace of the objects that this client uses.
+    fn namespace(&self) -> &str;
+
+    /// The clock used for the timestamps this client writes, e.g. `status.lastUpdate`.
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+
+    /// Check that the task states in `status` can be reached from the task states of `current`,
+    /// see [`TaskState::can_transition_to`](crate::TaskState::can_transition_to). Used by
+    /// `replace_status` before the status is replaced. Accepts every status by default.
+    fn check_status_transition(
+        &self,
+        _current: &Self::Crd,
+        _status: &Self::CrdStatus,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// The maximum number of attempts made by `patch` and `patch_status` when the API responds
+    /// with `409 Conflict` because the object was modified concurrently.
+    fn max_conflict_retries(&self) -> u32 {
+        DEFAULT_MAX_CONFLICT_RETRIES
+    }
 
     async fn new() -> Result<Self> {
         let k8s_client = kube::Client::try_default()
@@ -43,12 +96,30 @@ pub trait CrdClient: Sized {
         Ok(Self::new_from_k8s_client(k8s_client))
     }
 
+    /// Create a client for the objects in `namespace` using the default `kube::Client`.
+    async fn new_with_namespace(namespace: &str) -> Result<Self> {
+        let k8s_client = kube::Client::try_default()
+            .await
+            .context(error::InitializationSnafu)?;
+        Ok(Self::new_from_k8s_client_with_namespace(
+            k8s_client, namespace,
+        ))
+    }
+
     fn new_from_k8s_client(k8s_client: kube::Client) -> Self {
-        Self::new_from_api(Self::create_api(k8s_client))
+        Self::new_from_api(Self::create_api(k8s_client), &default_namespace())
     }
 
+    fn new_from_k8s_client_with_namespace(k8s_client: kube::Client, namespace: &str) -> Self {
+        Self::new_from_api(
+            Api::<Self::Crd>::namespaced(k8s_client, namespace),
+            namespace,
+        )
+    }
+
+    /// Create an api for the objects in the namespace returned by [`default_namespace`].
     fn create_api(k8s_client: kube::Client) -> Api<Self::Crd> {
-        Api::<Self::Crd>::namespaced(k8s_client, NAMESPACE)
+        Api::<Self::Crd>::namespaced(k8s_client, &default_namespace())
     }
 
     async fn get<S>(&self, name: S) -> Result<Self::Crd>
@@ -66,6 +137,43 @@ pub trait CrdClient: Sized {
             })?)
     }
 
+    /// Returns `true` if the object `name` exists and `false` if the API responds with
+    /// `404 Not Found`. Any other error is returned.
+    async fn exists<S>(&self, name: S) -> Result<bool>
+    where
+        S: AsRef<str> + Send,
+    {
+        Ok(self.get(name).await.allow_not_found(|_| ())?.is_some())
+    }
+
+    /// Get the object `name`, retrying with a growing delay while the API responds with
+    /// `404 Not Found`, e.g. right after the object was created. The `404 Not Found` error is
+    /// returned if the object does not appear within `timeout`. Any other error is returned
+    /// immediately.
+    async fn get_eventually(&self, name: &str, timeout: Duration) -> Result<Self::Crd> {
+        let deadline = Instant::now() + timeout;
+        let mut backoff = NOT_FOUND_BACKOFF;
+        loop {
+            match self.get(name).await {
+                Err(e) if e.is_status_code(StatusCode::NOT_FOUND) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(e);
+                    }
+                    trace!(
+                        "{} '{}' was not found, retrying in {:?}",
+                        self.kind(),
+                        name,
+                        backoff.min(remaining)
+                    );
+                    tokio::time::sleep(backoff.min(remaining)).await;
+                    backoff = (backoff * 2).min(MAX_NOT_FOUND_BACKOFF);
+                }
+                result => return result,
+            }
+        }
+    }
+
     async fn get_all(&self) -> Result<Vec<Self::Crd>> {
         Ok(self
             .api()
@@ -78,6 +186,92 @@ pub trait CrdClient: Sized {
             .items)
     }
 
+    /// Get all objects that match the label `selector`, e.g. `testsys.system/variant=aws-k8s-1.24`.
+    async fn list_with_labels(&self, selector: &str) -> Result<Vec<Self::Crd>> {
+        Ok(self
+            .api()
+            .list(&ListParams::default().labels(selector))
+            .await
+            .context(error::KubeApiCallForSnafu {
+                operation: format!("list with labels '{}'", selector),
+                name: format!("{}s", self.kind()),
+            })?
+            .items)
+    }
+
+    /// Get all objects that match the field `selector`, e.g. `metadata.name=my-test`. Custom
+    /// resources can only be selected by `metadata.name` and `metadata.namespace`, so a selector
+    /// that uses any other field is rejected before it is sent to the server.
+    async fn list_with_field_selector(&self, selector: &str) -> Result<Vec<Self::Crd>> {
+        check_field_selector(selector)?;
+        Ok(self
+            .api()
+            .list(&ListParams::default().fields(selector))
+            .await
+            .context(error::FieldSelectorRejectedSnafu {
+                selector,
+                name: format!("{}s", self.kind()),
+            })?
+            .items)
+    }
+
+    /// Get at most `limit` objects, starting from `continue_token` if it is provided. Returns the
+    /// objects along with the token for the next page, which is `None` once all objects have been
+    /// returned.
+    async fn list_paged(
+        &self,
+        limit: u32,
+        continue_token: Option<String>,
+    ) -> Result<(Vec<Self::Crd>, Option<String>)> {
+        let mut list_params = ListParams::default().limit(limit);
+        if let Some(continue_token) = &continue_token {
+            list_params = list_params.continue_token(continue_token);
+        }
+        let list = self
+            .api()
+            .list(&list_params)
+            .await
+            .context(error::KubeApiCallForSnafu {
+                operation: "list page",
+                name: format!("{}s", self.kind()),
+            })?;
+        let next_token = list
+            .metadata
+            .continue_
+            .filter(|continue_token| !continue_token.is_empty());
+        Ok((list.items, next_token))
+    }
+
+    /// Watch every object of this kind in the namespace. The existing objects are yielded as
+    /// [`WatchEvent::Added`] first. When the watch reconnects, the objects are listed again and
+    /// only the changes since the last event are yielded. Watch errors are yielded and the watch is
+    /// retried with a backoff.
+    fn watch_all(&self) -> BoxStream<'static, Result<WatchEvent<Self::Crd>>> {
+        self.watch_selected(watcher::Config::default())
+    }
+
+    /// Like [`CrdClient::watch_all`], but only the objects that match the label and field
+    /// selectors of `config` are watched.
+    fn watch_selected(
+        &self,
+        config: watcher::Config,
+    ) -> BoxStream<'static, Result<WatchEvent<Self::Crd>>> {
+        let name = format!("{}s", self.kind());
+        watcher(self.api().clone(), config)
+            .backoff(watcher::default_backoff())
+            .scan(BTreeMap::new(), move |known, event| {
+                let events: Vec<Result<WatchEvent<Self::Crd>>> = match event {
+                    Ok(event) => watch_events(known, event).into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(Box::new(e))
+                        .context(error::WatchSnafu { name: name.clone() })
+                        .map_err(Error::from)],
+                };
+                future::ready(Some(stream::iter(events)))
+            })
+            .flatten()
+            .boxed()
+    }
+
     async fn create(&self, crd: Self::Crd) -> Result<Self::Crd> {
         Ok(self
             .api()
@@ -89,6 +283,80 @@ pub trait CrdClient: Sized {
             })?)
     }
 
+    /// Send `item` to the API server as a dry-run create. The server validates the object, e.g.
+    /// against the CRD schema and with admission webhooks, and returns the object it would have
+    /// created, but nothing is persisted.
+    async fn create_dry_run(&self, item: &Self::Crd) -> Result<Self::Crd> {
+        Ok(self
+            .api()
+            .create(
+                &PostParams {
+                    dry_run: true,
+                    ..PostParams::default()
+                },
+                item,
+            )
+            .await
+            .context(error::KubeApiCallSnafu {
+                method: "dry-run create",
+                what: self.kind(),
+            })?)
+    }
+
+    /// Create each of the `items`. A failure to create one object does not prevent the remaining
+    /// objects from being created, the outcome for each object is recorded in the [`BulkResult`].
+    async fn create_all(&self, items: Vec<Self::Crd>) -> Result<BulkResult> {
+        let mut result = BulkResult::default();
+        for item in items {
+            let name = item.name_any();
+            match self.create(item).await {
+                Ok(_) => result.succeeded.push(name),
+                Err(e) => result.failed.push((name, e)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Create `item`, or if an object with the same name already exists, replace it with `item`.
+    /// The existing object's `resourceVersion` and finalizers are kept and its status is not
+    /// changed. The replacement is retried if the object is modified concurrently.
+    async fn create_or_update(&self, item: Self::Crd) -> Result<Self::Crd> {
+        let name = item.name_any();
+        match self.create(item.clone()).await {
+            Err(e) if e.is_status_code(StatusCode::CONFLICT) => {}
+            result => return result,
+        }
+        let params = PostParams::default();
+        let operation = format!("create or update {}", self.kind());
+        retry_on_conflict(self, &name, &operation, || {
+            let mut item = item.clone();
+            let name = name.as_str();
+            let params = &params;
+            async move {
+                let existing = self.api().get(name).await?;
+                item.meta_mut().resource_version = existing.resource_version();
+                item.meta_mut().finalizers = existing.meta().finalizers.clone();
+                self.api().replace(name, params, &item).await
+            }
+        })
+        .await
+    }
+
+    /// Create or update each of the `items`, see [`CrdClient::create_or_update`]. A failure for one
+    /// object does not prevent the remaining objects from being applied, the outcome for each object
+    /// is recorded in the [`BulkResult`].
+    async fn create_or_update_all(&self, items: Vec<Self::Crd>) -> Result<BulkResult> {
+        let mut result = BulkResult::default();
+        for item in items {
+            let name = item.name_any();
+            match self.create_or_update(item).await {
+                Ok(_) => result.succeeded.push(name),
+                Err(e) => result.failed.push((name, e)),
+            }
+        }
+        Ok(result)
+    }
+
     async fn delete<S>(&self, name: S) -> Result<Option<Self::Crd>>
     where
         S: AsRef<str> + Send,
@@ -121,6 +389,39 @@ pub trait CrdClient: Sized {
             .into_inner())
     }
 
+    /// Delete every object that matches the label `selector` with a single request and return the
+    /// names of the deleted objects. Objects with finalizers are marked for deletion and removed
+    /// once their finalizers are done.
+    async fn delete_collection(&self, selector: &str) -> Result<Vec<String>> {
+        let list_params = ListParams::default().labels(selector);
+        // The names are only returned when the deletion is not finished immediately, so they are
+        // listed first.
+        let listed: Vec<String> = self
+            .list_with_labels(selector)
+            .await?
+            .iter()
+            .map(|crd| crd.name_any())
+            .collect();
+        let deleted = self
+            .api()
+            .delete_collection(&Default::default(), &list_params)
+            .await
+            .context(error::KubeApiCallForSnafu {
+                operation: format!("delete collection with labels '{}'", selector),
+                name: format!("{}s", self.kind()),
+            })?;
+        Ok(deleted
+            .map_left(|deleted| {
+                deleted
+                    .items
+                    .iter()
+                    .map(|crd| crd.name_any())
+                    .collect::<Vec<_>>()
+            })
+            .map_right(|_| listed)
+            .into_inner())
+    }
+
     /// Loop until `get(name)` returns `StatusCode::NOT_FOUND`
     async fn wait_for_deletion<S>(&self, name: S) -> ()
     where
@@ -148,13 +449,71 @@ pub trait CrdClient: Sized {
             vec![
                 JsonPatch::new_test_operation("/status", Option::<Self::CrdStatus>::None),
                 JsonPatch::new_add_operation("/status", Self::CrdStatus::default()),
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
             ],
             "initialize status",
         )
         .await
     }
 
+    /// Like `initialize_status`, but returns `Ok(false)` instead of an error if the `status` field
+    /// is already populated, e.g. because a controller reconciled the object before it restarted.
+    /// Returns `Ok(true)` if the status was initialized by this call. The status is only added if
+    /// it is still `null` when the patch is applied, so only one of two concurrent calls returns
+    /// `Ok(true)`.
+    async fn initialize_status_if_needed(&self, name: &str) -> Result<bool> {
+        let e = match self.initialize_status(name).await {
+            Ok(_) => return Ok(true),
+            Err(e) => e,
+        };
+        // The server rejects a patch whose test operation fails with `422 Unprocessable Entity`.
+        if !e.is_status_code(StatusCode::UNPROCESSABLE_ENTITY) {
+            return Err(e);
+        }
+        let crd = serde_json::to_value(self.get(name).await?).context(error::SerdeSnafu {
+            what: format!("{} '{}'", self.kind(), name),
+        })?;
+        match crd.get("status") {
+            Some(status) if !status.is_null() => Ok(false),
+            _ => Err(e),
+        }
+    }
+
+    /// Replace the whole `status` of the object `name` with `status`, e.g. when a new status has
+    /// been computed rather than changed a field at a time with `patch_status`. The
+    /// `status.lastUpdate` timestamp is set by this call. Returns an error if the task states in
+    /// `status` cannot be reached from the current ones. The replacement is made against the
+    /// `resourceVersion` that was checked, so if the object is modified concurrently the
+    /// `409 Conflict` is returned rather than overwriting the other change.
+    async fn replace_status(&self, name: &str, status: Self::CrdStatus) -> Result<Self::Crd> {
+        let current = self.get(name).await?;
+        self.check_status_transition(&current, &status)?;
+        let what = format!("{} '{}' status", self.kind(), name);
+        let mut status = serde_json::to_value(status).context(error::SerdeSnafu { what: &what })?;
+        if let Some(status) = status.as_object_mut() {
+            status.insert(
+                "lastUpdate".to_string(),
+                Value::String(
+                    self.clock()
+                        .now()
+                        .to_rfc3339_opts(SecondsFormat::Secs, true),
+                ),
+            );
+        }
+        // The current object carries the `resourceVersion` that the replacement is checked against.
+        let mut crd = serde_json::to_value(current).context(error::SerdeSnafu { what: &what })?;
+        crd["status"] = status;
+        let data = serde_json::to_vec(&crd).context(error::SerdeSnafu { what })?;
+        Ok(self
+            .api()
+            .replace_status(name, &PostParams::default(), data)
+            .await
+            .context(error::KubeApiCallForSnafu {
+                operation: "replace status",
+                name,
+            })?)
+    }
+
     /// Add a finalizer. Checks `crd` to make sure the finalizer is not a duplicate. Replaces the
     /// finalizer array with those found in `crd` plus the new `finalizer`.
     async fn add_finalizer(&self, finalizer: &str, crd: &Self::Crd) -> Result<Self::Crd> {
@@ -165,7 +524,7 @@ pub trait CrdClient: Sized {
             self.patch(
                 crd.object_name(),
                 vec![
-                    JsonPatch::new_timestamp(),
+                    JsonPatch::new_timestamp(self.clock().now()),
                     JsonPatch::new_test_operation("/metadata/finalizers", Value::Null),
                     JsonPatch::new_add_operation("/metadata/finalizers", vec![finalizer]),
                 ],
@@ -180,12 +539,12 @@ pub trait CrdClient: Sized {
             self.patch(
                 crd.object_name(),
                 vec![
-                    JsonPatch::new_timestamp(),
+                    JsonPatch::new_timestamp(self.clock().now()),
                     JsonPatch::new_test_operation(
                         "/metadata/finalizers",
                         crd.meta().finalizers.clone(),
                     ),
-                    JsonPatch::new_add_operation("/metadata/finalizers/-", finalizer),
+                    JsonPatch::new_append_operation("/metadata/finalizers", finalizer),
                 ],
                 "add finalizer",
             )
@@ -205,7 +564,7 @@ pub trait CrdClient: Sized {
         self.patch(
             crd.object_name(),
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_test_operation(
                     format!("/metadata/finalizers/{}", finalizer_idx),
                     finalizer,
@@ -217,6 +576,52 @@ pub trait CrdClient: Sized {
         .await
     }
 
+    /// Add `finalizer` to the object `name` unless it already has it. Unlike `add_finalizer`, adding
+    /// a finalizer twice is not an error.
+    async fn ensure_finalizer(&self, name: &str, finalizer: &str) -> Result<Self::Crd> {
+        let crd = self.get(name).await?;
+        if crd.has_finalizer(finalizer) {
+            return Ok(crd);
+        }
+        self.add_finalizer(finalizer, &crd).await
+    }
+
+    /// Remove `finalizer` from the object `name` if it has it. Unlike `remove_finalizer`, removing
+    /// a finalizer that is not present is not an error.
+    async fn ensure_finalizer_removed(&self, name: &str, finalizer: &str) -> Result<Self::Crd> {
+        let crd = self.get(name).await?;
+        if !crd.has_finalizer(finalizer) {
+            return Ok(crd);
+        }
+        self.remove_finalizer(finalizer, &crd).await
+    }
+
+    /// Add `labels` to the object, keeping any existing labels that are not in `labels`. The
+    /// labels map is created if the object has no labels. Label keys may contain `/`, e.g.
+    /// `testsys.system/build-id`.
+    async fn add_labels(&self, name: &str, labels: BTreeMap<String, String>) -> Result<Self::Crd> {
+        let crd = self.get(name).await?;
+        let patches = metadata_map_patches("/metadata/labels", crd.meta().labels.is_none(), labels);
+        self.patch(name, patches, "add labels").await
+    }
+
+    /// Add `annotations` to the object, keeping any existing annotations that are not in
+    /// `annotations`. Like `add_labels`, keys may contain `/` and the annotations map is created
+    /// if the object has none.
+    async fn add_annotations(
+        &self,
+        name: &str,
+        annotations: BTreeMap<String, String>,
+    ) -> Result<Self::Crd> {
+        let crd = self.get(name).await?;
+        let patches = metadata_map_patches(
+            "/metadata/annotations",
+            crd.meta().annotations.is_none(),
+            annotations,
+        );
+        self.patch(name, patches, "add annotations").await
+    }
+
     /// Apply JSON patches to the object anywhere that is not in the `/status` path.
     async fn patch<I, S1, S2>(&self, name: S1, patches: I, description: S2) -> Result<Self::Crd>
     where
@@ -225,24 +630,18 @@ pub trait CrdClient: Sized {
         I: IntoIterator<Item = JsonPatch> + Send,
     {
         let name = name.as_ref();
-        let patch = json_patch::Patch(
+        let patch = Patch::<Self::Crd>::Json(json_patch::Patch(
             patches
                 .into_iter()
                 .map(|item| item.into_json_patch_operation())
                 .collect(),
-        );
-        Ok(self
-            .api()
-            .patch(
-                name,
-                &PatchParams::default(),
-                &Patch::<Self::Crd>::Json(patch),
-            )
-            .await
-            .context(error::KubeApiCallForSnafu {
-                operation: description,
-                name,
-            })?)
+        ));
+        let params = PatchParams::default();
+        let operation = description.into();
+        retry_on_conflict(self, name, &operation, || {
+            self.api().patch(name, &params, &patch)
+        })
+        .await
     }
 
     /// Apply JSON patches that apply to the `/status` path.
@@ -258,25 +657,269 @@ pub trait CrdClient: Sized {
         I: IntoIterator<Item = JsonPatch> + Send,
     {
         let name = name.as_ref();
-        let patch = json_patch::Patch(
+        let patch = Patch::<Self::Crd>::Json(json_patch::Patch(
             patches
                 .into_iter()
                 .map(|item| item.into_json_patch_operation())
                 .collect(),
+        ));
+        let params = PatchParams::default();
+        let operation = description.into();
+        retry_on_conflict(self, name, &operation, || {
+            self.api().patch_status(name, &params, &patch)
+        })
+        .await
+    }
+}
+
+/// The outcome of [`CrdClient::create_all`] and [`CrdClient::create_or_update_all`].
+#[derive(Debug, Default)]
+pub struct BulkResult {
+    /// The names of the objects that were created (or updated).
+    pub succeeded: Vec<String>,
+    /// The names of the objects that could not be created along with the reason.
+    pub failed: Vec<(String, Error)>,
+}
+
+impl BulkResult {
+    /// Returns `true` if every object was created.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// A change to an object observed by [`CrdClient::watch_all`].
+#[derive(Debug, Clone)]
+pub enum WatchEvent<T> {
+    /// The object was created, or existed when the watch started.
+    Added(T),
+    /// The object was changed.
+    Modified(T),
+    /// The object was deleted. This is the last version of the object that was seen.
+    Deleted(T),
+}
+
+impl<T> WatchEvent<T> {
+    /// Convert the object of this event with `f`.
+    pub fn map<U, F>(self, f: F) -> WatchEvent<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        match self {
+            WatchEvent::Added(object) => WatchEvent::Added(f(object)),
+            WatchEvent::Modified(object) => WatchEvent::Modified(f(object)),
+            WatchEvent::Deleted(object) => WatchEvent::Deleted(f(object)),
+        }
+    }
+}
+
+/// Convert a watcher `event` into the changes it makes to the `known` objects, keyed by name. A
+/// restart relists every object, so the objects that did not change are skipped and the objects
+/// that are missing from the list are reported as deleted.
+fn watch_events<T>(known: &mut BTreeMap<String, T>, event: watcher::Event<T>) -> Vec<WatchEvent<T>>
+where
+    T: Resource + Clone,
+{
+    match event {
+        watcher::Event::Applied(object) => applied_event(known, object).into_iter().collect(),
+        watcher::Event::Deleted(object) => {
+            known.remove(&object.name_any());
+            vec![WatchEvent::Deleted(object)]
+        }
+        watcher::Event::Restarted(objects) => {
+            let names: BTreeSet<String> = objects.iter().map(|object| object.name_any()).collect();
+            let mut events: Vec<WatchEvent<T>> = objects
+                .into_iter()
+                .filter_map(|object| applied_event(known, object))
+                .collect();
+            let deleted: Vec<String> = known
+                .keys()
+                .filter(|name| !names.contains(*name))
+                .cloned()
+                .collect();
+            events.extend(
+                deleted
+                    .into_iter()
+                    .filter_map(|name| known.remove(&name))
+                    .map(WatchEvent::Deleted),
+            );
+            events
+        }
+    }
+}
+
+/// The event for an object that was created or changed, or `None` if `known` already has this
+/// version of the object.
+fn applied_event<T>(known: &mut BTreeMap<String, T>, object: T) -> Option<WatchEvent<T>>
+where
+    T: Resource + Clone,
+{
+    match known.insert(object.name_any(), object.clone()) {
+        None => Some(WatchEvent::Added(object)),
+        Some(previous) if previous.resource_version() == object.resource_version() => None,
+        Some(_) => Some(WatchEvent::Modified(object)),
+    }
+}
+
+/// Calls `f` until it succeeds, fails with an error other than `409 Conflict`, or
+/// `client.max_conflict_retries()` attempts have been made. The delay between attempts grows
+/// exponentially. If every attempt conflicts, the last conflict is returned as the source of a
+/// `ConflictRetriesExhausted` error. `operation` describes what `f` does, e.g. `patch status`.
+async fn retry_on_conflict<C, F, Fut>(
+    client: &C,
+    name: &str,
+    operation: &str,
+    mut f: F,
+) -> Result<C::Crd>
+where
+    C: CrdClient + Sync,
+    F: FnMut() -> Fut + Send,
+    Fut: Future<Output = std::result::Result<C::Crd, kube::Error>> + Send,
+{
+    let max_attempts = client.max_conflict_retries();
+    let mut backoff = CONFLICT_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(e) if e.status_code() == Some(StatusCode::CONFLICT) && attempt < max_attempts => {
+                trace!(
+                    "conflict while patching {} '{}' (attempt {} of {}), retrying in {:?}",
+                    client.kind(),
+                    name,
+                    attempt,
+                    max_attempts,
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+                // Re-fetch the object so that we stop early if it has been deleted in the meantime.
+                client
+                    .api()
+                    .get(name)
+                    .await
+                    .context(error::KubeApiCallForSnafu { operation, name })?;
+            }
+            Err(e) if e.status_code() == Some(StatusCode::CONFLICT) => {
+                return Err(e).context(error::ConflictRetriesExhaustedSnafu {
+                    operation,
+                    name,
+                    attempts: attempt,
+                })?
+            }
+            result => return Ok(result.context(error::KubeApiCallForSnafu { operation, name })?),
+        }
+    }
+}
+
+/// Watch the object `name` until `check` returns a value or an error. `check` returns `None` while
+/// the object has not reached the state that is waited for. The object is watched using the k8s
+/// watch API. If the watch disconnects, the object is fetched again before a new watch is started.
+/// The error from `deleted` is returned if the object is deleted.
+pub(crate) async fn watch_until<C, T, F, D>(
+    client: &C,
+    name: &str,
+    check: F,
+    deleted: D,
+) -> Result<T>
+where
+    C: CrdClient + Sync,
+    F: Fn(&C::Crd) -> Result<Option<T>>,
+    D: Fn() -> Error,
+{
+    loop {
+        let crd = client.get(name).await?;
+        if let Some(value) = check(&crd)? {
+            return Ok(value);
+        }
+
+        let watch_params = WatchParams::default().fields(&format!("metadata.name={}", name));
+        let resource_version = crd.resource_version().unwrap_or_default();
+        let stream = match client.api().watch(&watch_params, &resource_version).await {
+            Ok(stream) => stream,
+            Err(_) => {
+                // Fall back to polling until the watch can be established.
+                tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+                continue;
+            }
+        };
+        futures::pin_mut!(stream);
+        while let Ok(Some(event)) = stream.try_next().await {
+            match event {
+                KubeWatchEvent::Added(crd) | KubeWatchEvent::Modified(crd) => {
+                    if let Some(value) = check(&crd)? {
+                        return Ok(value);
+                    }
+                }
+                KubeWatchEvent::Deleted(_) => return Err(deleted()),
+                KubeWatchEvent::Bookmark(_) => {}
+                // The resource version is too old or the watch failed, start over.
+                KubeWatchEvent::Error(_) => break,
+            }
+        }
+        tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+    }
+}
+
+/// Make the API calls of clients created from `config` fail with a timeout error if the server
+/// does not respond within `timeout`. The timeout applies to connecting and to each read and write,
+/// so a watch that receives no events for longer than `timeout` also fails.
+pub fn set_request_timeout(config: &mut kube::Config, timeout: Duration) {
+    config.connect_timeout = Some(timeout);
+    config.read_timeout = Some(timeout);
+    config.write_timeout = Some(timeout);
+}
+
+/// Returns an error if `selector` uses a field that is not one of the
+/// [`CRD_FIELD_SELECTORS`], e.g. `status.agent.taskState`. The values are not checked.
+pub(crate) fn check_field_selector(selector: &str) -> Result<()> {
+    for requirement in selector.split(',').filter(|r| !r.trim().is_empty()) {
+        let field = requirement
+            .split(['=', '!'])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        ensure!(
+            CRD_FIELD_SELECTORS.contains(&field),
+            error::UnsupportedFieldSelectorSnafu {
+                selector,
+                field,
+                supported: CRD_FIELD_SELECTORS.join(", "),
+            }
         );
-        Ok(self
-            .api()
-            .patch_status(
-                name,
-                &PatchParams::default(),
-                &Patch::<Self::Crd>::Json(patch),
-            )
-            .await
-            .context(error::KubeApiCallForSnafu {
-                operation: description,
-                name,
-            })?)
     }
+    Ok(())
+}
+
+/// The patches that add the `entries` to the string map at `path`, e.g. `/metadata/labels`. If the
+/// object does not have the map yet (`missing`), the whole map is added instead, guarded by a test
+/// so that a map created concurrently is not replaced.
+fn metadata_map_patches(
+    path: &str,
+    missing: bool,
+    entries: BTreeMap<String, String>,
+) -> Vec<JsonPatch> {
+    if missing {
+        vec![
+            JsonPatch::new_test_operation(path, Value::Null),
+            JsonPatch::new_add_operation(path, entries),
+        ]
+    } else {
+        entries
+            .into_iter()
+            .map(|(key, value)| {
+                JsonPatch::new_add_operation(
+                    format!("{}/{}", path, escape_json_pointer(&key)),
+                    value,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Escape `token` for use as a single segment of a JSON pointer, e.g. a map key containing `/`.
+fn escape_json_pointer(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
 }
 
 /// The JSON patch operation type.
@@ -308,6 +951,17 @@ impl JsonPatch {
         }
     }
 
+    /// Append `value` to the end of the array at `path`. This is an `add` operation to
+    /// `{path}/-`, so `path` must be the array itself, e.g. `/status/agent/results`. The patch
+    /// fails if there is no array at `path`.
+    pub fn new_append_operation<S, V>(path: S, value: V) -> Self
+    where
+        S: AsRef<str>,
+        V: Serialize,
+    {
+        Self::new_add_operation(format!("{}/-", path.as_ref().trim_end_matches('/')), value)
+    }
+
     pub fn new_replace_operation<S, V>(path: S, value: V) -> Self
     where
         S: Into<String>,
@@ -361,14 +1015,92 @@ impl JsonPatch {
         }
     }
 
-    pub fn new_timestamp() -> Self {
+    /// Set `status.lastUpdate` to `now`, see [`CrdClient::clock`].
+    pub fn new_timestamp(now: DateTime<Utc>) -> Self {
         Self {
             op: PatchOp::Replace,
             path: "/status/lastUpdate".to_string(),
-            value: serde_json::Value::String(
-                Into::<DateTime<Utc>>::into(SystemTime::now())
-                    .to_rfc3339_opts(SecondsFormat::Secs, true),
-            ),
+            value: serde_json::Value::String(now.to_rfc3339_opts(SecondsFormat::Secs, true)),
         }
     }
 }
+
+#[test]
+fn test_append_operation() {
+    for path in ["/status/agent/results", "/status/agent/results/"] {
+        let operation = JsonPatch::new_append_operation(path, 1).into_json_patch_operation();
+        assert_eq!(
+            serde_json::to_value(operation).unwrap(),
+            serde_json::json!({"op": "add", "path": "/status/agent/results/-", "value": 1})
+        );
+    }
+}
+
+#[test]
+fn test_escape_json_pointer() {
+    assert_eq!(escape_json_pointer("build"), "build");
+    assert_eq!(escape_json_pointer("build/id"), "build~1id");
+    assert_eq!(escape_json_pointer("a~b/c"), "a~0b~1c");
+}
+
+#[test]
+fn test_check_field_selector() {
+    check_field_selector("metadata.name=my-test").unwrap();
+    check_field_selector("metadata.name==my-test,metadata.namespace!=default").unwrap();
+    check_field_selector("").unwrap();
+    let err = check_field_selector("metadata.name=my-test,status.agent.taskState=running")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("'status.agent.taskState'"), "{}", err);
+    assert!(err.contains("metadata.name, metadata.namespace"), "{}", err);
+}
+
+#[test]
+fn test_watch_events() {
+    let test = |name: &str, version: &str| {
+        let mut test = crate::create_test_crd(name, None, crate::TestSpec::default());
+        test.metadata.resource_version = Some(version.to_string());
+        test
+    };
+    let summary = |events: Vec<WatchEvent<crate::Test>>| {
+        events
+            .into_iter()
+            .map(|event| match event {
+                WatchEvent::Added(test) => format!("added {}", test.name_any()),
+                WatchEvent::Modified(test) => format!("modified {}", test.name_any()),
+                WatchEvent::Deleted(test) => format!("deleted {}", test.name_any()),
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut known = BTreeMap::new();
+    assert_eq!(
+        summary(watch_events(
+            &mut known,
+            watcher::Event::Restarted(vec![test("a", "1"), test("b", "1")])
+        )),
+        vec!["added a", "added b"]
+    );
+    assert_eq!(
+        summary(watch_events(
+            &mut known,
+            watcher::Event::Applied(test("a", "2"))
+        )),
+        vec!["modified a"]
+    );
+    // Relisting after a reconnect only reports what changed while disconnected.
+    assert_eq!(
+        summary(watch_events(
+            &mut known,
+            watcher::Event::Restarted(vec![test("a", "2"), test("c", "1")])
+        )),
+        vec!["added c", "deleted b"]
+    );
+    assert_eq!(
+        summary(watch_events(
+            &mut known,
+            watcher::Event::Deleted(test("c", "1"))
+        )),
+        vec!["deleted c"]
+    );
+    assert_eq!(known.keys().collect::<Vec<_>>(), vec!["a"]);
+}
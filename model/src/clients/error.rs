@@ -5,9 +5,58 @@ use snafu::Snafu;
 /// The `Result` type returned by `clients`.
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// The public error type returned by `clients`.
+/// The public error type returned by `clients`. The inner error is boxed because many of its
+/// variants carry a `kube::Error`, which would make every `Result` returned by `clients` large.
 #[derive(Debug, Snafu)]
-pub struct Error(InnerError);
+pub struct Error(Box<InnerError>);
+
+/// The category of an [`Error`], which allows callers to handle errors without matching on their
+/// messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The object does not exist.
+    NotFound,
+    /// The object was modified concurrently or is not in a state that allows the operation.
+    Conflict,
+    /// An object or its configuration could not be serialized or deserialized.
+    Serialization,
+    /// A Kubernetes API call failed, e.g. because the cluster could not be reached.
+    Api,
+    /// The operation did not complete in time.
+    Timeout,
+    /// The input to the operation was not valid.
+    Invalid,
+    /// The test or resource agent reported a failure.
+    Failed,
+}
+
+impl From<InnerError> for Error {
+    fn from(e: InnerError) -> Self {
+        Error(Box::new(e))
+    }
+}
+
+impl Error {
+    /// The category of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.0.kind()
+    }
+
+    /// Returns `true` if the object does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+
+    /// Returns `true` if the object was modified concurrently or is in a conflicting state.
+    pub fn is_conflict(&self) -> bool {
+        self.kind() == ErrorKind::Conflict
+    }
+
+    /// Returns `true` if the operation did not complete in time.
+    pub fn is_timeout(&self) -> bool {
+        self.kind() == ErrorKind::Timeout
+    }
+}
 
 /// The private error type returned by `clients`.
 #[derive(Debug, Snafu)]
@@ -35,6 +84,21 @@ pub(crate) enum InnerError {
         source: kube::Error,
     },
 
+    #[snafu(display(
+        "Unable to {} for '{}' after {} attempts because it was modified concurrently: {}",
+        operation,
+        name,
+        attempts,
+        source
+    ))]
+    ConflictRetriesExhausted {
+        operation: String,
+        name: String,
+        attempts: u32,
+        /// The `409 Conflict` returned by the last attempt.
+        source: kube::Error,
+    },
+
     #[snafu(display("Unable to {} for '{}': {}", operation, name, source))]
     KubeApiCallFor {
         /// What we were trying to do, e.g. 'initialize status field'.
@@ -59,11 +123,185 @@ pub(crate) enum InnerError {
 
     #[snafu(display("A resource errored during deletion '{}'", name))]
     DeleteFail { name: String },
+
+    #[snafu(display("Unable to retry test '{}' because it is still running", name))]
+    RetryRunning { name: String },
+
+    #[snafu(display(
+        "Unable to retry test '{}' because a previous retry has not been acted on yet",
+        name
+    ))]
+    RetryPending { name: String },
+
+    #[snafu(display(
+        "Unable to rerun the failed cases of test '{}' because its latest run has no failed cases",
+        name
+    ))]
+    NoFailedCases { name: String },
+
+    #[snafu(display(
+        "Unable to skip test '{}' because its task state is '{}'",
+        name,
+        task_state
+    ))]
+    SkipStarted {
+        name: String,
+        task_state: crate::TaskState,
+    },
+
+    #[snafu(display("The keep running duration of {:?} is too large", duration))]
+    KeepRunningDuration { duration: std::time::Duration },
+
+    #[snafu(display("{} dependencies contain a cycle: {}", kind, cycle))]
+    DependencyCycle { kind: &'static str, cycle: String },
+
+    #[snafu(display(
+        "The secret name '{}' is not a valid Kubernetes secret name, it must be a lowercase \
+        DNS-1123 subdomain",
+        secret_name
+    ))]
+    InvalidSecretName { secret_name: String },
+
+    #[snafu(display(
+        "'{}' is not a valid environment variable name, it may only contain letters, digits, \
+        '_', '-' and '.' and must not start with a digit",
+        name
+    ))]
+    InvalidEnvVarName { name: String },
+
+    #[snafu(display("The agent {} of test '{}' must not be empty", field, test))]
+    EmptyAgentField { test: String, field: &'static str },
+
+    #[snafu(display(
+        "The spec of test '{}' is {} bytes, more than the limit of {} bytes, and {} bytes of it \
+        are the agent configuration. Store large values in a secret or config map and refer to \
+        them from the configuration instead",
+        test,
+        size,
+        limit,
+        configuration_size
+    ))]
+    SpecTooLarge {
+        test: String,
+        size: usize,
+        limit: usize,
+        configuration_size: usize,
+    },
+
+    #[snafu(display(
+        "The agent image '{}' of test '{}' is not a valid image reference: {}",
+        image,
+        test,
+        reason
+    ))]
+    InvalidImageReference {
+        test: String,
+        image: String,
+        reason: &'static str,
+    },
+
+    #[snafu(display("Init container {} must have a name and an image", index))]
+    InvalidInitContainer { index: usize },
+
+    #[snafu(display("Sidecar {} must have a name and an image", index))]
+    InvalidSidecar { index: usize },
+
+    #[snafu(display(
+        "The container name '{}' is used by more than one container in the agent pod",
+        name
+    ))]
+    ContainerNameCollision { name: String },
+
+    #[snafu(display(
+        "The field selector '{}' uses '{}', but only {} can be used to select TestSys objects",
+        selector,
+        field,
+        supported
+    ))]
+    UnsupportedFieldSelector {
+        selector: String,
+        field: String,
+        supported: String,
+    },
+
+    #[snafu(display(
+        "Unable to list {} with field selector '{}': {}",
+        name,
+        selector,
+        source
+    ))]
+    FieldSelectorRejected {
+        selector: String,
+        name: String,
+        source: kube::Error,
+    },
+
+    #[snafu(display(
+        "The task state of '{}' cannot change from '{}' to '{}'",
+        name,
+        from,
+        to
+    ))]
+    IllegalTransition {
+        name: String,
+        from: crate::TaskState,
+        to: crate::TaskState,
+    },
+
+    #[snafu(display(
+        "Unable to import test bundle version {}, the newest supported version is {}",
+        version,
+        super::TEST_BUNDLE_VERSION
+    ))]
+    UnsupportedBundleVersion { version: u32 },
+
+    #[snafu(display(
+        "Unable to import test bundle{}: {}",
+        left_behind_message(left_behind),
+        source
+    ))]
+    BundleImport {
+        source: Error,
+        left_behind: Vec<String>,
+    },
+
+    #[snafu(display("'{}' cannot depend on itself", name))]
+    SelfDependency { name: String },
+
+    #[snafu(display("'{}' depends on '{}' which does not exist", name, dependency))]
+    MissingDependency { name: String, dependency: String },
+
+    #[snafu(display("The configuration of '{}' is invalid: {}", name, source))]
+    ConfigSchema { name: String, source: ModelError },
+
+    #[snafu(display("Unable to watch '{}': {}", name, source))]
+    Watch {
+        name: String,
+        source: Box<kube::runtime::watcher::Error>,
+    },
+
+    #[snafu(display("Resource '{}' was not created: {}", name, reason))]
+    CreationFailed { name: String, reason: String },
+
+    #[snafu(display("Resource '{}' was not created within {:?}", name, timeout))]
+    CreationTimeout {
+        name: String,
+        timeout: std::time::Duration,
+    },
+
+    #[snafu(display("Test '{}' did not complete successfully: {}", name, reason))]
+    WaitFailed { name: String, reason: String },
+
+    #[snafu(display("Test '{}' did not complete within {:?}", name, timeout))]
+    WaitTimeout {
+        name: String,
+        timeout: std::time::Duration,
+    },
 }
 
 impl From<ModelError> for Error {
     fn from(e: ModelError) -> Self {
-        Error(InnerError::ConfigSerde { source: e })
+        InnerError::ConfigSerde { source: e }.into()
     }
 }
 
@@ -72,6 +310,7 @@ impl HttpStatusCode for InnerError {
         match self {
             InnerError::ConfigSerde { .. }
             | InnerError::ConfigResolution { .. }
+            | InnerError::ConfigSchema { .. }
             | InnerError::Serde { .. }
             | InnerError::Initialization { .. } => None,
             InnerError::KubeApiCall {
@@ -84,15 +323,222 @@ impl HttpStatusCode for InnerError {
                 name: _,
                 source: e,
             } => e.status_code(),
+            InnerError::FieldSelectorRejected { source, .. }
+            | InnerError::ConflictRetriesExhausted { source, .. } => source.status_code(),
+            InnerError::BundleImport { source, .. } => source.status_code(),
             InnerError::DuplicateFinalizer { .. }
             | InnerError::DeleteMissingFinalizer { .. }
-            | InnerError::DeleteFail { .. } => None,
+            | InnerError::DeleteFail { .. }
+            | InnerError::RetryRunning { .. }
+            | InnerError::RetryPending { .. }
+            | InnerError::NoFailedCases { .. }
+            | InnerError::SkipStarted { .. }
+            | InnerError::KeepRunningDuration { .. }
+            | InnerError::DependencyCycle { .. }
+            | InnerError::InvalidSecretName { .. }
+            | InnerError::InvalidEnvVarName { .. }
+            | InnerError::EmptyAgentField { .. }
+            | InnerError::SpecTooLarge { .. }
+            | InnerError::InvalidImageReference { .. }
+            | InnerError::InvalidInitContainer { .. }
+            | InnerError::InvalidSidecar { .. }
+            | InnerError::ContainerNameCollision { .. }
+            | InnerError::UnsupportedFieldSelector { .. }
+            | InnerError::UnsupportedBundleVersion { .. }
+            | InnerError::IllegalTransition { .. }
+            | InnerError::SelfDependency { .. }
+            | InnerError::MissingDependency { .. }
+            | InnerError::Watch { .. }
+            | InnerError::CreationFailed { .. }
+            | InnerError::CreationTimeout { .. }
+            | InnerError::WaitFailed { .. }
+            | InnerError::WaitTimeout { .. } => None,
         }
     }
 }
 
+impl InnerError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            InnerError::ConfigSerde { .. } | InnerError::Serde { .. } => ErrorKind::Serialization,
+            InnerError::Initialization { source } => kube_error_kind(source),
+            InnerError::KubeApiCall { source, .. }
+            | InnerError::KubeApiCallFor { source, .. }
+            | InnerError::ConflictRetriesExhausted { source, .. } => kube_error_kind(source),
+            // The server responds with `400 Bad Request` to a field selector it cannot use.
+            InnerError::FieldSelectorRejected { source, .. }
+                if source.status_code() == Some(StatusCode::BAD_REQUEST) =>
+            {
+                ErrorKind::Invalid
+            }
+            InnerError::FieldSelectorRejected { source, .. } => kube_error_kind(source),
+            InnerError::BundleImport { source, .. } => source.kind(),
+            InnerError::DuplicateFinalizer { .. }
+            | InnerError::DeleteMissingFinalizer { .. }
+            | InnerError::RetryRunning { .. }
+            | InnerError::RetryPending { .. }
+            | InnerError::NoFailedCases { .. }
+            | InnerError::SkipStarted { .. }
+            | InnerError::IllegalTransition { .. } => ErrorKind::Conflict,
+            InnerError::ConfigResolution { .. }
+            | InnerError::ConfigSchema { .. }
+            | InnerError::KeepRunningDuration { .. }
+            | InnerError::DependencyCycle { .. }
+            | InnerError::InvalidSecretName { .. }
+            | InnerError::InvalidEnvVarName { .. }
+            | InnerError::EmptyAgentField { .. }
+            | InnerError::SpecTooLarge { .. }
+            | InnerError::InvalidImageReference { .. }
+            | InnerError::InvalidInitContainer { .. }
+            | InnerError::InvalidSidecar { .. }
+            | InnerError::ContainerNameCollision { .. }
+            | InnerError::UnsupportedFieldSelector { .. }
+            | InnerError::UnsupportedBundleVersion { .. }
+            | InnerError::SelfDependency { .. }
+            | InnerError::MissingDependency { .. } => ErrorKind::Invalid,
+            InnerError::Watch { .. } => ErrorKind::Api,
+            InnerError::DeleteFail { .. }
+            | InnerError::CreationFailed { .. }
+            | InnerError::WaitFailed { .. } => ErrorKind::Failed,
+            InnerError::CreationTimeout { .. } | InnerError::WaitTimeout { .. } => {
+                ErrorKind::Timeout
+            }
+        }
+    }
+}
+
+/// Describes the objects that could not be removed after a failed bundle import.
+fn left_behind_message(left_behind: &[String]) -> String {
+    if left_behind.is_empty() {
+        String::new()
+    } else {
+        format!(", unable to remove {}", left_behind.join(", "))
+    }
+}
+
+/// The category of an error returned by kube-rs.
+fn kube_error_kind(e: &kube::Error) -> ErrorKind {
+    match e {
+        kube::Error::SerdeError(_) => ErrorKind::Serialization,
+        kube::Error::HyperError(_) if is_timed_out(e) => ErrorKind::Timeout,
+        e if e.is_status_code(StatusCode::NOT_FOUND) => ErrorKind::NotFound,
+        e if e.is_status_code(StatusCode::CONFLICT) => ErrorKind::Conflict,
+        e if e.is_status_code(StatusCode::GATEWAY_TIMEOUT)
+            || e.is_status_code(StatusCode::REQUEST_TIMEOUT) =>
+        {
+            ErrorKind::Timeout
+        }
+        _ => ErrorKind::Api,
+    }
+}
+
+/// Returns `true` if `e` was caused by a connect, read or write timeout, see
+/// [`set_request_timeout`](super::set_request_timeout).
+fn is_timed_out(e: &kube::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(e) = source {
+        if let Some(io_error) = e.downcast_ref::<std::io::Error>() {
+            if io_error.kind() == std::io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+        source = e.source();
+    }
+    false
+}
+
 impl HttpStatusCode for Error {
     fn status_code(&self) -> Option<StatusCode> {
         self.0.status_code()
     }
 }
+
+#[cfg(test)]
+fn kube_api_error(code: u16) -> Error {
+    use snafu::IntoError;
+    KubeApiCallSnafu {
+        method: "get",
+        what: "test",
+    }
+    .into_error(kube::Error::Api(kube::error::ErrorResponse {
+        status: "Failure".to_string(),
+        message: "message".to_string(),
+        reason: "reason".to_string(),
+        code,
+    }))
+    .into()
+}
+
+#[test]
+fn kube_error_kinds() {
+    assert_eq!(kube_api_error(404).kind(), ErrorKind::NotFound);
+    assert!(kube_api_error(404).is_not_found());
+    assert_eq!(kube_api_error(409).kind(), ErrorKind::Conflict);
+    assert!(kube_api_error(409).is_conflict());
+    assert_eq!(kube_api_error(504).kind(), ErrorKind::Timeout);
+    assert!(kube_api_error(504).is_timeout());
+    assert_eq!(kube_api_error(403).kind(), ErrorKind::Api);
+    assert_eq!(kube_api_error(500).kind(), ErrorKind::Api);
+    assert!(!kube_api_error(500).is_not_found());
+
+    let serde_error = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+    let error = Error::from(InnerError::KubeApiCallFor {
+        operation: "patch".to_string(),
+        name: "my-test".to_string(),
+        source: kube::Error::SerdeError(serde_error),
+    });
+    assert_eq!(error.kind(), ErrorKind::Serialization);
+
+    let error = Error::from(InnerError::ConflictRetriesExhausted {
+        operation: "patch status".to_string(),
+        name: "my-test".to_string(),
+        attempts: 5,
+        source: kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: "the object has been modified".to_string(),
+            reason: "Conflict".to_string(),
+            code: 409,
+        }),
+    });
+    assert!(error.is_conflict());
+    assert_eq!(error.status_code(), Some(StatusCode::CONFLICT));
+    assert!(error.to_string().contains("the object has been modified"));
+}
+
+#[test]
+fn error_kinds() {
+    assert_eq!(
+        Error::from(InnerError::RetryRunning {
+            name: "my-test".to_string()
+        })
+        .kind(),
+        ErrorKind::Conflict
+    );
+    assert_eq!(
+        Error::from(InnerError::WaitTimeout {
+            name: "my-test".to_string(),
+            timeout: std::time::Duration::from_secs(1)
+        })
+        .kind(),
+        ErrorKind::Timeout
+    );
+    assert_eq!(
+        Error::from(InnerError::InvalidSecretName {
+            secret_name: "Secret".to_string()
+        })
+        .kind(),
+        ErrorKind::Invalid
+    );
+    assert_eq!(
+        Error::from(InnerError::WaitFailed {
+            name: "my-test".to_string(),
+            reason: "failed".to_string()
+        })
+        .kind(),
+        ErrorKind::Failed
+    );
+    // The category does not change how the error is displayed.
+    assert!(kube_api_error(404)
+        .to_string()
+        .starts_with("Unable to get test: ApiError: message"));
+}
@@ -1,14 +1,17 @@
-pub use error::{Error, Result};
+pub use bundle::{TestBundle, TEST_BUNDLE_VERSION};
+pub use error::{Error, ErrorKind, Result};
 pub use resource_client::ResourceClient;
-pub use test_client::TestClient;
+pub use test_client::{TestClient, DEFAULT_MAX_SPEC_BYTES};
 
+mod bundle;
 mod crd_client;
 mod error;
 mod http_status_code;
 mod resource_client;
 mod test_client;
 
-pub use crd_client::CrdClient;
+pub(crate) use crd_client::check_field_selector;
+pub use crd_client::{set_request_timeout, BulkResult, CrdClient, WatchEvent};
 pub use http_status_code::{AllowNotFound, HttpStatusCode, StatusCode};
 pub use resource_client::create_resource_crd;
 pub use test_client::create_test_crd;
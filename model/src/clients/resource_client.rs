@@ -1,10 +1,13 @@
 use super::error::{self, Result};
 use super::HttpStatusCode;
-use crate::clients::crd_client::JsonPatch;
+use crate::clients::crd_client::{watch_until, JsonPatch};
 use crate::clients::CrdClient;
 use crate::constants::{FINALIZER_RESOURCE, NAMESPACE};
 use crate::resource::{ResourceAction, ResourceError};
-use crate::{Configuration, Resource, ResourceSpec, ResourceStatus, TaskState};
+use crate::{
+    Clock, Configuration, DestructionPolicy, Resource, ResourceSpec, ResourceStatus, SystemClock,
+    TaskState,
+};
 use async_recursion::async_recursion;
 use futures::stream::{self, StreamExt};
 use http::StatusCode;
@@ -15,7 +18,8 @@ use log::trace;
 use regex::Regex;
 use serde_json::{Map, Value};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
 use std::time::Duration;
 
 const TEMPLATE_PATTERN_REGEX: &str = r"^\$\{(.+)\.(.+)\}$";
@@ -42,9 +46,17 @@ lazy_static::lazy_static! {
 #[derive(Clone)]
 pub struct ResourceClient {
     api: Api<Resource>,
+    namespace: String,
+    clock: Arc<dyn Clock>,
 }
 
 impl ResourceClient {
+    /// Use `clock` instead of the system time for the timestamps written by this client.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
     pub async fn get_agent_info<C>(&self, name: &str) -> Result<C>
     where
         C: Configuration,
@@ -65,7 +77,7 @@ impl ResourceClient {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation("/status/agentInfo", info),
             ],
             "send agent info",
@@ -104,6 +116,114 @@ impl ResourceClient {
             .collect::<Result<Map<String, Value>>>()
     }
 
+    /// Get the names of the resources that the TestSys [`Resource`] `name` depends on.
+    pub async fn get_dependencies(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self.get(name).await?.spec.depends_on.unwrap_or_default())
+    }
+
+    /// Set the resources that must be created before the TestSys [`Resource`] `name`. The
+    /// dependencies of all resources in the cluster are checked first and an error is returned,
+    /// without modifying the resource, if the change would introduce a cycle.
+    pub async fn set_depends_on(&self, name: &str, depends_on: Vec<String>) -> Result<Resource> {
+        let mut dependencies: BTreeMap<String, Vec<String>> = self
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|resource| {
+                (
+                    resource.name_any(),
+                    resource.spec.depends_on.unwrap_or_default(),
+                )
+            })
+            .collect();
+        dependencies.insert(name.to_string(), depends_on.clone());
+        check_dependency_cycles("Resource", &dependencies)?;
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation("/spec/dependsOn", depends_on)],
+            "set depends on",
+        )
+        .await
+    }
+
+    /// Set when the controller destroys the TestSys [`Resource`] `name`, see [`DestructionPolicy`].
+    pub async fn set_destruction_policy(
+        &self,
+        name: &str,
+        destruction_policy: DestructionPolicy,
+    ) -> Result<Resource> {
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/destructionPolicy",
+                destruction_policy,
+            )],
+            "set destruction policy",
+        )
+        .await
+    }
+
+    /// Record that the test `user` is using the TestSys [`Resource`] `name` by adding it to
+    /// `spec.usedBy`. Nothing is changed if `user` is already listed. The patch fails if the
+    /// resource is modified concurrently.
+    pub async fn add_user(&self, name: &str, user: &str) -> Result<Resource> {
+        let resource = self.get(name).await?;
+        if resource.spec.used_by.iter().any(|u| u == user) {
+            return Ok(resource);
+        }
+        let mut used_by = resource.spec.used_by.clone();
+        used_by.push(user.to_string());
+        self.patch_used_by(&resource, used_by, "add user").await
+    }
+
+    /// Record that the test `user` is no longer using the TestSys [`Resource`] `name` by removing
+    /// it from `spec.usedBy`. Nothing is changed if `user` is not listed. The patch fails if the
+    /// resource is modified concurrently.
+    pub async fn remove_user(&self, name: &str, user: &str) -> Result<Resource> {
+        let resource = self.get(name).await?;
+        if !resource.spec.used_by.iter().any(|u| u == user) {
+            return Ok(resource);
+        }
+        let used_by = resource
+            .spec
+            .used_by
+            .iter()
+            .filter(|u| *u != user)
+            .cloned()
+            .collect();
+        self.patch_used_by(&resource, used_by, "remove user").await
+    }
+
+    /// Returns `true` if no tests are using the TestSys [`Resource`] `name`, i.e. it is safe to
+    /// destroy the resource.
+    pub async fn can_destroy(&self, name: &str) -> Result<bool> {
+        Ok(self.get(name).await?.spec.used_by.is_empty())
+    }
+
+    /// Replace `spec.usedBy` with `used_by`, testing that `resource` has not been modified since it
+    /// was fetched so that concurrent changes to the list are not lost.
+    async fn patch_used_by(
+        &self,
+        resource: &Resource,
+        used_by: Vec<String>,
+        description: &str,
+    ) -> Result<Resource> {
+        self.patch(
+            resource.name_any(),
+            vec![
+                JsonPatch::new_test_operation(
+                    "/metadata/resourceVersion",
+                    resource.resource_version(),
+                ),
+                JsonPatch::new_add_operation("/spec/usedBy", used_by),
+            ],
+            description,
+        )
+        .await
+    }
+
+    /// Mark the creation of the TestSys [`Resource`] as completed and record the
+    /// `created_resource` in `status.createdResource`.
     pub async fn send_creation_success<R>(
         &self,
         name: &str,
@@ -116,7 +236,7 @@ impl ResourceClient {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation("/status/creation/taskState", TaskState::Completed),
                 JsonPatch::new_add_operation("/status/createdResource", created_resource),
             ],
@@ -125,6 +245,7 @@ impl ResourceClient {
         .await
     }
 
+    /// Get the created resource that the resource agent recorded with `send_creation_success`.
     pub async fn get_created_resource<R>(&self, name: &str) -> Result<Option<R>>
     where
         R: Configuration,
@@ -137,6 +258,41 @@ impl ResourceClient {
         Ok(Some(R::from_map(map).context(error::ConfigSerdeSnafu)?))
     }
 
+    /// Wait for the resource agent to finish creating the TestSys [`Resource`] and return the
+    /// created resource it recorded with `send_creation_success`. The resource is watched using the
+    /// k8s watch API. If the watch disconnects, the resource is fetched again before a new watch is
+    /// started. Returns an error if the creation fails, if the resource is deleted, or if the
+    /// resource has not been created within `timeout`.
+    pub async fn wait_for_creation<R>(&self, name: &str, timeout: Duration) -> Result<R>
+    where
+        R: Configuration,
+    {
+        tokio::time::timeout(timeout, self.watch_for_creation(name))
+            .await
+            .map_err(|_| error::CreationTimeoutSnafu { name, timeout }.build())?
+    }
+
+    async fn watch_for_creation<R>(&self, name: &str) -> Result<R>
+    where
+        R: Configuration,
+    {
+        watch_until(
+            self,
+            name,
+            |resource| creation_result(name, resource),
+            || {
+                error::CreationFailedSnafu {
+                    name,
+                    reason: "the resource was deleted",
+                }
+                .build()
+                .into()
+            },
+        )
+        .await
+    }
+
+    /// Set the task state of `resource_action` to `Error` and record the `error`.
     pub async fn send_error(
         &self,
         name: &str,
@@ -157,7 +313,7 @@ impl ResourceClient {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation(error_path, error),
                 JsonPatch::new_add_operation(task_state_path, TaskState::Error),
             ],
@@ -166,6 +322,43 @@ impl ResourceClient {
         .await
     }
 
+    /// Set the state of the step `step` of the `resource_action` task of the TestSys
+    /// [`Resource`], adding the step if it has not been reported before, see
+    /// `ResourceStatus::update_step`. The resource agent is expected to be the only writer of
+    /// its steps.
+    pub async fn send_step_update(
+        &self,
+        name: &str,
+        resource_action: ResourceAction,
+        step: &str,
+        state: TaskState,
+    ) -> Result<Resource> {
+        trace!(
+            "patching {:?} step '{}' to '{:?}' for resource '{}'",
+            resource_action,
+            step,
+            state,
+            name
+        );
+        let mut status = self.get_status(name).await?;
+        let path = match resource_action {
+            ResourceAction::Create => "/status/creationSteps",
+            ResourceAction::Destroy => "/status/destructionSteps",
+        };
+        let now = self.clock().now();
+        status.update_step(resource_action, step, state, now);
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(now),
+                JsonPatch::new_add_operation(path, status.steps(resource_action)),
+            ],
+            "send step update",
+        )
+        .await
+    }
+
+    /// Set the task state of the `op` (creation or destruction) of the TestSys [`Resource`].
     pub async fn send_task_state(
         &self,
         name: &str,
@@ -187,7 +380,7 @@ impl ResourceClient {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation(path, state),
             ],
             "send task state",
@@ -274,6 +467,45 @@ impl ResourceClient {
     }
 }
 
+/// Returns the created resource once the creation of `resource` has completed, `None` while it is
+/// still being created, or an error if the creation failed.
+fn creation_result<R>(name: &str, resource: &Resource) -> Result<Option<R>>
+where
+    R: Configuration,
+{
+    match resource.creation_task_state() {
+        TaskState::Unknown | TaskState::Running => Ok(None),
+        TaskState::Completed => {
+            let created_resource =
+                resource
+                    .created_resource()
+                    .cloned()
+                    .context(error::CreationFailedSnafu {
+                        name,
+                        reason: "the resource agent did not report a created resource",
+                    })?;
+            Ok(Some(
+                R::from_map(created_resource).context(error::ConfigSerdeSnafu)?,
+            ))
+        }
+        TaskState::Error => Err(error::CreationFailedSnafu {
+            name,
+            reason: resource
+                .creation_error()
+                .map(|error| error.error.clone())
+                .unwrap_or_else(|| "the resource agent reported an error".to_string()),
+        }
+        .build()
+        .into()),
+        TaskState::Cancelled => Err(error::CreationFailedSnafu {
+            name,
+            reason: "the creation was cancelled",
+        }
+        .build()
+        .into()),
+    }
+}
+
 fn resource_name_and_field_name(input: &str) -> Result<Option<(String, String)>> {
     let captures = match REGEX.captures(input) {
         None => return Ok(None),
@@ -294,6 +526,73 @@ fn resource_name_and_field_name(input: &str) -> Result<Option<(String, String)>>
     Ok(Some((resource_name.to_string(), field_name.to_string())))
 }
 
+/// Returns an error if the dependency graph, a map of object name to the names of the objects it
+/// depends on, contains a cycle. Dependencies that are not in the map are ignored. `kind` is used
+/// in the error message, e.g. `Resource`.
+pub(super) fn check_dependency_cycles(
+    kind: &'static str,
+    dependencies: &BTreeMap<String, Vec<String>>,
+) -> Result<()> {
+    fn visit<'a>(
+        kind: &'static str,
+        name: &'a str,
+        dependencies: &'a BTreeMap<String, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        visited: &mut BTreeSet<&'a str>,
+    ) -> Result<()> {
+        if let Some(position) = path.iter().position(|&seen| seen == name) {
+            let mut cycle = path[position..].to_vec();
+            cycle.push(name);
+            return Err(error::DependencyCycleSnafu {
+                kind,
+                cycle: cycle.join(" -> "),
+            }
+            .build()
+            .into());
+        }
+        if !visited.insert(name) {
+            return Ok(());
+        }
+        path.push(name);
+        for dependency in dependencies.get(name).into_iter().flatten() {
+            visit(kind, dependency, dependencies, path, visited)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    let mut visited = BTreeSet::new();
+    for name in dependencies.keys() {
+        visit(kind, name, dependencies, &mut Vec::new(), &mut visited)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_linear_dependencies() {
+    let dependencies = BTreeMap::from([
+        ("cluster".to_string(), vec!["vpc".to_string()]),
+        ("instances".to_string(), vec!["cluster".to_string()]),
+        ("vpc".to_string(), vec![]),
+    ]);
+    assert!(check_dependency_cycles("Resource", &dependencies).is_ok());
+}
+
+#[test]
+fn test_cyclic_dependencies() {
+    let dependencies = BTreeMap::from([
+        ("cluster".to_string(), vec!["vpc".to_string()]),
+        ("instances".to_string(), vec!["cluster".to_string()]),
+        ("vpc".to_string(), vec!["instances".to_string()]),
+    ]);
+    assert_eq!(
+        check_dependency_cycles("Resource", &dependencies)
+            .unwrap_err()
+            .to_string(),
+        "Resource dependencies contain a cycle: cluster -> vpc -> instances -> cluster"
+    );
+}
+
 #[test]
 fn test_pattern1() {
     let (resource_name, field_name) = resource_name_and_field_name(r"${dup1.info}")
@@ -322,17 +621,51 @@ impl CrdClient for ResourceClient {
     type Crd = Resource;
     type CrdStatus = ResourceStatus;
 
-    fn new_from_api(api: Api<Self::Crd>) -> Self {
-        Self { api }
+    fn new_from_api(api: Api<Self::Crd>, namespace: &str) -> Self {
+        Self {
+            api,
+            namespace: namespace.to_string(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
     }
 
     fn kind(&self) -> &'static str {
         "resource"
     }
 
+    /// The creation and destruction task states must each be able to transition to the ones in
+    /// `status`.
+    fn check_status_transition(&self, current: &Self::Crd, status: &Self::CrdStatus) -> Result<()> {
+        for (from, to) in [
+            (current.creation_task_state(), status.creation.task_state),
+            (
+                current.destruction_task_state(),
+                status.destruction.task_state,
+            ),
+        ] {
+            ensure!(
+                from.can_transition_to(to),
+                error::IllegalTransitionSnafu {
+                    name: current.name_any(),
+                    from,
+                    to
+                }
+            );
+        }
+        Ok(())
+    }
+
     fn api(&self) -> &Api<Self::Crd> {
         &self.api
     }
+
+    fn namespace(&self) -> &str {
+        &self.namespace
+    }
 }
 
 pub fn create_resource_crd<S1>(
@@ -371,6 +704,7 @@ mod test {
     use std::fmt::Debug;
 
     const CLUSTER_NAME: &str = "resource-client";
+    const WAIT_CLUSTER_NAME: &str = "resource-client-wait";
     const RESOURCE_NAME: &str = "my-resource";
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
@@ -414,7 +748,10 @@ mod test {
         let k8s_client = cluster.k8s_client().await.unwrap();
         let ns_api: Api<Namespace> = Api::all(k8s_client.clone());
         ns_api
-            .create(&PostParams::default(), &crate::system::testsys_namespace())
+            .create(
+                &PostParams::default(),
+                &crate::system::testsys_namespace(NAMESPACE),
+            )
             .await
             .unwrap();
         cluster
@@ -504,6 +841,47 @@ mod test {
             TaskState::Running,
         ));
 
+        // Steps are appended in the order they are first reported and updated in place by name.
+        rc.send_step_update(
+            RESOURCE_NAME,
+            ResourceAction::Create,
+            "vpc",
+            TaskState::Running,
+        )
+        .await
+        .unwrap();
+        rc.send_step_update(
+            RESOURCE_NAME,
+            ResourceAction::Create,
+            "nodegroup",
+            TaskState::Running,
+        )
+        .await
+        .unwrap();
+        let resource = rc
+            .send_step_update(
+                RESOURCE_NAME,
+                ResourceAction::Create,
+                "vpc",
+                TaskState::Completed,
+            )
+            .await
+            .unwrap();
+        let steps = resource.steps(ResourceAction::Create);
+        assert_eq!(
+            steps
+                .iter()
+                .map(|step| (step.name.as_str(), step.state))
+                .collect::<Vec<_>>(),
+            vec![
+                ("vpc", TaskState::Completed),
+                ("nodegroup", TaskState::Running)
+            ]
+        );
+        assert!(steps[0].finished_at.is_some());
+        assert!(steps[1].finished_at.is_none());
+        assert!(resource.steps(ResourceAction::Destroy).is_empty());
+
         rc.send_task_state(RESOURCE_NAME, ResourceAction::Destroy, TaskState::Running)
             .await
             .unwrap();
@@ -566,6 +944,20 @@ mod test {
         assert_eq!(status.destruction.error.unwrap(), destroy_error);
         assert!(matches!(status.destruction.task_state, TaskState::Error));
 
+        // A resource can only be destroyed once every test has stopped using it.
+        assert!(rc.can_destroy(RESOURCE_NAME).await.unwrap());
+        rc.add_user(RESOURCE_NAME, "test-a").await.unwrap();
+        rc.add_user(RESOURCE_NAME, "test-b").await.unwrap();
+        let resource = rc.add_user(RESOURCE_NAME, "test-a").await.unwrap();
+        assert_eq!(resource.spec.used_by, vec!["test-a", "test-b"]);
+        assert!(!rc.can_destroy(RESOURCE_NAME).await.unwrap());
+        let resource = rc.remove_user(RESOURCE_NAME, "test-a").await.unwrap();
+        assert_eq!(resource.spec.used_by, vec!["test-b"]);
+        assert!(!rc.can_destroy(RESOURCE_NAME).await.unwrap());
+        rc.remove_user(RESOURCE_NAME, "not-a-user").await.unwrap();
+        rc.remove_user(RESOURCE_NAME, "test-b").await.unwrap();
+        assert!(rc.can_destroy(RESOURCE_NAME).await.unwrap());
+
         // Add a finalizer
         rc.add_finalizer("foobar", &rc.get(RESOURCE_NAME).await.unwrap())
             .await
@@ -593,5 +985,126 @@ mod test {
             .remove_finalizer("foobar", &rc.get(RESOURCE_NAME).await.unwrap())
             .await
             .is_err());
+
+        // The idempotent helpers can add and remove a finalizer more than once.
+        rc.ensure_finalizer(RESOURCE_NAME, "foobar").await.unwrap();
+        let resource = rc.ensure_finalizer(RESOURCE_NAME, "foobar").await.unwrap();
+        assert_eq!(
+            resource
+                .finalizers()
+                .iter()
+                .filter(|finalizer| *finalizer == "foobar")
+                .count(),
+            1
+        );
+        rc.ensure_finalizer_removed(RESOURCE_NAME, "foobar")
+            .await
+            .unwrap();
+        let resource = rc
+            .ensure_finalizer_removed(RESOURCE_NAME, "foobar")
+            .await
+            .unwrap();
+        assert!(!resource.has_finalizer("foobar"));
+    }
+
+    #[tokio::test]
+    async fn wait_for_creation() {
+        let cluster = Cluster::new(WAIT_CLUSTER_NAME).unwrap();
+        let k8s_client = cluster.k8s_client().await.unwrap();
+        let ns_api: Api<Namespace> = Api::all(k8s_client.clone());
+        ns_api
+            .create(
+                &PostParams::default(),
+                &crate::system::testsys_namespace(NAMESPACE),
+            )
+            .await
+            .unwrap();
+        cluster
+            .wait_for_object::<Namespace>(
+                NAMESPACE,
+                cluster.api().await.unwrap(),
+                tokio::time::Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        let crd_api: Api<CustomResourceDefinition> = Api::all(k8s_client.clone());
+        crd_api
+            .create(&PostParams::default(), &Resource::crd())
+            .await
+            .unwrap();
+        cluster
+            .wait_for_object::<CustomResourceDefinition>(
+                "resources.testsys.system",
+                cluster.api().await.unwrap(),
+                tokio::time::Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        let rc = ResourceClient::new_from_k8s_client(k8s_client);
+        for name in ["created", "failed"] {
+            rc.create(Resource {
+                metadata: ObjectMeta {
+                    name: Some(name.into()),
+                    ..ObjectMeta::default()
+                },
+                spec: ResourceSpec {
+                    agent: Agent {
+                        name: "my-agent".into(),
+                        image: "foo:v0.1.0".into(),
+                        configuration: Some(ROBOT_REQUEST.into_map().unwrap()),
+                        ..Agent::default()
+                    },
+                    ..Default::default()
+                },
+                ..Resource::default()
+            })
+            .await
+            .unwrap();
+            rc.initialize_status(name).await.unwrap();
+            rc.send_task_state(name, ResourceAction::Create, TaskState::Running)
+                .await
+                .unwrap();
+        }
+
+        // Waiting fails while the resource agent is still creating the resource.
+        assert!(rc
+            .wait_for_creation::<CreatedRobot>("created", Duration::from_secs(1))
+            .await
+            .is_err());
+
+        // Act as the resource agent and finish creating the resource while it is being waited on.
+        let agent_rc = rc.clone();
+        let agent = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            agent_rc
+                .send_creation_success("created", CREATED_ROBOT)
+                .await
+                .unwrap();
+        });
+        assert_eq!(
+            rc.wait_for_creation::<CreatedRobot>("created", Duration::from_secs(30))
+                .await
+                .unwrap(),
+            CREATED_ROBOT
+        );
+        agent.await.unwrap();
+
+        // The error reported by the resource agent is returned.
+        rc.send_error(
+            "failed",
+            ResourceAction::Create,
+            &ResourceError {
+                error: "c".to_string(),
+                error_resources: ErrorResources::Clear,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(rc
+            .wait_for_creation::<CreatedRobot>("failed", Duration::from_secs(30))
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("was not created: c"));
     }
 }
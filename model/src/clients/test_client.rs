@@ -1,11 +1,37 @@
-use super::error::Result;
-use crate::clients::crd_client::JsonPatch;
-use crate::clients::CrdClient;
+use super::error::{self, Result};
+use crate::clients::crd_client::{watch_until, JsonPatch};
+use crate::clients::resource_client::check_dependency_cycles;
+use crate::clients::{AllowNotFound, CrdClient};
+use crate::configuration::{json_byte_size, validate_value};
 use crate::constants::NAMESPACE;
-use crate::{AgentStatus, TaskState, Test, TestResults, TestSpec, TestStatus};
+use crate::{
+    Agent, AgentStatus, Clock, LogsLocation, Outcome, RetryPolicy, SecretName, SecretType,
+    SystemClock, TaskState, Test, TestResults, TestSpec, TestStatus,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{future, Stream, StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{Container, Toleration};
+use kube::api::PostParams;
 use kube::core::ObjectMeta;
-use kube::Api;
-use std::collections::BTreeMap;
+use kube::runtime::{watcher, WatchStreamExt};
+use kube::{Api, ResourceExt};
+use log::debug;
+use serde_json::Value;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// The maximum number of gets that `get_many` has in flight at once.
+const GET_MANY_CONCURRENCY: usize = 8;
+
+/// The number of tests requested per page by `count_by_state`.
+const COUNT_PAGE_SIZE: u32 = 500;
+
+/// The largest serialized `spec` that `create` accepts unless the limit is changed with
+/// `TestClient::with_max_spec_bytes`. This leaves room for the metadata and status within etcd's
+/// default limit of 1.5 MiB per object.
+pub const DEFAULT_MAX_SPEC_BYTES: usize = 1024 * 1024;
 
 /// An API Client for TestSys Test CRD objects.
 ///
@@ -21,9 +47,46 @@ use std::collections::BTreeMap;
 #[derive(Clone)]
 pub struct TestClient {
     api: Api<Test>,
+    namespace: String,
+    clock: Arc<dyn Clock>,
+    max_spec_bytes: usize,
 }
 
 impl TestClient {
+    /// The current time of the client's clock as an RFC 3339 timestamp.
+    fn timestamp(&self) -> String {
+        self.clock()
+            .now()
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+
+    /// Use `clock` instead of the system time for the timestamps written by this client, e.g. the
+    /// start, finish and heartbeat times of the test agent.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Change the largest serialized `spec` that `create` accepts from `DEFAULT_MAX_SPEC_BYTES`,
+    /// e.g. for a cluster whose etcd allows larger objects.
+    pub fn with_max_spec_bytes(mut self, max_spec_bytes: usize) -> Self {
+        self.max_spec_bytes = max_spec_bytes;
+        self
+    }
+
+    /// Create the [`Test`] without the checks that `create` makes, leaving validation to the API
+    /// server, e.g. to see how the controller handles a test whose agent cannot start.
+    pub async fn create_unchecked(&self, test: Test) -> Result<Test> {
+        Ok(self
+            .api
+            .create(&PostParams::default(), &test)
+            .await
+            .context(error::KubeApiCallSnafu {
+                method: "create",
+                what: self.kind(),
+            })?)
+    }
+
     /// Mark the TestSys [`Test`] as ok to delete by setting the `keep_running`
     /// flag to false
     pub async fn send_keep_running<S>(&self, name: S, keep_running: bool) -> Result<Test>
@@ -41,6 +104,38 @@ impl TestClient {
         .await
     }
 
+    /// Set the TestSys [`Test`]'s `keep_running` flag for at most `duration`. The duration is
+    /// stored in `spec.agent.keepRunningTimeoutSeconds` and the resulting expiry time is written
+    /// to `status.agent.keepRunningExpiry`.
+    pub async fn send_keep_running_for<S>(
+        &self,
+        name: S,
+        keep_running: bool,
+        duration: Duration,
+    ) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        let name = name.as_ref();
+        let expiry = keep_running_expiry(self.clock().now().into(), duration)
+            .context(error::KeepRunningDurationSnafu { duration })?;
+        let agent = self.get(name).await?.spec.agent;
+        let spec_patches = keep_running_patches(&agent, keep_running, duration);
+        if !spec_patches.is_empty() {
+            self.patch(name, spec_patches, "set 'keep running' with timeout")
+                .await?;
+        }
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(self.clock().now()),
+                JsonPatch::new_add_operation("/status/agent/keepRunningExpiry", expiry),
+            ],
+            "send 'keep running' expiry",
+        )
+        .await
+    }
+
     /// Get the TestSys [`Test`]'s `status.agent` field.
     pub async fn get_agent_status<S>(&self, name: S) -> Result<AgentStatus>
     where
@@ -49,11 +144,297 @@ impl TestClient {
         Ok(self.get(name).await?.status.unwrap_or_default().agent)
     }
 
+    /// Get the results of every run of the TestSys [`Test`], oldest first. This includes the
+    /// results of previous runs that were retried followed by the results of the current run.
+    pub async fn get_results_history<S>(&self, name: S) -> Result<Vec<TestResults>>
+    where
+        S: AsRef<str> + Send,
+    {
+        let agent_status = self.get_agent_status(name).await?;
+        Ok(agent_status
+            .results_history
+            .into_iter()
+            .chain(agent_status.results)
+            .collect())
+    }
+
+    /// Get each of the TestSys [`Test`]s in `names`, returning the name of each test along with
+    /// the test, or `None` if it does not exist. The results are in the same order as `names`. At
+    /// most `GET_MANY_CONCURRENCY` gets are run at the same time.
+    pub async fn get_many(&self, names: &[&str]) -> Result<Vec<(String, Option<Test>)>> {
+        futures::stream::iter(names)
+            .map(|name| async move {
+                self.get(name)
+                    .await
+                    .allow_not_found(|_| ())
+                    .map(|test| (name.to_string(), test))
+            })
+            .buffered(GET_MANY_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+
+    /// Wait for the TestSys [`Test`] to finish and return the results of its latest run. The test
+    /// is watched using the k8s watch API. If the watch disconnects, the test is fetched again
+    /// before a new watch is started. Returns an error if the test agent reports an error, if the
+    /// test is cancelled or deleted, or if the test has not finished within `timeout`.
+    pub async fn wait_for_completion(&self, name: &str, timeout: Duration) -> Result<TestResults> {
+        tokio::time::timeout(timeout, self.watch_for_completion(name))
+            .await
+            .map_err(|_| error::WaitTimeoutSnafu { name, timeout }.build())?
+    }
+
+    async fn watch_for_completion(&self, name: &str) -> Result<TestResults> {
+        watch_until(
+            self,
+            name,
+            |test| completion_results(name, test),
+            || {
+                error::WaitFailedSnafu {
+                    name,
+                    reason: "the test was deleted",
+                }
+                .build()
+                .into()
+            },
+        )
+        .await
+    }
+
+    /// Get the secrets that are made available to the TestSys [`Test`]'s agent, a map of the
+    /// agent-defined `SecretType` to the name of a Kubernetes secret.
+    pub async fn get_secrets(&self, name: &str) -> Result<BTreeMap<SecretType, SecretName>> {
+        Ok(self.get(name).await?.spec.agent.secrets.unwrap_or_default())
+    }
+
+    /// Make the Kubernetes secret `secret_name` available to the TestSys [`Test`]'s agent as
+    /// `secret_type`. An existing secret of the same type is replaced. Returns an error if
+    /// `secret_name` is not a valid Kubernetes secret name (a DNS-1123 subdomain).
+    pub async fn add_secret(
+        &self,
+        name: &str,
+        secret_type: SecretType,
+        secret_name: SecretName,
+    ) -> Result<Test> {
+        ensure!(
+            is_dns_subdomain(secret_name.as_str()),
+            error::InvalidSecretNameSnafu {
+                secret_name: secret_name.as_str()
+            }
+        );
+        let mut secrets = self.get_secrets(name).await?;
+        secrets.insert(secret_type, secret_name);
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation("/spec/agent/secrets", secrets)],
+            "add secret",
+        )
+        .await
+    }
+
+    /// Count the TestSys [`Test`]s in each [`TaskState`]. The tests are listed in pages so that
+    /// they are not all held in memory at once. Every state is included, with a count of 0 if no
+    /// test is in it.
+    pub async fn count_by_state(&self) -> Result<BTreeMap<TaskState, usize>> {
+        let mut counts: BTreeMap<TaskState, usize> =
+            TaskState::ALL.iter().map(|state| (*state, 0)).collect();
+        let mut continue_token = None;
+        loop {
+            let (tests, next_token) = self.list_paged(COUNT_PAGE_SIZE, continue_token).await?;
+            for test in tests {
+                *counts.entry(test.agent_status().task_state).or_default() += 1;
+            }
+            if next_token.is_none() {
+                return Ok(counts);
+            }
+            continue_token = next_token;
+        }
+    }
+
+    /// Set the extra environment variables that the controller passes to the agent container of
+    /// the TestSys [`Test`], replacing any that were set before. Returns an error, without
+    /// modifying the test, if any of the names is not a valid environment variable name.
+    pub async fn set_env(&self, name: &str, env: BTreeMap<String, String>) -> Result<Test> {
+        check_env(&env)?;
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation("/spec/agent/env", env)],
+            "set env",
+        )
+        .await
+    }
+
+    /// Set the containers that run before the agent container of the TestSys [`Test`], replacing
+    /// any that were set before. Returns an error, without modifying the test, if a container does
+    /// not have a name and an image.
+    pub async fn set_init_containers(
+        &self,
+        name: &str,
+        init_containers: Vec<Container>,
+    ) -> Result<Test> {
+        check_init_containers(&init_containers)?;
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/agent/initContainers",
+                init_containers,
+            )],
+            "set init containers",
+        )
+        .await
+    }
+
+    /// Set the containers that run alongside the agent container of the TestSys [`Test`], e.g. to
+    /// ship its logs, replacing any that were set before. Returns an error, without modifying the
+    /// test, if a container does not have a name and an image or if its name is already used by
+    /// another container of the agent pod.
+    pub async fn set_sidecars(&self, name: &str, sidecars: Vec<Container>) -> Result<Test> {
+        let test = self.get(name).await?;
+        check_sidecars(name, &test.spec.agent.init_containers, &sidecars)?;
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/agent/sidecars",
+                sidecars,
+            )],
+            "set sidecars",
+        )
+        .await
+    }
+
+    /// Set the node labels that the agent pod of the TestSys [`Test`] must be scheduled on,
+    /// replacing any that were set before.
+    pub async fn set_node_selector(
+        &self,
+        name: &str,
+        node_selector: BTreeMap<String, String>,
+    ) -> Result<Test> {
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/agent/nodeSelector",
+                node_selector,
+            )],
+            "set node selector",
+        )
+        .await
+    }
+
+    /// Set the policy the controller uses to retry the TestSys [`Test`] when it fails, replacing
+    /// any that was set before. A `retry_policy` of `None` turns automatic retries off.
+    pub async fn set_retry_policy(
+        &self,
+        name: &str,
+        retry_policy: Option<RetryPolicy>,
+    ) -> Result<Test> {
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/retryPolicy",
+                retry_policy,
+            )],
+            "set retry policy",
+        )
+        .await
+    }
+
+    /// Set the tolerations of the agent pod of the TestSys [`Test`], replacing any that were set
+    /// before.
+    pub async fn set_tolerations(&self, name: &str, tolerations: Vec<Toleration>) -> Result<Test> {
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/agent/tolerations",
+                tolerations,
+            )],
+            "set tolerations",
+        )
+        .await
+    }
+
+    /// Set the tests that must pass before the TestSys [`Test`] `name` is run. An error is
+    /// returned, without modifying the test, if `name` depends on itself or if the dependencies of
+    /// the tests in the cluster would contain a cycle.
+    pub async fn set_depends_on(&self, name: &str, depends_on: Vec<String>) -> Result<Test> {
+        ensure!(
+            !depends_on.iter().any(|dependency| dependency == name),
+            error::SelfDependencySnafu { name }
+        );
+        let mut dependencies: BTreeMap<String, Vec<String>> = self
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|test| (test.name_any(), test.spec.depends_on.unwrap_or_default()))
+            .collect();
+        dependencies.insert(name.to_string(), depends_on.clone());
+        check_dependency_cycles("Test", &dependencies)?;
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation("/spec/dependsOn", depends_on)],
+            "set depends on",
+        )
+        .await
+    }
+
+    /// Returns `true` if every test that the TestSys [`Test`] `name` depends on has completed and
+    /// passed. An error is returned if one of the dependencies does not exist.
+    pub async fn dependencies_satisfied(&self, name: &str) -> Result<bool> {
+        let depends_on = self.get(name).await?.spec.depends_on.unwrap_or_default();
+        for dependency in depends_on {
+            let dependency_test = self
+                .get(&dependency)
+                .await
+                .allow_not_found(|_| ())?
+                .context(error::MissingDependencySnafu { name, dependency })?;
+            if !has_passed(&dependency_test) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Stream the `status` of the TestSys [`Test`]. A new item is yielded each time the status
+    /// changes, starting with the current status. Transient watch errors are retried with a
+    /// backoff. The stream ends when the test is deleted.
+    pub fn stream_status(&self, name: &str) -> impl Stream<Item = Result<TestStatus>> + Send {
+        let name = name.to_string();
+        watcher::watch_object(self.api.clone(), &name)
+            .backoff(watcher::default_backoff())
+            .filter_map(move |event| {
+                future::ready(match event {
+                    Ok(test) => Some(Ok(test)),
+                    // The apiserver returned more than one object for a single name, retrying
+                    // will not help.
+                    Err(e @ watcher::Error::TooManyObjects) => {
+                        Some(Err(Box::new(e)).context(error::WatchSnafu { name: name.clone() }))
+                    }
+                    Err(e) => {
+                        debug!("Retrying watch of test '{}': {}", name, e);
+                        None
+                    }
+                })
+            })
+            .take_while(|test| future::ready(!matches!(test, Ok(None))))
+            .scan(None, |last_status, test| {
+                let status = test.map(|test| test.and_then(|test| test.status).unwrap_or_default());
+                let item = match status {
+                    Ok(status) if last_status.as_ref() == Some(&status) => None,
+                    Ok(status) => {
+                        *last_status = Some(status.clone());
+                        Some(Ok(status))
+                    }
+                    Err(e) => Some(Err(e.into())),
+                };
+                future::ready(Some(item))
+            })
+            .filter_map(future::ready)
+    }
+
     pub async fn send_resource_error(&self, test_name: &str, error: &str) -> Result<Test> {
         self.patch_status(
             test_name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation("/status/controller/resourceError", error),
             ],
             "send resource error",
@@ -61,11 +442,78 @@ impl TestClient {
         .await
     }
 
+    /// Set the agent's task state. Returns an error if the test's current task state cannot
+    /// transition to `task_state`, see [`TaskState::can_transition_to`]. The patch fails if the
+    /// task state is changed concurrently.
     pub async fn send_agent_task_state(&self, name: &str, task_state: TaskState) -> Result<Test> {
+        let test = self.get(name).await?;
+        let current = test.agent_status().task_state;
+        let mut patches = self.task_state_patches(&test, task_state)?;
+        if current != task_state {
+            match task_state {
+                TaskState::Running => {
+                    patches.push(JsonPatch::new_add_operation(
+                        "/status/agent/startedAt",
+                        self.timestamp(),
+                    ));
+                    patches.push(JsonPatch::new_add_operation(
+                        "/status/agent/finishedAt",
+                        Option::<String>::None,
+                    ));
+                    patches.push(JsonPatch::new_add_operation(
+                        "/status/agent/lastHeartbeat",
+                        self.timestamp(),
+                    ));
+                }
+                TaskState::Completed | TaskState::Error | TaskState::Cancelled => patches.push(
+                    JsonPatch::new_add_operation("/status/agent/finishedAt", self.timestamp()),
+                ),
+                TaskState::Unknown => {}
+            }
+        }
+        self.patch_status(name, patches, "send agent task state")
+            .await
+    }
+
+    /// The patches that set the agent's task state of `test` to `task_state`. Returns an error if
+    /// the current task state cannot transition to `task_state`, see
+    /// [`TaskState::can_transition_to`]. The patches fail if the task state is changed
+    /// concurrently.
+    fn task_state_patches(&self, test: &Test, task_state: TaskState) -> Result<Vec<JsonPatch>> {
+        let current = test.agent_status().task_state;
+        ensure!(
+            current.can_transition_to(task_state),
+            error::IllegalTransitionSnafu {
+                name: test.name_any(),
+                from: current,
+                to: task_state
+            }
+        );
+        let mut patches = vec![JsonPatch::new_timestamp(self.clock().now())];
+        if test.status.is_some() {
+            patches.push(JsonPatch::new_test_operation(
+                "/status/agent/taskState",
+                current,
+            ));
+        }
+        patches.push(JsonPatch::new_add_operation(
+            "/status/agent/taskState",
+            task_state,
+        ));
+        Ok(patches)
+    }
+
+    /// Set the agent's task state without checking that the transition is allowed. This is meant
+    /// for the controller to recover tests that are in a bad state.
+    pub async fn send_agent_task_state_forced(
+        &self,
+        name: &str,
+        task_state: TaskState,
+    ) -> Result<Test> {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation("/status/agent/taskState", task_state),
             ],
             "send agent task state",
@@ -77,9 +525,9 @@ impl TestClient {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_remove_operation("/status/agent/currentTest"),
-                JsonPatch::new_add_operation("/status/agent/results/-", results),
+                JsonPatch::new_append_operation("/status/agent/results", results),
             ],
             "send test results",
         )
@@ -90,7 +538,7 @@ impl TestClient {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
                 JsonPatch::new_add_operation("/status/agent/currentTest".to_string(), results),
             ],
             "update test results",
@@ -98,39 +546,289 @@ impl TestClient {
         .await
     }
 
+    /// Mark the TestSys [`Test`] as completed and append `results` to `status.agent.results`. If
+    /// `results` contains test cases, the passed, failed and skipped counts are set from them.
+    /// Returns an error if the test's task state cannot transition to `Completed`.
     pub async fn send_test_completed(&self, name: &str, results: TestResults) -> Result<Test> {
+        let results = results.with_case_counts();
+        let test = self.get(name).await?;
+        let mut patches = self.task_state_patches(&test, TaskState::Completed)?;
+        patches.push(JsonPatch::new_add_operation(
+            "/status/agent/finishedAt",
+            self.timestamp(),
+        ));
+        patches.push(JsonPatch::new_append_operation(
+            "/status/agent/results",
+            results,
+        ));
+        self.patch_status(name, patches, "send test completion results")
+            .await
+    }
+
+    /// Request that the TestSys [`Test`] be run again in place by incrementing `spec.retryCount`.
+    /// Once the controller has removed the previous test agent it resets the agent's status, see
+    /// `send_retry_observed`. Every test case is run, even if the previous run was limited by
+    /// `rerun_failed_cases_only`. Returns an error if the test is currently running or if a retry
+    /// is already pending.
+    pub async fn retry<S>(&self, name: S) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        let name = name.as_ref();
+        let test = self.get(name).await?;
+        self.retry_cases(test, Vec::new()).await
+    }
+
+    /// Like `retry`, but the agent is asked to run only the test cases that failed in the latest
+    /// run, which are written to `spec.agent.rerunCases`. Returns an error if the latest run did
+    /// not report any failed cases.
+    pub async fn rerun_failed_cases_only(&self, name: &str) -> Result<Test> {
+        let test = self.get(name).await?;
+        let rerun_cases = failed_case_names(&test);
+        ensure!(!rerun_cases.is_empty(), error::NoFailedCasesSnafu { name });
+        self.retry_cases(test, rerun_cases).await
+    }
+
+    /// Request a retry of `test`, limiting the next run to `rerun_cases` unless it is empty. The
+    /// request is a single patch that only applies if the task state and retry count have not
+    /// changed since `test` was read.
+    async fn retry_cases(&self, test: Test, rerun_cases: Vec<String>) -> Result<Test> {
+        let name = test.name_any();
+        let name = name.as_str();
+        let task_state = test.agent_status().task_state;
+        ensure!(
+            task_state != TaskState::Running,
+            error::RetryRunningSnafu { name }
+        );
+        let retry_count = test.spec.retry_count.unwrap_or_default();
+        ensure!(
+            retry_count == observed_retry_count(&test),
+            error::RetryPendingSnafu { name }
+        );
+        self.patch(
+            name,
+            vec![
+                JsonPatch::new_test_operation("/status/agent/taskState", task_state),
+                JsonPatch::new_test_operation("/spec/retryCount", test.spec.retry_count),
+                JsonPatch::new_add_operation("/spec/agent/rerunCases", rerun_cases),
+                JsonPatch::new_add_operation("/spec/agent/cancelRequested", false),
+                JsonPatch::new_add_operation("/spec/retryCount", retry_count + 1),
+            ],
+            "request retry",
+        )
+        .await
+    }
+
+    /// Mark the TestSys [`Test`] as completed without running it and record why it was skipped
+    /// in `status.agent.skipReason`. Returns an error if the test has already started.
+    pub async fn mark_skipped<S>(&self, name: S, reason: &str) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        let name = name.as_ref();
+        let task_state = self.get(name).await?.agent_status().task_state;
+        ensure!(
+            task_state == TaskState::Unknown,
+            error::SkipStartedSnafu { name, task_state }
+        );
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
+                JsonPatch::new_timestamp(self.clock().now()),
+                JsonPatch::new_test_operation("/status/agent/taskState", TaskState::Unknown),
                 JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Completed),
-                JsonPatch::new_add_operation("/status/agent/results/-", results),
+                JsonPatch::new_add_operation("/status/agent/skipReason", reason),
             ],
-            "send test completion results",
+            "mark skipped",
         )
         .await
     }
 
-    pub async fn send_agent_error(&self, name: &str, error: &str) -> Result<Test> {
+    /// Set how long the TestSys [`Test`] is allowed to run before the controller marks it as timed
+    /// out. This is written to `spec.agent.timeout` as a whole number of seconds, e.g. `600s`.
+    pub async fn set_timeout<S>(&self, name: S, timeout: Duration) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/agent/timeout",
+                format!("{}s", timeout.as_secs()),
+            )],
+            "set timeout",
+        )
+        .await
+    }
+
+    /// Mark the TestSys [`Test`] as having exceeded its `spec.agent.timeout`.
+    pub async fn send_timed_out<S>(&self, name: S) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(self.clock().now()),
+                JsonPatch::new_add_operation("/status/agent/timedOut", true),
+            ],
+            "send timed out",
+        )
+        .await
+    }
+
+    /// Cancel the TestSys [`Test`]. This sets `spec.agent.cancelRequested` so that the test agent
+    /// knows to stop and clean up. The controller then sets the agent's task state to `Cancelled`,
+    /// see `send_cancelled`.
+    pub async fn send_cancel<S>(&self, name: S) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.patch(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/spec/agent/cancelRequested",
+                true,
+            )],
+            "request cancellation",
+        )
+        .await
+    }
+
+    /// Set the agent's task state to `Cancelled` after the cancellation requested with
+    /// `send_cancel` has been observed. Like `send_agent_task_state`, this fails if the task state
+    /// cannot transition to `Cancelled` or was changed concurrently, e.g. to `Completed`.
+    pub async fn send_cancelled<S>(&self, name: S) -> Result<Test>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.send_agent_task_state(name.as_ref(), TaskState::Cancelled)
+            .await
+    }
+
+    /// Record that the controller has acted on the `retry_count` found in the [`Test`]'s spec. The
+    /// agent's task state and error are cleared and the results are moved to
+    /// `status.agent.resultsHistory` in the same patch, so the test is never seen half reset.
+    pub async fn send_retry_observed(&self, name: &str, retry_count: u32) -> Result<Test> {
+        let test = self.get(name).await?;
+        let agent_status = test.agent_status();
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(self.clock().now()),
+                JsonPatch::new_test_operation(
+                    "/status/controller/observedRetryCount",
+                    test.status
+                        .as_ref()
+                        .and_then(|status| status.controller.observed_retry_count),
+                ),
+                JsonPatch::new_add_operation("/status/controller/observedRetryCount", retry_count),
+                JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Unknown),
+                JsonPatch::new_add_operation(
+                    "/status/agent/resultsHistory",
+                    agent_status
+                        .results_history
+                        .iter()
+                        .chain(agent_status.results.iter())
+                        .collect::<Vec<_>>(),
+                ),
+                JsonPatch::new_add_operation("/status/agent/results", Vec::<TestResults>::new()),
+                JsonPatch::new_add_operation("/status/agent/error", Option::<String>::None),
+                JsonPatch::new_add_operation(
+                    "/status/agent/currentTest",
+                    Option::<TestResults>::None,
+                ),
+                JsonPatch::new_add_operation("/status/agent/startedAt", Option::<String>::None),
+                JsonPatch::new_add_operation("/status/agent/finishedAt", Option::<String>::None),
+                JsonPatch::new_add_operation("/status/agent/lastHeartbeat", Option::<String>::None),
+                JsonPatch::new_add_operation("/status/agent/progress", Option::<u8>::None),
+            ],
+            "reset status for retry",
+        )
+        .await
+    }
+
+    /// Record that the test agent is still alive by setting `status.agent.lastHeartbeat` to the
+    /// current time.
+    pub async fn send_heartbeat(&self, name: &str) -> Result<Test> {
+        self.patch_status(
+            name,
+            vec![JsonPatch::new_add_operation(
+                "/status/agent/lastHeartbeat",
+                self.timestamp(),
+            )],
+            "send heartbeat",
+        )
+        .await
+    }
+
+    /// Report how far the test agent is through its task, in percent. Values above 100 are
+    /// recorded as 100.
+    pub async fn send_progress(&self, name: &str, percent: u8) -> Result<Test> {
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(self.clock().now()),
+                JsonPatch::new_add_operation("/status/agent/progress", percent.min(100)),
+            ],
+            "send progress",
+        )
+        .await
+    }
+
+    /// Record where the logs of the test agent are stored, e.g. after they were uploaded to S3.
+    pub async fn send_logs_location(&self, name: &str, location: &LogsLocation) -> Result<Test> {
         self.patch_status(
             name,
             vec![
-                JsonPatch::new_timestamp(),
-                JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Error),
-                JsonPatch::new_add_operation("/status/agent/error", error),
+                JsonPatch::new_timestamp(self.clock().now()),
+                JsonPatch::new_add_operation("/status/agent/logsLocation", location),
             ],
-            "send agent error",
+            "send logs location",
         )
         .await
     }
+
+    /// Get where the logs of the test agent are stored. Tests whose agent has not reported a
+    /// location have their logs in the agent pod.
+    pub async fn get_logs_location(&self, name: &str) -> Result<LogsLocation> {
+        Ok(self
+            .get_agent_status(name)
+            .await?
+            .logs_location
+            .unwrap_or_default())
+    }
+
+    /// Set the agent's task state to `Error` and record `error`. Returns an error if the test's
+    /// task state cannot transition to `Error`.
+    pub async fn send_agent_error(&self, name: &str, error: &str) -> Result<Test> {
+        let test = self.get(name).await?;
+        let mut patches = self.task_state_patches(&test, TaskState::Error)?;
+        patches.push(JsonPatch::new_add_operation("/status/agent/error", error));
+        patches.push(JsonPatch::new_add_operation(
+            "/status/agent/finishedAt",
+            self.timestamp(),
+        ));
+        self.patch_status(name, patches, "send agent error").await
+    }
 }
 
+#[async_trait::async_trait]
 impl CrdClient for TestClient {
     type Crd = Test;
     type CrdStatus = TestStatus;
 
-    fn new_from_api(api: Api<Self::Crd>) -> Self {
-        Self { api }
+    fn new_from_api(api: Api<Self::Crd>, namespace: &str) -> Self {
+        Self {
+            api,
+            namespace: namespace.to_string(),
+            clock: Arc::new(SystemClock),
+            max_spec_bytes: DEFAULT_MAX_SPEC_BYTES,
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
     }
 
     fn kind(&self) -> &'static str {
@@ -140,6 +838,723 @@ impl CrdClient for TestClient {
     fn api(&self) -> &Api<Self::Crd> {
         &self.api
     }
+
+    fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The agent's task state must be able to transition to the one in `status`.
+    fn check_status_transition(&self, current: &Self::Crd, status: &Self::CrdStatus) -> Result<()> {
+        let from = current.agent_status().task_state;
+        let to = status.agent.task_state;
+        ensure!(
+            from.can_transition_to(to),
+            error::IllegalTransitionSnafu {
+                name: current.name_any(),
+                from,
+                to
+            }
+        );
+        Ok(())
+    }
+
+    /// Create the [`Test`]. The agent must have a name and a valid image reference, its environment
+    /// variables must have valid names, and its init containers must each have a name and an
+    /// image. If the test has a `config_schema`, the
+    /// agent's configuration is validated against it before the test is created. The serialized
+    /// spec must not be larger than the client's `with_max_spec_bytes` limit. Use
+    /// `TestClient::create_unchecked` to skip these checks.
+    async fn create(&self, crd: Self::Crd) -> Result<Self::Crd> {
+        check_test(&crd, self.max_spec_bytes)?;
+        self.create_unchecked(crd).await
+    }
+
+    /// Validate the [`Test`] like `create` does, then send it to the API server as a dry-run
+    /// create.
+    async fn create_dry_run(&self, crd: &Self::Crd) -> Result<Self::Crd> {
+        check_test(crd, self.max_spec_bytes)?;
+        Ok(self
+            .api()
+            .create(
+                &PostParams {
+                    dry_run: true,
+                    ..PostParams::default()
+                },
+                crd,
+            )
+            .await
+            .context(error::KubeApiCallSnafu {
+                method: "dry-run create",
+                what: self.kind(),
+            })?)
+    }
+}
+
+/// The checks made before a [`Test`] is sent to the API server: its agent must have a name and a
+/// valid image reference, its configuration must match its `config_schema` if it has one, its
+/// environment variables must have valid names, every init container and sidecar must have a name
+/// and an image, no two containers of the agent pod
+/// may have the same name, and its serialized spec must not be larger than `max_spec_bytes`.
+fn check_test(test: &Test, max_spec_bytes: usize) -> Result<()> {
+    let agent = &test.spec.agent;
+    ensure!(
+        !agent.name.is_empty(),
+        error::EmptyAgentFieldSnafu {
+            test: test.name_any(),
+            field: "name",
+        }
+    );
+    ensure!(
+        !agent.image.is_empty(),
+        error::EmptyAgentFieldSnafu {
+            test: test.name_any(),
+            field: "image",
+        }
+    );
+    check_image_reference(&agent.image).map_err(|reason| {
+        error::InvalidImageReferenceSnafu {
+            test: test.name_any(),
+            image: &agent.image,
+            reason,
+        }
+        .build()
+    })?;
+    check_env(&agent.env)?;
+    check_init_containers(&agent.init_containers)?;
+    check_sidecars(&test.name_any(), &agent.init_containers, &agent.sidecars)?;
+    if let Some(schema) = &test.spec.config_schema {
+        let configuration =
+            Value::Object(test.spec.agent.configuration.clone().unwrap_or_default());
+        validate_value(&configuration, schema).context(error::ConfigSchemaSnafu {
+            name: test.name_any(),
+        })?;
+    }
+    let size = json_byte_size(&test.spec);
+    ensure!(
+        size <= max_spec_bytes,
+        error::SpecTooLargeSnafu {
+            test: test.name_any(),
+            size,
+            limit: max_spec_bytes,
+            configuration_size: json_byte_size(&test.spec.agent.configuration),
+        }
+    );
+    Ok(())
+}
+
+/// Make sure every name in `env` is a valid environment variable name.
+fn check_env(env: &BTreeMap<String, String>) -> Result<()> {
+    for var_name in env.keys() {
+        ensure!(
+            is_env_var_name(var_name),
+            error::InvalidEnvVarNameSnafu { name: var_name }
+        );
+    }
+    Ok(())
+}
+
+/// Make sure every init container has a name and an image.
+fn check_init_containers(init_containers: &[Container]) -> Result<()> {
+    for (index, container) in init_containers.iter().enumerate() {
+        ensure!(
+            !container.name.is_empty()
+                && container
+                    .image
+                    .as_ref()
+                    .map(|image| !image.is_empty())
+                    .unwrap_or(false),
+            error::InvalidInitContainerSnafu { index }
+        );
+    }
+    Ok(())
+}
+
+/// Check that `image` is a container image reference of the form
+/// `[registry[:port]/]repository[:tag][@algorithm:digest]`, returning what is wrong with it if it
+/// is not.
+fn check_image_reference(image: &str) -> std::result::Result<(), &'static str> {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+    if let Some(digest) = digest {
+        let (algorithm, hex) = digest
+            .split_once(':')
+            .ok_or("the digest must be of the form 'algorithm:hex'")?;
+        if algorithm.is_empty()
+            || !algorithm
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '_' | '-'))
+        {
+            return Err("the digest algorithm is not valid");
+        }
+        if hex.len() < 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("the digest must be at least 32 hexadecimal characters");
+        }
+    }
+
+    // A ':' after the last '/' starts the tag, an earlier one separates the registry's port.
+    let (repository, tag) = match name.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag)),
+        _ => (name, None),
+    };
+    if let Some(tag) = tag {
+        if tag.is_empty()
+            || tag.len() > 128
+            || tag.starts_with(['.', '-'])
+            || !tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        {
+            return Err("the tag must be 1 to 128 letters, digits, '_', '.' or '-' and must not start with '.' or '-'");
+        }
+    }
+
+    let mut components = repository.split('/').collect::<Vec<_>>();
+    // Like Docker, the first component is only a registry if it looks like a host name.
+    if components.len() > 1 && (components[0].contains(['.', ':']) || components[0] == "localhost")
+    {
+        let registry = components.remove(0);
+        let (host, port) = match registry.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (registry, None),
+        };
+        if host.is_empty()
+            || !host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-'))
+        {
+            return Err("the registry host name is not valid");
+        }
+        if matches!(port, Some(port) if port.is_empty() || !port.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err("the registry port must be a number");
+        }
+    }
+    let valid_component = |component: &str| {
+        component
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+            && component.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && component.ends_with(|c: char| c.is_ascii_alphanumeric())
+    };
+    if !components.into_iter().all(valid_component) {
+        return Err("each repository path component must be lowercase letters and digits, optionally separated by '.', '_' or '-'");
+    }
+    Ok(())
+}
+
+/// Make sure every sidecar has a name and an image, and that its name is not used by another
+/// container in the agent pod of the test `test_name`. The controller names the agent container
+/// after the test.
+fn check_sidecars(
+    test_name: &str,
+    init_containers: &[Container],
+    sidecars: &[Container],
+) -> Result<()> {
+    let mut names: BTreeSet<&str> = std::iter::once(test_name)
+        .chain(
+            init_containers
+                .iter()
+                .map(|container| container.name.as_str()),
+        )
+        .collect();
+    for (index, sidecar) in sidecars.iter().enumerate() {
+        ensure!(
+            !sidecar.name.is_empty()
+                && sidecar
+                    .image
+                    .as_ref()
+                    .map(|image| !image.is_empty())
+                    .unwrap_or(false),
+            error::InvalidSidecarSnafu { index }
+        );
+        ensure!(
+            names.insert(&sidecar.name),
+            error::ContainerNameCollisionSnafu {
+                name: &sidecar.name
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Returns `true` if `name` is a DNS-1123 subdomain, which Kubernetes requires for the names of
+/// most objects, including secrets.
+fn is_dns_subdomain(name: &str) -> bool {
+    name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+}
+
+/// Returns `true` if `name` can be used as the name of a container environment variable in
+/// Kubernetes.
+fn is_env_var_name(name: &str) -> bool {
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    name.chars().all(valid) && matches!(name.chars().next(), Some(c) if !c.is_ascii_digit())
+}
+
+/// The latest `spec.retryCount` of `test` that the controller has acted on.
+fn observed_retry_count(test: &Test) -> u32 {
+    test.status
+        .as_ref()
+        .and_then(|status| status.controller.observed_retry_count)
+        .unwrap_or_default()
+}
+
+/// The names of the test cases that failed in the latest run of `test`.
+fn failed_case_names(test: &Test) -> Vec<String> {
+    test.agent_status()
+        .results
+        .last()
+        .map(|results| {
+            results
+                .failed_cases()
+                .into_iter()
+                .map(|case| case.name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns `true` if `test` has completed and the results of its latest run have no failures.
+fn has_passed(test: &Test) -> bool {
+    let agent_status = test.agent_status();
+    agent_status.task_state == TaskState::Completed
+        && agent_status
+            .results
+            .last()
+            .map(|results| results.outcome == Outcome::Pass && results.num_failed == 0)
+            .unwrap_or(false)
+}
+
+/// The results of the latest run of `test` if it has completed, or `None` if it is still running.
+fn completion_results(name: &str, test: &Test) -> Result<Option<TestResults>> {
+    let agent_status = test.agent_status();
+    match agent_status.task_state {
+        TaskState::Unknown | TaskState::Running => Ok(None),
+        TaskState::Completed => Ok(Some(agent_status.results.last().cloned().context(
+            error::WaitFailedSnafu {
+                name,
+                reason: "the test agent did not report any results",
+            },
+        )?)),
+        TaskState::Error => Err(error::WaitFailedSnafu {
+            name,
+            reason: agent_status
+                .error
+                .clone()
+                .unwrap_or_else(|| "the test agent reported an error".to_string()),
+        }
+        .build()
+        .into()),
+        TaskState::Cancelled => Err(error::WaitFailedSnafu {
+            name,
+            reason: "the test was cancelled",
+        }
+        .build()
+        .into()),
+    }
+}
+
+/// The spec patches that `send_keep_running_for` needs to make to `agent`. Fields that already have
+/// the requested value are not written.
+fn keep_running_patches(agent: &Agent, keep_running: bool, duration: Duration) -> Vec<JsonPatch> {
+    let mut patches = Vec::new();
+    if agent.keep_running != keep_running {
+        patches.push(JsonPatch::new_replace_operation(
+            "/spec/agent/keepRunning",
+            keep_running,
+        ));
+    }
+    if agent.keep_running_timeout_seconds != Some(duration.as_secs()) {
+        patches.push(JsonPatch::new_add_operation(
+            "/spec/agent/keepRunningTimeoutSeconds",
+            duration.as_secs(),
+        ));
+    }
+    patches
+}
+
+/// The RFC 3339 timestamp at which a `keep_running` flag set at `now` for `duration` expires.
+/// Returns `None` if the expiry cannot be represented.
+fn keep_running_expiry(now: SystemTime, duration: Duration) -> Option<String> {
+    now.checked_add(duration)
+        .map(|expiry| DateTime::<Utc>::from(expiry).to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+#[test]
+fn test_keep_running_patches() {
+    let hour = Duration::from_secs(3600);
+    let mut agent = Agent {
+        keep_running: true,
+        ..Agent::default()
+    };
+    assert_eq!(keep_running_patches(&agent, true, hour).len(), 1);
+    agent.keep_running_timeout_seconds = Some(3600);
+    assert!(keep_running_patches(&agent, true, hour).is_empty());
+    assert_eq!(keep_running_patches(&agent, false, hour).len(), 1);
+    assert_eq!(keep_running_patches(&agent, false, hour * 2).len(), 2);
+}
+
+#[test]
+fn test_keep_running_expiry() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    assert_eq!(
+        keep_running_expiry(now, Duration::from_secs(90 * 60)).unwrap(),
+        "2020-09-13T13:56:40Z"
+    );
+    assert!(keep_running_expiry(now, Duration::MAX).is_none());
+}
+
+#[test]
+fn test_completion_results() {
+    let mut test = create_test_crd("my-test", None, TestSpec::default());
+    assert!(completion_results("my-test", &test).unwrap().is_none());
+
+    let mut status = TestStatus::default();
+    status.agent.task_state = TaskState::Completed;
+    status.agent.results = vec![TestResults {
+        num_passed: 3,
+        ..TestResults::default()
+    }];
+    test.status = Some(status.clone());
+    assert_eq!(
+        completion_results("my-test", &test)
+            .unwrap()
+            .unwrap()
+            .num_passed,
+        3
+    );
+
+    status.agent.task_state = TaskState::Error;
+    status.agent.error = Some("agent failed".to_string());
+    test.status = Some(status);
+    let error = completion_results("my-test", &test).unwrap_err();
+    assert!(error.to_string().contains("agent failed"), "{}", error);
+}
+
+#[test]
+fn test_check_init_containers() {
+    let container = |name: &str, image: Option<&str>| Container {
+        name: name.to_string(),
+        image: image.map(str::to_string),
+        ..Container::default()
+    };
+    check_init_containers(&[]).unwrap();
+    check_init_containers(&[container("fetch", Some("fetcher:v1"))]).unwrap();
+    let error = check_init_containers(&[
+        container("fetch", Some("fetcher:v1")),
+        container("unpack", None),
+    ])
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Init container 1 must have a name and an image"
+    );
+    assert!(check_init_containers(&[container("", Some("fetcher:v1"))]).is_err());
+    assert!(check_init_containers(&[container("fetch", Some(""))]).is_err());
+}
+
+#[test]
+fn test_check_image_reference() {
+    for image in [
+        "foo",
+        "foo:v0.1.0",
+        "library/foo_bar-baz.qux:latest",
+        "localhost/foo",
+        "localhost:5000/foo/bar:v1",
+        "public.ecr.aws/bottlerocket/agent:v0.0.3",
+        "123456789012.dkr.ecr.us-west-2.amazonaws.com/agent@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        "ghcr.io/org/agent:v1@sha256:0123456789abcdef0123456789abcdef",
+    ] {
+        assert_eq!(check_image_reference(image), Ok(()), "{}", image);
+    }
+    for image in [
+        "Foo",
+        "foo bar",
+        "foo:",
+        "foo:-v1",
+        "foo:v1:v2",
+        "/foo",
+        "foo//bar",
+        "foo-",
+        "example.com:port/foo",
+        "exa mple.com/foo",
+        "foo@sha256",
+        "foo@sha256:abc",
+        "foo@:0123456789abcdef0123456789abcdef",
+        "@sha256:0123456789abcdef0123456789abcdef",
+    ] {
+        assert!(check_image_reference(image).is_err(), "{}", image);
+    }
+}
+
+#[test]
+fn test_check_test_agent() {
+    let test = |name: &str, image: &str| {
+        create_test_crd(
+            "my-test",
+            None,
+            TestSpec {
+                agent: crate::Agent {
+                    name: name.to_string(),
+                    image: image.to_string(),
+                    ..crate::Agent::default()
+                },
+                ..TestSpec::default()
+            },
+        )
+    };
+    check_test(&test("my-agent", "foo:v0.1.0"), DEFAULT_MAX_SPEC_BYTES).unwrap();
+    assert_eq!(
+        check_test(&test("", "foo:v0.1.0"), DEFAULT_MAX_SPEC_BYTES)
+            .unwrap_err()
+            .to_string(),
+        "The agent name of test 'my-test' must not be empty"
+    );
+    assert_eq!(
+        check_test(&test("my-agent", ""), DEFAULT_MAX_SPEC_BYTES)
+            .unwrap_err()
+            .to_string(),
+        "The agent image of test 'my-test' must not be empty"
+    );
+    let error = check_test(&test("my-agent", "Foo:v0.1.0"), DEFAULT_MAX_SPEC_BYTES).unwrap_err();
+    assert_eq!(error.kind(), crate::clients::ErrorKind::Invalid);
+    assert!(
+        error.to_string().starts_with(
+            "The agent image 'Foo:v0.1.0' of test 'my-test' is not a valid image reference: "
+        ),
+        "{}",
+        error
+    );
+    let mut invalid_env = test("my-agent", "foo:v0.1.0");
+    invalid_env
+        .spec
+        .agent
+        .env
+        .insert("1BAD".to_string(), "value".to_string());
+    assert_eq!(
+        check_test(&invalid_env, DEFAULT_MAX_SPEC_BYTES)
+            .unwrap_err()
+            .kind(),
+        crate::clients::ErrorKind::Invalid
+    );
+}
+
+#[test]
+fn test_check_test_spec_size() {
+    let configured = |value: &str| {
+        create_test_crd(
+            "my-test",
+            None,
+            TestSpec {
+                agent: crate::Agent {
+                    name: "my-agent".to_string(),
+                    image: "foo:v0.1.0".to_string(),
+                    configuration: Some(serde_json::Map::from_iter([(
+                        "kubeconfig".to_string(),
+                        Value::String(value.to_string()),
+                    )])),
+                    ..crate::Agent::default()
+                },
+                ..TestSpec::default()
+            },
+        )
+    };
+    let oversized = configured(&"a".repeat(DEFAULT_MAX_SPEC_BYTES));
+    let error = check_test(&oversized, DEFAULT_MAX_SPEC_BYTES).unwrap_err();
+    assert_eq!(error.kind(), crate::clients::ErrorKind::Invalid);
+    let message = error.to_string();
+    assert!(
+        message.starts_with("The spec of test 'my-test' is ")
+            && message.contains("more than the limit of 1048576 bytes")
+            && message.contains(&format!(
+                "{} bytes of it are the agent configuration",
+                DEFAULT_MAX_SPEC_BYTES + r#"{"kubeconfig":""}"#.len()
+            ))
+            && message.contains("Store large values in a secret or config map"),
+        "{}",
+        message
+    );
+    // The limit can be raised for clusters that allow larger objects.
+    check_test(&oversized, 2 * DEFAULT_MAX_SPEC_BYTES).unwrap();
+    check_test(&configured("small"), DEFAULT_MAX_SPEC_BYTES).unwrap();
+}
+
+#[test]
+fn test_check_sidecars() {
+    let container = |name: &str, image: Option<&str>| Container {
+        name: name.to_string(),
+        image: image.map(str::to_string),
+        ..Container::default()
+    };
+    let fluent_bit = || container("fluent-bit", Some("fluent/fluent-bit:2.1"));
+    check_sidecars("my-test", &[], &[]).unwrap();
+    check_sidecars("my-test", &[], &[fluent_bit()]).unwrap();
+    assert_eq!(
+        check_sidecars("my-test", &[], &[fluent_bit(), container("", Some("foo"))])
+            .unwrap_err()
+            .to_string(),
+        "Sidecar 1 must have a name and an image"
+    );
+    assert!(check_sidecars("my-test", &[], &[container("fluent-bit", None)]).is_err());
+
+    // The agent container is named after the test.
+    let error = check_sidecars("my-test", &[], &[container("my-test", Some("foo"))]).unwrap_err();
+    assert_eq!(error.kind(), crate::clients::ErrorKind::Invalid);
+    assert_eq!(
+        error.to_string(),
+        "The container name 'my-test' is used by more than one container in the agent pod"
+    );
+    assert!(check_sidecars(
+        "my-test",
+        &[container("fluent-bit", Some("fetcher:v1"))],
+        &[fluent_bit()]
+    )
+    .is_err());
+    assert!(check_sidecars("my-test", &[], &[fluent_bit(), fluent_bit()]).is_err());
+}
+
+#[test]
+fn test_is_dns_subdomain() {
+    for name in ["my-secret", "aws-creds.v1", "0"] {
+        assert!(is_dns_subdomain(name), "{}", name);
+    }
+    for name in ["", "My-Secret", "my_secret", "-secret", "secret-", "a..b"] {
+        assert!(!is_dns_subdomain(name), "{}", name);
+    }
+    assert!(!is_dns_subdomain(&"a".repeat(254)));
+}
+
+#[test]
+fn test_is_env_var_name() {
+    for name in ["HTTP_PROXY", "http_proxy", "_private", "my.var-1"] {
+        assert!(is_env_var_name(name), "{}", name);
+    }
+    for name in ["", "HTTP PROXY", "FOO=bar", "1VAR", " FOO", "FOO/BAR"] {
+        assert!(!is_env_var_name(name), "{}", name);
+    }
+}
+
+#[test]
+fn test_failed_case_names() {
+    use crate::{TestCaseOutcome, TestCaseResult};
+
+    let case = |name: &str, outcome| TestCaseResult {
+        name: name.to_string(),
+        outcome,
+        ..TestCaseResult::default()
+    };
+    let results = |cases| TestResults {
+        cases: Some(cases),
+        ..TestResults::default()
+    };
+    let mut test = create_test_crd("my-test", None, TestSpec::default());
+    assert!(failed_case_names(&test).is_empty());
+    test.status = Some(TestStatus {
+        agent: AgentStatus {
+            results: vec![
+                results(vec![case("a", TestCaseOutcome::Fail)]),
+                results(vec![
+                    case("a", TestCaseOutcome::Pass),
+                    case("b", TestCaseOutcome::Fail),
+                    case("c", TestCaseOutcome::Skip),
+                    case("d", TestCaseOutcome::Fail),
+                ]),
+            ],
+            ..AgentStatus::default()
+        },
+        ..TestStatus::default()
+    });
+    // Only the failures of the latest run are rerun.
+    assert_eq!(failed_case_names(&test), vec!["b", "d"]);
+    test.status.as_mut().unwrap().agent.results[1] =
+        results(vec![case("a", TestCaseOutcome::Pass)]);
+    assert!(failed_case_names(&test).is_empty());
+}
+
+#[test]
+fn test_has_passed() {
+    let mut test = create_test_crd("setup", None, TestSpec::default());
+    assert!(!has_passed(&test));
+
+    let mut status = TestStatus::default();
+    status.agent.task_state = TaskState::Completed;
+    status.agent.results = vec![TestResults {
+        outcome: Outcome::Pass,
+        num_passed: 2,
+        ..TestResults::default()
+    }];
+    test.status = Some(status.clone());
+    assert!(has_passed(&test));
+
+    status.agent.results[0].num_failed = 1;
+    test.status = Some(status.clone());
+    assert!(!has_passed(&test));
+
+    status.agent.results[0].num_failed = 0;
+    status.agent.task_state = TaskState::Running;
+    test.status = Some(status);
+    assert!(!has_passed(&test));
+}
+
+#[tokio::test]
+async fn test_client_namespace() {
+    let config = kube::Config::new("http://localhost:8080".parse().unwrap());
+    let k8s_client = kube::Client::try_from(config).unwrap();
+    let tc = TestClient::new_from_k8s_client_with_namespace(k8s_client, "team-a");
+    assert_eq!(
+        tc.api().resource_url(),
+        "/apis/testsys.system/v1/namespaces/team-a/tests"
+    );
+}
+
+#[tokio::test]
+async fn test_client_request_timeout() {
+    // A server that accepts connections but never responds.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let _connections: Vec<_> = listener.incoming().collect();
+    });
+    let mut config = kube::Config::new(format!("http://{}", addr).parse().unwrap());
+    crate::clients::set_request_timeout(&mut config, Duration::from_millis(100));
+    let tc = TestClient::new_from_k8s_client(kube::Client::try_from(config).unwrap());
+    let err = tokio::time::timeout(Duration::from_secs(10), tc.get("my-test"))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert!(err.is_timeout(), "{}", err);
+}
+
+#[tokio::test]
+async fn test_client_clock() {
+    use chrono::TimeZone;
+    let config = kube::Config::new("http://localhost:8080".parse().unwrap());
+    let clock = crate::FakeClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 12, 30, 0).unwrap());
+    let tc = TestClient::new_from_k8s_client(kube::Client::try_from(config).unwrap())
+        .with_clock(clock.clone());
+    assert_eq!(tc.timestamp(), "2023-01-01T12:30:00Z");
+    clock.advance(Duration::from_secs(15));
+    assert_eq!(tc.timestamp(), "2023-01-01T12:30:15Z");
+    assert_eq!(
+        serde_json::to_value(
+            JsonPatch::new_timestamp(tc.clock().now()).into_json_patch_operation()
+        )
+        .unwrap(),
+        serde_json::json!({
+            "op": "replace",
+            "path": "/status/lastUpdate",
+            "value": "2023-01-01T12:30:15Z"
+        })
+    );
 }
 
 pub fn create_test_crd<S1>(
@@ -166,8 +1581,9 @@ where
 #[cfg(feature = "integ")]
 mod test {
     use super::*;
+    use crate::clients::{HttpStatusCode, StatusCode, WatchEvent};
     use crate::constants::NAMESPACE;
-    use crate::{Agent, Configuration, TestSpec};
+    use crate::{Agent, AllowNotFound, Configuration, TestSpec};
     use k8s_openapi::api::core::v1::Namespace;
     use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
     use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
@@ -181,6 +1597,18 @@ mod test {
     const CLUSTER_NAME: &str = "test-client";
     const TEST_NAME: &str = "my-test";
 
+    /// A spec whose agent passes the checks that `TestClient::create` makes.
+    fn agent_spec() -> TestSpec {
+        TestSpec {
+            agent: Agent {
+                name: "my-agent".into(),
+                image: "foo:v0.1.0".into(),
+                ..Agent::default()
+            },
+            ..TestSpec::default()
+        }
+    }
+
     #[derive(Default, Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
     #[serde(rename_all = "camelCase")]
     struct TestConfig {
@@ -201,7 +1629,10 @@ mod test {
         let k8s_client = cluster.k8s_client().await.unwrap();
         let ns_api: Api<Namespace> = Api::all(k8s_client.clone());
         ns_api
-            .create(&PostParams::default(), &crate::system::testsys_namespace())
+            .create(
+                &PostParams::default(),
+                &crate::system::testsys_namespace(NAMESPACE),
+            )
             .await
             .unwrap();
         cluster
@@ -246,6 +1677,73 @@ mod test {
         .await
         .unwrap();
 
+        // A get right after the create should find the test.
+        let created = tc
+            .get_eventually(TEST_NAME, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(created.name_any(), TEST_NAME);
+        assert!(tc
+            .get_eventually("does-not-exist", Duration::from_millis(500))
+            .await
+            .unwrap_err()
+            .is_not_found());
+
+        // A dry-run create is validated by the API server but does not create the test.
+        let dry_run = tc
+            .create_dry_run(&Test {
+                metadata: ObjectMeta {
+                    name: Some("dry-run-test".into()),
+                    ..ObjectMeta::default()
+                },
+                ..created.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(dry_run.name_any(), "dry-run-test");
+        assert!(!tc.exists("dry-run-test").await.unwrap());
+
+        // Tests can be selected by name, but not by status fields.
+        let selected = tc
+            .list_with_field_selector(&format!("metadata.name={}", TEST_NAME))
+            .await
+            .unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].name_any(), TEST_NAME);
+        assert!(tc
+            .list_with_field_selector("metadata.name=does-not-exist")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            tc.list_with_field_selector("status.agent.taskState=running")
+                .await
+                .unwrap_err()
+                .kind(),
+            crate::clients::ErrorKind::Invalid
+        );
+
+        // The existing test is seen first, then each change to it.
+        let mut events = tc.watch_all();
+        match events.next().await.unwrap().unwrap() {
+            WatchEvent::Added(test) => assert_eq!(test.name_any(), TEST_NAME),
+            event => panic!("expected an added event, got {:?}", event),
+        }
+        tc.add_labels(
+            TEST_NAME,
+            BTreeMap::from([("watched".to_string(), "true".to_string())]),
+        )
+        .await
+        .unwrap();
+        match events.next().await.unwrap().unwrap() {
+            WatchEvent::Modified(test) => {
+                assert_eq!(test.name_any(), TEST_NAME);
+                assert_eq!(test.labels()["watched"], "true");
+            }
+            event => panic!("expected a modified event, got {:?}", event),
+        }
+        drop(events);
+
         tc.initialize_status(TEST_NAME).await.unwrap();
 
         // If status is already initialized, it should be an error to do so again.
@@ -259,7 +1757,12 @@ mod test {
             TaskState::Error
         ));
 
-        tc.send_agent_task_state(TEST_NAME, TaskState::Running)
+        // A test that has errored cannot start running again unless the state is forced.
+        assert!(tc
+            .send_agent_task_state(TEST_NAME, TaskState::Running)
+            .await
+            .is_err());
+        tc.send_agent_task_state_forced(TEST_NAME, TaskState::Running)
             .await
             .unwrap();
         assert!(matches!(
@@ -302,5 +1805,503 @@ mod test {
             tc.get(TEST_NAME).await.unwrap().agent_status().task_state,
             TaskState::Error
         ));
+
+        tc.send_test_results(TEST_NAME, TestResults::default())
+            .await
+            .unwrap();
+        let test = tc.retry(TEST_NAME).await.unwrap();
+        assert_eq!(test.spec.retry_count, Some(1));
+        // The status is only reset once the controller acknowledges the retry.
+        assert!(matches!(test.agent_status().task_state, TaskState::Error));
+        assert!(tc.retry(TEST_NAME).await.is_err());
+        tc.send_retry_observed(TEST_NAME, 1).await.unwrap();
+        let test = tc.get(TEST_NAME).await.unwrap();
+        assert!(matches!(test.agent_status().task_state, TaskState::Unknown));
+        assert!(test.agent_error().is_none());
+        assert!(test.agent_status().results.is_empty());
+        assert_eq!(test.spec.retry_count, Some(1));
+        assert_eq!(
+            tc.get_results_history(TEST_NAME).await.unwrap(),
+            vec![TestResults::default()]
+        );
+
+        // A running test cannot be retried.
+        let test = tc
+            .send_agent_task_state(TEST_NAME, TaskState::Running)
+            .await
+            .unwrap();
+        assert!(test.agent_status().started_at.is_some());
+        assert!(test.agent_status().finished_at.is_none());
+        assert!(test.status.as_ref().unwrap().running_duration().is_some());
+        assert!(test.agent_status().last_heartbeat.is_some());
+        let test = tc.send_heartbeat(TEST_NAME).await.unwrap();
+        assert!(test.agent_status().last_heartbeat.is_some());
+        let test = tc.send_progress(TEST_NAME, 42).await.unwrap();
+        assert_eq!(test.agent_status().progress, Some(42));
+        // Values out of range are clamped.
+        tc.send_progress(TEST_NAME, 250).await.unwrap();
+        assert_eq!(
+            tc.get(TEST_NAME).await.unwrap().agent_status().progress,
+            Some(100)
+        );
+        assert!(tc.retry(TEST_NAME).await.is_err());
+        assert_eq!(tc.get(TEST_NAME).await.unwrap().spec.retry_count, Some(1));
+
+        let test = tc
+            .send_keep_running_for(TEST_NAME, true, Duration::from_secs(3600))
+            .await
+            .unwrap();
+        assert!(test.agent_status().keep_running_expiry.is_some());
+        let test = tc.get(TEST_NAME).await.unwrap();
+        assert!(test.spec.agent.keep_running);
+        assert_eq!(test.spec.agent.keep_running_timeout_seconds, Some(3600));
+
+        let test = tc.send_cancel(TEST_NAME).await.unwrap();
+        assert!(test.spec.agent.cancel_requested);
+        let test = tc.send_cancelled(TEST_NAME).await.unwrap();
+        assert!(matches!(
+            test.agent_status().task_state,
+            TaskState::Cancelled
+        ));
+
+        for (name, variant) in [("test-a", "aws-k8s-1.24"), ("test-b", "aws-ecs-1")] {
+            tc.create(create_test_crd(
+                name,
+                Some(&BTreeMap::from([(
+                    "testsys.system/variant".to_string(),
+                    variant.to_string(),
+                )])),
+                agent_spec(),
+            ))
+            .await
+            .unwrap();
+        }
+        let tests = tc
+            .list_with_labels("testsys.system/variant=aws-k8s-1.24")
+            .await
+            .unwrap();
+        assert_eq!(tests.len(), 1);
+        assert_eq!(tests[0].metadata.name.as_deref(), Some("test-a"));
+
+        // Delete two of three labeled tests with a single request.
+        for (name, run) in [
+            ("cleanup-a", "run-1"),
+            ("cleanup-b", "run-1"),
+            ("cleanup-c", "run-2"),
+        ] {
+            tc.create(create_test_crd(
+                name,
+                Some(&BTreeMap::from([(
+                    "testsys.system/run".to_string(),
+                    run.to_string(),
+                )])),
+                agent_spec(),
+            ))
+            .await
+            .unwrap();
+        }
+        let mut deleted = tc
+            .delete_collection("testsys.system/run=run-1")
+            .await
+            .unwrap();
+        deleted.sort();
+        assert_eq!(deleted, vec!["cleanup-a", "cleanup-b"]);
+        tc.wait_for_deletion("cleanup-a").await;
+        tc.wait_for_deletion("cleanup-b").await;
+        assert!(tc.exists("cleanup-c").await.unwrap());
+        tc.delete("cleanup-c").await.unwrap();
+        tc.wait_for_deletion("cleanup-c").await;
+
+        let mut names = Vec::new();
+        let mut continue_token = None;
+        loop {
+            let (tests, next_token) = tc.list_paged(1, continue_token).await.unwrap();
+            assert!(tests.len() <= 1);
+            names.extend(tests.into_iter().filter_map(|test| test.metadata.name));
+            continue_token = next_token;
+            if continue_token.is_none() {
+                break;
+            }
+        }
+        names.sort();
+        assert_eq!(names, vec![TEST_NAME, "test-a", "test-b"]);
+
+        // `test-b` has no status yet, so its task state is `Unknown`.
+        tc.initialize_status("test-a").await.unwrap();
+        tc.send_agent_task_state("test-a", TaskState::Running)
+            .await
+            .unwrap();
+        let mut expected: BTreeMap<TaskState, usize> =
+            TaskState::ALL.iter().map(|state| (*state, 0)).collect();
+        *expected.get_mut(&TaskState::Running).unwrap() += 1;
+        *expected.get_mut(&TaskState::Unknown).unwrap() += 1;
+        *expected
+            .get_mut(&tc.get(TEST_NAME).await.unwrap().agent_status().task_state)
+            .unwrap() += 1;
+        assert_eq!(tc.count_by_state().await.unwrap(), expected);
+
+        // A test whose configuration does not satisfy its schema must not be created.
+        let invalid = tc
+            .create(crate::create_test_crd(
+                "invalid-config",
+                None,
+                TestSpec {
+                    agent: Agent {
+                        name: "my-agent".into(),
+                        image: "foo:v0.1.0".into(),
+                        configuration: Some(TEST_CONFIG.into_map().unwrap()),
+                        ..Agent::default()
+                    },
+                    config_schema: Some(serde_json::json!({
+                        "type": "object",
+                        "required": ["fieldC"]
+                    })),
+                    ..TestSpec::default()
+                },
+            ))
+            .await;
+        assert!(invalid.is_err());
+        assert!(tc
+            .get("invalid-config")
+            .await
+            .allow_not_found(|_| ())
+            .unwrap()
+            .is_none());
+        assert!(tc.exists(TEST_NAME).await.unwrap());
+        assert!(!tc.exists("invalid-config").await.unwrap());
+
+        // The status is only initialized by the first call.
+        tc.create(crate::create_test_crd("status-test", None, agent_spec()))
+            .await
+            .unwrap();
+        assert!(tc.initialize_status_if_needed("status-test").await.unwrap());
+        assert!(!tc.initialize_status_if_needed("status-test").await.unwrap());
+        assert!(tc
+            .initialize_status_if_needed("does-not-exist")
+            .await
+            .unwrap_err()
+            .is_not_found());
+
+        // The whole status can be replaced at once.
+        let replaced = tc
+            .replace_status(
+                "status-test",
+                TestStatus {
+                    agent: AgentStatus {
+                        task_state: TaskState::Running,
+                        started_at: Some("2023-01-01T00:00:00Z".to_string()),
+                        ..AgentStatus::default()
+                    },
+                    ..TestStatus::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(replaced.agent_status().task_state, TaskState::Running);
+        assert!(replaced.status().unwrap().last_update.is_some());
+        let replaced = tc
+            .replace_status(
+                "status-test",
+                TestStatus {
+                    agent: AgentStatus {
+                        task_state: TaskState::Completed,
+                        ..AgentStatus::default()
+                    },
+                    ..TestStatus::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(replaced.agent_status().task_state, TaskState::Completed);
+        assert_eq!(replaced.agent_status().started_at, None);
+        // A completed test cannot go back to `Unknown`.
+        assert_eq!(
+            tc.replace_status("status-test", TestStatus::default())
+                .await
+                .unwrap_err()
+                .kind(),
+            crate::clients::ErrorKind::Conflict
+        );
+        assert!(tc
+            .replace_status("does-not-exist", TestStatus::default())
+            .await
+            .unwrap_err()
+            .is_not_found());
+
+        // A targeted rerun runs only the cases that failed in the latest run.
+        tc.create(crate::create_test_crd("rerun-test", None, agent_spec()))
+            .await
+            .unwrap();
+        tc.initialize_status("rerun-test").await.unwrap();
+        assert_eq!(
+            tc.rerun_failed_cases_only("rerun-test")
+                .await
+                .unwrap_err()
+                .kind(),
+            crate::clients::ErrorKind::Conflict
+        );
+        tc.send_test_results(
+            "rerun-test",
+            TestResults {
+                cases: Some(vec![
+                    crate::TestCaseResult {
+                        name: "passes".to_string(),
+                        outcome: crate::TestCaseOutcome::Pass,
+                        ..Default::default()
+                    },
+                    crate::TestCaseResult {
+                        name: "fails".to_string(),
+                        outcome: crate::TestCaseOutcome::Fail,
+                        ..Default::default()
+                    },
+                ]),
+                ..TestResults::default()
+            }
+            .with_case_counts(),
+        )
+        .await
+        .unwrap();
+        tc.rerun_failed_cases_only("rerun-test").await.unwrap();
+        let test = tc.send_retry_observed("rerun-test", 1).await.unwrap();
+        assert!(test.agent_status().results.is_empty());
+        let test = tc.get("rerun-test").await.unwrap();
+        assert_eq!(test.spec.agent.rerun_cases, vec!["fails"]);
+        assert_eq!(test.spec.retry_count, Some(1));
+        // A plain retry runs every case again.
+        tc.retry("rerun-test").await.unwrap();
+        assert!(tc
+            .get("rerun-test")
+            .await
+            .unwrap()
+            .spec
+            .agent
+            .rerun_cases
+            .is_empty());
+
+        // A test that has not started can be skipped, but only once.
+        tc.create(crate::create_test_crd("skip-test", None, agent_spec()))
+            .await
+            .unwrap();
+        tc.initialize_status("skip-test").await.unwrap();
+        let test = tc.mark_skipped("skip-test", "not needed").await.unwrap();
+        assert_eq!(test.agent_status().task_state, TaskState::Completed);
+        assert_eq!(
+            test.agent_status().skip_reason.as_deref(),
+            Some("not needed")
+        );
+        assert_eq!(test.test_user_state(), crate::TestUserState::Skipped);
+        assert!(tc.mark_skipped("skip-test", "not needed").await.is_err());
+
+        // The status stream should observe task state transitions in order.
+        tc.create(crate::create_test_crd("stream-test", None, agent_spec()))
+            .await
+            .unwrap();
+        tc.initialize_status("stream-test").await.unwrap();
+        let stream = tc.stream_status("stream-test");
+        futures::pin_mut!(stream);
+        let mut task_states = vec![stream.next().await.unwrap().unwrap().agent.task_state];
+        tc.send_agent_task_state("stream-test", TaskState::Running)
+            .await
+            .unwrap();
+        tc.send_agent_task_state("stream-test", TaskState::Completed)
+            .await
+            .unwrap();
+        while task_states.last() != Some(&TaskState::Completed) {
+            let status = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if task_states.last() != Some(&status.agent.task_state) {
+                task_states.push(status.agent.task_state);
+            }
+        }
+        assert_eq!(
+            task_states,
+            vec![TaskState::Unknown, TaskState::Running, TaskState::Completed]
+        );
+
+        // The stream ends once the test is deleted.
+        tc.delete("stream-test").await.unwrap();
+        let end = tokio::time::timeout(Duration::from_secs(10), async {
+            while stream.next().await.is_some() {}
+        })
+        .await;
+        assert!(end.is_ok());
+
+        // Missing tests are returned as `None` instead of failing the whole request.
+        let many = tc
+            .get_many(&["bulk-missing", TEST_NAME, "another-missing"])
+            .await
+            .unwrap();
+        assert_eq!(
+            many.iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["bulk-missing", TEST_NAME, "another-missing"]
+        );
+        assert!(many[0].1.is_none());
+        assert_eq!(many[1].1.as_ref().unwrap().name_any(), TEST_NAME);
+        assert!(many[2].1.is_none());
+
+        // Labels are added without removing the existing ones.
+        tc.create(crate::create_test_crd("labelled", None, agent_spec()))
+            .await
+            .unwrap();
+        tc.add_labels(
+            "labelled",
+            BTreeMap::from([("team".to_string(), "os".to_string())]),
+        )
+        .await
+        .unwrap();
+        let labelled = tc
+            .add_labels(
+                "labelled",
+                BTreeMap::from([("build/id".to_string(), "1234".to_string())]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            labelled.labels(),
+            &BTreeMap::from([
+                ("build/id".to_string(), "1234".to_string()),
+                ("team".to_string(), "os".to_string()),
+            ])
+        );
+
+        // Annotations are added the same way, and can be read back.
+        tc.add_annotations(
+            "labelled",
+            BTreeMap::from([(
+                crate::constants::ANNOTATION_GIT_SHA.to_string(),
+                "0123abc".to_string(),
+            )]),
+        )
+        .await
+        .unwrap();
+        tc.add_annotations(
+            "labelled",
+            BTreeMap::from([("ci/job".to_string(), "https://ci.example.com/1".to_string())]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            tc.get("labelled").await.unwrap().annotations(),
+            &BTreeMap::from([
+                ("ci/job".to_string(), "https://ci.example.com/1".to_string()),
+                (
+                    crate::constants::ANNOTATION_GIT_SHA.to_string(),
+                    "0123abc".to_string()
+                ),
+            ])
+        );
+
+        // The logs are in the agent pod until the agent reports another location.
+        assert_eq!(
+            tc.get_logs_location("labelled").await.unwrap(),
+            LogsLocation::Pod
+        );
+        let location = LogsLocation::S3 {
+            uri: "s3://bucket/labelled/agent.log".to_string(),
+        };
+        tc.send_logs_location("labelled", &location).await.unwrap();
+        assert_eq!(tc.get_logs_location("labelled").await.unwrap(), location);
+
+        // Applying a test that already exists updates its spec instead of failing.
+        let applied = tc
+            .create_or_update(crate::create_test_crd("applied", None, agent_spec()))
+            .await
+            .unwrap();
+        assert_eq!(applied.spec.retry_count, None);
+        let applied = tc
+            .create_or_update(crate::create_test_crd(
+                "applied",
+                None,
+                TestSpec {
+                    retry_count: Some(3),
+                    ..agent_spec()
+                },
+            ))
+            .await
+            .unwrap();
+        assert_eq!(applied.spec.retry_count, Some(3));
+        assert_eq!(tc.get("applied").await.unwrap().spec.retry_count, Some(3));
+
+        // A failure to create one test does not prevent the others from being created.
+        let bulk = tc
+            .create_all(
+                ["bulk-a", TEST_NAME, "bulk-b"]
+                    .into_iter()
+                    .map(|name| crate::create_test_crd(name, None, agent_spec()))
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        assert!(!bulk.is_success());
+        assert_eq!(bulk.succeeded, vec!["bulk-a", "bulk-b"]);
+        assert_eq!(bulk.failed.len(), 1);
+        assert_eq!(bulk.failed[0].0, TEST_NAME);
+        assert!(bulk.failed[0].1.is_status_code(StatusCode::CONFLICT));
+        tc.get("bulk-b").await.unwrap();
+
+        // The agent is checked before the test is sent, unless the checks are skipped.
+        let no_image = crate::create_test_crd("no-image", None, TestSpec::default());
+        assert_eq!(
+            tc.create(no_image.clone()).await.unwrap_err().kind(),
+            crate::clients::ErrorKind::Invalid
+        );
+        assert!(!tc.exists("no-image").await.unwrap());
+        tc.create_unchecked(no_image).await.unwrap();
+
+        // A test's dependencies must exist and have passed.
+        tc.create(crate::create_test_crd(
+            "smoke",
+            None,
+            TestSpec {
+                depends_on: Some(vec!["setup".to_string()]),
+                ..agent_spec()
+            },
+        ))
+        .await
+        .unwrap();
+        assert!(tc.dependencies_satisfied("smoke").await.is_err());
+        tc.create(crate::create_test_crd("setup", None, agent_spec()))
+            .await
+            .unwrap();
+        tc.initialize_status("setup").await.unwrap();
+        assert!(!tc.dependencies_satisfied("smoke").await.unwrap());
+        tc.send_test_completed(
+            "setup",
+            TestResults {
+                outcome: crate::Outcome::Pass,
+                num_passed: 1,
+                ..TestResults::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(tc.dependencies_satisfied("smoke").await.unwrap());
+        assert!(tc
+            .set_depends_on("setup", vec!["smoke".to_string()])
+            .await
+            .is_err());
+        assert!(tc
+            .set_depends_on("smoke", vec!["smoke".to_string()])
+            .await
+            .is_err());
+
+        // A test can be exported, deleted and imported again with its status.
+        let bundle = tc.export_bundle("setup").await.unwrap();
+        assert!(bundle.resources.is_empty());
+        tc.delete("setup").await.unwrap();
+        tc.wait_for_deletion("setup").await;
+        let imported = tc.import_bundle(bundle.clone()).await.unwrap();
+        assert_eq!(imported.spec, bundle.test.spec);
+        assert_eq!(imported.labels(), bundle.test.labels());
+        assert_eq!(imported.status, bundle.test.status);
+        assert!(tc
+            .import_bundle(tc.export_bundle("setup").await.unwrap())
+            .await
+            .is_err());
     }
 }
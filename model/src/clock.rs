@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A source of the current time. Code that writes or compares timestamps takes a `Clock` so
+/// that it can be tested with a [`FakeClock`] instead of the system time.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The [`Clock`] that reads the system time. This is the clock used unless another is given.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A [`Clock`] that only moves when it is set or advanced. Clones share the same time, so a test
+/// can keep a clone to move the time of a clock it has given away.
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl FakeClock {
+    /// A clock that is stopped at `now`.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Move the clock to `now`, which may be in the past.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.lock() = now;
+    }
+
+    /// Move the clock forward by `duration`. The clock stops at the latest representable time.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.lock();
+        *now = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| now.checked_add_signed(duration))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DateTime<Utc>> {
+        // The time is always valid, even if another thread panicked while holding the lock.
+        self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.lock()
+    }
+}
+
+#[test]
+fn test_fake_clock() {
+    use chrono::TimeZone;
+    let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let clock = FakeClock::new(start);
+    let shared = clock.clone();
+    assert_eq!(clock.now(), start);
+    shared.advance(Duration::from_secs(90));
+    assert_eq!(
+        clock.now(),
+        Utc.with_ymd_and_hms(2023, 1, 1, 0, 1, 30).unwrap()
+    );
+    shared.set(start);
+    assert_eq!(clock.now(), start);
+    shared.advance(Duration::MAX);
+    assert_eq!(clock.now(), DateTime::<Utc>::MAX_UTC);
+}
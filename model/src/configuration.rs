@@ -1,8 +1,9 @@
 use crate::error::{self, Result};
+use regex::Regex;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::{Map, Value};
-use snafu::ResultExt;
+use snafu::{IntoError, ResultExt};
 use std::fmt::Debug;
 
 /// The `Configuration` trait is for structs that can be used for custom data, which is represented
@@ -44,6 +45,312 @@ pub trait Configuration:
     fn from_value(value: Value) -> Result<Self> {
         Ok(serde_json::from_value(value).context(error::ConfigDeserializationSnafu)?)
     }
+
+    /// Convert the `Configuration` object into another type, e.g. the typed configuration of a
+    /// single agent. If a field is missing or has the wrong type, the error names the field.
+    fn deserialize_into<T: DeserializeOwned>(&self) -> Result<T> {
+        deserialize_value(&self.clone().into_value()?)
+    }
+
+    /// Check the `Configuration` object against a JSON Schema. The commonly used validation
+    /// keywords are supported: `type`, `enum`, `const`, `required`, `properties`,
+    /// `additionalProperties`, `items`, `minimum`, `maximum`, `minLength`, `maxLength`, `pattern`,
+    /// `minItems` and `maxItems`. A schema that uses any other validation keyword, e.g. `oneOf` or
+    /// `format`, is rejected instead of being partially checked.
+    fn validate_against_schema(&self, schema: &Value) -> Result<()> {
+        validate_value(&self.clone().into_value()?, schema)
+    }
+
+    /// The number of bytes in the compact JSON of the `Configuration` object, which is how it is
+    /// stored in the CRD. Returns `0` if the object cannot be serialized.
+    fn byte_size(&self) -> usize {
+        json_byte_size(self)
+    }
+
+    /// Deep merge `overrides` on top of `base`. Objects are merged recursively, while scalars and
+    /// arrays from `overrides` replace those in `base`. `null` values in `overrides` are treated as
+    /// unset so that fields left as `None` do not erase the values from `base`.
+    fn merge(base: &Self, overrides: &Self) -> Result<Self> {
+        let mut merged = base.clone().into_value()?;
+        merge_value(&mut merged, overrides.clone().into_value()?);
+        Self::from_value(merged)
+    }
+}
+
+/// The length of the compact JSON of `value`, counted without building the JSON. Returns `0` if
+/// `value` cannot be serialized.
+pub(crate) fn json_byte_size<T: Serialize + ?Sized>(value: &T) -> usize {
+    struct ByteCounter(usize);
+
+    impl std::io::Write for ByteCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = ByteCounter(0);
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => counter.0,
+        Err(_) => 0,
+    }
+}
+
+/// Deserialize `value`, naming the field that could not be deserialized in the error, see
+/// `Configuration::deserialize_into`.
+pub(crate) fn deserialize_value<T: DeserializeOwned>(value: &Value) -> Result<T> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        // The path is empty for an error in the top level object, e.g. a missing field.
+        let field = e
+            .path()
+            .iter()
+            .next()
+            .is_some()
+            .then(|| e.path().to_string());
+        let source = e.into_inner();
+        match field {
+            Some(field) => error::ConfigFieldDeserializationSnafu { field }.into_error(source),
+            None => error::ConfigDeserializationSnafu.into_error(source),
+        }
+        .into()
+    })
+}
+
+/// Deep merge `overrides` into `base`, see `Configuration::merge`.
+pub(crate) fn merge_value(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (_, Value::Null) => {}
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_value(base_value, value),
+                    None => {
+                        if !value.is_null() {
+                            base.insert(key, value);
+                        }
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Check a configuration `value` against a JSON Schema, see
+/// `Configuration::validate_against_schema`.
+pub(crate) fn validate_value(value: &Value, schema: &Value) -> Result<()> {
+    check_keywords(schema, "$")
+        .and_then(|()| check_schema(value, schema, "$"))
+        .map_err(|reason| error::ConfigSchemaValidationSnafu { reason }.build().into())
+}
+
+/// The JSON Schema keywords that `check_schema` enforces.
+const SUPPORTED_KEYWORDS: &[&str] = &[
+    "type",
+    "enum",
+    "const",
+    "required",
+    "properties",
+    "additionalProperties",
+    "items",
+    "minItems",
+    "maxItems",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minimum",
+    "maximum",
+];
+
+/// JSON Schema keywords that only annotate a schema and do not constrain the value.
+const ANNOTATION_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+];
+
+/// Make sure `schema`, and every schema nested in it, only uses keywords that `check_schema`
+/// enforces. A keyword that was silently ignored would let values that violate it through.
+fn check_keywords(schema: &Value, path: &str) -> std::result::Result<(), String> {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // Booleans are valid schemas and anything else is reported by `check_schema`.
+        _ => return Ok(()),
+    };
+    for (keyword, subschema) in schema {
+        if !SUPPORTED_KEYWORDS.contains(&keyword.as_str())
+            && !ANNOTATION_KEYWORDS.contains(&keyword.as_str())
+        {
+            return Err(format!(
+                "the schema for '{}' uses '{}', which is not supported",
+                path, keyword
+            ));
+        }
+        match keyword.as_str() {
+            "properties" => {
+                for (key, property_schema) in subschema.as_object().into_iter().flatten() {
+                    check_keywords(property_schema, &format!("{}.{}", path, key))?;
+                }
+            }
+            "additionalProperties" => check_keywords(subschema, &format!("{}.*", path))?,
+            "items" => check_keywords(subschema, &format!("{}[]", path))?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_schema(value: &Value, schema: &Value, path: &str) -> std::result::Result<(), String> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(format!("'{}' is not allowed", path)),
+        Value::Object(schema) => schema,
+        _ => return Err(format!("the schema for '{}' is not an object", path)),
+    };
+
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(instance_type) => vec![instance_type],
+            Value::Array(instance_types) => {
+                instance_types.iter().filter_map(Value::as_str).collect()
+            }
+            _ => return Err(format!("the schema for '{}' has an invalid 'type'", path)),
+        };
+        if !types
+            .iter()
+            .any(|instance_type| is_type(value, instance_type))
+        {
+            return Err(format!(
+                "'{}' should be of type '{}' but is '{}'",
+                path,
+                types.join("|"),
+                value
+            ));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!(
+                "'{}' must be one of {:?} but is '{}'",
+                path, allowed, value
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!(
+                "'{}' must be '{}' but is '{}'",
+                path, expected, value
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(map) => check_object(map, schema, path)?,
+        Value::Array(items) => {
+            check_bounds(items.len() as f64, schema, "minItems", "maxItems", path)?;
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check_schema(item, item_schema, &format!("{}[{}]", path, index))?;
+                }
+            }
+        }
+        Value::String(string) => {
+            check_bounds(
+                string.chars().count() as f64,
+                schema,
+                "minLength",
+                "maxLength",
+                path,
+            )?;
+            if let Some(Value::String(pattern)) = schema.get("pattern") {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| format!("the pattern for '{}' is invalid: {}", path, e))?;
+                if !regex.is_match(string) {
+                    return Err(format!("'{}' does not match pattern '{}'", path, pattern));
+                }
+            }
+        }
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                check_bounds(number, schema, "minimum", "maximum", path)?;
+            }
+        }
+        Value::Null | Value::Bool(_) => {}
+    }
+    Ok(())
+}
+
+fn check_object(
+    map: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> std::result::Result<(), String> {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !map.contains_key(key) {
+                return Err(format!("'{}' is missing required field '{}'", path, key));
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, value) in map {
+        let field_path = format!("{}.{}", path, key);
+        match (
+            properties.and_then(|properties| properties.get(key)),
+            schema.get("additionalProperties"),
+        ) {
+            (Some(property_schema), _) => check_schema(value, property_schema, &field_path)?,
+            (None, Some(additional_schema)) => check_schema(value, additional_schema, &field_path)?,
+            (None, None) => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_bounds(
+    value: f64,
+    schema: &Map<String, Value>,
+    min_key: &str,
+    max_key: &str,
+    path: &str,
+) -> std::result::Result<(), String> {
+    if let Some(min) = schema.get(min_key).and_then(Value::as_f64) {
+        if value < min {
+            return Err(format!("'{}' is below the {} of {}", path, min_key, min));
+        }
+    }
+    if let Some(max) = schema.get(max_key).and_then(Value::as_f64) {
+        if value > max {
+            return Err(format!("'{}' is above the {} of {}", path, max_key, max));
+        }
+    }
+    Ok(())
+}
+
+fn is_type(value: &Value, instance_type: &str) -> bool {
+    match instance_type {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64()
+                || value.is_u64()
+                || value.as_f64().map(|n| n.fract() == 0.0).unwrap_or(false)
+        }
+        _ => false,
+    }
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -64,3 +371,199 @@ impl<T: Serialize + DeserializeOwned + Clone + Debug + Default + Send + Sync + S
         Self::None
     }
 }
+
+#[cfg(test)]
+#[derive(Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct ExampleConfig {
+    cluster_name: String,
+    instance_count: Option<i64>,
+    tags: Vec<String>,
+}
+
+#[cfg(test)]
+impl Configuration for ExampleConfig {}
+
+#[cfg(test)]
+fn example_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["clusterName"],
+        "additionalProperties": false,
+        "properties": {
+            "clusterName": { "type": "string", "minLength": 1 },
+            "instanceCount": { "type": ["integer", "null"], "minimum": 1 },
+            "tags": { "type": "array", "items": { "type": "string" } }
+        }
+    })
+}
+
+#[test]
+fn test_config_matches_schema() {
+    let config = ExampleConfig {
+        cluster_name: "my-cluster".to_string(),
+        instance_count: Some(2),
+        tags: vec!["a".to_string()],
+    };
+    config.validate_against_schema(&example_schema()).unwrap();
+}
+
+#[test]
+fn test_config_does_not_match_schema() {
+    let config = ExampleConfig {
+        cluster_name: "my-cluster".to_string(),
+        instance_count: Some(0),
+        tags: Vec::new(),
+    };
+    let error = config
+        .validate_against_schema(&example_schema())
+        .unwrap_err();
+    assert!(error.to_string().contains("$.instanceCount"), "{}", error);
+
+    let error = validate_value(&serde_json::json!({}), &example_schema()).unwrap_err();
+    assert!(
+        error.to_string().contains("required field 'clusterName'"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_unsupported_schema_keywords() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "description": "annotations are allowed",
+        "properties": {
+            "tags": { "type": "array", "items": { "type": "string", "format": "uuid" } }
+        }
+    });
+    // The keyword is rejected even though there is no value for it to apply to.
+    let error = validate_value(&serde_json::json!({}), &schema).unwrap_err();
+    assert!(
+        error.to_string().contains("'$.tags[]' uses 'format'"),
+        "{}",
+        error
+    );
+
+    let schema = serde_json::json!({ "oneOf": [{ "type": "string" }, { "type": "integer" }] });
+    assert!(validate_value(&serde_json::json!(1), &schema).is_err());
+}
+
+#[test]
+fn test_merge_nested() {
+    let mut base = serde_json::json!({
+        "region": "us-west-2",
+        "cluster": { "name": "base", "version": "1.24", "tags": { "team": "a" } }
+    });
+    merge_value(
+        &mut base,
+        serde_json::json!({
+            "cluster": { "version": "1.25", "tags": { "env": "ci" } },
+            "assumeRole": "role",
+            "unset": null
+        }),
+    );
+    assert_eq!(
+        base,
+        serde_json::json!({
+            "region": "us-west-2",
+            "assumeRole": "role",
+            "cluster": { "name": "base", "version": "1.25", "tags": { "team": "a", "env": "ci" } }
+        })
+    );
+}
+
+#[test]
+fn test_merge_replaces_arrays() {
+    let base = ExampleConfig {
+        cluster_name: "base".to_string(),
+        instance_count: Some(2),
+        tags: vec!["a".to_string(), "b".to_string()],
+    };
+    let overrides = ExampleConfig {
+        cluster_name: "override".to_string(),
+        instance_count: None,
+        tags: vec!["c".to_string()],
+    };
+    let merged = ExampleConfig::merge(&base, &overrides).unwrap();
+    assert_eq!(merged.cluster_name, "override");
+    assert_eq!(merged.instance_count, Some(2));
+    assert_eq!(merged.tags, vec!["c".to_string()]);
+}
+
+#[cfg(test)]
+#[derive(serde::Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct AgentConfig {
+    cluster_name: String,
+    instance_count: u32,
+}
+
+#[test]
+fn test_deserialize_into() {
+    let config = ExampleConfig {
+        cluster_name: "my-cluster".to_string(),
+        instance_count: Some(2),
+        tags: vec!["a".to_string()],
+    };
+    assert_eq!(
+        config.deserialize_into::<AgentConfig>().unwrap(),
+        AgentConfig {
+            cluster_name: "my-cluster".to_string(),
+            instance_count: 2,
+        }
+    );
+}
+
+#[test]
+fn test_deserialize_into_missing_field() {
+    let config = ExampleConfig {
+        cluster_name: "my-cluster".to_string(),
+        instance_count: None,
+        tags: Vec::new(),
+    };
+    let error = config.deserialize_into::<AgentConfig>().unwrap_err();
+    assert!(error.to_string().contains("instanceCount"), "{}", error);
+
+    #[derive(serde::Deserialize, Debug)]
+    struct Nested {
+        #[allow(dead_code)]
+        cluster: AgentConfig,
+    }
+    let error = deserialize_value::<Nested>(&serde_json::json!({
+        "cluster": { "clusterName": "my-cluster", "instanceCount": "two" }
+    }))
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("field 'cluster.instanceCount'"),
+        "{}",
+        error
+    );
+    let error = deserialize_value::<Nested>(&serde_json::json!({
+        "cluster": { "clusterName": "my-cluster" }
+    }))
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("field 'cluster'")
+            && error.to_string().contains("missing field `instanceCount`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_byte_size() {
+    let config = ExampleConfig {
+        cluster_name: "my-cluster".to_string(),
+        instance_count: Some(2),
+        tags: vec!["a".to_string(); 100],
+    };
+    assert_eq!(
+        config.byte_size(),
+        serde_json::to_vec(&config).unwrap().len()
+    );
+    assert_eq!(
+        ExampleConfig::default().byte_size(),
+        r#"{"clusterName":"","instanceCount":null,"tags":[]}"#.len()
+    );
+}
@@ -32,8 +32,20 @@ pub const LABEL_TEST_NAME: &str = testsys!("test-name");
 pub const LABEL_TEST_UID: &str = testsys!("test-uid");
 pub const LABEL_PROVIDER_NAME: &str = testsys!("provider-name");
 pub const LABEL_COMPONENT: &str = testsys!("component");
+pub const LABEL_REGION: &str = testsys!("region");
+pub const LABEL_VARIANT: &str = testsys!("variant");
+
+// Annotation keys
+pub const ANNOTATION_CI_JOB_URL: &str = testsys!("ci-job-url");
+pub const ANNOTATION_GIT_SHA: &str = testsys!("git-sha");
+pub const ANNOTATION_IMPORTED: &str = testsys!("imported");
 
 // Environment variables
+pub const ENV_LEADER_ELECTION_LEASE: &str = "TESTSYS_LEADER_ELECTION_LEASE";
+pub const ENV_LOG_FORMAT: &str = "TESTSYS_LOG_FORMAT";
+pub const ENV_MAX_CONCURRENT_AGENTS: &str = "TESTSYS_MAX_CONCURRENT_AGENTS";
+pub const ENV_NAMESPACE: &str = "TESTSYS_NAMESPACE";
+pub const ENV_POD_NAME: &str = "TESTSYS_POD_NAME";
 pub const ENV_PROVIDER_NAME: &str = "TESTSYS_PROVIDER_NAME";
 pub const ENV_RESOURCE_ACTION: &str = "TESTSYS_RESOURCE_ACTION";
 pub const ENV_RESOURCE_NAME: &str = "TESTSYS_RESOURCE_NAME";
@@ -1,3 +1,4 @@
+use crate::constants::ANNOTATION_IMPORTED;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use serde::Serialize;
 use std::collections::HashSet;
@@ -65,4 +66,14 @@ pub trait CrdExt: Serialize {
     fn is_delete_requested(&self) -> bool {
         self.object_meta().deletion_timestamp.is_some()
     }
+
+    /// Was the object created from a test bundle, see `TestClient::import_bundle`. The controllers
+    /// do not act on imported objects.
+    fn is_imported(&self) -> bool {
+        self.object_meta()
+            .annotations
+            .as_ref()
+            .map(|annotations| annotations.contains_key(ANNOTATION_IMPORTED))
+            .unwrap_or(false)
+    }
 }
@@ -10,6 +10,12 @@ pub(crate) enum OpaqueError {
     #[snafu(display("Error deserializing configuration: {}", source))]
     ConfigDeserialization { source: serde_json::Error },
 
+    #[snafu(display("Error deserializing configuration field '{}': {}", field, source))]
+    ConfigFieldDeserialization {
+        field: String,
+        source: serde_json::Error,
+    },
+
     #[snafu(display("Error serializing configuration: {}", source))]
     ConfigSerialization { source: serde_json::Error },
 
@@ -18,6 +24,9 @@ pub(crate) enum OpaqueError {
     ))]
     ConfigWrongValueType {},
 
+    #[snafu(display("Configuration does not match its schema: {}", reason))]
+    ConfigSchemaValidation { reason: String },
+
     #[snafu(display(
         "The secret name '{}' is invalid, it must match regex pattern '{}'",
         secret_name,
@@ -30,4 +39,13 @@ pub(crate) enum OpaqueError {
 
     #[snafu(display("Parse error: {}", source))]
     SerdePlain { source: serde_plain::Error },
+
+    #[snafu(display("Error deserializing rendered test template: {}", source))]
+    TemplateDeserialization { source: serde_json::Error },
+
+    #[snafu(display("Error serializing test template: {}", source))]
+    TemplateSerialization { source: serde_json::Error },
+
+    #[snafu(display("No value was given for the test template parameters: {}", params))]
+    TemplateUnresolved { params: String },
 }
@@ -16,22 +16,27 @@ This library provides the Kubernetes custom resource definitions and their API c
 
 pub use agent::{Agent, SecretName, SecretType, TaskState};
 pub use clients::{create_resource_crd, create_test_crd, AllowNotFound};
+pub use clock::{Clock, FakeClock, SystemClock};
 pub use configuration::{ConfigValue, Configuration};
 pub use crd_ext::CrdExt;
 pub use error::{Error, Result};
 use kube::ResourceExt;
 pub use resource::{
     DestructionPolicy, ErrorResources, Resource, ResourceAction, ResourceError, ResourceSpec,
-    ResourceStatus,
+    ResourceStatus, StepStatus,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 pub use test::{
-    AgentStatus, ControllerStatus, Outcome, Test, TestResults, TestSpec, TestStatus, TestUserState,
+    is_agent_stale, next_tests_to_start, select_by_priority, AgentStatus, Backoff,
+    ControllerStatus, LogsLocation, Outcome, Regression, RetryPolicy, Test, TestCaseOutcome,
+    TestCaseResult, TestResults, TestSpec, TestStatus, TestUserState,
 };
+pub use test_template::TestTemplate;
 
 mod agent;
 pub mod clients;
+mod clock;
 mod configuration;
 pub mod constants;
 mod crd_ext;
@@ -41,6 +46,7 @@ mod schema_utils;
 pub mod system;
 mod test;
 pub mod test_manager;
+mod test_template;
 
 /// `CrdName` provides a way of determining which type of testsys object a name refers to.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -59,6 +65,9 @@ impl CrdName {
 }
 
 /// `Crd` provides an interface to combine `Test` and `Resource` when actions can be performed on both.
+// `Test` is much larger than `Resource`, but boxing it would change every match on `Crd` and the
+// objects are short lived, so the size difference is accepted.
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Crd {
@@ -1,6 +1,7 @@
 use crate::constants::TRUNC_LEN;
 use crate::test_manager::ResourceState;
 use crate::{agent::config_schema, Agent, CrdExt, TaskState};
+use chrono::{DateTime, SecondsFormat, Utc};
 use core::option::Option;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::{CustomResource, Resource as Kresource};
@@ -13,9 +14,7 @@ use std::fmt::{Display, Formatter};
 /// A resource required by a test. For example, a compute instance or cluster. The `CustomResource`
 /// derive also produces a struct named `Resource` which represents a resource CRD object in the k8s
 /// API.
-#[derive(
-    Clone, CustomResource, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize,
-)]
+#[derive(Clone, CustomResource, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[kube(
     derive = "Default",
     derive = "PartialEq",
@@ -45,6 +44,10 @@ pub struct ResourceSpec {
     #[serde(default)]
     #[schemars(schema_with = "crate::schema_utils::nullable_enum::<DestructionPolicy>")]
     pub destruction_policy: DestructionPolicy,
+    /// The names of the tests that are using this resource. A resource that is shared by several
+    /// tests should not be destroyed until this is empty, see `ResourceClient::can_destroy`.
+    #[serde(default)]
+    pub used_by: Vec<String>,
 }
 
 impl Resource {
@@ -117,6 +120,38 @@ impl Resource {
         }
     }
 
+    /// Returns `true` if the resource should be destroyed now according to its
+    /// `DestructionPolicy`, given the task states of the tests that use it. Resources that are
+    /// marked for deletion are destroyed unless the policy is `Never`. With `OnTestCompletion`, a
+    /// created resource is also destroyed once every test using it has completed. Tests that
+    /// errored keep their resources. `OnTestSuccess` depends on the outcome of the tests, which
+    /// task states do not include, so it is only destroyed on deletion here. Other resources that
+    /// depend on this one are not considered.
+    pub fn should_destroy_now(&self, test_states: &[TaskState]) -> bool {
+        match self.spec.destruction_policy {
+            DestructionPolicy::Never => false,
+            DestructionPolicy::OnDeletion | DestructionPolicy::OnTestSuccess => {
+                self.is_delete_requested()
+            }
+            DestructionPolicy::OnTestCompletion => {
+                self.is_delete_requested()
+                    || (self.created_resource().is_some()
+                        && test_states
+                            .iter()
+                            .all(|state| *state == TaskState::Completed))
+            }
+        }
+    }
+
+    /// The steps reported by the resource agent for `resource_action`, see
+    /// [`ResourceStatus::steps`].
+    pub fn steps(&self, resource_action: ResourceAction) -> &[StepStatus] {
+        match &self.status {
+            Some(status) => status.steps(resource_action),
+            None => &[],
+        }
+    }
+
     /// Gets either the current creation task state or the destruction task state based on
     /// `resource_action`. `Unknown` is returned if the desired `resource_action` task state does
     /// not exist.
@@ -206,6 +241,62 @@ pub struct ResourceStatus {
 
     /// The time of the last change to this CRD.
     pub last_update: Option<String>,
+
+    /// The progress of each step of the creation task, in the order the steps were first reported,
+    /// e.g. the sub-objects created for a cluster. See `ResourceClient::send_step_update`.
+    #[serde(default)]
+    pub creation_steps: Vec<StepStatus>,
+
+    /// The progress of each step of the destruction task, like `creation_steps`.
+    #[serde(default)]
+    pub destruction_steps: Vec<StepStatus>,
+}
+
+impl ResourceStatus {
+    /// The steps reported by the resource agent for `resource_action`.
+    pub fn steps(&self, resource_action: ResourceAction) -> &[StepStatus] {
+        match resource_action {
+            ResourceAction::Create => &self.creation_steps,
+            ResourceAction::Destroy => &self.destruction_steps,
+        }
+    }
+
+    /// Set the state of the step named `name` of the `resource_action` task at the time `now`,
+    /// adding the step if it has not been reported before. A step's start time is the first time it
+    /// is reported in a state other than `Unknown`, and its finish time is the last time it
+    /// completed, errored or was cancelled.
+    pub fn update_step(
+        &mut self,
+        resource_action: ResourceAction,
+        name: &str,
+        state: TaskState,
+        now: DateTime<Utc>,
+    ) {
+        let steps = match resource_action {
+            ResourceAction::Create => &mut self.creation_steps,
+            ResourceAction::Destroy => &mut self.destruction_steps,
+        };
+        let index = match steps.iter().position(|step| step.name == name) {
+            Some(index) => index,
+            None => {
+                steps.push(StepStatus {
+                    name: name.to_string(),
+                    ..StepStatus::default()
+                });
+                steps.len() - 1
+            }
+        };
+        let step = &mut steps[index];
+        let now = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        step.state = state;
+        if step.started_at.is_none() && state != TaskState::Unknown {
+            step.started_at = Some(now.clone());
+        }
+        step.finished_at = match state {
+            TaskState::Completed | TaskState::Error | TaskState::Cancelled => Some(now),
+            TaskState::Unknown | TaskState::Running => None,
+        };
+    }
 }
 
 impl CrdExt for Resource {
@@ -221,6 +312,20 @@ pub struct ResourceAgentState {
     pub error: Option<ResourceError>,
 }
 
+/// The progress of one step of a resource agent's task.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StepStatus {
+    /// The name of the step, which is unique within the task.
+    pub name: String,
+    /// The state of the step.
+    pub state: TaskState,
+    /// The RFC 3339 timestamp at which the step started.
+    pub started_at: Option<String>,
+    /// The RFC 3339 timestamp at which the step finished.
+    pub finished_at: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceError {
@@ -263,3 +368,130 @@ impl Default for DestructionPolicy {
 
 derive_display_from_serialize!(DestructionPolicy);
 derive_fromstr_from_deserialize!(DestructionPolicy);
+
+#[test]
+fn test_should_destroy_now() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+
+    let resource = |policy, created: bool, deleted: bool| Resource {
+        metadata: ObjectMeta {
+            deletion_timestamp: deleted.then(|| Time(chrono::Utc::now())),
+            ..ObjectMeta::default()
+        },
+        spec: ResourceSpec {
+            destruction_policy: policy,
+            ..ResourceSpec::default()
+        },
+        status: Some(ResourceStatus {
+            created_resource: created.then(Map::new),
+            ..ResourceStatus::default()
+        }),
+    };
+    let completed = [TaskState::Completed, TaskState::Completed];
+    let running = [TaskState::Completed, TaskState::Running];
+    let errored = [TaskState::Completed, TaskState::Error];
+
+    // `OnDeletion` is the default and only destroys deleted resources.
+    assert_eq!(
+        ResourceSpec::default().destruction_policy,
+        DestructionPolicy::OnDeletion
+    );
+    assert!(!resource(DestructionPolicy::OnDeletion, true, false).should_destroy_now(&completed));
+    assert!(resource(DestructionPolicy::OnDeletion, true, true).should_destroy_now(&running));
+
+    // `Never` is never destroyed.
+    assert!(!resource(DestructionPolicy::Never, true, true).should_destroy_now(&completed));
+    assert!(!resource(DestructionPolicy::Never, true, false).should_destroy_now(&[]));
+
+    // `OnTestCompletion` waits for every test to complete.
+    let on_completion = resource(DestructionPolicy::OnTestCompletion, true, false);
+    assert!(on_completion.should_destroy_now(&completed));
+    assert!(on_completion.should_destroy_now(&[]));
+    assert!(!on_completion.should_destroy_now(&running));
+    assert!(!on_completion.should_destroy_now(&errored));
+    assert!(
+        !resource(DestructionPolicy::OnTestCompletion, false, false).should_destroy_now(&completed)
+    );
+    assert!(resource(DestructionPolicy::OnTestCompletion, true, true).should_destroy_now(&running));
+
+    // `OnTestSuccess` needs test outcomes, so only deletion destroys it.
+    assert!(!resource(DestructionPolicy::OnTestSuccess, true, false).should_destroy_now(&completed));
+    assert!(resource(DestructionPolicy::OnTestSuccess, true, true).should_destroy_now(&running));
+}
+
+#[test]
+fn test_update_step() {
+    use chrono::TimeZone;
+
+    let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let later = Utc.with_ymd_and_hms(2023, 1, 1, 0, 5, 0).unwrap();
+    let mut status = ResourceStatus::default();
+    status.update_step(ResourceAction::Create, "vpc", TaskState::Running, start);
+    status.update_step(
+        ResourceAction::Create,
+        "nodegroup",
+        TaskState::Unknown,
+        start,
+    );
+    assert_eq!(
+        status.creation_steps,
+        vec![
+            StepStatus {
+                name: "vpc".to_string(),
+                state: TaskState::Running,
+                started_at: Some("2023-01-01T00:00:00Z".to_string()),
+                finished_at: None,
+            },
+            StepStatus {
+                name: "nodegroup".to_string(),
+                state: TaskState::Unknown,
+                started_at: None,
+                finished_at: None,
+            },
+        ]
+    );
+
+    // Steps are updated in place by name.
+    status.update_step(ResourceAction::Create, "vpc", TaskState::Completed, later);
+    status.update_step(
+        ResourceAction::Create,
+        "nodegroup",
+        TaskState::Running,
+        later,
+    );
+    assert_eq!(status.creation_steps.len(), 2);
+    assert_eq!(status.creation_steps[0].name, "vpc");
+    assert_eq!(status.creation_steps[0].state, TaskState::Completed);
+    assert_eq!(
+        status.creation_steps[0].started_at.as_deref(),
+        Some("2023-01-01T00:00:00Z")
+    );
+    assert_eq!(
+        status.creation_steps[0].finished_at.as_deref(),
+        Some("2023-01-01T00:05:00Z")
+    );
+    assert_eq!(status.creation_steps[1].name, "nodegroup");
+    assert_eq!(
+        status.creation_steps[1].started_at.as_deref(),
+        Some("2023-01-01T00:05:00Z")
+    );
+
+    // A step that runs again is no longer finished.
+    status.update_step(ResourceAction::Create, "vpc", TaskState::Running, later);
+    assert_eq!(status.creation_steps[0].finished_at, None);
+    assert!(status.steps(ResourceAction::Destroy).is_empty());
+}
+
+#[test]
+fn resource_status_without_steps() {
+    let status: ResourceStatus = serde_json::from_value(serde_json::json!({
+        "creation": { "taskState": "running", "error": null },
+        "destruction": { "taskState": "unknown", "error": null },
+        "agentInfo": null,
+        "createdResource": null,
+        "lastUpdate": null
+    }))
+    .unwrap();
+    assert!(status.creation_steps.is_empty());
+    assert!(status.destruction_steps.is_empty());
+}
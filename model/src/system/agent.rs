@@ -1,9 +1,12 @@
+use super::namespace::namespaced_name;
 use crate::constants::{
-    NAMESPACE, RESOURCE_AGENT_BINDING, RESOURCE_AGENT_ROLE, RESOURCE_AGENT_SERVICE_ACCOUNT,
-    TESTSYS, TEST_AGENT_BINDING, TEST_AGENT_ROLE, TEST_AGENT_SERVICE_ACCOUNT,
+    RESOURCE_AGENT_BINDING, RESOURCE_AGENT_ROLE, RESOURCE_AGENT_SERVICE_ACCOUNT, TESTSYS,
+    TEST_AGENT_BINDING, TEST_AGENT_ROLE, TEST_AGENT_SERVICE_ACCOUNT,
 };
 use k8s_openapi::api::core::v1::ServiceAccount;
-use k8s_openapi::api::rbac::v1::{ClusterRole, ClusterRoleBinding, PolicyRule, RoleRef, Subject};
+use k8s_openapi::api::rbac::v1::{
+    ClusterRole, ClusterRoleBinding, PolicyRule, Role, RoleBinding, RoleRef, Subject,
+};
 use kube::api::ObjectMeta;
 use maplit::btreemap;
 
@@ -13,12 +16,12 @@ pub enum AgentType {
     Resource,
 }
 
-/// Defines the service account for an agent of type `agent_type`.
-pub fn agent_service_account(agent_type: AgentType) -> ServiceAccount {
+/// Defines the service account for an agent of type `agent_type` in `namespace`.
+pub fn agent_service_account(agent_type: AgentType, namespace: &str) -> ServiceAccount {
     ServiceAccount {
         metadata: ObjectMeta {
             name: Some(agent_type.service_account_name()),
-            namespace: Some(NAMESPACE.to_string()),
+            namespace: Some(namespace.to_string()),
             annotations: Some(btreemap! {
                 "kubernetes.io/service-account.name".to_string() => agent_type.service_account_name()
             }),
@@ -33,7 +36,6 @@ pub fn agent_cluster_role(agent_type: AgentType) -> ClusterRole {
     ClusterRole {
         metadata: ObjectMeta {
             name: Some(agent_type.role_name()),
-            namespace: Some(NAMESPACE.to_string()),
             ..Default::default()
         },
         rules: Some(agent_type.policy_rules()),
@@ -41,12 +43,12 @@ pub fn agent_cluster_role(agent_type: AgentType) -> ClusterRole {
     }
 }
 
-/// Defines the cluster role binding for an agent of type `agent_type`.
-pub fn agent_cluster_role_binding(agent_type: AgentType) -> ClusterRoleBinding {
+/// Defines the cluster role binding for the service account of an agent of type `agent_type` in
+/// `namespace`.
+pub fn agent_cluster_role_binding(agent_type: AgentType, namespace: &str) -> ClusterRoleBinding {
     ClusterRoleBinding {
         metadata: ObjectMeta {
-            name: Some(agent_type.binding_name()),
-            namespace: Some(NAMESPACE.to_string()),
+            name: Some(namespaced_name(&agent_type.binding_name(), namespace)),
             ..Default::default()
         },
         role_ref: RoleRef {
@@ -57,7 +59,42 @@ pub fn agent_cluster_role_binding(agent_type: AgentType) -> ClusterRoleBinding {
         subjects: Some(vec![Subject {
             kind: "ServiceAccount".to_string(),
             name: agent_type.service_account_name(),
-            namespace: Some(NAMESPACE.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        }]),
+    }
+}
+
+/// Defines a role for an agent of type `agent_type` that only grants access to `namespace`.
+pub fn agent_role(agent_type: AgentType, namespace: &str) -> Role {
+    Role {
+        metadata: ObjectMeta {
+            name: Some(agent_type.role_name()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        rules: Some(agent_type.policy_rules()),
+    }
+}
+
+/// Defines the role binding for the service account of an agent of type `agent_type` in
+/// `namespace`.
+pub fn agent_role_binding(agent_type: AgentType, namespace: &str) -> RoleBinding {
+    RoleBinding {
+        metadata: ObjectMeta {
+            name: Some(agent_type.binding_name()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            kind: "Role".to_string(),
+            name: agent_type.role_name(),
+            api_group: "rbac.authorization.k8s.io".to_string(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: agent_type.service_account_name(),
+            namespace: Some(namespace.to_string()),
             ..Default::default()
         }]),
     }
@@ -1,30 +1,44 @@
+use super::namespace::namespaced_name;
 use crate::constants::{
-    APP_COMPONENT, APP_MANAGED_BY, APP_PART_OF, LABEL_COMPONENT, NAMESPACE, TESTSYS,
+    APP_COMPONENT, APP_MANAGED_BY, APP_PART_OF, ENV_LEADER_ELECTION_LEASE, ENV_LOG_FORMAT,
+    ENV_MAX_CONCURRENT_AGENTS, ENV_NAMESPACE, ENV_POD_NAME, LABEL_COMPONENT, TESTSYS,
 };
 use k8s_openapi::api::apps::v1::{
     Deployment, DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment,
 };
+use k8s_openapi::api::coordination::v1::Lease;
 use k8s_openapi::api::core::v1::{
-    Affinity, Container, LocalObjectReference, NodeAffinity, NodeSelector, NodeSelectorRequirement,
-    NodeSelectorTerm, PodSpec, PodTemplateSpec, ServiceAccount,
+    Affinity, Container, EnvVar, EnvVarSource, LocalObjectReference, NodeAffinity, NodeSelector,
+    NodeSelectorRequirement, NodeSelectorTerm, ObjectFieldSelector, PodSpec, PodTemplateSpec,
+    ResourceRequirements, ServiceAccount,
+};
+use k8s_openapi::api::rbac::v1::{
+    ClusterRole, ClusterRoleBinding, PolicyRule, Role, RoleBinding, RoleRef, Subject,
 };
-use k8s_openapi::api::rbac::v1::{ClusterRole, ClusterRoleBinding, PolicyRule, RoleRef, Subject};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use kube::api::ObjectMeta;
 use maplit::btreemap;
+use std::str::FromStr;
 
-const TESTSYS_CONTROLLER_SERVICE_ACCOUNT: &str = "testsys-controller-service-account";
+pub const TESTSYS_CONTROLLER_SERVICE_ACCOUNT: &str = "testsys-controller-service-account";
+pub const TESTSYS_CONTROLLER_DEPLOYMENT: &str = "testsys-controller";
 const TESTSYS_CONTROLLER_CLUSTER_ROLE: &str = "testsys-controller-role";
+const TESTSYS_CONTROLLER_ROLE_BINDING: &str = "testsys-controller-role-binding";
+/// The `Lease` that controller replicas hold to be the leader, see [`controller_lease`].
+pub const TESTSYS_CONTROLLER_LEASE: &str = "testsys-controller-leader";
+const TESTSYS_CONTROLLER_LEADER_ELECTION_ROLE: &str = "testsys-controller-leader-election";
+const TESTSYS_CONTROLLER_LEADER_ELECTION_ROLE_BINDING: &str =
+    "testsys-controller-leader-election-binding";
 
-/// Defines the testsys-controller service account
-pub fn controller_service_account() -> ServiceAccount {
+/// Defines the testsys-controller service account `service_account` in `namespace`
+pub fn controller_service_account(namespace: &str, service_account: &str) -> ServiceAccount {
     ServiceAccount {
         metadata: ObjectMeta {
-            name: Some(TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string()),
-            namespace: Some(NAMESPACE.to_string()),
+            name: Some(service_account.to_string()),
+            namespace: Some(namespace.to_string()),
             annotations: Some(btreemap! {
-                "kubernetes.io/service-account.name".to_string() => TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string()
+                "kubernetes.io/service-account.name".to_string() => service_account.to_string()
             }),
             ..Default::default()
         },
@@ -37,7 +51,6 @@ pub fn controller_cluster_role() -> ClusterRole {
     ClusterRole {
         metadata: ObjectMeta {
             name: Some(TESTSYS_CONTROLLER_CLUSTER_ROLE.to_string()),
-            namespace: Some(NAMESPACE.to_string()),
             ..Default::default()
         },
         rules: Some(vec![
@@ -119,12 +132,15 @@ pub fn controller_cluster_role() -> ClusterRole {
     }
 }
 
-/// Defines the testsys-controller cluster role binding
-pub fn controller_cluster_role_binding() -> ClusterRoleBinding {
+/// Defines the testsys-controller cluster role binding for the controller's `service_account` in
+/// `namespace`
+pub fn controller_cluster_role_binding(
+    namespace: &str,
+    service_account: &str,
+) -> ClusterRoleBinding {
     ClusterRoleBinding {
         metadata: ObjectMeta {
-            name: Some("testsys-controller-role-binding".to_string()),
-            namespace: Some(NAMESPACE.to_string()),
+            name: Some(namespaced_name(TESTSYS_CONTROLLER_ROLE_BINDING, namespace)),
             ..Default::default()
         },
         role_ref: RoleRef {
@@ -134,20 +150,287 @@ pub fn controller_cluster_role_binding() -> ClusterRoleBinding {
         },
         subjects: Some(vec![Subject {
             kind: "ServiceAccount".to_string(),
-            name: TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string(),
-            namespace: Some(NAMESPACE.to_string()),
+            name: service_account.to_string(),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        }]),
+    }
+}
+
+/// Defines a testsys-controller role that only grants access to `namespace`. This is used instead
+/// of the cluster role when installing with namespaced RBAC. The controller can watch and update
+/// `Test` and `Resource` objects, delete `Resource` objects, and manage deployments and the agent
+/// jobs and pods, like it can with the cluster role.
+pub fn controller_role(namespace: &str) -> Role {
+    Role {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_CLUSTER_ROLE.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        rules: Some(vec![
+            policy_rule(
+                TESTSYS,
+                &["tests", "tests/status"],
+                &["get", "list", "watch", "patch", "update"],
+            ),
+            policy_rule(
+                TESTSYS,
+                &["resources", "resources/status"],
+                &["get", "list", "watch", "patch", "update", "delete"],
+            ),
+            policy_rule(
+                "apps",
+                &["deployments"],
+                &[
+                    "create",
+                    "delete",
+                    "deletecollection",
+                    "get",
+                    "list",
+                    "patch",
+                    "update",
+                ],
+            ),
+            policy_rule(
+                "batch",
+                &["jobs"],
+                &["create", "delete", "get", "list", "watch"],
+            ),
+            policy_rule("", &["pods"], &["delete", "get", "list", "watch"]),
+        ]),
+    }
+}
+
+/// Defines the testsys-controller role binding for the controller's `service_account` in
+/// `namespace`
+pub fn controller_role_binding(namespace: &str, service_account: &str) -> RoleBinding {
+    RoleBinding {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_ROLE_BINDING.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "Role".to_string(),
+            name: TESTSYS_CONTROLLER_CLUSTER_ROLE.to_string(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: service_account.to_string(),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        }]),
+    }
+}
+
+/// Defines the `Lease` in `namespace` that is held by the replica of the controller that is the
+/// leader when there is more than one replica. The holder is set by the controller.
+pub fn controller_lease(namespace: &str) -> Lease {
+    Lease {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_LEASE.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Defines the role that allows the controller replicas to elect a leader with the `Lease`s in
+/// `namespace`. The lease is always in the testsys namespace, so this is a role even when the
+/// controller is given cluster roles.
+pub fn controller_leader_election_role(namespace: &str) -> Role {
+    Role {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_LEADER_ELECTION_ROLE.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        rules: Some(vec![policy_rule(
+            "coordination.k8s.io",
+            &["leases"],
+            &["create", "get", "list", "patch", "update", "watch"],
+        )]),
+    }
+}
+
+/// Defines the leader election role binding for the controller's `service_account` in `namespace`
+pub fn controller_leader_election_role_binding(
+    namespace: &str,
+    service_account: &str,
+) -> RoleBinding {
+    RoleBinding {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_LEADER_ELECTION_ROLE_BINDING.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "Role".to_string(),
+            name: TESTSYS_CONTROLLER_LEADER_ELECTION_ROLE.to_string(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: service_account.to_string(),
+            namespace: Some(namespace.to_string()),
             ..Default::default()
         }]),
     }
 }
 
-/// Defines the testsys-controller deployment
+fn policy_rule(api_group: &str, resources: &[&str], verbs: &[&str]) -> PolicyRule {
+    PolicyRule {
+        api_groups: Some(vec![api_group.to_string()]),
+        resources: Some(resources.iter().map(|s| s.to_string()).collect()),
+        verbs: verbs.iter().map(|s| s.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+/// The format of the controller's log lines, passed to the controller in `TESTSYS_LOG_FORMAT`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControllerLogFormat {
+    /// The human-readable `env_logger` format.
+    #[default]
+    Text,
+    /// One JSON object per line, e.g. for a log pipeline.
+    Json,
+}
+
+impl ControllerLogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControllerLogFormat::Text => "text",
+            ControllerLogFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ControllerLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ControllerLogFormat::Text),
+            "json" => Ok(ControllerLogFormat::Json),
+            _ => Err(format!(
+                "Unknown log format '{}', expected 'text' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+/// How the controller logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControllerLogging {
+    /// The `RUST_LOG` filter of the controller, e.g. `info` or `controller=debug`. By default the
+    /// controller logs everything from itself and `testsys_model`.
+    pub level: Option<String>,
+    /// The format of the controller's log lines.
+    pub format: ControllerLogFormat,
+}
+
+/// The settings of the testsys-controller deployment other than its image, see
+/// [`controller_deployment`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerDeploymentOptions {
+    /// The resource requests and limits of the controller container.
+    pub resources: Option<ResourceRequirements>,
+    /// The name of the service account that the controller runs as.
+    pub service_account: String,
+    /// The maximum number of test agents the controller starts at once. There is no limit if this
+    /// is not set.
+    pub max_concurrent_agents: Option<usize>,
+    /// How the controller logs.
+    pub logging: ControllerLogging,
+    /// The number of controller replicas. When there is more than one, each replica is told to
+    /// elect a leader with the [`controller_lease`].
+    pub replicas: i32,
+}
+
+impl Default for ControllerDeploymentOptions {
+    fn default() -> Self {
+        Self {
+            resources: None,
+            service_account: TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string(),
+            max_concurrent_agents: None,
+            logging: ControllerLogging::default(),
+            replicas: 1,
+        }
+    }
+}
+
+/// Defines the testsys-controller deployment in `namespace` with the settings in `options`.
 pub fn controller_deployment(
     controller_image: String,
     image_pull_secret: Option<String>,
+    namespace: &str,
+    options: &ControllerDeploymentOptions,
 ) -> Deployment {
+    let ControllerDeploymentOptions {
+        resources,
+        service_account,
+        max_concurrent_agents,
+        logging,
+        replicas,
+    } = options;
     let image_pull_secrets =
         image_pull_secret.map(|secret| vec![LocalObjectReference { name: Some(secret) }]);
+    let mut env = vec![EnvVar {
+        name: ENV_NAMESPACE.to_string(),
+        value_from: Some(EnvVarSource {
+            field_ref: Some(ObjectFieldSelector {
+                field_path: "metadata.namespace".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }];
+    if let Some(max_concurrent_agents) = max_concurrent_agents {
+        env.push(EnvVar {
+            name: ENV_MAX_CONCURRENT_AGENTS.to_string(),
+            value: Some(max_concurrent_agents.to_string()),
+            ..Default::default()
+        });
+    }
+    if let Some(level) = &logging.level {
+        env.push(EnvVar {
+            name: "RUST_LOG".to_string(),
+            value: Some(level.clone()),
+            ..Default::default()
+        });
+    }
+    if logging.format != ControllerLogFormat::Text {
+        env.push(EnvVar {
+            name: ENV_LOG_FORMAT.to_string(),
+            value: Some(logging.format.as_str().to_string()),
+            ..Default::default()
+        });
+    }
+    if *replicas > 1 {
+        env.push(EnvVar {
+            name: ENV_LEADER_ELECTION_LEASE.to_string(),
+            value: Some(TESTSYS_CONTROLLER_LEASE.to_string()),
+            ..Default::default()
+        });
+        // Each replica holds the lease under its own pod name.
+        env.push(EnvVar {
+            name: ENV_POD_NAME.to_string(),
+            value_from: Some(EnvVarSource {
+                field_ref: Some(ObjectFieldSelector {
+                    field_path: "metadata.name".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
 
     Deployment {
         metadata: ObjectMeta {
@@ -162,12 +445,12 @@ pub fn controller_deployment(
                 .map(|(k, v)| (k.to_string(), v.to_string()))
                 .collect(),
             ),
-            name: Some("testsys-controller".to_string()),
-            namespace: Some(NAMESPACE.to_string()),
+            name: Some(TESTSYS_CONTROLLER_DEPLOYMENT.to_string()),
+            namespace: Some(namespace.to_string()),
             ..Default::default()
         },
         spec: Some(DeploymentSpec {
-            replicas: Some(1),
+            replicas: Some(*replicas),
             selector: LabelSelector {
                 match_labels: Some(
                     btreemap! { LABEL_COMPONENT.to_string() => "controller".to_string()},
@@ -186,7 +469,7 @@ pub fn controller_deployment(
                     labels: Some(btreemap! {
                         LABEL_COMPONENT.to_string() => "controller".to_string(),
                     }),
-                    namespace: Some(NAMESPACE.to_string()),
+                    namespace: Some(namespace.to_string()),
                     ..Default::default()
                 }),
                 spec: Some(PodSpec {
@@ -211,13 +494,16 @@ pub fn controller_deployment(
                         ..Default::default()
                     }),
                     containers: vec![Container {
+                        // The controller watches the namespace that it is deployed in.
+                        env: Some(env),
                         image: Some(controller_image),
                         image_pull_policy: None,
                         name: "controller".to_string(),
+                        resources: resources.clone(),
                         ..Default::default()
                     }],
                     image_pull_secrets,
-                    service_account_name: Some(TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string()),
+                    service_account_name: Some(service_account.clone()),
                     ..Default::default()
                 }),
             },
@@ -226,3 +512,156 @@ pub fn controller_deployment(
         ..Default::default()
     }
 }
+
+#[test]
+fn controller_role_rules() {
+    let role = controller_role("team-a");
+    assert_eq!(role.metadata.namespace.as_deref(), Some("team-a"));
+    let rules: Vec<(String, Vec<String>, Vec<String>)> = role
+        .rules
+        .unwrap()
+        .into_iter()
+        .map(|rule| {
+            (
+                rule.api_groups.unwrap().join(","),
+                rule.resources.unwrap(),
+                rule.verbs,
+            )
+        })
+        .collect();
+    let strings = |values: &[&str]| values.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        rules,
+        vec![
+            (
+                TESTSYS.to_string(),
+                strings(&["tests", "tests/status"]),
+                strings(&["get", "list", "watch", "patch", "update"])
+            ),
+            (
+                TESTSYS.to_string(),
+                strings(&["resources", "resources/status"]),
+                strings(&["get", "list", "watch", "patch", "update", "delete"])
+            ),
+            (
+                "apps".to_string(),
+                strings(&["deployments"]),
+                strings(&[
+                    "create",
+                    "delete",
+                    "deletecollection",
+                    "get",
+                    "list",
+                    "patch",
+                    "update"
+                ])
+            ),
+            (
+                "batch".to_string(),
+                strings(&["jobs"]),
+                strings(&["create", "delete", "get", "list", "watch"])
+            ),
+            (
+                "".to_string(),
+                strings(&["pods"]),
+                strings(&["delete", "get", "list", "watch"])
+            ),
+        ]
+    );
+    let binding = controller_role_binding("team-a", "my-account");
+    assert_eq!(binding.role_ref.kind, "Role");
+    assert_eq!(binding.role_ref.name, TESTSYS_CONTROLLER_CLUSTER_ROLE);
+    assert_eq!(binding.subjects.unwrap()[0].name, "my-account".to_string());
+}
+
+#[test]
+fn controller_deployment_logging_env() {
+    let env = |logging: &ControllerLogging| {
+        controller_deployment(
+            "controller:latest".to_string(),
+            None,
+            "testsys",
+            &ControllerDeploymentOptions {
+                logging: logging.clone(),
+                ..Default::default()
+            },
+        )
+        .spec
+        .unwrap()
+        .template
+        .spec
+        .unwrap()
+        .containers[0]
+            .env
+            .clone()
+            .unwrap()
+            .into_iter()
+            .filter_map(|env| env.value.map(|value| (env.name, value)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(env(&ControllerLogging::default()), vec![]);
+    assert_eq!(
+        env(&ControllerLogging {
+            level: Some("controller=debug".to_string()),
+            format: ControllerLogFormat::Json,
+        }),
+        vec![
+            ("RUST_LOG".to_string(), "controller=debug".to_string()),
+            (ENV_LOG_FORMAT.to_string(), "json".to_string()),
+        ]
+    );
+    assert_eq!("json".parse(), Ok(ControllerLogFormat::Json));
+    assert!("yaml".parse::<ControllerLogFormat>().is_err());
+}
+
+#[test]
+fn controller_deployment_leader_election() {
+    let deployment = |replicas| {
+        controller_deployment(
+            "controller:latest".to_string(),
+            None,
+            "testsys",
+            &ControllerDeploymentOptions {
+                replicas,
+                ..Default::default()
+            },
+        )
+        .spec
+        .unwrap()
+    };
+    let env_names = |spec: &DeploymentSpec| {
+        spec.template.spec.as_ref().unwrap().containers[0]
+            .env
+            .iter()
+            .flatten()
+            .map(|env| env.name.clone())
+            .collect::<Vec<_>>()
+    };
+    let single = deployment(1);
+    assert_eq!(single.replicas, Some(1));
+    assert_eq!(env_names(&single), vec![ENV_NAMESPACE.to_string()]);
+    let replicated = deployment(2);
+    assert_eq!(replicated.replicas, Some(2));
+    assert_eq!(
+        env_names(&replicated),
+        vec![
+            ENV_NAMESPACE.to_string(),
+            ENV_LEADER_ELECTION_LEASE.to_string(),
+            ENV_POD_NAME.to_string()
+        ]
+    );
+
+    let role = controller_leader_election_role("team-a");
+    let rule = &role.rules.unwrap()[0];
+    assert_eq!(
+        rule.api_groups.as_deref(),
+        Some(&["coordination.k8s.io".to_string()][..])
+    );
+    assert_eq!(rule.resources.as_deref(), Some(&["leases".to_string()][..]));
+    let binding = controller_leader_election_role_binding("team-a", "my-account");
+    assert_eq!(
+        binding.role_ref.name,
+        TESTSYS_CONTROLLER_LEADER_ELECTION_ROLE
+    );
+    assert_eq!(binding.subjects.unwrap()[0].name, "my-account".to_string());
+}
@@ -3,9 +3,15 @@ mod agent;
 mod controller;
 mod namespace;
 
-pub use agent::{agent_cluster_role, agent_cluster_role_binding, agent_service_account, AgentType};
+pub use agent::{
+    agent_cluster_role, agent_cluster_role_binding, agent_role, agent_role_binding,
+    agent_service_account, AgentType,
+};
 pub use controller::{
     controller_cluster_role, controller_cluster_role_binding, controller_deployment,
-    controller_service_account,
+    controller_leader_election_role, controller_leader_election_role_binding, controller_lease,
+    controller_role, controller_role_binding, controller_service_account,
+    ControllerDeploymentOptions, ControllerLogFormat, ControllerLogging,
+    TESTSYS_CONTROLLER_DEPLOYMENT, TESTSYS_CONTROLLER_LEASE, TESTSYS_CONTROLLER_SERVICE_ACCOUNT,
 };
-pub use namespace::testsys_namespace;
+pub use namespace::{default_namespace, testsys_namespace};
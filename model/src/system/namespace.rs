@@ -1,19 +1,36 @@
-use crate::constants::NAMESPACE;
+use crate::constants::{ENV_NAMESPACE, NAMESPACE};
 use k8s_openapi::api::core::v1::Namespace;
 use kube::api::ObjectMeta;
 use maplit::btreemap;
 
 /// Defines the testsys K8S namespace
-pub fn testsys_namespace() -> Namespace {
+pub fn testsys_namespace(namespace: &str) -> Namespace {
     Namespace {
         metadata: ObjectMeta {
             labels: Some(btreemap! {
-                "name".to_string() => "testsys".to_string()
+                "name".to_string() => namespace.to_string()
             }),
-            name: Some(NAMESPACE.to_string()),
+            name: Some(namespace.to_string()),
             ..Default::default()
         },
         spec: None,
         status: None,
     }
 }
+
+/// The namespace that TestSys objects are in. This is the value of the `TESTSYS_NAMESPACE`
+/// environment variable, which is set for the controller and agent pods, or `NAMESPACE` if it is not
+/// set.
+pub fn default_namespace() -> String {
+    std::env::var(ENV_NAMESPACE).unwrap_or_else(|_| NAMESPACE.to_string())
+}
+
+/// The name of a cluster-scoped object that refers to objects in `namespace`, e.g. a cluster role
+/// binding. Installations in a namespace other than `NAMESPACE` get their own copy of the object.
+pub(crate) fn namespaced_name(name: &str, namespace: &str) -> String {
+    if namespace == NAMESPACE {
+        name.to_string()
+    } else {
+        format!("{}-{}", name, namespace)
+    }
+}
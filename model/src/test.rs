@@ -1,18 +1,21 @@
 use crate::constants::FINALIZER_MAIN;
 use crate::crd_ext::CrdExt;
-use crate::{Agent, TaskState};
+use crate::{Agent, Clock, SystemClock, TaskState};
+use chrono::{DateTime, Utc};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::CustomResource;
+use schemars::schema::{InstanceType, ObjectValidation, SchemaObject};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use serde_plain::derive_display_from_serialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 /// A TestSys Test. The `CustomResource` derive also produces a struct named `Test` which represents
 /// a test CRD object in the k8s API.
-#[derive(
-    Clone, CustomResource, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq, Serialize,
-)]
+#[derive(Clone, CustomResource, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize)]
 #[kube(
     derive = "Default",
     derive = "PartialEq",
@@ -38,6 +41,111 @@ pub struct TestSpec {
     pub agent: Agent,
     /// The number of retries the agent is allowed to perform after a failed test.
     pub retries: Option<u32>,
+    /// The number of times this test has been re-run in place. Incrementing this value signals
+    /// the controller to recreate the test agent.
+    pub retry_count: Option<u32>,
+    /// An optional JSON Schema that the agent's `configuration` must satisfy. When present, the
+    /// configuration is validated before the test is created.
+    #[schemars(schema_with = "crate::agent::config_schema")]
+    pub config_schema: Option<serde_json::Value>,
+    /// An expression over the test's labels, e.g. `variant != aws-k8s-1.24`. If the expression is
+    /// true the controller skips the test instead of running it. See [`TestSpec::should_skip`].
+    pub skip_if: Option<String>,
+    /// Tests with a higher priority are started first when the number of running test agents is
+    /// capped. Negative priorities are started after the default of `0`.
+    #[serde(default)]
+    pub priority: i32,
+    /// Retry the test automatically when it fails or errors. See [`RetryPolicy`].
+    pub retry_policy: Option<RetryPolicy>,
+}
+
+impl TestSpec {
+    /// Evaluate the `skip_if` expression against `labels`. The expression is one or more
+    /// comparisons of the form `key == value` or `key != value` joined by `&&`. A label that is not
+    /// present is not equal to any value. Returns `false` if there is no `skip_if` expression or if
+    /// it cannot be parsed.
+    pub fn should_skip(&self, labels: &BTreeMap<String, String>) -> bool {
+        match &self.skip_if {
+            Some(expression) => expression
+                .split("&&")
+                .map(|comparison| evaluate_comparison(comparison, labels))
+                .collect::<Option<Vec<_>>>()
+                .is_some_and(|results| results.into_iter().all(|result| result)),
+            None => false,
+        }
+    }
+}
+
+/// Evaluate a single `key == value` or `key != value` comparison, returning `None` if it is not
+/// a valid comparison.
+fn evaluate_comparison(comparison: &str, labels: &BTreeMap<String, String>) -> Option<bool> {
+    let (key, value, equal) = if let Some((key, value)) = comparison.split_once("!=") {
+        (key, value, false)
+    } else if let Some((key, value)) = comparison.split_once("==") {
+        (key, value, true)
+    } else {
+        return None;
+    };
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() || value.is_empty() {
+        return None;
+    }
+    let matches = labels.get(key).map(String::as_str) == Some(value);
+    Some(matches == equal)
+}
+
+/// How the controller retries a failed `Test` in place. Each retry recreates the test agent as if
+/// `TestClient::retry` had been called, after waiting for the delay given by the `backoff`.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// The most times the test is retried. Manual retries, counted by `spec.retryCount`, count
+    /// towards this limit.
+    pub max_retries: u32,
+    /// How long to wait after a failure before each retry.
+    #[serde(default)]
+    pub backoff: Backoff,
+}
+
+impl RetryPolicy {
+    /// The delay before the retry that follows `attempt` earlier retries, so `next_delay(0)` is the
+    /// delay before the first retry. The delay is multiplied by the `multiplier` for each earlier
+    /// retry and is capped at `max_delay_seconds`.
+    pub fn next_delay(&self, attempt: u32) -> Duration {
+        let backoff = &self.backoff;
+        let delay = backoff
+            .multiplier
+            .checked_pow(attempt)
+            .and_then(|factor| backoff.initial_delay_seconds.checked_mul(u64::from(factor)))
+            .unwrap_or(u64::MAX);
+        Duration::from_secs(match backoff.max_delay_seconds {
+            Some(max_delay) => delay.min(max_delay),
+            None => delay,
+        })
+    }
+}
+
+/// An exponential backoff between the retries of a [`RetryPolicy`]. Use a `multiplier` of `1` to
+/// wait the same time before every retry.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Backoff {
+    /// The delay before the first retry. Defaults to `10` seconds.
+    pub initial_delay_seconds: u64,
+    /// The factor the delay grows by with each retry. Defaults to `2`.
+    pub multiplier: u32,
+    /// The longest delay between two retries. Defaults to `300` seconds, `null` for no limit.
+    pub max_delay_seconds: Option<u64>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial_delay_seconds: 10,
+            multiplier: 2,
+            max_delay_seconds: Some(300),
+        }
+    }
 }
 
 /// The status field of the TestSys Test CRD. This is where the controller and agents will write
@@ -53,6 +161,44 @@ pub struct TestStatus {
     pub last_update: Option<String>,
 }
 
+impl TestStatus {
+    /// How long the test agent has been running. If the agent has not finished, this is the time
+    /// since it started. Returns `None` if the agent has not started or a timestamp is invalid.
+    pub fn running_duration(&self) -> Option<Duration> {
+        self.running_duration_at(&SystemClock)
+    }
+
+    /// Like `running_duration`, but the time since an agent that has not finished started is
+    /// measured with `clock`.
+    pub fn running_duration_at(&self, clock: &dyn Clock) -> Option<Duration> {
+        let started_at = DateTime::parse_from_rfc3339(self.agent.started_at.as_ref()?).ok()?;
+        let finished_at = match &self.agent.finished_at {
+            Some(finished_at) => DateTime::parse_from_rfc3339(finished_at).ok()?,
+            None => clock.now().into(),
+        };
+        finished_at.signed_duration_since(started_at).to_std().ok()
+    }
+
+    /// Returns `true` if a run of the test failed and a later run passed, e.g. after a retry. The
+    /// runs in `results_history` are considered before those in `results`.
+    pub fn is_flaky(&self) -> bool {
+        let mut failed = false;
+        for results in self
+            .agent
+            .results_history
+            .iter()
+            .chain(self.agent.results.iter())
+        {
+            if results.is_failure() {
+                failed = true;
+            } else if failed && results.is_pass() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
 /// The `Outcome` of a test run, reported by the test agent.
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -80,6 +226,15 @@ pub struct TestResults {
     pub num_failed: u64,
     pub num_skipped: u64,
     pub other_info: Option<String>,
+    /// The location of any artifacts, e.g. a results tarball, that the test agent uploaded.
+    pub artifact_uri: Option<String>,
+    /// The RFC 3339 timestamp of when the test run started.
+    pub start_time: Option<String>,
+    /// The RFC 3339 timestamp of when the test run finished.
+    pub end_time: Option<String>,
+    /// The result of each test case, if the test agent reports them. See
+    /// [`TestResults::with_case_counts`] for keeping the counts above consistent with the cases.
+    pub cases: Option<Vec<TestCaseResult>>,
 }
 
 impl TestResults {
@@ -87,8 +242,134 @@ impl TestResults {
     pub fn total(&self) -> u64 {
         self.num_passed + self.num_failed + self.num_skipped
     }
+
+    /// The fraction of all tests counted that passed, from `0.0` to `1.0`. Returns `0.0` if no
+    /// tests were counted.
+    pub fn pass_rate(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.num_passed as f64 / total as f64,
+        }
+    }
+
+    /// How long the test run took, if both the `start_time` and `end_time` are present and valid.
+    pub fn duration(&self) -> Option<Duration> {
+        let start = DateTime::parse_from_rfc3339(self.start_time.as_ref()?).ok()?;
+        let end = DateTime::parse_from_rfc3339(self.end_time.as_ref()?).ok()?;
+        end.signed_duration_since(start).to_std().ok()
+    }
+
+    /// The test cases that failed. Empty if the test agent did not report any cases.
+    pub fn failed_cases(&self) -> Vec<&TestCaseResult> {
+        self.cases
+            .iter()
+            .flatten()
+            .filter(|case| case.outcome == TestCaseOutcome::Fail)
+            .collect()
+    }
+
+    /// Returns `true` if the run passed without any failed tests.
+    pub fn is_pass(&self) -> bool {
+        self.outcome == Outcome::Pass && self.num_failed == 0
+    }
+
+    /// Returns `true` if the run failed, timed out or had failed tests.
+    pub fn is_failure(&self) -> bool {
+        matches!(self.outcome, Outcome::Fail | Outcome::Timeout) || self.num_failed > 0
+    }
+
+    /// Set `num_passed`, `num_failed` and `num_skipped` from the outcomes of the `cases`. The
+    /// counts are left unchanged if there are no `cases`.
+    pub fn with_case_counts(mut self) -> Self {
+        if let Some(cases) = &self.cases {
+            let count =
+                |outcome| cases.iter().filter(|case| case.outcome == outcome).count() as u64;
+            self.num_passed = count(TestCaseOutcome::Pass);
+            self.num_failed = count(TestCaseOutcome::Fail);
+            self.num_skipped = count(TestCaseOutcome::Skip);
+        }
+        self
+    }
+
+    /// Compare the `cases` of these results with the `cases` of a `previous` run of the same test.
+    /// Cases are matched by name, cases that are only in one of the runs are ignored.
+    pub fn regression_against(&self, previous: &TestResults) -> Regression {
+        let previous: BTreeMap<&str, TestCaseOutcome> = previous
+            .cases
+            .iter()
+            .flatten()
+            .map(|case| (case.name.as_str(), case.outcome))
+            .collect();
+        let mut regression = Regression::default();
+        for case in self.cases.iter().flatten() {
+            let name = case.name.clone();
+            match (previous.get(case.name.as_str()), case.outcome) {
+                (Some(TestCaseOutcome::Pass), TestCaseOutcome::Fail) => {
+                    regression.new_failures.push(name)
+                }
+                (Some(TestCaseOutcome::Pass | TestCaseOutcome::Fail), TestCaseOutcome::Skip) => {
+                    regression.newly_skipped.push(name)
+                }
+                (Some(TestCaseOutcome::Fail), TestCaseOutcome::Pass) => regression.fixed.push(name),
+                _ => {}
+            }
+        }
+        regression
+    }
+}
+
+/// The test cases whose outcome changed since a previous run, see
+/// [`TestResults::regression_against`].
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Regression {
+    /// The cases that passed in the previous run and failed in this one.
+    pub new_failures: Vec<String>,
+    /// The cases that passed or failed in the previous run and were skipped in this one.
+    pub newly_skipped: Vec<String>,
+    /// The cases that failed in the previous run and passed in this one.
+    pub fixed: Vec<String>,
+}
+
+impl Regression {
+    /// Returns `true` if any case that passed in the previous run failed, or if any case that ran
+    /// in the previous run was skipped.
+    pub fn is_regression(&self) -> bool {
+        !self.new_failures.is_empty() || !self.newly_skipped.is_empty()
+    }
 }
 
+/// The result of a single test case within a test run.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCaseResult {
+    pub name: String,
+    pub outcome: TestCaseOutcome,
+    /// How long the test case took to run, in milliseconds.
+    pub duration_ms: Option<u64>,
+    /// Details about the outcome, e.g. the reason the test case failed.
+    pub message: Option<String>,
+}
+
+impl TestCaseResult {
+    /// How long the test case took to run, if it was reported.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration_ms.map(Duration::from_millis)
+    }
+}
+
+/// The outcome of a single test case.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Copy, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum TestCaseOutcome {
+    Pass,
+    Fail,
+    #[default]
+    Skip,
+}
+
+derive_display_from_serialize!(TestCaseOutcome);
+
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AgentStatus {
@@ -98,14 +379,101 @@ pub struct AgentStatus {
     /// *may* be an error message here. If there is an error message here and the `run_state` is
     /// *not* `Error`, the this is a bad state and the `error_message` should be ignored.
     pub error: Option<String>,
+    /// The results reported by the test agent for the current run of the test.
+    #[serde(default)]
     pub results: Vec<TestResults>,
+    /// The results of previous runs of the test, oldest first. Results are moved here from
+    /// `results` when the test is retried.
+    #[serde(default)]
+    pub results_history: Vec<TestResults>,
     pub current_test: Option<TestResults>,
+    /// The RFC 3339 timestamp after which `keep_running` should no longer be honored, if a
+    /// `keep_running_timeout_seconds` was given.
+    pub keep_running_expiry: Option<String>,
+    /// Set by the controller when the test ran longer than `spec.agent.timeout`.
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Set by the controller when the test was not run because its `skip_if` expression matched.
+    pub skip_reason: Option<String>,
+    /// The RFC 3339 timestamp at which the task state last became `running`.
+    pub started_at: Option<String>,
+    /// The RFC 3339 timestamp at which the test agent last finished, either by completing or by
+    /// reporting an error.
+    pub finished_at: Option<String>,
+    /// The RFC 3339 timestamp of the last heartbeat sent by the running test agent.
+    pub last_heartbeat: Option<String>,
+    /// How far the running test agent is through its task, from 0 to 100 percent. This is set by
+    /// `TestClient::send_progress`.
+    pub progress: Option<u8>,
+    /// Where the logs of the test agent are stored. `None` means the logs are only in the agent
+    /// pod. This is set by `TestClient::send_logs_location`.
+    #[schemars(schema_with = "logs_location_schema")]
+    pub logs_location: Option<LogsLocation>,
+}
+
+/// Where the logs of a test agent are stored, so that they can be read from the right backend.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(tag = "backend", rename_all = "camelCase")]
+pub enum LogsLocation {
+    /// The logs are in the agent pod and are removed with it.
+    #[default]
+    Pod,
+    /// The logs were uploaded to S3.
+    S3 {
+        /// The URI of the logs, e.g. `s3://bucket/my-test/agent.log`.
+        uri: String,
+    },
+    /// The logs are sent to CloudWatch Logs.
+    #[serde(rename = "cloudWatch")]
+    CloudWatch {
+        /// The log group.
+        group: String,
+        /// The log stream within the `group`.
+        stream: String,
+    },
+}
+
+/// The schema of `AgentStatus::logs_location`. Kubernetes requires a structural schema, which the
+/// `oneOf` that schemars derives for `LogsLocation` is not, so the fields of every variant are
+/// described as optional properties of a single object.
+fn logs_location_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    let string = || -> schemars::schema::Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            ..SchemaObject::default()
+        }
+        .into()
+    };
+    let backend = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        enum_values: Some(vec!["pod".into(), "s3".into(), "cloudWatch".into()]),
+        ..SchemaObject::default()
+    };
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            required: ["backend".to_string()].into(),
+            properties: [
+                ("backend".to_string(), backend.into()),
+                ("uri".to_string(), string()),
+                ("group".to_string(), string()),
+                ("stream".to_string(), string()),
+            ]
+            .into(),
+            ..ObjectValidation::default()
+        })),
+        extensions: [("nullable".to_string(), Value::Bool(true))].into(),
+        ..SchemaObject::default()
+    }
+    .into()
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ControllerStatus {
     pub resource_error: Option<String>,
+    /// The most recent `spec.retryCount` that the controller has acted on.
+    pub observed_retry_count: Option<u32>,
 }
 
 /// A simplified summary of the test's current state. This can be used by a user interface to
@@ -133,6 +501,10 @@ pub enum TestUserState {
     ResourceError,
     /// The test is in the process of being deleted.
     Deleting,
+    /// The test was cancelled before it finished.
+    Cancelled,
+    /// The test was not run because its `skip_if` expression matched.
+    Skipped,
 }
 
 impl Default for TestUserState {
@@ -182,6 +554,7 @@ impl Test {
                 }
             }
             TaskState::Running => TestUserState::Running,
+            TaskState::Completed if agent_status.skip_reason.is_some() => TestUserState::Skipped,
             TaskState::Completed => {
                 if let Some(results) = agent_status.results.last() {
                     match results.outcome {
@@ -203,7 +576,41 @@ impl Test {
                 }
             }
             TaskState::Error => TestUserState::Error,
+            TaskState::Cancelled => TestUserState::Cancelled,
+        }
+    }
+
+    /// When the controller should retry the test because of its `retry_policy`. Returns `None` if
+    /// the test has no retry policy, did not fail or error, or has used all of its retries. The
+    /// delay is measured from when the test agent finished, or from the last update of the test if
+    /// the agent did not report when it finished.
+    pub fn next_automatic_retry(&self) -> Option<DateTime<Utc>> {
+        let policy = self.spec.retry_policy.as_ref()?;
+        if !matches!(
+            self.test_user_state(),
+            TestUserState::Failed | TestUserState::Error
+        ) {
+            return None;
         }
+        let attempt = self.spec.retry_count.unwrap_or_default();
+        if attempt >= policy.max_retries {
+            return None;
+        }
+        let status = self.status.as_ref()?;
+        let failed_at = status
+            .agent
+            .finished_at
+            .as_ref()
+            .or(status.last_update.as_ref())
+            .and_then(|failed_at| DateTime::parse_from_rfc3339(failed_at).ok())
+            .map(|failed_at| failed_at.with_timezone(&Utc))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        Some(
+            chrono::Duration::from_std(policy.next_delay(attempt))
+                .ok()
+                .and_then(|delay| failed_at.checked_add_signed(delay))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        )
     }
 }
 
@@ -212,3 +619,735 @@ impl CrdExt for Test {
         &self.metadata
     }
 }
+
+/// Choose which of the `pending` tests to start when `running` test agents are already running and
+/// at most `cap` may run at once. See [`select_by_priority`] for the order tests are started in.
+pub fn next_tests_to_start(running: usize, cap: usize, pending: &[Test]) -> Vec<&Test> {
+    select_by_priority(pending, cap.saturating_sub(running))
+}
+
+/// Choose up to `slots` of the `pending` tests to start. Tests with the highest `priority` are
+/// chosen first, ties are broken by the oldest creation timestamp and then by name.
+pub fn select_by_priority(pending: &[Test], slots: usize) -> Vec<&Test> {
+    let mut pending: Vec<&Test> = pending.iter().collect();
+    pending.sort_by(|a, b| {
+        b.spec
+            .priority
+            .cmp(&a.spec.priority)
+            .then_with(|| {
+                a.metadata
+                    .creation_timestamp
+                    .cmp(&b.metadata.creation_timestamp)
+            })
+            .then_with(|| a.metadata.name.cmp(&b.metadata.name))
+    });
+    pending.truncate(slots);
+    pending
+}
+
+impl AgentStatus {
+    /// Returns `true` if the agent has sent a heartbeat, but not within `threshold` of the time
+    /// of `clock`, see [`is_agent_stale`]. An agent that has never sent a heartbeat, or whose
+    /// heartbeat cannot be parsed, is not stale.
+    pub fn is_heartbeat_stale(&self, clock: &dyn Clock, threshold: Duration) -> bool {
+        self.last_heartbeat
+            .as_ref()
+            .and_then(|last| DateTime::parse_from_rfc3339(last).ok())
+            .map(|last| is_agent_stale(last.into(), clock.now(), threshold))
+            .unwrap_or(false)
+    }
+}
+
+/// Returns `true` if an agent whose `last` heartbeat was sent more than `threshold` before `now`
+/// should be considered dead. A heartbeat from the future is never stale.
+pub fn is_agent_stale(last: DateTime<Utc>, now: DateTime<Utc>, threshold: Duration) -> bool {
+    now.signed_duration_since(last)
+        .to_std()
+        .map(|elapsed| elapsed > threshold)
+        .unwrap_or(false)
+}
+
+#[test]
+fn agent_status_without_results_history() {
+    let agent_status: AgentStatus = serde_json::from_value(serde_json::json!({
+        "taskState": "completed",
+        "error": null,
+        "results": [{"outcome": "pass", "numPassed": 1, "numFailed": 0, "numSkipped": 0}],
+        "currentTest": null
+    }))
+    .unwrap();
+    assert_eq!(agent_status.results.len(), 1);
+    assert!(agent_status.results_history.is_empty());
+}
+
+#[test]
+fn test_status_is_flaky() {
+    let run = |outcome, num_failed| TestResults {
+        outcome,
+        num_passed: 1,
+        num_failed,
+        ..TestResults::default()
+    };
+    let status = |history: Vec<TestResults>, results: Vec<TestResults>| TestStatus {
+        agent: AgentStatus {
+            results_history: history,
+            results,
+            ..AgentStatus::default()
+        },
+        ..TestStatus::default()
+    };
+    // Stable passes.
+    assert!(!status(vec![run(Outcome::Pass, 0)], vec![run(Outcome::Pass, 0)]).is_flaky());
+    // Stable failures.
+    assert!(!status(vec![run(Outcome::Fail, 1)], vec![run(Outcome::Timeout, 0)]).is_flaky());
+    // A pass followed by a failure is a regression, not a flake.
+    assert!(!status(vec![run(Outcome::Pass, 0)], vec![run(Outcome::Fail, 1)]).is_flaky());
+    // A failure that passed on retry.
+    assert!(status(vec![run(Outcome::Fail, 1)], vec![run(Outcome::Pass, 0)]).is_flaky());
+    assert!(status(
+        vec![run(Outcome::Pass, 0), run(Outcome::Timeout, 0)],
+        vec![run(Outcome::Pass, 0)]
+    )
+    .is_flaky());
+    // A failure and pass within the same set of results.
+    assert!(status(vec![], vec![run(Outcome::Pass, 2), run(Outcome::Pass, 0)]).is_flaky());
+    assert!(!TestStatus::default().is_flaky());
+}
+
+#[test]
+fn retry_policy_next_delay() {
+    let policy = RetryPolicy {
+        max_retries: 10,
+        backoff: Backoff::default(),
+    };
+    let delays: Vec<_> = (0..7).map(|attempt| policy.next_delay(attempt)).collect();
+    assert_eq!(
+        delays,
+        [10, 20, 40, 80, 160, 300, 300].map(Duration::from_secs)
+    );
+    assert_eq!(policy.next_delay(u32::MAX), Duration::from_secs(300));
+
+    let constant = RetryPolicy {
+        max_retries: 3,
+        backoff: Backoff {
+            initial_delay_seconds: 30,
+            multiplier: 1,
+            max_delay_seconds: None,
+        },
+    };
+    assert_eq!(constant.next_delay(0), Duration::from_secs(30));
+    assert_eq!(constant.next_delay(100), Duration::from_secs(30));
+
+    let unbounded = RetryPolicy {
+        max_retries: 3,
+        backoff: Backoff {
+            max_delay_seconds: None,
+            ..Backoff::default()
+        },
+    };
+    assert_eq!(unbounded.next_delay(64), Duration::from_secs(u64::MAX));
+}
+
+#[test]
+fn retry_policy_serde() {
+    let policy: RetryPolicy = serde_json::from_value(serde_json::json!({
+        "maxRetries": 2,
+        "backoff": {"initialDelaySeconds": 5}
+    }))
+    .unwrap();
+    assert_eq!(policy.max_retries, 2);
+    assert_eq!(
+        policy.backoff,
+        Backoff {
+            initial_delay_seconds: 5,
+            ..Backoff::default()
+        }
+    );
+    let policy: RetryPolicy = serde_json::from_value(serde_json::json!({"maxRetries": 1})).unwrap();
+    assert_eq!(policy.backoff, Backoff::default());
+}
+
+#[test]
+fn test_next_automatic_retry() {
+    let failed = |retry_count, outcome| Test {
+        spec: TestSpec {
+            retry_count,
+            retry_policy: Some(RetryPolicy {
+                max_retries: 2,
+                backoff: Backoff::default(),
+            }),
+            ..TestSpec::default()
+        },
+        status: Some(TestStatus {
+            agent: AgentStatus {
+                task_state: TaskState::Completed,
+                finished_at: Some("2023-01-01T00:00:00Z".to_string()),
+                results: vec![TestResults {
+                    outcome,
+                    ..TestResults::default()
+                }],
+                ..AgentStatus::default()
+            },
+            ..TestStatus::default()
+        }),
+        ..Test::default()
+    };
+    assert_eq!(
+        failed(None, Outcome::Fail)
+            .next_automatic_retry()
+            .unwrap()
+            .to_rfc3339(),
+        "2023-01-01T00:00:10+00:00"
+    );
+    assert_eq!(
+        failed(Some(1), Outcome::Timeout)
+            .next_automatic_retry()
+            .unwrap()
+            .to_rfc3339(),
+        "2023-01-01T00:00:20+00:00"
+    );
+    // The retries are used up.
+    assert_eq!(failed(Some(2), Outcome::Fail).next_automatic_retry(), None);
+    // The test passed.
+    assert_eq!(failed(None, Outcome::Pass).next_automatic_retry(), None);
+    // There is no retry policy.
+    let mut test = failed(None, Outcome::Fail);
+    test.spec.retry_policy = None;
+    assert_eq!(test.next_automatic_retry(), None);
+    // An error without a finish time is retried straight away.
+    let mut test = failed(None, Outcome::Unknown);
+    let status = test.status.as_mut().unwrap();
+    status.agent.task_state = TaskState::Error;
+    status.agent.finished_at = None;
+    assert_eq!(
+        test.next_automatic_retry(),
+        Some(DateTime::<Utc>::MIN_UTC + chrono::Duration::seconds(10))
+    );
+}
+
+#[test]
+fn logs_location_serde() {
+    for (location, json) in [
+        (LogsLocation::Pod, serde_json::json!({"backend": "pod"})),
+        (
+            LogsLocation::S3 {
+                uri: "s3://bucket/my-test/agent.log".to_string(),
+            },
+            serde_json::json!({"backend": "s3", "uri": "s3://bucket/my-test/agent.log"}),
+        ),
+        (
+            LogsLocation::CloudWatch {
+                group: "/testsys/agents".to_string(),
+                stream: "my-test".to_string(),
+            },
+            serde_json::json!({
+                "backend": "cloudWatch",
+                "group": "/testsys/agents",
+                "stream": "my-test"
+            }),
+        ),
+    ] {
+        assert_eq!(serde_json::to_value(&location).unwrap(), json);
+        assert_eq!(
+            serde_json::from_value::<LogsLocation>(json).unwrap(),
+            location
+        );
+    }
+    let agent_status: AgentStatus = serde_json::from_value(serde_json::json!({
+        "taskState": "running",
+        "error": null,
+        "currentTest": null
+    }))
+    .unwrap();
+    assert_eq!(agent_status.logs_location, None);
+}
+
+#[test]
+fn agent_status_timed_out_serde() {
+    let agent_status: AgentStatus = serde_json::from_value(serde_json::json!({
+        "taskState": "running",
+        "error": null,
+        "currentTest": null
+    }))
+    .unwrap();
+    assert!(!agent_status.timed_out);
+
+    let agent_status = AgentStatus {
+        timed_out: true,
+        ..agent_status
+    };
+    let serialized = serde_json::to_value(&agent_status).unwrap();
+    assert_eq!(serialized["timedOut"], serde_json::json!(true));
+    assert_eq!(
+        serde_json::from_value::<AgentStatus>(serialized).unwrap(),
+        agent_status
+    );
+}
+
+#[test]
+fn agent_status_timestamps_serde() {
+    let agent_status: AgentStatus = serde_json::from_value(serde_json::json!({
+        "taskState": "completed",
+        "startedAt": "2023-01-01T00:00:00Z",
+        "finishedAt": "2023-01-01T00:10:00Z"
+    }))
+    .unwrap();
+    assert_eq!(
+        agent_status.started_at.as_deref(),
+        Some("2023-01-01T00:00:00Z")
+    );
+    assert_eq!(
+        agent_status.finished_at.as_deref(),
+        Some("2023-01-01T00:10:00Z")
+    );
+    let serialized = serde_json::to_value(&agent_status).unwrap();
+    assert_eq!(
+        serialized["startedAt"],
+        serde_json::json!("2023-01-01T00:00:00Z")
+    );
+    assert_eq!(
+        serde_json::from_value::<AgentStatus>(serialized).unwrap(),
+        agent_status
+    );
+
+    let agent_status: AgentStatus =
+        serde_json::from_value(serde_json::json!({ "taskState": "unknown" })).unwrap();
+    assert!(agent_status.started_at.is_none());
+    assert!(agent_status.finished_at.is_none());
+}
+
+#[test]
+fn test_status_running_duration() {
+    let mut status = TestStatus::default();
+    assert!(status.running_duration().is_none());
+
+    // The agent starts running.
+    status.agent.task_state = TaskState::Running;
+    status.agent.started_at = Some("2023-01-01T00:00:00Z".to_string());
+    assert!(status.running_duration().unwrap() > Duration::from_secs(60));
+
+    // The agent finishes.
+    status.agent.task_state = TaskState::Completed;
+    status.agent.finished_at = Some("2023-01-01T01:30:15Z".to_string());
+    assert_eq!(status.running_duration(), Some(Duration::from_secs(5415)));
+
+    status.agent.finished_at = Some("invalid".to_string());
+    assert!(status.running_duration().is_none());
+}
+
+#[test]
+fn test_status_running_duration_at() {
+    use k8s_openapi::chrono::TimeZone;
+    let clock = crate::FakeClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 10, 0).unwrap());
+    let mut status = TestStatus::default();
+    status.agent.started_at = Some("2023-01-01T00:00:00Z".to_string());
+    assert_eq!(
+        status.running_duration_at(&clock),
+        Some(Duration::from_secs(600))
+    );
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(
+        status.running_duration_at(&clock),
+        Some(Duration::from_secs(605))
+    );
+    // A finished agent's duration does not depend on the clock.
+    status.agent.finished_at = Some("2023-01-01T00:01:00Z".to_string());
+    assert_eq!(
+        status.running_duration_at(&clock),
+        Some(Duration::from_secs(60))
+    );
+}
+
+#[test]
+fn agent_heartbeat_stale_at() {
+    use k8s_openapi::chrono::TimeZone;
+    let clock = crate::FakeClock::new(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap());
+    let threshold = Duration::from_secs(300);
+    let mut agent_status = AgentStatus::default();
+    assert!(!agent_status.is_heartbeat_stale(&clock, threshold));
+    agent_status.last_heartbeat = Some("2023-01-01T00:00:00Z".to_string());
+    clock.advance(threshold);
+    assert!(!agent_status.is_heartbeat_stale(&clock, threshold));
+    clock.advance(Duration::from_secs(1));
+    assert!(agent_status.is_heartbeat_stale(&clock, threshold));
+    agent_status.last_heartbeat = Some("invalid".to_string());
+    assert!(!agent_status.is_heartbeat_stale(&clock, threshold));
+}
+
+#[cfg(test)]
+fn skip_if_spec(expression: &str) -> TestSpec {
+    TestSpec {
+        skip_if: Some(expression.to_string()),
+        ..TestSpec::default()
+    }
+}
+
+#[test]
+fn skip_if_equality() {
+    let labels = BTreeMap::from([("variant".to_string(), "aws-k8s-1.24".to_string())]);
+    assert!(skip_if_spec("variant == aws-k8s-1.24").should_skip(&labels));
+    assert!(!skip_if_spec("variant == aws-ecs-1").should_skip(&labels));
+    assert!(!TestSpec::default().should_skip(&labels));
+}
+
+#[test]
+fn skip_if_inequality() {
+    let labels = BTreeMap::from([
+        ("variant".to_string(), "aws-k8s-1.24".to_string()),
+        ("arch".to_string(), "x86_64".to_string()),
+    ]);
+    assert!(!skip_if_spec("variant != aws-k8s-1.24").should_skip(&labels));
+    assert!(skip_if_spec("variant != aws-ecs-1").should_skip(&labels));
+    assert!(skip_if_spec("variant!=aws-ecs-1 && arch==x86_64").should_skip(&labels));
+    assert!(!skip_if_spec("variant != aws-ecs-1 && arch == aarch64").should_skip(&labels));
+}
+
+#[test]
+fn skip_if_missing_label() {
+    let labels = BTreeMap::new();
+    assert!(!skip_if_spec("variant == aws-k8s-1.24").should_skip(&labels));
+    assert!(skip_if_spec("variant != aws-k8s-1.24").should_skip(&labels));
+}
+
+#[test]
+fn skip_if_invalid() {
+    let labels = BTreeMap::from([("variant".to_string(), "aws-k8s-1.24".to_string())]);
+    assert!(!skip_if_spec("").should_skip(&labels));
+    assert!(!skip_if_spec("variant").should_skip(&labels));
+    assert!(!skip_if_spec("== aws-k8s-1.24").should_skip(&labels));
+    assert!(!skip_if_spec("variant == aws-k8s-1.24 &&").should_skip(&labels));
+}
+
+#[test]
+fn test_results_empty() {
+    let results = TestResults::default();
+    assert_eq!(results.total(), 0);
+    assert_eq!(results.pass_rate(), 0.0);
+    assert_eq!(results.duration(), None);
+}
+
+#[test]
+fn test_results_all_pass() {
+    let results = TestResults {
+        outcome: Outcome::Pass,
+        num_passed: 4,
+        start_time: Some("2023-03-01T10:00:00Z".to_string()),
+        end_time: Some("2023-03-01T10:01:30Z".to_string()),
+        ..TestResults::default()
+    };
+    assert_eq!(results.total(), 4);
+    assert_eq!(results.pass_rate(), 1.0);
+    assert_eq!(results.duration(), Some(Duration::from_secs(90)));
+}
+
+#[test]
+fn test_results_mixed() {
+    let results = TestResults {
+        outcome: Outcome::Fail,
+        num_passed: 2,
+        num_failed: 1,
+        num_skipped: 1,
+        start_time: Some("2023-03-01T10:00:00Z".to_string()),
+        end_time: Some("not a timestamp".to_string()),
+        ..TestResults::default()
+    };
+    assert_eq!(results.total(), 4);
+    assert_eq!(results.pass_rate(), 0.5);
+    assert_eq!(results.duration(), None);
+}
+
+#[cfg(test)]
+fn case(name: &str, outcome: TestCaseOutcome) -> TestCaseResult {
+    TestCaseResult {
+        name: name.to_string(),
+        outcome,
+        ..TestCaseResult::default()
+    }
+}
+
+#[test]
+fn test_results_cases_serde() {
+    let results = TestResults {
+        outcome: Outcome::Fail,
+        cases: Some(vec![TestCaseResult {
+            name: "conformance".to_string(),
+            outcome: TestCaseOutcome::Fail,
+            duration_ms: Some(1500),
+            message: Some("timed out".to_string()),
+        }]),
+        ..TestResults::default()
+    };
+    let value = serde_json::to_value(&results).unwrap();
+    assert_eq!(
+        value["cases"],
+        serde_json::json!([{
+            "name": "conformance",
+            "outcome": "fail",
+            "durationMs": 1500,
+            "message": "timed out"
+        }])
+    );
+    assert_eq!(
+        serde_json::from_value::<TestResults>(value).unwrap(),
+        results
+    );
+    assert_eq!(
+        results.cases.unwrap()[0].duration(),
+        Some(Duration::from_millis(1500))
+    );
+
+    // Results reported before cases existed can still be read.
+    let results: TestResults = serde_json::from_value(serde_json::json!({
+        "outcome": "pass",
+        "numPassed": 1,
+        "numFailed": 0,
+        "numSkipped": 0
+    }))
+    .unwrap();
+    assert_eq!(results.cases, None);
+    assert!(results.failed_cases().is_empty());
+}
+
+#[test]
+fn test_results_failed_cases() {
+    let results = TestResults {
+        num_passed: 10,
+        cases: Some(vec![
+            case("a", TestCaseOutcome::Pass),
+            case("b", TestCaseOutcome::Fail),
+            case("c", TestCaseOutcome::Skip),
+            case("d", TestCaseOutcome::Fail),
+        ]),
+        ..TestResults::default()
+    }
+    .with_case_counts();
+    let failed: Vec<&str> = results
+        .failed_cases()
+        .into_iter()
+        .map(|case| case.name.as_str())
+        .collect();
+    assert_eq!(failed, vec!["b", "d"]);
+    assert_eq!(results.num_passed, 1);
+    assert_eq!(results.num_failed, 2);
+    assert_eq!(results.num_skipped, 1);
+
+    // Without cases the counts reported by the agent are kept.
+    let results = TestResults {
+        num_passed: 10,
+        ..TestResults::default()
+    }
+    .with_case_counts();
+    assert_eq!(results.num_passed, 10);
+}
+
+#[test]
+fn test_results_regression_against() {
+    let results = |cases: &[(&str, TestCaseOutcome)]| TestResults {
+        cases: Some(
+            cases
+                .iter()
+                .map(|(name, outcome)| case(name, *outcome))
+                .collect(),
+        ),
+        ..TestResults::default()
+    };
+    let previous = results(&[
+        ("a", TestCaseOutcome::Pass),
+        ("b", TestCaseOutcome::Pass),
+        ("c", TestCaseOutcome::Fail),
+        ("d", TestCaseOutcome::Pass),
+        ("f", TestCaseOutcome::Fail),
+    ]);
+
+    // New failures and newly skipped cases are regressions, whether the skipped cases passed or
+    // failed before.
+    let regression = results(&[
+        ("a", TestCaseOutcome::Fail),
+        ("b", TestCaseOutcome::Skip),
+        ("c", TestCaseOutcome::Fail),
+        ("d", TestCaseOutcome::Pass),
+        ("e", TestCaseOutcome::Fail),
+        ("f", TestCaseOutcome::Skip),
+    ])
+    .regression_against(&previous);
+    assert_eq!(regression.new_failures, vec!["a"]);
+    assert_eq!(regression.newly_skipped, vec!["b", "f"]);
+    assert!(regression.fixed.is_empty());
+    assert!(regression.is_regression());
+
+    // Fixed failures are not.
+    let regression = results(&[
+        ("a", TestCaseOutcome::Pass),
+        ("b", TestCaseOutcome::Pass),
+        ("c", TestCaseOutcome::Pass),
+        ("d", TestCaseOutcome::Pass),
+    ])
+    .regression_against(&previous);
+    assert_eq!(regression.fixed, vec!["c"]);
+    assert!(!regression.is_regression());
+
+    // Nothing changed.
+    assert_eq!(
+        previous.regression_against(&previous),
+        Regression::default()
+    );
+    assert_eq!(
+        TestResults::default().regression_against(&previous),
+        Regression::default()
+    );
+}
+
+#[cfg(test)]
+fn pending_test(name: &str, created: i64) -> Test {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::TimeZone;
+    Test {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            creation_timestamp: Some(Time(Utc.timestamp_opt(created, 0).unwrap())),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+fn names(tests: Vec<&Test>) -> Vec<&str> {
+    tests
+        .into_iter()
+        .filter_map(|test| test.metadata.name.as_deref())
+        .collect()
+}
+
+#[test]
+fn next_tests_to_start_capped() {
+    let pending = vec![
+        pending_test("c", 30),
+        pending_test("a", 10),
+        pending_test("b", 20),
+    ];
+    assert_eq!(names(next_tests_to_start(0, 2, &pending)), vec!["a", "b"]);
+    assert_eq!(names(next_tests_to_start(1, 2, &pending)), vec!["a"]);
+    assert_eq!(
+        names(next_tests_to_start(0, 10, &pending)),
+        vec!["a", "b", "c"]
+    );
+}
+
+#[test]
+fn next_tests_to_start_full() {
+    let pending = vec![pending_test("a", 10)];
+    assert!(next_tests_to_start(2, 2, &pending).is_empty());
+    // More agents can be running than the cap if it was lowered.
+    assert!(next_tests_to_start(5, 2, &pending).is_empty());
+    assert!(next_tests_to_start(0, 0, &pending).is_empty());
+    assert!(next_tests_to_start(0, 2, &[]).is_empty());
+}
+
+#[test]
+fn next_tests_to_start_ties() {
+    let pending = vec![pending_test("b", 10), pending_test("a", 10)];
+    assert_eq!(names(next_tests_to_start(0, 1, &pending)), vec!["a"]);
+}
+
+#[cfg(test)]
+fn prioritized_test(name: &str, created: i64, priority: i32) -> Test {
+    let mut test = pending_test(name, created);
+    test.spec.priority = priority;
+    test
+}
+
+#[test]
+fn select_by_priority_order() {
+    let pending = vec![
+        prioritized_test("soak", 10, -5),
+        prioritized_test("conformance", 20, 0),
+        prioritized_test("smoke", 30, 10),
+    ];
+    assert_eq!(
+        names(select_by_priority(&pending, 3)),
+        vec!["smoke", "conformance", "soak"]
+    );
+    assert_eq!(names(select_by_priority(&pending, 1)), vec!["smoke"]);
+    assert!(select_by_priority(&pending, 0).is_empty());
+    assert_eq!(
+        names(next_tests_to_start(1, 3, &pending)),
+        vec!["smoke", "conformance"]
+    );
+}
+
+#[test]
+fn select_by_priority_negative() {
+    let pending = vec![
+        prioritized_test("lowest", 10, -10),
+        prioritized_test("low", 20, -1),
+    ];
+    assert_eq!(
+        names(select_by_priority(&pending, 2)),
+        vec!["low", "lowest"]
+    );
+}
+
+#[test]
+fn select_by_priority_ties() {
+    let pending = vec![
+        prioritized_test("newer", 20, 1),
+        prioritized_test("b-older", 10, 1),
+        prioritized_test("a-older", 10, 1),
+        prioritized_test("other", 5, 0),
+    ];
+    assert_eq!(
+        names(select_by_priority(&pending, 4)),
+        vec!["a-older", "b-older", "newer", "other"]
+    );
+}
+
+#[test]
+fn priority_default() {
+    let spec: TestSpec = serde_json::from_value(serde_json::json!({
+        "resources": [],
+        "agent": {
+            "name": "agent",
+            "image": "example.com/agent:v0.1",
+            "keepRunning": false
+        }
+    }))
+    .unwrap();
+    assert_eq!(spec.priority, 0);
+}
+
+#[test]
+fn agent_heartbeat_fresh() {
+    use k8s_openapi::chrono::TimeZone;
+    let last = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let threshold = Duration::from_secs(300);
+    assert!(!is_agent_stale(last, last, threshold));
+    assert!(!is_agent_stale(
+        last,
+        last + chrono::Duration::seconds(300),
+        threshold
+    ));
+    // A heartbeat from the future, e.g. due to clock skew, is not stale.
+    assert!(!is_agent_stale(
+        last,
+        last - chrono::Duration::seconds(60),
+        threshold
+    ));
+}
+
+#[test]
+fn agent_heartbeat_stale() {
+    use k8s_openapi::chrono::TimeZone;
+    let last = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let threshold = Duration::from_secs(300);
+    assert!(is_agent_stale(
+        last,
+        last + chrono::Duration::seconds(301),
+        threshold
+    ));
+    assert!(is_agent_stale(
+        last,
+        last + chrono::Duration::hours(2),
+        threshold
+    ));
+}
@@ -0,0 +1,213 @@
+use super::{error, LogOptions, ResourceState, Result, TestManager};
+use crate::clients::{CrdClient, HttpStatusCode, StatusCode};
+use crate::Crd;
+use chrono::{DateTime, Utc};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::io::AsyncBufReadExt;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{ListParams, LogParams};
+use kube::{Api, ResourceExt};
+use snafu::ResultExt;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// How long to wait between checks for agent pods that have started while following their logs.
+const POD_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A line from the logs of one of the agent pods of a test, see `TestManager::all_agent_logs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentLogLine {
+    /// The name of the pod that logged the line.
+    pub pod: String,
+    /// When the line was logged, if Kubernetes reported it.
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The line without its timestamp.
+    pub line: String,
+}
+
+impl AgentLogLine {
+    /// Split the timestamp that Kubernetes adds to the start of each line when
+    /// `LogParams::timestamps` is set from the rest of the `line`.
+    fn parse(pod: &str, line: &str) -> Self {
+        let (timestamp, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match DateTime::parse_from_rfc3339(timestamp) {
+            Ok(timestamp) => Self {
+                pod: pod.to_string(),
+                timestamp: Some(timestamp.with_timezone(&Utc)),
+                line: rest.to_string(),
+            },
+            Err(_) => Self {
+                pod: pod.to_string(),
+                timestamp: None,
+                line: line.to_string(),
+            },
+        }
+    }
+}
+
+impl TestManager {
+    /// Get the logs of the agent pods of the TestSys `Test` `test_name` and of the resources it
+    /// depends on, see `TestManager::all_agent_logs`.
+    pub(super) async fn agent_logs(
+        &self,
+        test_name: &str,
+        options: &LogOptions,
+    ) -> Result<BoxStream<'static, Result<AgentLogLine>>> {
+        let job_names = self.agent_job_names(test_name).await?;
+        let pod_api: Api<Pod> = self.namespaced_api();
+        let log_params = LogParams {
+            timestamps: true,
+            ..options.log_params()
+        };
+        if options.follow {
+            let (tx, rx) = unbounded();
+            tokio::task::spawn(follow_agent_logs(pod_api, job_names, log_params, tx));
+            return Ok(rx.boxed());
+        }
+        let mut lines = Vec::new();
+        for pod in agent_pods(&pod_api, &job_names).await? {
+            match pod_log_lines(&pod_api, pod, &log_params).await {
+                Ok(pod_lines) => lines.extend(pod_lines.try_collect::<Vec<_>>().await?),
+                Err(e) if is_container_starting(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        // The sort is stable, so the lines of each pod stay in order.
+        lines.sort_by_key(|line| line.timestamp);
+        Ok(stream::iter(lines.into_iter().map(Ok)).boxed())
+    }
+
+    /// The names of the jobs for the test agent of `test_name` and for the resource agents of the
+    /// resources it depends on.
+    async fn agent_job_names(&self, test_name: &str) -> Result<Vec<String>> {
+        let test = self
+            .test_client()
+            .get(test_name)
+            .await
+            .context(error::ClientSnafu { action: "get test" })?;
+        let mut job_names = Vec::new();
+        for crd in self.add_dependencies_to_vec(vec![Crd::Test(test)]).await? {
+            match crd {
+                Crd::Test(test) => job_names.push(test.name_any()),
+                Crd::Resource(resource) => {
+                    job_names.push(resource.job_name(ResourceState::Creation));
+                    job_names.push(resource.job_name(ResourceState::Destruction));
+                }
+            }
+        }
+        // A resource that more than one object depends on is visited more than once.
+        job_names.sort();
+        job_names.dedup();
+        Ok(job_names)
+    }
+}
+
+/// The names of the pods of the jobs in `job_names`. Jobs that have not created a pod yet are
+/// skipped.
+async fn agent_pods(pod_api: &Api<Pod>, job_names: &[String]) -> Result<Vec<String>> {
+    Ok(pod_api
+        .list(&ListParams::default().labels(&format!("job-name in ({})", job_names.join(","))))
+        .await
+        .context(error::KubeSnafu { action: "get pods" })?
+        .items
+        .iter()
+        .map(|pod| pod.name_any())
+        .collect())
+}
+
+/// Stream the lines logged by `pod`.
+async fn pod_log_lines(
+    pod_api: &Api<Pod>,
+    pod: String,
+    log_params: &LogParams,
+) -> Result<impl Stream<Item = Result<AgentLogLine>> + Send> {
+    let logs = pod_api
+        .log_stream(&pod, log_params)
+        .await
+        .context(error::KubeSnafu {
+            action: "stream logs",
+        })?;
+    Ok(logs
+        .map_err(std::io::Error::other)
+        .into_async_read()
+        .lines()
+        .map(move |line| {
+            line.map(|line| AgentLogLine::parse(&pod, &line))
+                .context(error::IoSnafu {
+                    action: "read logs",
+                })
+        }))
+}
+
+/// Returns `true` if `error` was caused by a pod whose container has not started yet.
+fn is_container_starting(error: &error::Error) -> bool {
+    match error {
+        error::Error::Kube { source, .. } => source.status_code() == Some(StatusCode::BAD_REQUEST),
+        _ => false,
+    }
+}
+
+/// Send the lines logged by each pod of the jobs in `job_names` to `tx` as they are logged until
+/// the receiver is dropped. Pods that are created later, e.g. for resource destruction, are
+/// followed once their containers start.
+async fn follow_agent_logs(
+    pod_api: Api<Pod>,
+    job_names: Vec<String>,
+    log_params: LogParams,
+    tx: UnboundedSender<Result<AgentLogLine>>,
+) {
+    let mut followed = BTreeSet::new();
+    while !tx.is_closed() {
+        let pods = match agent_pods(&pod_api, &job_names).await {
+            Ok(pods) => pods,
+            Err(e) => {
+                let _ = tx.unbounded_send(Err(e));
+                Vec::new()
+            }
+        };
+        for pod in pods {
+            if followed.contains(&pod) {
+                continue;
+            }
+            match pod_log_lines(&pod_api, pod.clone(), &log_params).await {
+                Ok(lines) => {
+                    followed.insert(pod);
+                    let tx = tx.clone();
+                    tokio::task::spawn(async move {
+                        // The receiver was dropped if this fails, so there is nobody to tell.
+                        let _ = lines.map(Ok).forward(tx).await;
+                    });
+                }
+                // Try again once the container has started.
+                Err(e) if is_container_starting(&e) => {}
+                Err(e) => {
+                    followed.insert(pod);
+                    let _ = tx.unbounded_send(Err(e));
+                }
+            }
+        }
+        tokio::time::sleep(POD_POLL_INTERVAL).await;
+    }
+}
+
+#[test]
+fn test_parse_agent_log_line() {
+    let line = AgentLogLine::parse(
+        "my-test-abcde",
+        "2022-06-01T12:30:00.123456789Z Running 3 tests",
+    );
+    assert_eq!(line.pod, "my-test-abcde");
+    assert_eq!(line.line, "Running 3 tests");
+    assert_eq!(
+        line.timestamp.unwrap().to_rfc3339(),
+        "2022-06-01T12:30:00.123456789+00:00"
+    );
+    let line = AgentLogLine::parse("my-test-abcde", "2022-06-01T12:30:00Z");
+    assert!(line.timestamp.is_some());
+    assert_eq!(line.line, "");
+    let line = AgentLogLine::parse("my-test-abcde", "no timestamp here");
+    assert_eq!(line.timestamp, None);
+    assert_eq!(line.line, "no timestamp here");
+}
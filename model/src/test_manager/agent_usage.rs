@@ -0,0 +1,150 @@
+use super::{error, Result, TestManager};
+use crate::clients::{HttpStatusCode, StatusCode};
+use crate::constants::{APP_COMPONENT, APP_NAME, TEST_AGENT};
+use kube::api::{ApiResource, DynamicObject, GroupVersionKind, ListParams};
+use kube::{Api, ResourceExt};
+use serde_json::Value;
+use snafu::ResultExt;
+
+/// The resources used by the agent pod of a running test, as reported by the metrics API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentUsage {
+    /// The name of the test the pod is running.
+    pub test: String,
+    /// The name of the agent pod.
+    pub pod: String,
+    /// The CPU used by all containers of the pod, in cores.
+    pub cpu_cores: f64,
+    /// The memory used by all containers of the pod, in bytes.
+    pub memory_bytes: f64,
+}
+
+impl AgentUsage {
+    /// Read the usage of the pod from a `PodMetrics` object. Containers whose usage is missing or
+    /// cannot be parsed are counted as using nothing.
+    fn from_pod_metrics(metrics: &DynamicObject) -> Self {
+        let usage = |resource: &str| -> f64 {
+            metrics
+                .data
+                .get("containers")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|container| container.get("usage")?.get(resource)?.as_str())
+                .filter_map(parse_quantity)
+                .sum()
+        };
+        Self {
+            test: metrics.labels().get(APP_NAME).cloned().unwrap_or_default(),
+            pod: metrics.name_any(),
+            cpu_cores: usage("cpu"),
+            memory_bytes: usage("memory"),
+        }
+    }
+}
+
+impl TestManager {
+    /// Get the CPU and memory used by the agent pod of each running test from the
+    /// `metrics.k8s.io` API. Returns `MetricsUnavailable` if the cluster does not serve the
+    /// metrics API, e.g. because metrics-server is not installed.
+    pub async fn agent_usage(&self) -> Result<Vec<AgentUsage>> {
+        let api_resource = ApiResource::from_gvk_with_plural(
+            &GroupVersionKind::gvk("metrics.k8s.io", "v1beta1", "PodMetrics"),
+            "pods",
+        );
+        let api: Api<DynamicObject> =
+            Api::namespaced_with(self.k8s_client.clone(), self.namespace(), &api_resource);
+        let pod_metrics = api
+            .list(&ListParams::default().labels(&format!("{}={}", APP_COMPONENT, TEST_AGENT)))
+            .await;
+        let pod_metrics = match pod_metrics {
+            Err(e)
+                if matches!(
+                    e.status_code(),
+                    Some(StatusCode::NOT_FOUND) | Some(StatusCode::SERVICE_UNAVAILABLE)
+                ) =>
+            {
+                return error::MetricsUnavailableSnafu.fail()
+            }
+            result => result.context(error::KubeSnafu {
+                action: "get pod metrics",
+            })?,
+        };
+        Ok(pod_metrics
+            .items
+            .iter()
+            .map(AgentUsage::from_pod_metrics)
+            .collect())
+    }
+}
+
+/// Parse a Kubernetes quantity, e.g. `250m`, `1.5` or `128Mi`, into its value in base units.
+/// Returns `None` if `quantity` is not a valid quantity. Exponents, e.g. `1e3`, are not supported
+/// because the metrics API does not report them.
+fn parse_quantity(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let split = quantity
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+    let multiplier = match suffix {
+        "" => 1.0,
+        "n" => 1e-9,
+        "u" => 1e-6,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 1024.0,
+        "Mi" => 1024f64.powi(2),
+        "Gi" => 1024f64.powi(3),
+        "Ti" => 1024f64.powi(4),
+        "Pi" => 1024f64.powi(5),
+        "Ei" => 1024f64.powi(6),
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    number.is_finite().then_some(number * multiplier)
+}
+
+#[test]
+fn test_parse_quantity() {
+    assert_eq!(parse_quantity("2"), Some(2.0));
+    assert_eq!(parse_quantity("250m"), Some(0.25));
+    assert_eq!(parse_quantity("1500000n"), Some(0.0015));
+    assert_eq!(parse_quantity("0.5"), Some(0.5));
+    assert_eq!(parse_quantity("128Mi"), Some(134217728.0));
+    assert_eq!(parse_quantity("64Ki"), Some(65536.0));
+    assert_eq!(parse_quantity("1G"), Some(1e9));
+    assert_eq!(parse_quantity(""), None);
+    assert_eq!(parse_quantity("12Xi"), None);
+    assert_eq!(parse_quantity("Mi"), None);
+}
+
+#[test]
+fn test_agent_usage_from_pod_metrics() {
+    let metrics: DynamicObject = serde_json::from_value(serde_json::json!({
+        "apiVersion": "metrics.k8s.io/v1beta1",
+        "kind": "PodMetrics",
+        "metadata": {
+            "name": "my-test-abcde",
+            "labels": {APP_NAME: "my-test", APP_COMPONENT: TEST_AGENT}
+        },
+        "timestamp": "2023-01-01T00:00:00Z",
+        "window": "15s",
+        "containers": [
+            {"name": "my-test", "usage": {"cpu": "250m", "memory": "96Mi"}},
+            {"name": "fluent-bit", "usage": {"cpu": "5000000n", "memory": "32Mi"}},
+            {"name": "no-usage"}
+        ]
+    }))
+    .unwrap();
+    let usage = AgentUsage::from_pod_metrics(&metrics);
+    assert_eq!(usage.test, "my-test");
+    assert_eq!(usage.pod, "my-test-abcde");
+    assert!((usage.cpu_cores - 0.255).abs() < 1e-9);
+    assert_eq!(usage.memory_bytes, 128.0 * 1024.0 * 1024.0);
+}
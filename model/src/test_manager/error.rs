@@ -41,18 +41,56 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("'{}' is not a valid Kubernetes quantity", quantity))]
+    InvalidQuantity { quantity: String },
+
     #[snafu(display("Unable to {}: {}", action, source))]
     Kube { action: String, source: kube::Error },
 
     #[snafu(display("Could not serialize object: {}", source))]
     JsonSerialize { source: serde_json::Error },
 
+    #[snafu(display(
+        "TestSys is not installed in namespace '{}', the controller deployment does not exist",
+        namespace
+    ))]
+    NotInstalled { namespace: String },
+
+    #[snafu(display(
+        "The metrics API is not available in the cluster, install metrics-server to see the \
+        resource usage of agent pods"
+    ))]
+    MetricsUnavailable,
+
+    #[snafu(display(
+        "The namespace '{}' does not exist, it must be created before installing without \
+        creating the namespace",
+        namespace
+    ))]
+    MissingNamespace { namespace: String },
+
     #[snafu(display("Unable to find {}", what))]
     NotFound { what: String },
 
     #[snafu(display("Some resources are still in the cluster"))]
     ResourceExisting,
 
+    #[snafu(display(
+        "The kubeconfig has no context named '{}', the available contexts are: {}",
+        context,
+        available
+    ))]
+    UnknownContext { context: String, available: String },
+
+    #[snafu(display(
+        "Unable to install object of kind '{}', only objects generated by `install` are supported",
+        kind
+    ))]
+    UnsupportedInstallKind { kind: String },
+
+    #[snafu(display("Some tests are still in the cluster"))]
+    TestExisting,
+
     #[snafu(display("Unable to send event: {}", source))]
     Sender {
         source: futures::channel::mpsc::SendError,
@@ -63,4 +101,10 @@ pub enum Error {
         action: String,
         source: serde_yaml::Error,
     },
+
+    #[snafu(display("Unable to {}: {}", action, source))]
+    Watch {
+        action: String,
+        source: Box<kube::runtime::watcher::Error>,
+    },
 }
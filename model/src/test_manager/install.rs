@@ -1,161 +1,333 @@
 use super::{error, Result};
 use crate::clients::AllowNotFound;
-use crate::constants::NAMESPACE;
 use crate::system::{
-    agent_cluster_role, agent_cluster_role_binding, agent_service_account, controller_cluster_role,
-    controller_cluster_role_binding, controller_deployment, controller_service_account,
-    testsys_namespace, AgentType,
+    agent_cluster_role, agent_cluster_role_binding, agent_role, agent_role_binding,
+    agent_service_account, controller_cluster_role, controller_cluster_role_binding,
+    controller_deployment, controller_leader_election_role,
+    controller_leader_election_role_binding, controller_lease, controller_role,
+    controller_role_binding, controller_service_account, testsys_namespace, AgentType,
+    ControllerDeploymentOptions, TESTSYS_CONTROLLER_SERVICE_ACCOUNT,
 };
-use crate::test_manager::TestManager;
+use crate::test_manager::{ImageConfig, InstallOptions, TestManager};
 use crate::{Resource, Test};
-use k8s_openapi::api::core::v1::Namespace;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::coordination::v1::Lease;
+use k8s_openapi::api::core::v1::{Namespace, ServiceAccount};
+use k8s_openapi::api::rbac::v1::{ClusterRole, ClusterRoleBinding, Role, RoleBinding};
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
-use kube::{Api, CustomResourceExt, ResourceExt};
+use k8s_openapi::NamespaceResourceScope;
+use kube::{Api, CustomResourceExt, Resource as KubeResource, ResourceExt};
 use log::info;
-use snafu::ResultExt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use snafu::{OptionExt, ResultExt};
+use std::fmt::Debug;
+use std::path::Path;
 use std::time::Duration;
 
-impl TestManager {
-    /// Create the testsys namespace
-    pub(super) async fn create_namespace(&self) -> Result<()> {
-        // Add the namespace to the cluster.
-        let ns = testsys_namespace();
+/// A Kubernetes object from a manifest generated by [`install_manifest`]. Only the kinds of objects
+/// that `install` creates are supported.
+#[derive(Debug, Clone)]
+pub enum InstallObject {
+    Namespace(Namespace),
+    CustomResourceDefinition(Box<CustomResourceDefinition>),
+    ClusterRole(ClusterRole),
+    ClusterRoleBinding(ClusterRoleBinding),
+    Role(Role),
+    RoleBinding(RoleBinding),
+    ServiceAccount(ServiceAccount),
+    Lease(Lease),
+    Deployment(Box<Deployment>),
+}
 
-        self.create_or_update(self.api(), &ns, "namespace").await?;
+impl TestManager {
+    /// Make sure the testsys namespace exists when it is not created by `install`.
+    pub(super) async fn check_namespace_exists(&self) -> Result<()> {
+        self.api::<Namespace>()
+            .get(self.namespace())
+            .await
+            .allow_not_found(|_| {})
+            .context(error::KubeSnafu {
+                action: "get namespace",
+            })?
+            .context(error::MissingNamespaceSnafu {
+                namespace: self.namespace(),
+            })?;
+        Ok(())
+    }
 
-        // Give the object enough time to settle.
+    /// Give a namespace that was just created enough time to settle.
+    async fn wait_for_namespace(&self, namespace: &str) -> Result<()> {
         let mut sleep_count = 0;
         let api = self.api::<Namespace>();
-        while api.get(NAMESPACE).await.is_err() && sleep_count < 20 {
+        while api.get(namespace).await.is_err() && sleep_count < 20 {
             tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
             sleep_count += 1;
         }
 
-        api.get(NAMESPACE)
+        api.get(namespace)
             .await
             .context(error::CreateSnafu { what: "namespace" })?;
 
         Ok(())
     }
 
-    pub(super) async fn create_crd(&self) -> Result<()> {
-        // Create the `Test` crd.
-        let testcrd = Test::crd();
-        // Create the `Resource` crd.
-        let resourcecrd = Resource::crd();
-
-        self.create_or_update(self.api(), &testcrd, "Test CRD")
-            .await?;
-        self.create_or_update(self.api(), &resourcecrd, "Resource Provider CRD")
-            .await
-    }
-
-    pub(super) async fn create_roles(&self, agent_type: AgentType) -> Result<()> {
-        // If the role exists merge the new role, if not create the role.
-        let test_agent_cluster_role = agent_cluster_role(agent_type);
-        self.create_or_update(self.api(), &test_agent_cluster_role, "Agent Cluster Role")
-            .await?;
-
-        // If the role already exists, update it with the new one using Patch. If not create a new
-        // role.
-        let controller_cluster_role = controller_cluster_role();
-        self.create_or_update(
-            self.api(),
-            &controller_cluster_role,
-            "Controller Cluster Role",
-        )
-        .await?;
-
-        // If the cluster role binding already exists, update it with the new one using Patch. If
-        // not create a new cluster role binding.
-        let agent_cluster_role_binding = agent_cluster_role_binding(agent_type);
-        self.create_or_update(
-            self.api(),
-            &agent_cluster_role_binding,
-            "Agent Cluster Role Binding",
-        )
-        .await?;
-
-        // If the cluster role binding already exists, update it with the new one using Patch. If
-        // not create a new cluster role binding.
-        let controller_cluster_role_binding = controller_cluster_role_binding();
-        self.create_or_update(
-            self.api(),
-            &controller_cluster_role_binding,
-            "Controller Cluster Role Binding",
-        )
-        .await?;
-
+    /// Apply `objects`, e.g. from [`read_install_manifest`], in order instead of generating them.
+    /// Namespaced objects are created in the namespace given in their metadata, or in the
+    /// `TestManager`'s namespace if they do not have one.
+    pub(super) async fn install_objects(
+        &self,
+        objects: &[InstallObject],
+        apply: bool,
+    ) -> Result<()> {
+        for object in objects {
+            match object {
+                InstallObject::Namespace(o) => {
+                    self.install_object(self.api(), o, "namespace", apply)
+                        .await?;
+                    self.wait_for_namespace(&o.name_any()).await?
+                }
+                InstallObject::CustomResourceDefinition(o) => {
+                    self.install_object(self.api(), o.as_ref(), "CRD", apply)
+                        .await?
+                }
+                InstallObject::ClusterRole(o) => {
+                    self.install_object(self.api(), o, "Cluster Role", apply)
+                        .await?
+                }
+                InstallObject::ClusterRoleBinding(o) => {
+                    self.install_object(self.api(), o, "Cluster Role Binding", apply)
+                        .await?
+                }
+                InstallObject::Role(o) => {
+                    self.install_object(self.object_api(o), o, "Role", apply)
+                        .await?
+                }
+                InstallObject::RoleBinding(o) => {
+                    self.install_object(self.object_api(o), o, "Role Binding", apply)
+                        .await?
+                }
+                InstallObject::ServiceAccount(o) => {
+                    self.install_object(self.object_api(o), o, "Service Account", apply)
+                        .await?
+                }
+                InstallObject::Lease(o) => {
+                    self.install_object(self.object_api(o), o, "Lease", apply)
+                        .await?
+                }
+                InstallObject::Deployment(o) => {
+                    self.install_object(
+                        self.object_api(o.as_ref()),
+                        o.as_ref(),
+                        "Deployment",
+                        apply,
+                    )
+                    .await?
+                }
+            }
+        }
         Ok(())
     }
 
-    pub(super) async fn create_service_accts(&self, agent_type: AgentType) -> Result<()> {
-        // If the service accounts already exist, update them with the new ones using Patch. If not
-        // create new service accounts.
-        let agent_service_account = agent_service_account(agent_type);
-        self.create_or_update(
-            self.namespaced_api(),
-            &agent_service_account,
-            "Agent Service Account",
-        )
-        .await?;
-
-        Ok(())
+    /// Creates an api for the namespace of `object`, or the `TestManager`'s namespace if `object`
+    /// does not have one.
+    fn object_api<T>(&self, object: &T) -> Api<T>
+    where
+        T: KubeResource<Scope = NamespaceResourceScope>,
+        <T as KubeResource>::DynamicType: Default,
+    {
+        match object.meta().namespace.as_deref() {
+            Some(namespace) => Api::namespaced(self.k8s_client.clone(), namespace),
+            None => self.namespaced_api(),
+        }
     }
 
-    pub(super) async fn create_controller_service_acct(&self) -> Result<()> {
-        let controller_service_account = controller_service_account();
-        self.create_or_update(
-            self.namespaced_api(),
-            &controller_service_account,
-            "Controller Service Account",
+    /// Delete the objects created by `install`, returning a description of each object that was
+    /// deleted. Objects that do not exist are skipped.
+    pub(super) async fn uninstall_testsys(&self, delete_namespace: bool) -> Result<Vec<String>> {
+        // The controller may have been installed with a custom service account.
+        let deployment_name = controller_deployment(
+            String::new(),
+            None,
+            self.namespace(),
+            &ControllerDeploymentOptions::default(),
         )
-        .await?;
-
-        Ok(())
-    }
-
-    pub(super) async fn create_deployment(
-        &self,
-        uri: String,
-        secret: Option<String>,
-    ) -> Result<()> {
-        let controller_deployment = controller_deployment(uri, secret);
-
-        // If the controller deployment already exists, update it with the new one using Patch. If
-        // not create a new controller deployment.
-        self.create_or_update(self.namespaced_api(), &controller_deployment, "namespace")
+        .name_any();
+        let service_account = self
+            .namespaced_api::<Deployment>()
+            .get(&deployment_name)
             .await
+            .allow_not_found(|_| ())
+            .context(error::KubeSnafu {
+                action: "get Controller Deployment",
+            })?
+            .and_then(|deployment| deployment.spec)
+            .and_then(|spec| spec.template.spec)
+            .and_then(|spec| spec.service_account_name)
+            .unwrap_or_else(|| TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string());
+        let deleted = vec![
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_deployment(
+                    String::new(),
+                    None,
+                    self.namespace(),
+                    &ControllerDeploymentOptions {
+                        service_account: service_account.clone(),
+                        ..Default::default()
+                    },
+                ),
+                "Controller Deployment",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_service_account(self.namespace(), &service_account),
+                "Controller Service Account",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &agent_service_account(AgentType::Test, self.namespace()),
+                "Agent Service Account",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &agent_service_account(AgentType::Resource, self.namespace()),
+                "Agent Service Account",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_lease(self.namespace()),
+                "Controller Lease",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_leader_election_role_binding(self.namespace(), &service_account),
+                "Controller Leader Election Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_leader_election_role(self.namespace()),
+                "Controller Leader Election Role",
+            )
+            .await?,
+            self.delete_object(
+                self.api(),
+                &controller_cluster_role_binding(self.namespace(), &service_account),
+                "Controller Cluster Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_role_binding(self.namespace(), &service_account),
+                "Controller Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &agent_role_binding(AgentType::Test, self.namespace()),
+                "Agent Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &agent_role_binding(AgentType::Resource, self.namespace()),
+                "Agent Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &controller_role(self.namespace()),
+                "Controller Role",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &agent_role(AgentType::Test, self.namespace()),
+                "Agent Role",
+            )
+            .await?,
+            self.delete_object(
+                self.namespaced_api(),
+                &agent_role(AgentType::Resource, self.namespace()),
+                "Agent Role",
+            )
+            .await?,
+            self.delete_object(
+                self.api(),
+                &agent_cluster_role_binding(AgentType::Test, self.namespace()),
+                "Agent Cluster Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.api(),
+                &agent_cluster_role_binding(AgentType::Resource, self.namespace()),
+                "Agent Cluster Role Binding",
+            )
+            .await?,
+            self.delete_object(
+                self.api(),
+                &controller_cluster_role(),
+                "Controller Cluster Role",
+            )
+            .await?,
+            self.delete_object(
+                self.api(),
+                &agent_cluster_role(AgentType::Test),
+                "Agent Cluster Role",
+            )
+            .await?,
+            self.delete_object(
+                self.api(),
+                &agent_cluster_role(AgentType::Resource),
+                "Agent Cluster Role",
+            )
+            .await?,
+            self.delete_object(self.api(), &Test::crd(), "Test CRD")
+                .await?,
+            self.delete_object(self.api(), &Resource::crd(), "Resource Provider CRD")
+                .await?,
+        ];
+        let mut deleted: Vec<String> = deleted.into_iter().flatten().collect();
+        if delete_namespace {
+            deleted.extend(
+                self.delete_object(
+                    self.api(),
+                    &testsys_namespace(self.namespace()),
+                    "namespace",
+                )
+                .await?,
+            );
+        }
+        Ok(deleted)
     }
 
-    pub(super) async fn uninstall_testsys(&self) -> Result<()> {
-        let namespace_api: Api<Namespace> = self.api();
-        namespace_api
-            .delete(NAMESPACE, &Default::default())
-            .await
-            .context(error::KubeSnafu {
-                action: "delete TestSys namespace",
-            })?;
-        let crd_api: Api<CustomResourceDefinition> = self.api();
-        crd_api
-            .delete(&Test::crd().name_any(), &Default::default())
+    /// Delete `object` if it exists, returning a description of the deleted object.
+    async fn delete_object<T>(&self, api: Api<T>, object: &T, what: &str) -> Result<Option<String>>
+    where
+        T: KubeResource + Clone + DeserializeOwned + Debug,
+        <T as KubeResource>::DynamicType: Default,
+    {
+        let name = object.name_any();
+        Ok(api
+            .delete(&name, &Default::default())
             .await
+            .allow_not_found(|_| ())
             .context(error::KubeSnafu {
-                action: "delete TestSys Test CRD",
-            })?;
-        crd_api
-            .delete(&Resource::crd().name_any(), &Default::default())
-            .await
-            .context(error::KubeSnafu {
-                action: "delete TestSys Resource CRD",
-            })?;
-        Ok(())
+                action: format!("delete {} '{}'", what, name),
+            })?
+            .map(|_| format!("{} '{}'", what, name)))
     }
 
     pub(super) async fn wait_for_namespace_deletion(&self) -> Result<()> {
         let namespace_api: Api<Namespace> = self.api();
         while namespace_api
-            .get(NAMESPACE)
+            .get(self.namespace())
             .await
             .allow_not_found(|_| {})
             .context(error::KubeSnafu {
@@ -169,3 +341,370 @@ impl TestManager {
         Ok(())
     }
 }
+
+/// The objects that `TestManager::install` applies to `namespace`, in the order they are applied.
+pub fn install_objects(
+    controller_config: ImageConfig,
+    options: &InstallOptions,
+    namespace: &str,
+) -> Result<Vec<InstallObject>> {
+    options.resources.validate()?;
+    let (image, secret) = controller_config.into_image_and_secret();
+    let service_account = options.service_account();
+    let mut objects = Vec::new();
+    if !options.skip_namespace_creation {
+        objects.push(InstallObject::Namespace(testsys_namespace(namespace)));
+    }
+    objects.push(InstallObject::CustomResourceDefinition(Box::new(
+        Test::crd(),
+    )));
+    objects.push(InstallObject::CustomResourceDefinition(Box::new(
+        Resource::crd(),
+    )));
+    if options.namespaced_rbac {
+        for agent_type in [AgentType::Test, AgentType::Resource] {
+            objects.push(InstallObject::Role(agent_role(agent_type, namespace)));
+            objects.push(InstallObject::RoleBinding(agent_role_binding(
+                agent_type, namespace,
+            )));
+        }
+        objects.push(InstallObject::Role(controller_role(namespace)));
+        objects.push(InstallObject::RoleBinding(controller_role_binding(
+            namespace,
+            service_account,
+        )));
+    } else {
+        for agent_type in [AgentType::Test, AgentType::Resource] {
+            objects.push(InstallObject::ClusterRole(agent_cluster_role(agent_type)));
+            objects.push(InstallObject::ClusterRoleBinding(
+                agent_cluster_role_binding(agent_type, namespace),
+            ));
+        }
+        objects.push(InstallObject::ClusterRole(controller_cluster_role()));
+        objects.push(InstallObject::ClusterRoleBinding(
+            controller_cluster_role_binding(namespace, service_account),
+        ));
+    }
+    for agent_type in [AgentType::Test, AgentType::Resource] {
+        objects.push(InstallObject::ServiceAccount(agent_service_account(
+            agent_type, namespace,
+        )));
+    }
+    objects.push(InstallObject::ServiceAccount(controller_service_account(
+        namespace,
+        service_account,
+    )));
+    if options.leader_election() {
+        objects.push(InstallObject::Role(controller_leader_election_role(
+            namespace,
+        )));
+        objects.push(InstallObject::RoleBinding(
+            controller_leader_election_role_binding(namespace, service_account),
+        ));
+        objects.push(InstallObject::Lease(controller_lease(namespace)));
+    }
+    objects.push(InstallObject::Deployment(Box::new(controller_deployment(
+        image,
+        secret,
+        namespace,
+        &options.deployment_options(),
+    ))));
+    Ok(objects)
+}
+
+/// Render every object that `TestManager::install` applies to `namespace` as a multi-document YAML
+/// manifest, see [`install_objects`]. The objects are listed in the order they are applied.
+pub fn install_manifest(
+    controller_config: ImageConfig,
+    options: &InstallOptions,
+    namespace: &str,
+) -> Result<String> {
+    let documents = install_objects(controller_config, options, namespace)?
+        .iter()
+        .map(to_yaml_document)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(documents.join("\n"))
+}
+
+/// Read the install manifests at `path`, e.g. the output of `install_manifest`. If `path` is a
+/// directory, every `.yaml` and `.yml` file in it is read in file name order. Every object is
+/// checked before any of them are returned so that nothing is installed from an invalid manifest.
+pub fn read_install_manifest(path: &Path) -> Result<Vec<InstallObject>> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path).context(error::FileSnafu { path })? {
+            let file = entry.context(error::FileSnafu { path })?.path();
+            if matches!(
+                file.extension().and_then(|extension| extension.to_str()),
+                Some("yaml" | "yml")
+            ) {
+                files.push(file);
+            }
+        }
+        files.sort();
+    } else {
+        files.push(path.to_path_buf());
+    }
+    let mut objects = Vec::new();
+    for file in files {
+        let manifest = std::fs::read_to_string(&file).context(error::FileSnafu { path: &file })?;
+        objects.extend(convert_install_manifest(&manifest)?);
+    }
+    Ok(objects)
+}
+
+/// Parse the multi-document YAML `manifest` into the objects it contains. Returns an error if any
+/// document is not one of the kinds of objects that `install` creates.
+pub fn convert_install_manifest(manifest: &str) -> Result<Vec<InstallObject>> {
+    let mut objects = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(manifest) {
+        let value = serde_yaml::Value::deserialize(document).context(error::SerdeYamlSnafu {
+            action: "deserialize install manifest",
+        })?;
+        if value.is_null() {
+            continue;
+        }
+        let kind = value
+            .get("kind")
+            .and_then(|kind| kind.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let action = format!("deserialize {}", kind);
+        let object = match kind.as_str() {
+            "Namespace" => InstallObject::Namespace(from_yaml_value(value, action)?),
+            "CustomResourceDefinition" => {
+                InstallObject::CustomResourceDefinition(from_yaml_value(value, action)?)
+            }
+            "ClusterRole" => InstallObject::ClusterRole(from_yaml_value(value, action)?),
+            "ClusterRoleBinding" => {
+                InstallObject::ClusterRoleBinding(from_yaml_value(value, action)?)
+            }
+            "Role" => InstallObject::Role(from_yaml_value(value, action)?),
+            "RoleBinding" => InstallObject::RoleBinding(from_yaml_value(value, action)?),
+            "ServiceAccount" => InstallObject::ServiceAccount(from_yaml_value(value, action)?),
+            "Lease" => InstallObject::Lease(from_yaml_value(value, action)?),
+            "Deployment" => InstallObject::Deployment(from_yaml_value(value, action)?),
+            _ => return error::UnsupportedInstallKindSnafu { kind }.fail(),
+        };
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+fn from_yaml_value<T: DeserializeOwned>(value: serde_yaml::Value, action: String) -> Result<T> {
+    serde_yaml::from_value(value).context(error::SerdeYamlSnafu { action })
+}
+
+fn to_yaml_document(object: &InstallObject) -> Result<String> {
+    let document = match object {
+        InstallObject::Namespace(o) => serde_yaml::to_string(o),
+        InstallObject::CustomResourceDefinition(o) => serde_yaml::to_string(o),
+        InstallObject::ClusterRole(o) => serde_yaml::to_string(o),
+        InstallObject::ClusterRoleBinding(o) => serde_yaml::to_string(o),
+        InstallObject::Role(o) => serde_yaml::to_string(o),
+        InstallObject::RoleBinding(o) => serde_yaml::to_string(o),
+        InstallObject::ServiceAccount(o) => serde_yaml::to_string(o),
+        InstallObject::Lease(o) => serde_yaml::to_string(o),
+        InstallObject::Deployment(o) => serde_yaml::to_string(o),
+    };
+    let mut document = document.context(error::SerdeYamlSnafu {
+        action: "serialize install manifest",
+    })?;
+    // `serde_yaml` starts each document with `---`, make sure it is also terminated.
+    if !document.ends_with('\n') {
+        document.push('\n');
+    }
+    Ok(document)
+}
+
+#[test]
+fn test_install_manifest() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions::default(),
+        crate::constants::NAMESPACE,
+    )
+    .unwrap();
+    let documents: Vec<&str> = manifest
+        .split("---\n")
+        .filter(|document| !document.trim().is_empty())
+        .collect();
+    // namespace, 2 CRDs, 3 cluster roles, 3 cluster role bindings, 3 service accounts and the
+    // controller deployment.
+    assert_eq!(documents.len(), 13);
+    assert!(documents[0].contains("kind: Namespace"));
+    assert!(documents[12].contains("kind: Deployment"));
+    assert!(documents[12].contains("example.com/controller:v0.1"));
+    assert!(documents[12].contains("memory: 128Mi"));
+    assert!(documents[12].contains("fieldPath: metadata.namespace"));
+}
+
+#[test]
+fn test_install_manifest_namespace() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions::default(),
+        "team-a",
+    )
+    .unwrap();
+    assert!(!manifest.contains(&format!("namespace: {}\n", crate::constants::NAMESPACE)));
+    assert!(manifest.contains("name: team-a\n"));
+    assert!(manifest.contains("name: testsys-controller-role-binding-team-a\n"));
+}
+
+#[test]
+fn test_install_manifest_skip_namespace_creation() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions {
+            skip_namespace_creation: true,
+            ..InstallOptions::default()
+        },
+        "team-a",
+    )
+    .unwrap();
+    let documents: Vec<&str> = manifest
+        .split("---\n")
+        .filter(|document| !document.trim().is_empty())
+        .collect();
+    // Everything except the namespace is still installed into it.
+    assert_eq!(documents.len(), 12);
+    assert!(!manifest.contains("kind: Namespace"));
+    assert!(documents[0].contains("kind: CustomResourceDefinition"));
+    assert!(manifest.contains("namespace: team-a\n"));
+}
+
+#[test]
+fn test_install_manifest_namespaced_rbac() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions {
+            service_account: Some("team-a-controller".to_string()),
+            namespaced_rbac: true,
+            ..Default::default()
+        },
+        "team-a",
+    )
+    .unwrap();
+    let documents: Vec<&str> = manifest
+        .split("---\n")
+        .filter(|document| !document.trim().is_empty())
+        .collect();
+    assert_eq!(documents.len(), 13);
+    assert!(!manifest.contains("kind: ClusterRole"));
+    assert_eq!(manifest.matches("kind: Role\n").count(), 6);
+    assert_eq!(manifest.matches("kind: RoleBinding\n").count(), 3);
+    assert!(documents[12].contains("serviceAccountName: team-a-controller"));
+    assert!(documents[11].contains("kind: ServiceAccount"));
+    assert!(documents[11].contains("name: team-a-controller"));
+}
+
+#[test]
+fn test_install_manifest_max_concurrent_agents() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions {
+            max_concurrent_agents: Some(5),
+            ..Default::default()
+        },
+        crate::constants::NAMESPACE,
+    )
+    .unwrap();
+    assert!(manifest.contains("name: TESTSYS_MAX_CONCURRENT_AGENTS\n"));
+    assert!(manifest.contains("value: \"5\"\n"));
+    let default_manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions::default(),
+        crate::constants::NAMESPACE,
+    )
+    .unwrap();
+    assert!(!default_manifest.contains("TESTSYS_MAX_CONCURRENT_AGENTS"));
+}
+
+#[test]
+fn test_install_manifest_controller_replicas() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions {
+            controller_replicas: Some(2),
+            ..Default::default()
+        },
+        "team-a",
+    )
+    .unwrap();
+    let documents: Vec<&str> = manifest
+        .split("---\n")
+        .filter(|document| !document.trim().is_empty())
+        .collect();
+    // The leader election role, role binding and lease are installed before the deployment.
+    assert_eq!(documents.len(), 16);
+    assert!(documents[12].contains("kind: Role\n"));
+    assert!(documents[12].contains("- coordination.k8s.io\n"));
+    assert!(documents[12].contains("- leases\n"));
+    assert!(documents[13].contains("kind: RoleBinding\n"));
+    assert!(documents[13].contains("name: testsys-controller-service-account\n"));
+    assert!(documents[14].contains("kind: Lease\n"));
+    assert!(documents[14].contains("name: testsys-controller-leader\n"));
+    assert!(documents[14].contains("namespace: team-a\n"));
+    assert!(documents[15].contains("kind: Deployment\n"));
+    assert!(documents[15].contains("replicas: 2\n"));
+    assert!(documents[15].contains("name: TESTSYS_LEADER_ELECTION_LEASE\n"));
+
+    let objects = convert_install_manifest(&manifest).unwrap();
+    assert!(matches!(objects[14], InstallObject::Lease(_)));
+
+    // A single replica does not need a leader.
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions::default(),
+        "team-a",
+    )
+    .unwrap();
+    assert!(manifest.contains("replicas: 1\n"));
+    assert!(!manifest.contains("kind: Lease"));
+    assert!(!manifest.contains("leases"));
+    assert!(!manifest.contains("TESTSYS_LEADER_ELECTION_LEASE"));
+}
+
+#[test]
+fn test_convert_install_manifest() {
+    let manifest = install_manifest(
+        ImageConfig::Image("example.com/controller:v0.1".to_string()),
+        &InstallOptions::default(),
+        crate::constants::NAMESPACE,
+    )
+    .unwrap();
+    let objects = convert_install_manifest(&manifest).unwrap();
+    assert_eq!(objects.len(), 13);
+    assert!(matches!(objects[0], InstallObject::Namespace(_)));
+    assert!(matches!(
+        objects[1],
+        InstallObject::CustomResourceDefinition(_)
+    ));
+    match &objects[12] {
+        InstallObject::Deployment(deployment) => assert_eq!(
+            deployment.metadata.namespace.as_deref(),
+            Some(crate::constants::NAMESPACE)
+        ),
+        object => panic!("Expected a Deployment, got {:?}", object),
+    }
+}
+
+#[test]
+fn test_convert_install_manifest_unsupported_kind() {
+    let error = convert_install_manifest(
+        r#"---
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: testsys
+---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: my-secret
+"#,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("'Secret'"), "{}", error);
+}
@@ -1,22 +1,37 @@
 use super::{
-    error, CrdState, CrdType, DeleteEvent, DockerConfigJson, ImageConfig, ResourceState, Result,
-    SelectionParams, StatusSnapshot,
+    error, install_objects, AgentLogLine, CrdState, CrdType, DeleteEvent, DockerConfigJson,
+    ImageConfig, InstallObject, InstallOptions, LogOptions, ResourceState, Result, SelectionParams,
+    StatusSnapshot,
+};
+use crate::clients::{
+    check_field_selector, set_request_timeout, AllowNotFound, CrdClient, ResourceClient,
+    TestClient, WatchEvent,
 };
-use crate::clients::{AllowNotFound, CrdClient, ResourceClient, TestClient};
 use crate::constants::TESTSYS_RESULTS_FILE;
-use crate::system::AgentType;
+use crate::system::{default_namespace, TESTSYS_CONTROLLER_DEPLOYMENT};
 use crate::{Crd, CrdName, Resource, SecretName, TaskState, Test, TestUserState};
 use bytes::Bytes;
-use futures::{Stream, StreamExt};
-use k8s_openapi::api::core::v1::{Pod, Secret};
-use kube::api::{ListParams, LogParams};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::stream::{self, BoxStream};
+use futures::{future, Stream, StreamExt, TryStreamExt};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{Event, Pod, Secret};
+use kube::api::{ListParams, Patch, PatchParams};
 use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::runtime::{watcher, WatchStreamExt};
 use kube::{Api, Client, Config, Error, ResourceExt};
 use serde::Deserialize;
 use snafu::{OptionExt, ResultExt};
+use std::time::Duration;
 use std::{collections::BTreeMap, path::Path};
 use tokio::io::AsyncWriteExt;
 
+/// The pod template annotation that `kubectl rollout restart` sets to the time of the restart.
+const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+/// How long `wait_for_controller_rollout` waits between checks of the controller deployment.
+const ROLLOUT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 /// # Test Manager
 ///
 /// The test manager provides operations for coordinating the creation, operation, deletion and
@@ -39,39 +54,95 @@ use tokio::io::AsyncWriteExt;
 ///
 pub struct TestManager {
     pub k8s_client: Client,
+    namespace: String,
 }
 
 impl TestManager {
     /// Create a `TestManager` from the path to a kubeconfig file.
     pub async fn new_from_kubeconfig_path(kubeconfig_path: &Path) -> Result<Self> {
-        let kubeconfig = Kubeconfig::read_from(kubeconfig_path).context(error::ConfigReadSnafu)?;
-        let config = Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
-            .await
-            .context(error::ClientCreateKubeconfigSnafu)?;
-        Ok(TestManager {
-            k8s_client: config.try_into().context(error::KubeSnafu {
-                action: "create client from `Kubeconfig`",
-            })?,
-        })
+        Self::new_with_options(Some(kubeconfig_path), None, None).await
+    }
+
+    /// Create a `TestManager` that uses the kubeconfig `context` instead of the current context. The
+    /// kubeconfig is read from `kubeconfig_path`, or from its default location if no path is given.
+    /// Returns an error listing the available contexts if `context` does not exist.
+    pub async fn new_with_context(kubeconfig_path: Option<&Path>, context: &str) -> Result<Self> {
+        Self::new_with_options(kubeconfig_path, Some(context), None).await
     }
 
     /// Create a `TestManager` using the default `kube::Client`.
     pub async fn new() -> Result<Self> {
+        Self::new_with_options(None, None, None).await
+    }
+
+    /// Create a `TestManager` from the kubeconfig at `kubeconfig_path` using `context`, see
+    /// `new_from_kubeconfig_path` and `new_with_context`. If neither is given, the configuration is
+    /// inferred like the default `kube::Client`. If `request_timeout` is given, API calls that are
+    /// not answered within it fail with a timeout error, see
+    /// [`set_request_timeout`](crate::clients::set_request_timeout).
+    pub async fn new_with_options(
+        kubeconfig_path: Option<&Path>,
+        context: Option<&str>,
+        request_timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let mut config = match (kubeconfig_path, context) {
+            (None, None) => {
+                Config::infer()
+                    .await
+                    .map_err(Error::InferConfig)
+                    .context(error::KubeSnafu {
+                        action: "infer the Kubernetes config",
+                    })?
+            }
+            (kubeconfig_path, context) => {
+                let kubeconfig = match kubeconfig_path {
+                    Some(kubeconfig_path) => Kubeconfig::read_from(kubeconfig_path),
+                    None => Kubeconfig::read(),
+                }
+                .context(error::ConfigReadSnafu)?;
+                if let Some(context) = context {
+                    check_context(&kubeconfig, context)?;
+                }
+                let options = KubeConfigOptions {
+                    context: context.map(str::to_string),
+                    ..Default::default()
+                };
+                Config::from_custom_kubeconfig(kubeconfig, &options)
+                    .await
+                    .context(error::ClientCreateKubeconfigSnafu)?
+            }
+        };
+        if let Some(request_timeout) = request_timeout {
+            set_request_timeout(&mut config, request_timeout);
+        }
         Ok(TestManager {
-            k8s_client: Client::try_default().await.context(error::KubeSnafu {
+            k8s_client: config.try_into().context(error::KubeSnafu {
                 action: "create client from `Kubeconfig`",
             })?,
+            namespace: default_namespace(),
         })
     }
 
+    /// Use `namespace` for all TestSys objects instead of the default testsys namespace. This
+    /// allows multiple isolated TestSys installations in one cluster.
+    pub fn with_namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// The namespace that this `TestManager` installs and manages TestSys objects in.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
     /// Create a `TestClient`
     pub fn test_client(&self) -> TestClient {
-        TestClient::new_from_k8s_client(self.k8s_client.clone())
+        TestClient::new_from_k8s_client_with_namespace(self.k8s_client.clone(), &self.namespace)
     }
 
     /// Create a `ResourceClient`
     pub fn resource_client(&self) -> ResourceClient {
-        ResourceClient::new_from_k8s_client(self.k8s_client.clone())
+        ResourceClient::new_from_k8s_client_with_namespace(self.k8s_client.clone(), &self.namespace)
     }
 
     /// Create a secret for image pulls using `DockerConfigJson`
@@ -130,43 +201,113 @@ impl TestManager {
         Ok(secret)
     }
 
-    /// Install testsys to a cluster.
-    pub async fn install(&self, controller_config: ImageConfig) -> Result<()> {
-        self.create_namespace().await?;
-        self.create_crd().await?;
-        self.create_roles(AgentType::Test).await?;
-        self.create_roles(AgentType::Resource).await?;
-        self.create_service_accts(AgentType::Test).await?;
-        self.create_service_accts(AgentType::Resource).await?;
-        self.create_controller_service_acct().await?;
-
-        // Add the controller to the cluster
-        let (image, secret) = match controller_config {
-            ImageConfig::WithCreds { secret, image } => (image, Some(secret)),
-            ImageConfig::Image(image) => (image, None),
-        };
-        self.create_deployment(image, secret).await?;
+    /// Install testsys to a cluster. With `options.namespaced_rbac` the controller and agents are
+    /// given roles that only grant access to the testsys namespace instead of cluster roles.
+    pub async fn install(
+        &self,
+        controller_config: ImageConfig,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        // Fail before anything is added to the cluster.
+        let objects = install_objects(controller_config, options, self.namespace())?;
+        if options.skip_namespace_creation {
+            self.check_namespace_exists().await?;
+        }
+        self.install_objects(&objects, options.apply).await
+    }
 
-        Ok(())
+    /// Install testsys from previously generated manifests instead of generating the objects, see
+    /// [`read_install_manifest`](crate::test_manager::read_install_manifest). The objects are
+    /// applied verbatim in the order they are given. If `apply` is set the objects are sent with
+    /// server-side apply, see [`InstallOptions::apply`].
+    pub async fn install_from_objects(&self, objects: &[InstallObject], apply: bool) -> Result<()> {
+        self.install_objects(objects, apply).await
+    }
+
+    /// Uninstall testsys from a cluster. The controller deployment, service accounts, cluster
+    /// roles, cluster role bindings and CRDs are deleted. The testsys namespace is only deleted if
+    /// `delete_namespace` is set. Unless `force` is set, nothing is deleted while `Test` or
+    /// `Resource` objects exist, because deleting the CRDs would orphan their finalizers. Returns a
+    /// description of each object that was deleted.
+    pub async fn uninstall(&self, delete_namespace: bool, force: bool) -> Result<Vec<String>> {
+        if !force {
+            if !self
+                .test_client()
+                .get_all()
+                .await
+                .allow_not_found(|_| ())
+                .context(error::ClientSnafu {
+                    action: "get all tests",
+                })?
+                .unwrap_or_default()
+                .is_empty()
+            {
+                return Err(error::Error::TestExisting);
+            }
+            if !self
+                .resource_client()
+                .get_all()
+                .await
+                .allow_not_found(|_| ())
+                .context(error::ClientSnafu {
+                    action: "get all resources",
+                })?
+                .unwrap_or_default()
+                .is_empty()
+            {
+                return Err(error::Error::ResourceExisting);
+            }
+        }
+        let deleted = self.uninstall_testsys(delete_namespace).await?;
+        if delete_namespace {
+            self.wait_for_namespace_deletion().await?;
+        }
+        Ok(deleted)
     }
 
-    /// Uninstall testsys from a cluster.
-    pub async fn uninstall(&self) -> Result<()> {
-        if !self
-            .resource_client()
-            .get_all()
+    /// Restart the controller like `kubectl rollout restart` does, by setting the
+    /// `kubectl.kubernetes.io/restartedAt` annotation of the controller deployment's pod template
+    /// to the current time. Returns an error if testsys is not installed. The new controller pod
+    /// is started in the background, use `wait_for_controller_rollout` to wait for it.
+    pub async fn restart_controller(&self) -> Result<()> {
+        let deployment_api: Api<Deployment> = self.namespaced_api();
+        deployment_api
+            .patch(
+                TESTSYS_CONTROLLER_DEPLOYMENT,
+                &PatchParams::default(),
+                &Patch::Strategic(controller_restart_patch(Utc::now())),
+            )
             .await
             .allow_not_found(|_| ())
-            .context(error::ClientSnafu {
-                action: "get all resources",
+            .context(error::KubeSnafu {
+                action: "restart the controller deployment",
             })?
-            .unwrap_or_default()
-            .is_empty()
-        {
-            return Err(error::Error::ResourceExisting);
+            .context(error::NotInstalledSnafu {
+                namespace: self.namespace(),
+            })?;
+        Ok(())
+    }
+
+    /// Wait until every pod of the controller deployment is from its latest revision and ready,
+    /// e.g. after `restart_controller`.
+    pub async fn wait_for_controller_rollout(&self) -> Result<()> {
+        let deployment_api: Api<Deployment> = self.namespaced_api();
+        loop {
+            let deployment = deployment_api
+                .get(TESTSYS_CONTROLLER_DEPLOYMENT)
+                .await
+                .allow_not_found(|_| ())
+                .context(error::KubeSnafu {
+                    action: "get the controller deployment",
+                })?
+                .context(error::NotInstalledSnafu {
+                    namespace: self.namespace(),
+                })?;
+            if is_rollout_complete(&deployment) {
+                return Ok(());
+            }
+            tokio::time::sleep(ROLLOUT_POLL_INTERVAL).await;
         }
-        self.uninstall_testsys().await?;
-        self.wait_for_namespace_deletion().await
     }
 
     /// Restart a crd object by deleting the crd from the cluster and adding a copy of it with its
@@ -201,12 +342,13 @@ impl TestManager {
 
     /// List all testsys objects following `SelectionParams`
     pub async fn list(&self, selection_params: &SelectionParams) -> Result<Vec<Crd>> {
+        let (labels, fields) = selectors(selection_params)?;
         let mut list_params = ListParams::default();
-        if let Some(labels) = &selection_params.labels {
+        if let Some(labels) = &labels {
             list_params = list_params.labels(labels)
         }
-        if let Some(name) = &selection_params.name {
-            list_params = list_params.fields(&format!("metadata.name=={}", name));
+        if let Some(fields) = &fields {
+            list_params = list_params.fields(fields);
         }
         let mut objects = Vec::new();
         if matches!(selection_params.crd_type, Some(CrdType::Test) | None) {
@@ -241,6 +383,41 @@ impl TestManager {
         Ok(objects)
     }
 
+    /// Watch the testsys objects selected by the type, labels, name and field selector of
+    /// `SelectionParams`. Objects move in and out of a state as they change, so the `state` is not
+    /// used, see [`SelectionParams::matches_state`].
+    pub fn watch(
+        &self,
+        selection_params: &SelectionParams,
+    ) -> Result<BoxStream<'static, crate::clients::Result<WatchEvent<Crd>>>> {
+        let (labels, fields) = selectors(selection_params)?;
+        let mut config = watcher::Config::default();
+        if let Some(labels) = &labels {
+            config = config.labels(labels);
+        }
+        if let Some(fields) = &fields {
+            config = config.fields(fields);
+        }
+        let mut streams = Vec::new();
+        if matches!(selection_params.crd_type, Some(CrdType::Test) | None) {
+            streams.push(
+                self.test_client()
+                    .watch_selected(config.clone())
+                    .map_ok(|event| event.map(Crd::Test))
+                    .boxed(),
+            );
+        }
+        if matches!(selection_params.crd_type, Some(CrdType::Resource) | None) {
+            streams.push(
+                self.resource_client()
+                    .watch_selected(config)
+                    .map_ok(|event| event.map(Crd::Resource))
+                    .boxed(),
+            );
+        }
+        Ok(stream::select_all(streams).boxed())
+    }
+
     /// Delete all testsys `Test`s and `Resource`s from a cluster.
     pub async fn delete_all(&self) -> Result<impl Stream<Item = Result<DeleteEvent>>> {
         let deletion_order = self.all_objects_deletion_order().await?;
@@ -261,6 +438,20 @@ impl TestManager {
         Ok(self.delete_sorted_resources(Self::vec_to_deletion_order(objects)))
     }
 
+    /// Delete `tests` from a testsys cluster. If `include_resources` the `Resource`s that each test
+    /// depends on will be deleted after the test.
+    pub async fn delete_tests(
+        &self,
+        tests: Vec<Test>,
+        include_resources: bool,
+    ) -> Result<impl Stream<Item = Result<DeleteEvent>>> {
+        let mut objects = tests.into_iter().map(Crd::Test).collect();
+        if include_resources {
+            objects = self.add_dependencies_to_vec(objects).await?;
+        }
+        Ok(self.delete_sorted_resources(Self::vec_to_deletion_order(objects)))
+    }
+
     /// Delete the resource after a failed deletion attempt.
     /// Warning: the physical resources may not be deleted.
     /// The finalizers will be removed from the resource and the resource will be deleted.
@@ -302,21 +493,38 @@ impl TestManager {
         Ok(StatusSnapshot::new(crds))
     }
 
+    /// Get the name of the node that each test agent pod was scheduled on, keyed by test name.
+    /// Tests whose pods have not been scheduled are not included.
+    pub async fn test_node_names(&self) -> Result<BTreeMap<String, String>> {
+        let pod_api: Api<Pod> = self.namespaced_api();
+        Ok(pod_api
+            .list(&ListParams {
+                label_selector: Some("job-name".to_string()),
+                ..Default::default()
+            })
+            .await
+            .context(error::KubeSnafu { action: "get pods" })?
+            .items
+            .into_iter()
+            .filter_map(|pod| {
+                let job_name = pod.labels().get("job-name")?.to_string();
+                let node_name = pod.spec?.node_name?;
+                Some((job_name, node_name))
+            })
+            .collect())
+    }
+
     /// Retrieve the logs of a test.
     pub async fn test_logs<S>(
         &self,
         test_name: S,
-        follow: bool,
+        options: &LogOptions,
     ) -> Result<impl Stream<Item = Result<Bytes>>>
     where
         S: Into<String>,
     {
         let pod = self.test_pod(test_name).await?;
-        let log_params = LogParams {
-            follow,
-            pretty: true,
-            ..Default::default()
-        };
+        let log_params = options.log_params();
         let pod_api: Api<Pod> = self.namespaced_api();
         pod_api
             .log_stream(&pod.name_any(), &log_params)
@@ -338,17 +546,13 @@ impl TestManager {
         &self,
         resource_name: S,
         state: ResourceState,
-        follow: bool,
+        options: &LogOptions,
     ) -> Result<impl Stream<Item = Result<Bytes>>>
     where
         S: Into<String>,
     {
         let pod = self.resource_pod(resource_name, state).await?;
-        let log_params = LogParams {
-            follow,
-            pretty: true,
-            ..Default::default()
-        };
+        let log_params = options.log_params();
         let pod_api: Api<Pod> = self.namespaced_api();
         pod_api
             .log_stream(&pod.name_any(), &log_params)
@@ -365,18 +569,27 @@ impl TestManager {
             })
     }
 
+    /// Retrieve the logs of the test agent of the TestSys `Test` `test_name` together with the logs
+    /// of the resource agents of every resource it depends on. Without `options.follow`, the lines
+    /// of all of the pods are sorted by the time they were logged. With `options.follow`, the pods
+    /// are followed concurrently and lines are yielded as they are logged, including from pods that
+    /// start later. Pods that have not started yet are skipped.
+    pub async fn all_agent_logs(
+        &self,
+        test_name: &str,
+        options: &LogOptions,
+    ) -> Result<impl Stream<Item = Result<AgentLogLine>>> {
+        self.agent_logs(test_name, options).await
+    }
+
     /// Retrieve the logs of the controller.
     pub async fn controller_logs(
         &self,
-        follow: bool,
+        options: &LogOptions,
     ) -> Result<impl Stream<Item = core::result::Result<Bytes, Error>>> {
         let pod_api: Api<Pod> = self.namespaced_api();
         let pod = self.controller_pod().await?;
-        let log_params = LogParams {
-            follow,
-            pretty: true,
-            ..Default::default()
-        };
+        let log_params = options.log_params();
         pod_api
             .log_stream(&pod.name_any(), &log_params)
             .await
@@ -429,6 +642,146 @@ impl TestManager {
         })?;
         Ok(())
     }
+
+    /// Get the Kubernetes events for a testsys `Test`, its job and pods, and the `Resource`s it
+    /// depends on, oldest first.
+    pub async fn test_events(&self, test_name: &str) -> Result<Vec<Event>> {
+        let test = self
+            .test_client()
+            .get(test_name)
+            .await
+            .context(error::ClientSnafu {
+                action: format!("get test '{}'", test_name),
+            })?;
+        let pods = |pods: Vec<Pod>| {
+            pods.into_iter()
+                .map(|pod| involved_object_selector("Pod", &pod.name_any(), pod.uid().as_deref()))
+        };
+        let mut selectors = vec![involved_object_selector(
+            "Test",
+            test_name,
+            test.uid().as_deref(),
+        )];
+        selectors.extend(pods(
+            self.get_pods(&CrdName::Test(test_name.to_string())).await?,
+        ));
+        for resource_name in &test.spec.resources {
+            let resource = self
+                .resource_client()
+                .get(resource_name)
+                .await
+                .allow_not_found(|_| ())
+                .context(error::ClientSnafu {
+                    action: format!("get resource '{}'", resource_name),
+                })?;
+            selectors.push(involved_object_selector(
+                "Resource",
+                resource_name,
+                resource.and_then(|resource| resource.uid()).as_deref(),
+            ));
+            selectors.extend(pods(
+                self.get_pods(&CrdName::Resource(resource_name.clone()))
+                    .await?,
+            ));
+        }
+
+        let event_api: Api<Event> = self.namespaced_api();
+        let mut events = Vec::new();
+        for selector in selectors {
+            events.extend(
+                event_api
+                    .list(&ListParams::default().fields(&selector))
+                    .await
+                    .context(error::KubeSnafu {
+                        action: format!("get events for '{}'", selector),
+                    })?
+                    .items,
+            );
+        }
+        events.sort_by_key(event_time);
+        Ok(events)
+    }
+
+    /// Get the Kubernetes events in the testsys namespace for `Test`s, `Resource`s and pods, oldest
+    /// first. If `field_selector` is given, e.g. `involvedObject.name=my-test`, only the events
+    /// matching it are returned.
+    pub async fn events(&self, field_selector: Option<&str>) -> Result<Vec<Event>> {
+        let mut list_params = ListParams::default();
+        if let Some(field_selector) = field_selector {
+            list_params = list_params.fields(field_selector);
+        }
+        let event_api: Api<Event> = self.namespaced_api();
+        let mut events: Vec<Event> = event_api
+            .list(&list_params)
+            .await
+            .context(error::KubeSnafu {
+                action: "get events",
+            })?
+            .items
+            .into_iter()
+            .filter(is_testsys_event)
+            .collect();
+        events.sort_by_key(event_time);
+        Ok(events)
+    }
+
+    /// Stream the Kubernetes events in the testsys namespace for `Test`s, `Resource`s and pods,
+    /// starting with the existing events. An event is yielded again each time it is updated, e.g.
+    /// when its count increases. Watch errors are yielded and the watch is retried with a backoff.
+    pub fn watch_events(
+        &self,
+        field_selector: Option<&str>,
+    ) -> impl Stream<Item = Result<Event>> + Send {
+        let mut config = watcher::Config::default();
+        if let Some(field_selector) = field_selector {
+            config = config.fields(field_selector);
+        }
+        let event_api: Api<Event> = self.namespaced_api();
+        watcher(event_api, config)
+            .backoff(watcher::default_backoff())
+            .applied_objects()
+            .map(|event| {
+                event.map_err(Box::new).context(error::WatchSnafu {
+                    action: "watch events",
+                })
+            })
+            .try_filter(|event| future::ready(is_testsys_event(event)))
+            // The watcher lists every event again when it restarts, skip the ones already seen.
+            .scan(BTreeMap::new(), |seen, event| {
+                let item = match event {
+                    Ok(event) => {
+                        let version = event.resource_version();
+                        if seen.get(&event.uid()) == Some(&version) {
+                            None
+                        } else {
+                            seen.insert(event.uid(), version);
+                            Some(Ok(event))
+                        }
+                    }
+                    Err(e) => Some(Err(e)),
+                };
+                future::ready(Some(item))
+            })
+            .filter_map(future::ready)
+    }
+}
+
+/// Returns `true` if `event` is about a `Test`, `Resource` or pod.
+fn is_testsys_event(event: &Event) -> bool {
+    matches!(
+        event.involved_object.kind.as_deref(),
+        Some("Test" | "Resource" | "Pod")
+    )
+}
+
+/// The most recent time that `event` occurred, if it is known.
+pub fn event_time(event: &Event) -> Option<DateTime<Utc>> {
+    event
+        .last_timestamp
+        .as_ref()
+        .map(|time| time.0)
+        .or_else(|| event.event_time.as_ref().map(|time| time.0))
+        .or_else(|| event.first_timestamp.as_ref().map(|time| time.0))
 }
 
 /// Takes a path to a yaml manifest of testsys crds (`Test` and `Resource`) and creates a set of
@@ -455,6 +808,33 @@ pub fn convert_manifest(manifest_string: String) -> Result<Vec<Crd>> {
     Ok(crds)
 }
 
+/// The label and field selectors for the objects selected by `selection_params`.
+fn selectors(selection_params: &SelectionParams) -> Result<(Option<String>, Option<String>)> {
+    let mut fields = Vec::new();
+    if let Some(name) = &selection_params.name {
+        fields.push(format!("metadata.name=={}", name));
+    }
+    if let Some(field_selector) = &selection_params.field_selector {
+        check_field_selector(field_selector).context(error::ClientSnafu {
+            action: "check field selector",
+        })?;
+        fields.push(field_selector.clone());
+    }
+    let fields = (!fields.is_empty()).then(|| fields.join(","));
+    Ok((selection_params.labels.clone(), fields))
+}
+
+impl SelectionParams {
+    /// Returns `true` if `crd` is in the `state` of these `SelectionParams`, or if there is no
+    /// `state`.
+    pub fn matches_state(&self, crd: &Crd) -> bool {
+        match crd {
+            Crd::Test(test) => filter_test_by_state(test, &self.state),
+            Crd::Resource(resource) => filter_resource_by_state(resource, &self.state),
+        }
+    }
+}
+
 fn filter_test_by_state(test: &Test, state: &Option<CrdState>) -> bool {
     if let Some(state) = state {
         match state {
@@ -468,6 +848,8 @@ fn filter_test_by_state(test: &Test, state: &Option<CrdState>) -> bool {
                     | TestUserState::Failed
                     | TestUserState::Error
                     | TestUserState::ResourceError
+                    | TestUserState::Cancelled
+                    | TestUserState::Skipped
             ),
             CrdState::Passed => {
                 matches!(test.test_user_state(), TestUserState::Passed)
@@ -516,3 +898,170 @@ fn filter_resource_by_state(resource: &Resource, state: &Option<CrdState>) -> bo
         true
     }
 }
+
+/// Returns an error listing the available contexts if `kubeconfig` has no context named `context`.
+fn check_context(kubeconfig: &Kubeconfig, context: &str) -> Result<()> {
+    if kubeconfig
+        .contexts
+        .iter()
+        .any(|named_context| named_context.name == context)
+    {
+        return Ok(());
+    }
+    error::UnknownContextSnafu {
+        context,
+        available: kubeconfig
+            .contexts
+            .iter()
+            .map(|named_context| named_context.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+    .fail()
+}
+
+/// The strategic merge patch that `kubectl rollout restart` sends for a deployment restarted at
+/// `restarted_at`. Changing the pod template makes the deployment replace its pods.
+fn controller_restart_patch(restarted_at: DateTime<Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        RESTARTED_AT_ANNOTATION: restarted_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Returns `true` if the controller has seen the latest change to `deployment` and all of its pods
+/// are from the latest revision and available, using the same checks as `kubectl rollout status`.
+fn is_rollout_complete(deployment: &Deployment) -> bool {
+    let (spec, status) = match (&deployment.spec, &deployment.status) {
+        (Some(spec), Some(status)) => (spec, status),
+        _ => return false,
+    };
+    let replicas = spec.replicas.unwrap_or(1);
+    let updated = status.updated_replicas.unwrap_or_default();
+    status.observed_generation >= deployment.metadata.generation
+        && updated >= replicas
+        && status.replicas.unwrap_or_default() <= updated
+        && status.available_replicas.unwrap_or_default() >= updated
+}
+
+/// A field selector for the events of the object of `kind` named `name`. The `uid` is included when
+/// it is known so that the events of a deleted object with the same name are left out.
+fn involved_object_selector(kind: &str, name: &str, uid: Option<&str>) -> String {
+    let mut selector = format!("involvedObject.kind={},involvedObject.name={}", kind, name);
+    if let Some(uid) = uid {
+        selector.push_str(&format!(",involvedObject.uid={}", uid));
+    }
+    selector
+}
+
+#[test]
+fn test_involved_object_selector() {
+    assert_eq!(
+        involved_object_selector("Test", "my-test", Some("1234")),
+        "involvedObject.kind=Test,involvedObject.name=my-test,involvedObject.uid=1234"
+    );
+    assert_eq!(
+        involved_object_selector("Resource", "my-cluster", None),
+        "involvedObject.kind=Resource,involvedObject.name=my-cluster"
+    );
+}
+
+#[test]
+fn test_controller_restart_patch() {
+    use chrono::TimeZone;
+    let patch = controller_restart_patch(Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap());
+    assert_eq!(
+        patch,
+        serde_json::json!({ "spec": { "template": { "metadata": { "annotations": {
+            "kubectl.kubernetes.io/restartedAt": "2023-01-01T12:00:00Z"
+        }}}}})
+    );
+    // The patch applies to the deployment that `install` creates.
+    let deployment: Deployment = serde_json::from_value(patch).unwrap();
+    assert!(deployment
+        .spec
+        .unwrap()
+        .template
+        .metadata
+        .unwrap()
+        .annotations
+        .is_some());
+}
+
+#[test]
+fn test_is_rollout_complete() {
+    use k8s_openapi::api::apps::v1::{DeploymentSpec, DeploymentStatus};
+    let deployment = |generation, status: DeploymentStatus| Deployment {
+        metadata: kube::core::ObjectMeta {
+            generation: Some(generation),
+            ..Default::default()
+        },
+        spec: Some(DeploymentSpec {
+            replicas: Some(1),
+            ..Default::default()
+        }),
+        status: Some(status),
+    };
+    let rolled_out = DeploymentStatus {
+        observed_generation: Some(2),
+        replicas: Some(1),
+        updated_replicas: Some(1),
+        available_replicas: Some(1),
+        ..Default::default()
+    };
+    assert!(is_rollout_complete(&deployment(2, rolled_out.clone())));
+    // The deployment controller has not seen the restart yet.
+    assert!(!is_rollout_complete(&deployment(3, rolled_out.clone())));
+    // The old pod is still running next to the new one.
+    assert!(!is_rollout_complete(&deployment(
+        2,
+        DeploymentStatus {
+            replicas: Some(2),
+            ..rolled_out.clone()
+        }
+    )));
+    // The new pod is not ready yet.
+    assert!(!is_rollout_complete(&deployment(
+        2,
+        DeploymentStatus {
+            available_replicas: Some(0),
+            ..rolled_out
+        }
+    )));
+    assert!(!is_rollout_complete(&Deployment::default()));
+}
+
+#[test]
+fn test_check_context() {
+    let kubeconfig = Kubeconfig::from_yaml(
+        r#"
+apiVersion: v1
+kind: Config
+clusters: []
+users: []
+contexts:
+  - name: kind-testsys
+    context:
+      cluster: kind-testsys
+      user: kind-testsys
+  - name: prod
+    context:
+      cluster: prod
+      user: prod
+"#,
+    )
+    .unwrap();
+    assert!(check_context(&kubeconfig, "prod").is_ok());
+    let error = check_context(&kubeconfig, "staging")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("'staging'"));
+    assert!(error.contains("kind-testsys, prod"));
+}
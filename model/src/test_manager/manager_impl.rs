@@ -1,6 +1,6 @@
 use super::{error, ResourceState, Result, TestManager};
 use crate::clients::{AllowNotFound, CrdClient};
-use crate::constants::{LABEL_COMPONENT, NAMESPACE};
+use crate::constants::LABEL_COMPONENT;
 use crate::{Crd, CrdName, Resource, Test};
 use k8s_openapi::api::core::v1::Pod;
 use k8s_openapi::NamespaceResourceScope;
@@ -15,6 +15,46 @@ impl TestManager {
     const MAX_RETRIES: i32 = 3;
     /// Timeout for object creation/update retries.
     const BACKOFF_MS: u64 = 500;
+    /// The field manager that owns the fields of objects installed with server-side apply.
+    pub(super) const FIELD_MANAGER: &'static str = "testsys";
+
+    /// Add a k8s object to the cluster as part of the testsys installation. If `apply` is set the
+    /// object is sent with server-side apply, otherwise it is created or merge patched.
+    pub(super) async fn install_object<T>(
+        &self,
+        api: Api<T>,
+        data: &T,
+        what: &str,
+        apply: bool,
+    ) -> Result<()>
+    where
+        T: KubeResource + Clone + DeserializeOwned + Serialize + Debug,
+        <T as KubeResource>::DynamicType: Default,
+    {
+        if apply {
+            self.server_side_apply(api, data, what).await
+        } else {
+            self.create_or_update(api, data, what).await
+        }
+    }
+
+    /// Create or update a k8s object with server-side apply. Conflicts with other field managers,
+    /// e.g. `kubectl`, are forced so that re-applying the same object always succeeds.
+    pub(super) async fn server_side_apply<T>(&self, api: Api<T>, data: &T, what: &str) -> Result<()>
+    where
+        T: KubeResource + Clone + DeserializeOwned + Serialize + Debug,
+        <T as KubeResource>::DynamicType: Default,
+    {
+        api.patch(
+            &data.name_any(),
+            &PatchParams::apply(Self::FIELD_MANAGER).force(),
+            &Patch::Apply(data),
+        )
+        .await
+        .context(error::CreateSnafu { what })?;
+
+        Ok(())
+    }
 
     /// Create or update an existing k8s object
     pub(super) async fn create_or_update<T>(&self, api: Api<T>, data: &T, what: &str) -> Result<()>
@@ -80,7 +120,7 @@ impl TestManager {
         T: KubeResource<Scope = NamespaceResourceScope>,
         <T as KubeResource>::DynamicType: Default,
     {
-        Api::<T>::namespaced(self.k8s_client.clone(), NAMESPACE)
+        Api::<T>::namespaced(self.k8s_client.clone(), self.namespace())
     }
 
     /// Returns a list containing all dependencies for each object in a `Vec<Crd>` including the
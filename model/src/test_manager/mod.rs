@@ -1,11 +1,29 @@
+use crate::system::{
+    ControllerDeploymentOptions, ControllerLogging, TESTSYS_CONTROLLER_SERVICE_ACCOUNT,
+};
+pub use agent_logs::AgentLogLine;
+pub use agent_usage::AgentUsage;
 pub use delete::DeleteEvent;
 pub use error::{Error, Result};
-pub use manager::{read_manifest, TestManager};
+pub use install::{
+    convert_install_manifest, install_manifest, install_objects, read_install_manifest,
+    InstallObject,
+};
+use k8s_openapi::api::core::v1::ResourceRequirements;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::LogParams;
+pub use manager::{convert_manifest, event_time, read_manifest, TestManager};
+use maplit::btreemap;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_plain::derive_fromstr_from_deserialize;
-pub use status::{StatusColumn, StatusSnapshot};
+use snafu::ensure;
+pub use status::{GroupTotals, GroupedStatus, StatusColumn, StatusSnapshot, UNGROUPED};
 use std::collections::HashMap;
+use std::time::Duration;
 
+mod agent_logs;
+mod agent_usage;
 mod delete;
 mod error;
 mod install;
@@ -23,6 +41,9 @@ pub struct SelectionParams {
     pub labels: Option<String>,
     /// Filter based on the name of the CRD
     pub name: Option<String>,
+    /// Filter based on a field selector, e.g. `metadata.name=my-test`. Only `metadata.name` and
+    /// `metadata.namespace` can be used to select TestSys objects.
+    pub field_selector: Option<String>,
     /// Filter based on the state of the CRD
     pub state: Option<CrdState>,
 }
@@ -77,6 +98,144 @@ pub enum ImageConfig {
     Image(String),
 }
 
+/// The CPU and memory requests and limits of the controller container. Each value is a Kubernetes
+/// quantity string, e.g. `100m` or `128Mi`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerResources {
+    pub cpu_request: String,
+    pub memory_request: String,
+    pub cpu_limit: String,
+    pub memory_limit: String,
+}
+
+impl Default for ControllerResources {
+    fn default() -> Self {
+        Self {
+            cpu_request: "100m".to_string(),
+            memory_request: "128Mi".to_string(),
+            cpu_limit: "500m".to_string(),
+            memory_limit: "512Mi".to_string(),
+        }
+    }
+}
+
+/// The options used by `TestManager::install` and `install_manifest`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstallOptions {
+    /// The resources of the controller container.
+    pub resources: ControllerResources,
+    /// The name of the controller's service account. The default is
+    /// `testsys-controller-service-account`.
+    pub service_account: Option<String>,
+    /// Create roles and role bindings that only grant access to the testsys namespace instead of
+    /// cluster roles and cluster role bindings.
+    pub namespaced_rbac: bool,
+    /// Install into the existing testsys namespace instead of creating it, e.g. when the namespace
+    /// is created by a cluster admin. Installing fails if the namespace does not exist.
+    pub skip_namespace_creation: bool,
+    /// The maximum number of test agents the controller runs at once. The default is no limit.
+    pub max_concurrent_agents: Option<usize>,
+    /// The log level and format of the controller.
+    pub logging: ControllerLogging,
+    /// Install the objects with server-side apply using the `testsys` field manager instead of
+    /// creating or merge patching them, so that re-installing to upgrade is idempotent.
+    pub apply: bool,
+    /// The number of controller replicas. The default is 1. With more than one, the replicas elect
+    /// a leader with a `Lease`, which is installed along with the role that allows the controller
+    /// to use it.
+    pub controller_replicas: Option<u32>,
+}
+
+impl InstallOptions {
+    /// The name of the controller's service account.
+    pub fn service_account(&self) -> &str {
+        self.service_account
+            .as_deref()
+            .unwrap_or(TESTSYS_CONTROLLER_SERVICE_ACCOUNT)
+    }
+
+    /// The number of controller replicas.
+    pub fn controller_replicas(&self) -> i32 {
+        self.controller_replicas
+            .map_or(1, |replicas| i32::try_from(replicas).unwrap_or(i32::MAX))
+    }
+
+    /// Returns `true` if the controller replicas elect a leader.
+    pub fn leader_election(&self) -> bool {
+        self.controller_replicas() > 1
+    }
+
+    /// The settings of the controller deployment.
+    pub(crate) fn deployment_options(&self) -> ControllerDeploymentOptions {
+        ControllerDeploymentOptions {
+            resources: Some(self.resources.requirements()),
+            service_account: self.service_account().to_string(),
+            max_concurrent_agents: self.max_concurrent_agents,
+            logging: self.logging.clone(),
+            replicas: self.controller_replicas(),
+        }
+    }
+}
+
+impl ControllerResources {
+    /// Make sure each value is a valid Kubernetes quantity.
+    pub fn validate(&self) -> Result<()> {
+        for quantity in [
+            &self.cpu_request,
+            &self.memory_request,
+            &self.cpu_limit,
+            &self.memory_limit,
+        ] {
+            validate_quantity(quantity)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn requirements(&self) -> ResourceRequirements {
+        ResourceRequirements {
+            requests: Some(btreemap! {
+                "cpu".to_string() => Quantity(self.cpu_request.clone()),
+                "memory".to_string() => Quantity(self.memory_request.clone()),
+            }),
+            limits: Some(btreemap! {
+                "cpu".to_string() => Quantity(self.cpu_limit.clone()),
+                "memory".to_string() => Quantity(self.memory_limit.clone()),
+            }),
+        }
+    }
+}
+
+const QUANTITY_PATTERN_REGEX: &str =
+    r"^[+-]?(\d+(\.\d*)?|\.\d+)([KMGTPE]i|[numkMGTPE]|[eE][+-]?\d+)?$";
+
+lazy_static::lazy_static! {
+
+    static ref QUANTITY_REGEX: Regex = {
+        #[allow(clippy::unwrap_used)]
+        Regex::new(QUANTITY_PATTERN_REGEX).unwrap()
+    };
+}
+
+/// Returns an error if `quantity` is not a valid Kubernetes quantity, e.g. `250m`, `1.5`, `64Mi`
+/// or `1e3`.
+pub fn validate_quantity(quantity: &str) -> Result<()> {
+    ensure!(
+        QUANTITY_REGEX.is_match(quantity),
+        error::InvalidQuantitySnafu { quantity }
+    );
+    Ok(())
+}
+
+impl ImageConfig {
+    /// Split the config into the image uri and the name of its pull secret.
+    pub(crate) fn into_image_and_secret(self) -> (String, Option<String>) {
+        match self {
+            ImageConfig::WithCreds { image, secret } => (image, Some(secret)),
+            ImageConfig::Image(image) => (image, None),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ResourceState {
@@ -85,3 +244,65 @@ pub enum ResourceState {
 }
 
 derive_fromstr_from_deserialize!(ResourceState);
+
+#[derive(Default, Debug, Clone)]
+/// `LogOptions` control which lines are returned when retrieving the logs of a testsys pod.
+pub struct LogOptions {
+    /// Keep streaming new lines as they are written.
+    pub follow: bool,
+    /// Only return lines written within this duration.
+    pub since: Option<Duration>,
+    /// Only return this many of the most recent lines. If `follow` is set, streaming starts after
+    /// these lines.
+    pub tail: Option<i64>,
+}
+
+impl LogOptions {
+    pub(crate) fn log_params(&self) -> LogParams {
+        LogParams {
+            follow: self.follow,
+            pretty: true,
+            // Kubernetes rejects a `sinceSeconds` of 0.
+            since_seconds: self
+                .since
+                .map(|since| since.as_secs().max(1).try_into().unwrap_or(i64::MAX)),
+            tail_lines: self.tail,
+            ..Default::default()
+        }
+    }
+}
+
+#[test]
+fn test_log_params() {
+    let params = LogOptions::default().log_params();
+    assert!(!params.follow);
+    assert_eq!(params.since_seconds, None);
+    assert_eq!(params.tail_lines, None);
+
+    let params = LogOptions {
+        follow: true,
+        since: Some(Duration::from_secs(600)),
+        tail: Some(20),
+    }
+    .log_params();
+    assert!(params.follow);
+    assert_eq!(params.since_seconds, Some(600));
+    assert_eq!(params.tail_lines, Some(20));
+
+    let params = LogOptions {
+        since: Some(Duration::from_millis(10)),
+        ..Default::default()
+    }
+    .log_params();
+    assert_eq!(params.since_seconds, Some(1));
+}
+
+#[test]
+fn test_validate_quantity() {
+    for quantity in ["100m", "1", "1.5", ".5", "128Mi", "2G", "1e3"] {
+        assert!(validate_quantity(quantity).is_ok(), "{}", quantity);
+    }
+    for quantity in ["", "abc", "100mi", "1.2.3", "Mi"] {
+        assert!(validate_quantity(quantity).is_err(), "{}", quantity);
+    }
+}
@@ -1,20 +1,25 @@
-use crate::{Crd, TaskState};
+use crate::{Crd, TaskState, TestUserState};
 use kube::{core::object::HasStatus, ResourceExt};
 use serde::Serialize;
 use std::cmp::max;
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::sync::Arc;
 use tabled::builder::Builder;
 use tabled::locator::ByColumnName;
 use tabled::object::Rows;
 use tabled::width::MinWidth;
 use tabled::{Alignment, Disable, Modify, Style, Table, Width};
 
+/// Computes the values of a status column for a CRD.
+type ColumnValues = Arc<dyn Fn(&Crd) -> Vec<String> + Send + Sync>;
+
 #[derive(Clone)]
 pub struct StatusColumn {
     header: String,
     //  If the Vec contains more than 1 value, each value will occupy a single box stacked
     //  vertically. If no value should be printed an empty Vec should be returned.
-    values: fn(&Crd) -> Vec<String>,
+    values: ColumnValues,
     alignment: TextAlignment,
     width: Option<usize>,
 }
@@ -32,7 +37,7 @@ impl std::fmt::Debug for StatusColumn {
 impl Default for StatusColumn {
     fn default() -> Self {
         Self {
-            values: |_| Default::default(),
+            values: Arc::new(|_| Default::default()),
             header: Default::default(),
             alignment: Default::default(),
             width: Default::default(),
@@ -73,7 +78,9 @@ pub struct StatusSnapshot {
 }
 
 impl StatusSnapshot {
-    pub(super) fn new(crds: Vec<Crd>) -> Self {
+    /// A snapshot of the status of `crds`, e.g. objects that were kept up to date by
+    /// [`TestManager::watch`](super::TestManager::watch).
+    pub fn new(crds: Vec<Crd>) -> Self {
         let mut passed = true;
         let mut finished = true;
         let mut failed_tests = Vec::new();
@@ -88,6 +95,7 @@ impl StatusSnapshot {
                         passed = false;
                         failed_tests.push(test.name_any());
                     }
+                    TaskState::Cancelled => passed = false,
                     _ => continue,
                 },
                 Crd::Resource(resource) => {
@@ -96,7 +104,7 @@ impl StatusSnapshot {
                             passed = false;
                             finished = false
                         }
-                        TaskState::Error => passed = false,
+                        TaskState::Error | TaskState::Cancelled => passed = false,
                         _ => continue,
                     };
                     match resource.destruction_task_state() {
@@ -118,13 +126,37 @@ impl StatusSnapshot {
         }
     }
 
+    /// The objects in the snapshot.
+    pub fn crds(&self) -> &[Crd] {
+        &self.crds
+    }
+
+    /// Total the tests in the snapshot by the value of their `label`, e.g. a suite label. Tests
+    /// without the label are totaled under [`UNGROUPED`]. Resources are not counted.
+    pub fn group_by(&self, label: &str) -> GroupedStatus {
+        let mut grouped = GroupedStatus::default();
+        for crd in &self.crds {
+            if let Crd::Test(test) = crd {
+                let group = test
+                    .labels()
+                    .get(label)
+                    .cloned()
+                    .unwrap_or_else(|| UNGROUPED.to_string());
+                let state = test.test_user_state();
+                grouped.groups.entry(group).or_default().add(state);
+                grouped.total.add(state);
+            }
+        }
+        grouped
+    }
+
     pub fn new_column<S1>(&mut self, header: S1, f: fn(&Crd) -> Vec<String>) -> &mut Self
     where
         S1: Into<String>,
     {
         self.columns.push(StatusColumn {
             header: header.into(),
-            values: f,
+            values: Arc::new(f),
             ..Default::default()
         });
         self
@@ -141,6 +173,69 @@ impl StatusSnapshot {
     }
 }
 
+/// The group of the tests that do not have the label used by [`StatusSnapshot::group_by`].
+pub const UNGROUPED: &str = "<ungrouped>";
+
+/// The number of tests in each group and in total, see [`StatusSnapshot::group_by`].
+/// `GroupedStatus::to_string()` creates a table with a row for each group followed by the total.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GroupedStatus {
+    pub groups: BTreeMap<String, GroupTotals>,
+    pub total: GroupTotals,
+}
+
+/// The number of tests in a group by how far they have gotten.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct GroupTotals {
+    pub passed: usize,
+    /// Tests that failed, errored or could not get their resources.
+    pub failed: usize,
+    /// Tests that are running or waiting to run.
+    pub running: usize,
+    /// Tests that were skipped, cancelled, reported no tests or are being deleted.
+    pub other: usize,
+}
+
+impl GroupTotals {
+    fn add(&mut self, state: TestUserState) {
+        match state {
+            TestUserState::Passed => self.passed += 1,
+            TestUserState::Failed | TestUserState::Error | TestUserState::ResourceError => {
+                self.failed += 1
+            }
+            TestUserState::Unknown | TestUserState::Waiting | TestUserState::Running => {
+                self.running += 1
+            }
+            TestUserState::NoTests
+            | TestUserState::Skipped
+            | TestUserState::Cancelled
+            | TestUserState::Deleting => self.other += 1,
+        }
+    }
+
+    fn record(&self, group: &str) -> [String; 5] {
+        [
+            group.to_string(),
+            self.passed.to_string(),
+            self.failed.to_string(),
+            self.running.to_string(),
+            self.other.to_string(),
+        ]
+    }
+}
+
+impl Display for GroupedStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut builder = Builder::default();
+        builder.set_columns(["GROUP", "PASSED", "FAILED", "RUNNING", "OTHER"]);
+        for (group, totals) in &self.groups {
+            builder.add_record(totals.record(group));
+        }
+        builder.add_record(self.total.record("TOTAL"));
+        write!(f, "{}", builder.build().with(Style::blank()))
+    }
+}
+
 impl From<&StatusSnapshot> for Table {
     fn from(snapshot: &StatusSnapshot) -> Self {
         let headers: Vec<_> = snapshot
@@ -227,7 +322,7 @@ impl StatusColumn {
     pub fn name() -> StatusColumn {
         StatusColumn {
             header: "NAME".to_string(),
-            values: |crd| crd.name().into_iter().collect(),
+            values: Arc::new(|crd| crd.name().into_iter().collect()),
             ..Default::default()
         }
     }
@@ -235,7 +330,7 @@ impl StatusColumn {
     pub fn crd_type() -> StatusColumn {
         StatusColumn {
             header: "TYPE".to_string(),
-            values: crd_type,
+            values: Arc::new(crd_type),
             ..Default::default()
         }
     }
@@ -243,7 +338,7 @@ impl StatusColumn {
     pub fn state() -> StatusColumn {
         StatusColumn {
             header: "STATE".to_string(),
-            values: crd_state,
+            values: Arc::new(crd_state),
             ..Default::default()
         }
     }
@@ -251,7 +346,7 @@ impl StatusColumn {
     pub fn passed() -> StatusColumn {
         StatusColumn {
             header: "PASSED".to_string(),
-            values: |crd| crd_results(crd, ResultType::Passed),
+            values: Arc::new(|crd| crd_results(crd, ResultType::Passed)),
             alignment: TextAlignment::Right,
             width: Some(6),
         }
@@ -260,7 +355,7 @@ impl StatusColumn {
     pub fn failed() -> StatusColumn {
         StatusColumn {
             header: "FAILED".to_string(),
-            values: |crd| crd_results(crd, ResultType::Failed),
+            values: Arc::new(|crd| crd_results(crd, ResultType::Failed)),
             alignment: TextAlignment::Right,
             width: Some(6),
         }
@@ -269,28 +364,99 @@ impl StatusColumn {
     pub fn skipped() -> StatusColumn {
         StatusColumn {
             header: "SKIPPED".to_string(),
-            values: |crd| crd_results(crd, ResultType::Skipped),
+            values: Arc::new(|crd| crd_results(crd, ResultType::Skipped)),
             alignment: TextAlignment::Right,
             width: Some(7),
         }
     }
 
+    /// A column showing `yes` for tests that failed and later passed, see `TestStatus::is_flaky`.
+    pub fn flaky() -> StatusColumn {
+        StatusColumn {
+            header: "FLAKY".to_string(),
+            values: Arc::new(|crd| match crd {
+                Crd::Resource(_) => Default::default(),
+                Crd::Test(test) => match &test.status {
+                    Some(status) if status.is_flaky() => vec!["yes".to_string()],
+                    _ => vec!["no".to_string()],
+                },
+            }),
+            ..Default::default()
+        }
+    }
+
     pub fn last_update() -> StatusColumn {
         StatusColumn {
             header: "LAST UPDATE".to_string(),
-            values: crd_time,
+            values: Arc::new(crd_time),
             alignment: TextAlignment::Left,
             width: Some(20),
         }
     }
 
+    pub fn resource_error() -> StatusColumn {
+        StatusColumn {
+            header: "RESOURCE ERROR".to_string(),
+            values: Arc::new(crd_resource_error),
+            alignment: TextAlignment::Left,
+            width: Some(30),
+        }
+    }
+
     pub fn progress() -> StatusColumn {
         StatusColumn {
             header: "PROGRESS".to_string(),
-            values: crd_progress,
+            values: Arc::new(crd_progress),
             ..Default::default()
         }
     }
+
+    /// A column containing the value of the label `key` on each object, or `<none>` if the object
+    /// does not have the label.
+    pub fn label<S1, S2>(header: S1, key: S2) -> StatusColumn
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        let key = key.into();
+        StatusColumn {
+            header: header.into(),
+            values: Arc::new(move |crd| {
+                vec![crd_labels(crd)
+                    .get(&key)
+                    .cloned()
+                    .unwrap_or_else(|| NONE.to_string())]
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// A column containing the node each test agent pod was scheduled on, or `<none>` if the test
+    /// is not in `node_names`, e.g. from `TestManager::test_node_names`.
+    pub fn node(node_names: BTreeMap<String, String>) -> StatusColumn {
+        StatusColumn {
+            header: "NODE".to_string(),
+            values: Arc::new(move |crd| match crd {
+                Crd::Resource(_) => Default::default(),
+                Crd::Test(test) => vec![node_names
+                    .get(&test.name_any())
+                    .cloned()
+                    .unwrap_or_else(|| NONE.to_string())],
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// The value shown in place of missing information, matching `kubectl`.
+const NONE: &str = "<none>";
+
+/// Get the labels of the CRD
+fn crd_labels(crd: &Crd) -> &BTreeMap<String, String> {
+    match crd {
+        Crd::Test(test) => test.labels(),
+        Crd::Resource(resource) => resource.labels(),
+    }
 }
 
 /// Determine the time of the last update to the CRD
@@ -366,15 +532,137 @@ fn crd_results(crd: &Crd, res_type: ResultType) -> Vec<String> {
     }
 }
 
+/// Determine the resource error reported by the controller for a `Test`, if any.
+fn crd_resource_error(crd: &Crd) -> Vec<String> {
+    match crd {
+        Crd::Resource(_) => Default::default(),
+        Crd::Test(test) => test.resource_error().cloned().into_iter().collect(),
+    }
+}
+
+/// The percentage reported by the test agent, or the `other_info` of its current results if it
+/// has not reported a percentage.
 fn crd_progress(crd: &Crd) -> Vec<String> {
     match crd {
         Crd::Resource(_) => Default::default(),
-        Crd::Test(test) => test
-            .agent_status()
-            .current_test
-            .as_ref()
-            .and_then(|res| res.other_info.to_owned())
-            .into_iter()
-            .collect(),
+        Crd::Test(test) => match test.agent_status().progress {
+            Some(percent) => vec![format!("{}%", percent)],
+            None => test
+                .agent_status()
+                .current_test
+                .as_ref()
+                .and_then(|res| res.other_info.to_owned())
+                .into_iter()
+                .collect(),
+        },
     }
 }
+
+#[test]
+fn test_wide_columns() {
+    let mut labelled = crate::Test::default();
+    labelled.metadata.name = Some("labelled".to_string());
+    labelled.metadata.labels = Some(BTreeMap::from([(
+        crate::constants::LABEL_REGION.to_string(),
+        "us-west-2".to_string(),
+    )]));
+    let mut unlabelled = crate::Test::default();
+    unlabelled.metadata.name = Some("unlabelled".to_string());
+    let crds = [Crd::Test(labelled), Crd::Test(unlabelled)];
+    let region = StatusColumn::label("REGION", crate::constants::LABEL_REGION);
+    let node = StatusColumn::node(BTreeMap::from([(
+        "labelled".to_string(),
+        "node-1".to_string(),
+    )]));
+    assert_eq!((region.values)(&crds[0]), vec!["us-west-2"]);
+    assert_eq!((region.values)(&crds[1]), vec!["<none>"]);
+    assert_eq!((node.values)(&crds[0]), vec!["node-1"]);
+    assert_eq!((node.values)(&crds[1]), vec!["<none>"]);
+}
+
+#[test]
+fn test_progress_column() {
+    let mut test = crate::Test::default();
+    let mut status = crate::TestStatus::default();
+    status.agent.current_test = Some(crate::TestResults {
+        other_info: Some("3 of 10 suites".to_string()),
+        ..Default::default()
+    });
+    test.status = Some(status.clone());
+    let progress = StatusColumn::progress();
+    assert_eq!(
+        (progress.values)(&Crd::Test(test.clone())),
+        vec!["3 of 10 suites"]
+    );
+    status.agent.progress = Some(30);
+    test.status = Some(status);
+    assert_eq!((progress.values)(&Crd::Test(test)), vec!["30%"]);
+}
+
+#[test]
+fn test_flaky_column() {
+    let run = |outcome| crate::TestResults {
+        outcome,
+        ..Default::default()
+    };
+    let mut test = crate::Test::default();
+    let flaky = StatusColumn::flaky();
+    assert_eq!((flaky.values)(&Crd::Test(test.clone())), vec!["no"]);
+    let mut status = crate::TestStatus::default();
+    status.agent.results_history = vec![run(crate::Outcome::Fail)];
+    status.agent.results = vec![run(crate::Outcome::Pass)];
+    test.status = Some(status);
+    assert_eq!((flaky.values)(&Crd::Test(test)), vec!["yes"]);
+    assert!((flaky.values)(&Crd::Resource(crate::Resource::default())).is_empty());
+}
+
+#[test]
+fn test_group_by() {
+    let test = |name: &str, suite: Option<&str>, task_state: TaskState| {
+        let mut test = crate::Test::default();
+        test.metadata.name = Some(name.to_string());
+        test.metadata.labels =
+            suite.map(|suite| BTreeMap::from([("suite".to_string(), suite.to_string())]));
+        let mut status = crate::TestStatus::default();
+        status.agent.task_state = task_state;
+        if task_state == TaskState::Completed {
+            status.agent.results.push(crate::TestResults {
+                num_passed: 1,
+                ..Default::default()
+            });
+        }
+        test.status = Some(status);
+        Crd::Test(test)
+    };
+    let snapshot = StatusSnapshot::new(vec![
+        test("conformance-1", Some("conformance"), TaskState::Completed),
+        test("conformance-2", Some("conformance"), TaskState::Error),
+        test("migration-1", Some("migration"), TaskState::Running),
+        test("loose", None, TaskState::Completed),
+        Crd::Resource(crate::Resource::default()),
+    ]);
+    let grouped = snapshot.group_by("suite");
+    let totals = |passed, failed, running| GroupTotals {
+        passed,
+        failed,
+        running,
+        other: 0,
+    };
+    assert_eq!(
+        grouped.groups,
+        BTreeMap::from([
+            ("conformance".to_string(), totals(1, 1, 0)),
+            ("migration".to_string(), totals(0, 0, 1)),
+            (UNGROUPED.to_string(), totals(1, 0, 0)),
+        ])
+    );
+    assert_eq!(grouped.total, totals(2, 1, 1));
+    let table = grouped.to_string();
+    let rows: Vec<Vec<&str>> = table
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(rows[0], ["GROUP", "PASSED", "FAILED", "RUNNING", "OTHER"]);
+    assert_eq!(rows[1], [UNGROUPED, "1", "0", "0", "0"]);
+    assert_eq!(rows.last().unwrap(), &["TOTAL", "2", "1", "1", "0"]);
+}
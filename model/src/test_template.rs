@@ -0,0 +1,168 @@
+use crate::error::{self, Result};
+use crate::Test;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use snafu::{ensure, ResultExt};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A template parameter placeholder, e.g. `${variant}`. Resource references like
+/// `${my-cluster.endpoint}` contain a `.` so they do not match and are left for the controller to
+/// resolve.
+const PARAM_PATTERN_REGEX: &str = r"\$\{([A-Za-z0-9_-]+)\}";
+
+lazy_static::lazy_static! {
+
+    static ref PARAM_REGEX: Regex = {
+        #[allow(clippy::unwrap_used)]
+        Regex::new(PARAM_PATTERN_REGEX).unwrap()
+    };
+}
+
+/// A TestSys [`Test`] whose string fields, including configuration values, may contain `${param}`
+/// placeholders, so that the same test can be run for many variants without copying its manifest.
+/// A template is written and read like a `Test`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct TestTemplate {
+    pub test: Test,
+}
+
+impl TestTemplate {
+    pub fn new(test: Test) -> Self {
+        Self { test }
+    }
+
+    /// Create a [`Test`] by replacing every `${param}` placeholder with its value from `params`.
+    /// Returns an error listing the placeholders that have no value in `params`. Params that are
+    /// not used by the template are ignored.
+    pub fn render(&self, params: &BTreeMap<String, String>) -> Result<Test> {
+        let mut value =
+            serde_json::to_value(&self.test).context(error::TemplateSerializationSnafu)?;
+        let mut missing = BTreeSet::new();
+        substitute(&mut value, params, &mut missing);
+        ensure!(
+            missing.is_empty(),
+            error::TemplateUnresolvedSnafu {
+                params: missing.into_iter().collect::<Vec<_>>().join(", ")
+            }
+        );
+        Ok(serde_json::from_value(value).context(error::TemplateDeserializationSnafu)?)
+    }
+}
+
+/// Replace the placeholders in every string in `value`, adding the names of the placeholders that
+/// are not in `params` to `missing`.
+fn substitute(
+    value: &mut Value,
+    params: &BTreeMap<String, String>,
+    missing: &mut BTreeSet<String>,
+) {
+    match value {
+        Value::String(s) => {
+            let rendered = PARAM_REGEX.replace_all(s, |captures: &Captures<'_>| {
+                let name = &captures[1];
+                match params.get(name) {
+                    Some(param) => param.clone(),
+                    None => {
+                        missing.insert(name.to_string());
+                        captures[0].to_string()
+                    }
+                }
+            });
+            *s = rendered.into_owned();
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| substitute(value, params, missing)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| substitute(value, params, missing)),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+#[cfg(test)]
+fn template() -> TestTemplate {
+    serde_yaml::from_str(
+        r#"
+apiVersion: testsys.system/v1
+kind: Test
+metadata:
+  name: conformance-${variant}
+  labels:
+    testsys/variant: ${variant}
+spec:
+  agent:
+    name: sonobuoy
+    image: "sonobuoy-test-agent:${version}"
+    keepRunning: false
+    configuration:
+      mode: quick
+      kubeconfig: ${cluster-${variant}.encodedKubeconfig}
+      nested:
+        region: ${region}
+  resources: []
+"#,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+fn params(params: &[(&str, &str)]) -> BTreeMap<String, String> {
+    params
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_render_full() {
+    let test = template()
+        .render(&params(&[
+            ("variant", "aws-k8s-1.24"),
+            ("version", "v0.1.0"),
+            ("region", "us-west-2"),
+        ]))
+        .unwrap();
+    assert_eq!(
+        test.metadata.name.as_deref(),
+        Some("conformance-aws-k8s-1.24")
+    );
+    assert_eq!(
+        test.metadata.labels.unwrap()["testsys/variant"],
+        "aws-k8s-1.24"
+    );
+    assert_eq!(test.spec.agent.image, "sonobuoy-test-agent:v0.1.0");
+    let configuration = test.spec.agent.configuration.unwrap();
+    assert_eq!(configuration["mode"], "quick");
+    assert_eq!(configuration["nested"]["region"], "us-west-2");
+}
+
+#[test]
+fn test_render_partial() {
+    // Only the placeholder inside the resource reference is replaced, the reference itself is left
+    // for the controller. Unused params are ignored.
+    let test = template()
+        .render(&params(&[
+            ("variant", "x"),
+            ("version", "v1"),
+            ("region", "r"),
+            ("unused", "u"),
+        ]))
+        .unwrap();
+    assert_eq!(
+        test.spec.agent.configuration.unwrap()["kubeconfig"],
+        "${cluster-x.encodedKubeconfig}"
+    );
+}
+
+#[test]
+fn test_render_missing() {
+    let err = template()
+        .render(&params(&[("variant", "x")]))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("region, version"), "{}", err);
+    assert!(template().render(&BTreeMap::new()).is_err());
+}